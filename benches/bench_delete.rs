@@ -1,5 +1,3 @@
-use alloc_tree::bst::BstKey;
-use alloc_tree::sorted_slice::SortedSliceKey;
 use alloc_tree::{bst, rbt, sorted_slice};
 use core::num;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -13,8 +11,15 @@ use uint::construct_uint;
 
 const MAX_SIZE: usize = 4096;
 
-static mut MEM_U32: [u8; 163840] = [0; MAX_SIZE * bst::node_size::<u32>()];
-static mut MEM_U128: [u8; 262144] = [0; MAX_SIZE * bst::node_size::<u128>()];
+/// Backing memory shared by the benchmarks. RBT nodes are the largest of the structures, and the alignment covers
+/// every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
+static mut MEM_U32: Mem<{ MAX_SIZE * rbt::node_size::<u32>() }> =
+    Mem([0; MAX_SIZE * rbt::node_size::<u32>()]);
+static mut MEM_U128: Mem<{ MAX_SIZE * rbt::node_size::<u128>() }> =
+    Mem([0; MAX_SIZE * rbt::node_size::<u128>()]);
 
 /// The size of MemorySpaceDescriptor
 construct_uint! {
@@ -40,29 +45,29 @@ fn benchmark_delete_function(c: &mut Criterion) {
     let mut nums_shuffled = nums.clone();
     nums_shuffled.shuffle(&mut rand::thread_rng());
     // RBT 32bit
-    // group.bench_function(
-    //     BenchmarkId::new("rbt", "32bit"), |b| {
-    //     b.iter_batched_ref(
-    //         || {
-    //             let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(unsafe {&mut MEM_U32});
-    //             for i in &nums {
-    //                 rbt.insert(*i).unwrap();
-    //             }
-    //             rbt
-    //         }, |rbt|{
-    //             for i in &nums {
-    //                 rbt.delete(*i).unwrap();
-    //             }
-    //         },
-    //         criterion::BatchSize::PerIteration
-    //     );
-    // });
+    group.bench_function(BenchmarkId::new("rbt", "32bit"), |b| {
+        b.iter_batched_ref(
+            || {
+                let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(unsafe { &mut MEM_U32.0 });
+                for i in &nums {
+                    rbt.insert(*i).unwrap();
+                }
+                rbt
+            },
+            |rbt| {
+                for i in &nums_shuffled {
+                    rbt.delete(*i).unwrap();
+                }
+            },
+            criterion::BatchSize::PerIteration,
+        );
+    });
 
     // BST 32bit
     group.bench_function(BenchmarkId::new("bst", "32bit"), |b| {
         b.iter_batched_ref(
             || {
-                let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U32 });
+                let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U32.0 });
                 for i in &nums {
                     bst.insert(*i).unwrap();
                 }
@@ -82,7 +87,7 @@ fn benchmark_delete_function(c: &mut Criterion) {
         b.iter_batched_ref(
             || {
                 let mut ss: sorted_slice::SortedSlice<u32> =
-                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U32 });
+                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U32.0 });
                 for i in &nums {
                     ss.add(*i).unwrap();
                 }
@@ -102,29 +107,29 @@ fn benchmark_delete_function(c: &mut Criterion) {
     let mut nums_shuffled = nums.clone();
     nums_shuffled.shuffle(&mut rand::thread_rng());
     // RBT 128bit
-    // group.bench_function(
-    //     BenchmarkId::new("rbt", "128bit"), |b| {
-    //     b.iter_batched_ref(
-    //         || {
-    //             let mut rbt: rbt::Rbt<u128, MAX_SIZE> = rbt::Rbt::new(unsafe {&mut MEM_U128});
-    //             for i in &nums {
-    //                 rbt.insert(*i).unwrap();
-    //             }
-    //             rbt
-    //         }, |rbt|{
-    //             for i in &nums {
-    //                 rbt.delete(*i).unwrap();
-    //             }
-    //         },
-    //         criterion::BatchSize::PerIteration
-    //     );
-    // });
+    group.bench_function(BenchmarkId::new("rbt", "128bit"), |b| {
+        b.iter_batched_ref(
+            || {
+                let mut rbt: rbt::Rbt<u128, MAX_SIZE> = rbt::Rbt::new(unsafe { &mut MEM_U128.0 });
+                for i in &nums {
+                    rbt.insert(*i).unwrap();
+                }
+                rbt
+            },
+            |rbt| {
+                for i in &nums_shuffled {
+                    rbt.delete(*i).unwrap();
+                }
+            },
+            criterion::BatchSize::PerIteration,
+        );
+    });
 
     // BST 32bit
     group.bench_function(BenchmarkId::new("bst", "128bit"), |b| {
         b.iter_batched_ref(
             || {
-                let mut bst: bst::Bst<u128, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U128 });
+                let mut bst: bst::Bst<u128, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U128.0 });
                 for i in &nums {
                     bst.insert(*i).unwrap();
                 }
@@ -144,7 +149,7 @@ fn benchmark_delete_function(c: &mut Criterion) {
         b.iter_batched_ref(
             || {
                 let mut ss: sorted_slice::SortedSlice<u128> =
-                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U128 });
+                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U128.0 });
                 for i in &nums {
                     ss.add(*i).unwrap();
                 }
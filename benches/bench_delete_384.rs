@@ -1,5 +1,3 @@
-use alloc_tree::bst::BstKey;
-use alloc_tree::sorted_slice::SortedSliceKey;
 use alloc_tree::{bst, rbt, sorted_slice};
 use core::num;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -13,7 +11,13 @@ use uint::construct_uint;
 
 const MAX_SIZE: usize = 4096;
 
-static mut MEM_U384: [u8; 327680] = [0; MAX_SIZE * bst::node_size::<U384>()];
+/// Backing memory shared by the benchmarks. RBT nodes are the largest of the structures, and the alignment covers
+/// every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
+static mut MEM_U384: Mem<{ MAX_SIZE * rbt::node_size::<U384>() }> =
+    Mem([0; MAX_SIZE * rbt::node_size::<U384>()]);
 
 /// The size of MemorySpaceDescriptor
 construct_uint! {
@@ -43,29 +47,29 @@ fn benchmark_delete_function(c: &mut Criterion) {
     nums_shuffled.shuffle(&mut rand::thread_rng());
 
     // RBT 384bit
-    // group.bench_function(
-    //     BenchmarkId::new("rbt", "384bit"), |b| {
-    //     b.iter_batched_ref(
-    //         || {
-    //             let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(unsafe {&mut MEM_U384});
-    //             for i in &nums {
-    //                 rbt.insert(*i).unwrap();
-    //             }
-    //             rbt
-    //         }, |rbt|{
-    //             for i in &nums {
-    //                 rbt.delete(*i).unwrap();
-    //             }
-    //         },
-    //         criterion::BatchSize::PerIteration
-    //     );
-    // });
+    group.bench_function(BenchmarkId::new("rbt", "384bit"), |b| {
+        b.iter_batched_ref(
+            || {
+                let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(unsafe { &mut MEM_U384.0 });
+                for i in &nums {
+                    rbt.insert(*i).unwrap();
+                }
+                rbt
+            },
+            |rbt| {
+                for i in &nums_shuffled {
+                    rbt.delete(*i).unwrap();
+                }
+            },
+            criterion::BatchSize::PerIteration,
+        );
+    });
 
     // // BST 384bit
     group.bench_function(BenchmarkId::new("bst", "384bit"), |b| {
         b.iter_batched_ref(
             || {
-                let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U384 });
+                let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(unsafe { &mut MEM_U384.0 });
                 for i in &nums {
                     bst.insert(*i).unwrap();
                 }
@@ -85,7 +89,7 @@ fn benchmark_delete_function(c: &mut Criterion) {
         b.iter_batched_ref(
             || {
                 let mut ss: sorted_slice::SortedSlice<U384> =
-                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U384 });
+                    sorted_slice::SortedSlice::new(unsafe { &mut MEM_U384.0 });
                 for i in &nums {
                     ss.add(*i).unwrap();
                 }
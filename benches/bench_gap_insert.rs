@@ -0,0 +1,67 @@
+use alloc_tree::{gap_sorted_slice, sorted_slice};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::mem::size_of;
+
+const MAX_SIZE: usize = 4096;
+
+/// Backing memory for the benchmarks. The alignment covers every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
+/// Half of the slice is pre-filled with even numbers, then odd numbers from the middle of that range are inserted
+/// in ascending order, so every insert lands right next to the previous one.
+fn clustered_numbers() -> (Vec<u32>, Vec<u32>) {
+    let prefill = (0..MAX_SIZE as u32 / 2).map(|i| i * 2).collect();
+    let inserts = (0..MAX_SIZE as u32 / 4)
+        .map(|i| MAX_SIZE as u32 / 2 + i * 2 + 1)
+        .collect();
+    (prefill, inserts)
+}
+
+fn benchmark_clustered_insert_function(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clustered_insert");
+    let (prefill, inserts) = clustered_numbers();
+
+    group.bench_with_input(
+        BenchmarkId::new("sorted_slice", "32bit"),
+        &inserts,
+        |b, inserts| {
+            b.iter(|| {
+                let mut mem = Mem([0; MAX_SIZE * size_of::<u32>()]);
+                let mut ss: sorted_slice::SortedSlice<u32> =
+                    sorted_slice::SortedSlice::new(&mut mem.0);
+                for i in &prefill {
+                    ss.add(*i).unwrap();
+                }
+
+                for i in inserts {
+                    ss.add(*i).unwrap();
+                }
+            })
+        },
+    );
+
+    group.bench_with_input(
+        BenchmarkId::new("gap_sorted_slice", "32bit"),
+        &inserts,
+        |b, inserts| {
+            b.iter(|| {
+                let mut mem = Mem([0; MAX_SIZE * size_of::<u32>()]);
+                let mut gs: gap_sorted_slice::GapSortedSlice<u32> =
+                    gap_sorted_slice::GapSortedSlice::new(&mut mem.0);
+                for i in &prefill {
+                    gs.add(*i).unwrap();
+                }
+
+                for i in inserts {
+                    gs.add(*i).unwrap();
+                }
+            })
+        },
+    );
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_clustered_insert_function);
+criterion_main!(benches);
@@ -1,5 +1,3 @@
-use alloc_tree::bst::BstKey;
-use alloc_tree::sorted_slice::SortedSliceKey;
 use alloc_tree::{bst, rbt, sorted_slice};
 use core::num;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -13,6 +11,10 @@ use uint::construct_uint;
 
 const MAX_SIZE: usize = 4096;
 
+/// Backing memory for the benchmarks. The alignment covers every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
 /// The size of MemorySpaceDescriptor
 construct_uint! {
     pub struct U384(6);
@@ -36,8 +38,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
     let nums = random_numbers::<u32>(0, 100_000);
     group.bench_with_input(BenchmarkId::new("rbt", "32bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * rbt::node_size::<u32>()];
-            let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<u32>()]);
+            let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
 
             for i in nums {
                 rbt.insert(*i).unwrap();
@@ -47,8 +49,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
 
     group.bench_with_input(BenchmarkId::new("bst", "32bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * bst::node_size::<u32>()];
-            let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * bst::node_size::<u32>()]);
+            let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(&mut mem.0);
 
             for i in nums {
                 bst.insert(*i).unwrap();
@@ -61,9 +63,9 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
         &nums,
         |b, nums| {
             b.iter(|| {
-                let mut mem = [0; MAX_SIZE * size_of::<u32>()];
+                let mut mem = Mem([0; MAX_SIZE * size_of::<u32>()]);
                 let mut ss: sorted_slice::SortedSlice<u32> =
-                    sorted_slice::SortedSlice::new(&mut mem);
+                    sorted_slice::SortedSlice::new(&mut mem.0);
 
                 for i in nums {
                     ss.add(*i).unwrap();
@@ -76,8 +78,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
 
     group.bench_with_input(BenchmarkId::new("rbt", "128bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * rbt::node_size::<i128>()];
-            let mut rbt: rbt::Rbt<i128, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<i128>()]);
+            let mut rbt: rbt::Rbt<i128, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
 
             for i in nums {
                 rbt.insert(*i).unwrap();
@@ -87,8 +89,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
 
     group.bench_with_input(BenchmarkId::new("bst", "128bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * bst::node_size::<i128>()];
-            let mut bst: bst::Bst<i128, MAX_SIZE> = bst::Bst::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * bst::node_size::<i128>()]);
+            let mut bst: bst::Bst<i128, MAX_SIZE> = bst::Bst::new(&mut mem.0);
 
             for i in nums {
                 bst.insert(*i).unwrap();
@@ -101,9 +103,9 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
         &nums,
         |b, nums| {
             b.iter(|| {
-                let mut mem = [0; MAX_SIZE * size_of::<i128>()];
+                let mut mem = Mem([0; MAX_SIZE * size_of::<i128>()]);
                 let mut ss: sorted_slice::SortedSlice<i128> =
-                    sorted_slice::SortedSlice::new(&mut mem);
+                    sorted_slice::SortedSlice::new(&mut mem.0);
 
                 for i in nums {
                     ss.add(*i).unwrap();
@@ -116,8 +118,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
 
     group.bench_with_input(BenchmarkId::new("rbt", "384bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * rbt::node_size::<U384>()];
-            let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<U384>()]);
+            let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
 
             for i in nums {
                 rbt.insert((*i).into()).unwrap();
@@ -127,8 +129,8 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
 
     group.bench_with_input(BenchmarkId::new("bst", "384bit"), &nums, |b, nums| {
         b.iter(|| {
-            let mut mem = [0; MAX_SIZE * bst::node_size::<U384>()];
-            let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(&mut mem);
+            let mut mem = Mem([0; MAX_SIZE * bst::node_size::<U384>()]);
+            let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(&mut mem.0);
 
             for i in nums {
                 bst.insert((*i).into()).unwrap();
@@ -141,9 +143,9 @@ pub fn benchmark_insert_function(c: &mut Criterion) {
         &nums,
         |b, nums| {
             b.iter(|| {
-                let mut mem = [0; MAX_SIZE * size_of::<U384>()];
+                let mut mem = Mem([0; MAX_SIZE * size_of::<U384>()]);
                 let mut ss: sorted_slice::SortedSlice<U384> =
-                    sorted_slice::SortedSlice::new(&mut mem);
+                    sorted_slice::SortedSlice::new(&mut mem.0);
 
                 for i in nums {
                     ss.add((*i).into()).unwrap();
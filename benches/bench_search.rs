@@ -1,5 +1,3 @@
-use alloc_tree::bst::BstKey;
-use alloc_tree::sorted_slice::SortedSliceKey;
 use alloc_tree::{bst, rbt, sorted_slice};
 use core::num;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -13,6 +11,10 @@ use uint::construct_uint;
 
 const MAX_SIZE: usize = 4096;
 
+/// Backing memory for the benchmarks. The alignment covers every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
 /// The size of MemorySpaceDescriptor
 construct_uint! {
     pub struct U384(6);
@@ -36,8 +38,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     let nums = random_numbers::<u32>(0, 100_000);
 
     // RBT 32bit
-    let mut mem = [0; MAX_SIZE * rbt::node_size::<u32>()];
-    let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<u32>()]);
+    let mut rbt: rbt::Rbt<u32, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
     for i in &nums {
         rbt.insert(*i).unwrap();
     }
@@ -49,9 +51,24 @@ fn benchmark_search_function(c: &mut Criterion) {
         })
     });
 
+    // FROZEN RBT 32bit
+    let mut frozen_mem = vec![0; MAX_SIZE * size_of::<u32>() + 4];
+    let frozen = rbt.freeze_into(&mut frozen_mem).unwrap();
+    group.bench_with_input(
+        BenchmarkId::new("frozen_rbt", "32bit"),
+        &frozen,
+        |b, frozen| {
+            b.iter(|| {
+                for i in &nums {
+                    frozen.search(i).unwrap();
+                }
+            })
+        },
+    );
+
     // BST 32bit
-    let mut mem = [0; MAX_SIZE * bst::node_size::<u32>()];
-    let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * bst::node_size::<u32>()]);
+    let mut bst: bst::Bst<u32, MAX_SIZE> = bst::Bst::new(&mut mem.0);
     for i in &nums {
         bst.insert(*i).unwrap();
     }
@@ -64,8 +81,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     });
 
     // SORTED SLICE 32bit
-    let mut mem = [0; MAX_SIZE * size_of::<u32>()];
-    let mut ss: sorted_slice::SortedSlice<u32> = sorted_slice::SortedSlice::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * size_of::<u32>()]);
+    let mut ss: sorted_slice::SortedSlice<u32> = sorted_slice::SortedSlice::new(&mut mem.0);
     for i in &nums {
         ss.add(*i).unwrap();
     }
@@ -81,8 +98,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     let nums = random_numbers::<i128>(0, 100_000);
 
     // RBT 128bit
-    let mut mem = [0; MAX_SIZE * rbt::node_size::<i128>()];
-    let mut rbt: rbt::Rbt<i128, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<i128>()]);
+    let mut rbt: rbt::Rbt<i128, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
     for i in &nums {
         rbt.insert(*i).unwrap();
     }
@@ -95,8 +112,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     });
 
     // BST 128bit
-    let mut mem = [0; MAX_SIZE * bst::node_size::<i128>()];
-    let mut bst: bst::Bst<i128, MAX_SIZE> = bst::Bst::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * bst::node_size::<i128>()]);
+    let mut bst: bst::Bst<i128, MAX_SIZE> = bst::Bst::new(&mut mem.0);
     for i in &nums {
         bst.insert(*i).unwrap();
     }
@@ -109,8 +126,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     });
 
     // SORTED SLICE 128bit
-    let mut mem = [0; MAX_SIZE * size_of::<i128>()];
-    let mut ss: sorted_slice::SortedSlice<i128> = sorted_slice::SortedSlice::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * size_of::<i128>()]);
+    let mut ss: sorted_slice::SortedSlice<i128> = sorted_slice::SortedSlice::new(&mut mem.0);
     for i in &nums {
         ss.add(*i).unwrap();
     }
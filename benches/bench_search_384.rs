@@ -1,5 +1,3 @@
-use alloc_tree::bst::BstKey;
-use alloc_tree::sorted_slice::SortedSliceKey;
 use alloc_tree::{bst, rbt, sorted_slice};
 use core::num;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -13,6 +11,10 @@ use uint::construct_uint;
 
 const MAX_SIZE: usize = 4096;
 
+/// Backing memory for the benchmarks. The alignment covers every node type used here.
+#[repr(C, align(16))]
+struct Mem<const N: usize>([u8; N]);
+
 /// The size of MemorySpaceDescriptor
 construct_uint! {
     pub struct U384(6);
@@ -39,8 +41,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     let nums = nums.into_iter().map(|x| x.into()).collect::<Vec<U384>>();
 
     // RBT 384bit
-    let mut mem = [0; MAX_SIZE * rbt::node_size::<U384>()];
-    let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * rbt::node_size::<U384>()]);
+    let mut rbt: rbt::Rbt<U384, MAX_SIZE> = rbt::Rbt::new(&mut mem.0);
 
     for i in &nums {
         rbt.insert(*i).unwrap();
@@ -54,8 +56,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     });
 
     // BST 384bit
-    let mut mem = [0; MAX_SIZE * bst::node_size::<U384>()];
-    let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * bst::node_size::<U384>()]);
+    let mut bst: bst::Bst<U384, MAX_SIZE> = bst::Bst::new(&mut mem.0);
     for i in &nums {
         bst.insert(*i).unwrap();
     }
@@ -68,8 +70,8 @@ fn benchmark_search_function(c: &mut Criterion) {
     });
 
     // SORTED SLICE 384bit
-    let mut mem = [0; MAX_SIZE * size_of::<U384>()];
-    let mut ss: sorted_slice::SortedSlice<U384> = sorted_slice::SortedSlice::new(&mut mem);
+    let mut mem = Mem([0; MAX_SIZE * size_of::<U384>()]);
+    let mut ss: sorted_slice::SortedSlice<U384> = sorted_slice::SortedSlice::new(&mut mem.0);
     for i in &nums {
         ss.add(*i).unwrap();
     }
@@ -0,0 +1,382 @@
+//! Free-block tracking for a memory service, built from a [MultiRbt] with two indices over one caller-provided
+//! buffer.
+//!
+//! Every free block is stored once and linked into two orderings: by base address, so that a freed block finds the
+//! neighbours it merges with in one descent, and by size, so that an allocation finds the smallest block that fits in
+//! one descent.
+use crate::multi_rbt::{self, MultiRbt};
+use crate::{Error, Result};
+
+/// The index that orders the free blocks by base address.
+const BY_ADDRESS: usize = 0;
+/// The index that orders the free blocks by size, then base address, so the first at or after a size is the best fit.
+const BY_SIZE: usize = 1;
+
+/// Returns the number of bytes a single free block of an [AllocMap] occupies in its buffer.
+pub const fn node_size() -> usize {
+    multi_rbt::node_size::<Block, 2>()
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    base: u64,
+    size: u64,
+}
+
+/// The orderings of [BY_ADDRESS] and [BY_SIZE].
+const ORDERS: [fn(&Block, &Block) -> core::cmp::Ordering; 2] = [
+    |a, b| a.base.cmp(&b.base),
+    |a, b| (a.size, a.base).cmp(&(b.size, b.base)),
+];
+
+/// The free blocks of an address space, up to `SIZE` of them, or as many as fit in the buffer if `SIZE` is 0.
+///
+/// Blocks are handed out best fit: an allocation takes the smallest free block it fits in, and the lowest of those if
+/// several are the same size, so large blocks stay whole for as long as possible. Freed blocks are merged with any
+/// free block they touch, so the map never holds two adjacent blocks.
+pub struct AllocMap<'a, const SIZE: usize> {
+    blocks: MultiRbt<'a, Block, 2, SIZE>,
+    free_bytes: u64,
+}
+
+/// An [AllocMap] that holds as many free blocks as fit in the buffer it is created with.
+pub type AllocMapDyn<'a> = AllocMap<'a, 0>;
+
+impl<'a, const SIZE: usize> AllocMap<'a, SIZE> {
+    /// Creates a map with no free blocks backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size()`
+    /// bytes or is not aligned for the nodes.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self {
+            blocks: MultiRbt::new(slice, ORDERS),
+            free_bytes: 0,
+        }
+    }
+
+    /// Creates a map like [Self::new], but returns `Error::BadBuffer` instead of panicking if `slice` is too short or
+    /// not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Ok(Self {
+            blocks: MultiRbt::try_new(slice, ORDERS)?,
+            free_bytes: 0,
+        })
+    }
+
+    /// Returns the number of free blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of free blocks the map can hold.
+    pub fn capacity(&self) -> usize {
+        self.blocks.capacity()
+    }
+
+    /// Returns the total size of the free blocks.
+    pub fn free_bytes(&self) -> u64 {
+        self.free_bytes
+    }
+
+    /// Returns the size of the largest free block, or None if there are none.
+    pub fn largest(&self) -> Option<u64> {
+        self.blocks.max_by(BY_SIZE).map(|block| block.size)
+    }
+
+    /// Forgets every free block, keeping the storage.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.free_bytes = 0;
+    }
+
+    /// Adds the `size` bytes at `base` to the free blocks, merging them with the free blocks directly before and
+    /// after. Returns `Error::AlreadyExists` if any of the bytes are already free, and `Error::OutOfSpace` if the
+    /// block touches no free block and the map is full. Panics if the block wraps around the end of the address
+    /// space.
+    pub fn free(&mut self, base: u64, size: u64) -> Result<()> {
+        let end = Self::end(base, size);
+        if size == 0 {
+            return Ok(());
+        }
+        let before = self
+            .blocks
+            .floor_by(BY_ADDRESS, |block| base.cmp(&block.base))
+            .map(|block| (block.base, block.size));
+        let after = self
+            .blocks
+            .ceil_by(BY_ADDRESS, |block| base.cmp(&block.base))
+            .map(|block| (block.base, block.size));
+        if before.is_some_and(|(before, before_size)| before + before_size > base)
+            || after.is_some_and(|(after, _)| after < end)
+        {
+            return Err(Error::AlreadyExists);
+        }
+        let before = before.filter(|(before, before_size)| before + before_size == base);
+        let after = after.filter(|(after, _)| *after == end);
+        if before.is_none() && after.is_none() && self.blocks.remaining() == 0 {
+            return Err(Error::OutOfSpace);
+        }
+
+        let (mut merged_base, mut merged_size) = (base, size);
+        if let Some((before, before_size)) = before {
+            self.remove_block(before);
+            merged_base = before;
+            merged_size += before_size;
+        }
+        if let Some((after, after_size)) = after {
+            self.remove_block(after);
+            merged_size += after_size;
+        }
+        self.insert_block(merged_base, merged_size)?;
+        self.free_bytes += size;
+        Ok(())
+    }
+
+    /// Takes `size` bytes out of the smallest free block that holds them and returns their base address. Returns
+    /// `Error::NotFound` if no free block is large enough. Panics if `size` is 0.
+    pub fn allocate(&mut self, size: u64) -> Result<u64> {
+        self.allocate_aligned(size, 1)
+    }
+
+    /// Takes `size` bytes starting at a multiple of `align` out of the smallest free block that holds them, and
+    /// returns their base address. Returns `Error::NotFound` if no free block can hold them, and `Error::OutOfSpace`
+    /// if alignment would leave free bytes on both sides and the map is full. Panics if `size` is 0 or `align` is not
+    /// a power of two.
+    pub fn allocate_aligned(&mut self, size: u64, align: u64) -> Result<u64> {
+        assert!(size > 0, "Cannot allocate 0 bytes");
+        assert!(
+            align.is_power_of_two(),
+            "Alignment {:#x} is not a power of two",
+            align
+        );
+        let (block, block_size, start) = self
+            .blocks
+            .iter_from(BY_SIZE, |block| (size, 0).cmp(&(block.size, block.base)))
+            .find_map(|block| {
+                let start = block.base.checked_next_multiple_of(align)?;
+                (start - block.base <= block.size - size).then_some((block.base, block.size, start))
+            })
+            .ok_or(Error::NotFound)?;
+        self.carve(block, block_size, start, size)?;
+        Ok(start)
+    }
+
+    /// Takes the `size` bytes at `base` out of the free block that holds them, as for memory that is reserved at a
+    /// fixed address. Returns `Error::NotFound` if any of the bytes are not free, and `Error::OutOfSpace` if this
+    /// leaves free bytes on both sides and the map is full. Panics if the block wraps around the end of the address
+    /// space.
+    pub fn allocate_at(&mut self, base: u64, size: u64) -> Result<()> {
+        let end = Self::end(base, size);
+        let (block, block_size) = self
+            .blocks
+            .floor_by(BY_ADDRESS, |block| base.cmp(&block.base))
+            .map(|block| (block.base, block.size))
+            .filter(|(block, block_size)| block + block_size >= end)
+            .ok_or(Error::NotFound)?;
+        if size > 0 {
+            self.carve(block, block_size, base, size)?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the free blocks as base address and size, in ascending order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.blocks
+            .iter(BY_ADDRESS)
+            .map(|block| (block.base, block.size))
+    }
+
+    /// Checks both indices, that no two blocks overlap or touch, and that the sizes add up to [Self::free_bytes],
+    /// returning the number of blocks, or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        self.blocks.validate()?;
+        let mut free_bytes = 0;
+        let mut previous_end = None;
+        for (base, size) in self.iter() {
+            if size == 0 {
+                return Err("block is empty");
+            }
+            if previous_end.is_some_and(|end| end >= base) {
+                return Err("blocks overlap or touch");
+            }
+            free_bytes += size;
+            previous_end = Some(base + size);
+        }
+        if free_bytes != self.free_bytes {
+            return Err("block sizes do not add up to the free bytes");
+        }
+        Ok(self.len())
+    }
+
+    /// Returns the end of the `size` bytes at `base`. Panics if they wrap around the end of the address space.
+    fn end(base: u64, size: u64) -> u64 {
+        match base.checked_add(size) {
+            Some(end) => end,
+            None => panic!("Block of {:#x} bytes at {:#x} wraps around", size, base),
+        }
+    }
+
+    /// Removes the `size` bytes at `start` from the free block of `block_size` bytes at `block`, which holds them,
+    /// keeping whatever is left on either side free.
+    fn carve(&mut self, block: u64, block_size: u64, start: u64, size: u64) -> Result<()> {
+        let front = start - block;
+        let back = block + block_size - (start + size);
+        if front > 0 && back > 0 && self.blocks.remaining() == 0 {
+            return Err(Error::OutOfSpace);
+        }
+        self.remove_block(block);
+        if front > 0 {
+            self.insert_block(block, front)?;
+        }
+        if back > 0 {
+            self.insert_block(start + size, back)?;
+        }
+        self.free_bytes -= size;
+        Ok(())
+    }
+
+    fn insert_block(&mut self, base: u64, size: u64) -> Result<()> {
+        self.blocks.insert(Block { base, size })
+    }
+
+    fn remove_block(&mut self, base: u64) {
+        self.blocks
+            .remove_by(BY_ADDRESS, |block| base.cmp(&block.base));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, AllocMap, AllocMapDyn};
+    use crate::Error;
+    use rand::Rng;
+
+    #[test]
+    fn test_best_fit_and_coalescing() {
+        let mut mem = [0u64; 16 * node_size() / 8];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        let mut map = AllocMap::<16>::new(bytes);
+
+        map.free(0x1000, 0x4000).unwrap();
+        map.free(0x8000, 0x1000).unwrap();
+        map.free(0x10000, 0x2000).unwrap();
+        assert_eq!(map.free_bytes(), 0x7000);
+        assert_eq!(map.largest(), Some(0x4000));
+        assert!(matches!(
+            map.free(0x2000, 0x1000),
+            Err(Error::AlreadyExists)
+        ));
+        assert!(matches!(
+            map.free(0x7800, 0x1000),
+            Err(Error::AlreadyExists)
+        ));
+
+        // The smallest block that fits is used, not the first or the largest.
+        assert_eq!(map.allocate(0x1000).unwrap(), 0x8000);
+        assert_eq!(map.allocate(0x1800).unwrap(), 0x10000);
+        assert!(map.iter().eq([(0x1000, 0x4000), (0x11800, 0x800)]));
+        assert!(matches!(map.allocate(0x5000), Err(Error::NotFound)));
+
+        // Freeing the bytes between two free blocks merges all three.
+        map.free(0x10000, 0x1800).unwrap();
+        map.free(0x5000, 0x3000).unwrap();
+        map.free(0x8000, 0x1000).unwrap();
+        assert!(map.iter().eq([(0x1000, 0x8000), (0x10000, 0x2000)]));
+        assert_eq!(map.validate(), Ok(2));
+
+        // The smaller block has no aligned run that is large enough, so the larger one is split on both sides.
+        assert_eq!(map.allocate_aligned(0x2800, 0x4000).unwrap(), 0x4000);
+        assert!(map
+            .iter()
+            .eq([(0x1000, 0x3000), (0x6800, 0x2800), (0x10000, 0x2000)]));
+        map.allocate_at(0x11000, 0x800).unwrap();
+        assert!(matches!(
+            map.allocate_at(0x11000, 0x100),
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            map.allocate_at(0xf000, 0x2000),
+            Err(Error::NotFound)
+        ));
+        assert_eq!(map.validate(), Ok(4));
+        assert_eq!(map.free_bytes(), 0x7000);
+    }
+
+    #[test]
+    fn test_out_of_space() {
+        let mut mem = [0u64; 2 * node_size() / 8];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        let mut map = AllocMapDyn::new(bytes);
+        assert_eq!(map.capacity(), 2);
+
+        map.free(0, 0x1000).unwrap();
+        map.free(0x2000, 0x1000).unwrap();
+        assert!(matches!(map.free(0x4000, 0x1000), Err(Error::OutOfSpace)));
+        // Touching blocks merge, so they need no extra room.
+        map.free(0x1000, 0x800).unwrap();
+        map.free(0x3000, 0x1000).unwrap();
+        assert!(map.iter().eq([(0, 0x1800), (0x2000, 0x2000)]));
+        map.free(0x1800, 0x800).unwrap();
+        assert!(map.iter().eq([(0, 0x4000)]));
+
+        map.allocate_at(0x1000, 0x1000).unwrap();
+        assert!(matches!(
+            map.allocate_at(0x2800, 0x100),
+            Err(Error::OutOfSpace)
+        ));
+        assert_eq!(map.validate(), Ok(2));
+
+        map.clear();
+        assert!(map.is_empty());
+        assert!(matches!(map.allocate(1), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn test_against_bitmap() {
+        // Every allocation is checked against a bitmap of 4 KiB pages.
+        const PAGES: usize = 256;
+        let mut mem = [0u64; 256 * node_size() / 8];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        let mut map = AllocMap::<256>::new(bytes);
+        map.free(0, PAGES as u64 * 0x1000).unwrap();
+        let mut used = [false; PAGES];
+        let mut allocations = std::vec::Vec::new();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..2000 {
+            if allocations.is_empty() || rng.gen_bool(0.55) {
+                let pages = rng.gen_range(1..8u64);
+                let align = 0x1000 << rng.gen_range(0..3);
+                let Ok(base) = map.allocate_aligned(pages * 0x1000, align) else {
+                    continue;
+                };
+                assert_eq!(base % align, 0);
+                for page in used
+                    .iter_mut()
+                    .skip(base as usize / 0x1000)
+                    .take(pages as usize)
+                {
+                    assert!(!*page);
+                    *page = true;
+                }
+                allocations.push((base, pages));
+            } else {
+                let (base, pages) = allocations.swap_remove(rng.gen_range(0..allocations.len()));
+                map.free(base, pages * 0x1000).unwrap();
+                for page in used
+                    .iter_mut()
+                    .skip(base as usize / 0x1000)
+                    .take(pages as usize)
+                {
+                    *page = false;
+                }
+            }
+            map.validate().unwrap();
+            let free_pages = used.iter().filter(|page| !**page).count() as u64;
+            assert_eq!(map.free_bytes(), free_pages * 0x1000);
+        }
+    }
+}
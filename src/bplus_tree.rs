@@ -0,0 +1,834 @@
+//! A B+ tree over a caller-provided buffer, with every element in a leaf and the leaves linked into a list.
+//!
+//! The internal nodes only hold copies of keys to steer the descent. Once a range scan has found its first element it
+//! follows the leaf links, so enumerating a range costs one descent plus a linear walk instead of a descent per
+//! element.
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
+use core::{ptr, slice};
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::{Error, OrderedKey, Result};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
+
+/// Returns the number of bytes a single node of a [BPlusTree] with fanout `B` occupies in its storage.
+pub const fn node_size<D, const B: usize>() -> usize {
+    size_of::<Node<D, B>>()
+}
+
+/// A B+ tree holding elements of type `D` in leaves of up to `B - 1` elements. Internal nodes hold up to `B - 1`
+/// separating keys and `B` children. `B` must be an even number of at least 4, so a full node splits into two halves
+/// that are both at least half full.
+///
+/// Separators are copies of elements, so `D` must be `Copy`. A separator may outlive the element it was copied from,
+/// which is harmless: it still divides the keys of its children.
+pub struct BPlusTree<'a, D, const B: usize>
+where
+    D: Copy + OrderedKey,
+{
+    storage: Storage<'a, Node<D, B>, 0>,
+    /// The slot of the head node, or [NULL] if the tree is empty.
+    head: u16,
+    length: usize,
+}
+
+impl<'a, D, const B: usize> BPlusTree<'a, D, B>
+where
+    D: Copy + OrderedKey,
+{
+    /// Creates an empty tree backed by `slice`. Panics if `slice` is not aligned for the nodes, or if `B` is odd or
+    /// less than 4.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice))
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Storage::<Node<D, B>, 0>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice))
+    }
+
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        assert!(
+            B >= 4 && B.is_multiple_of(2),
+            "Fanout must be an even number of at least 4, not {}",
+            B
+        );
+        Self {
+            storage: Storage::new(slice),
+            head: NULL,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of nodes the buffer holds.
+    pub fn node_capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Removes every element from the tree. The backing storage is kept, and all of its nodes can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.head = NULL;
+        self.length = 0;
+    }
+
+    /// Takes a free slot and turns it into an empty leaf that is not linked to any other.
+    fn alloc(&mut self) -> Result<u16> {
+        Ok(self.storage.add(Node::new)?.index)
+    }
+
+    /// Gives slot `index` back to the storage.
+    fn release(&mut self, index: u16) {
+        self.storage.delete(index);
+    }
+
+    /// Inserts `data` into the tree. Full nodes are split on the way down, so the insert never has to walk back up.
+    /// Returns `Error::AlreadyExists` if an element with the same key is in the tree, and `Error::OutOfSpace` if a
+    /// node had to be split but no free node was left. The tree stays valid either way.
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        // Check for the key before growing or splitting anything, so a duplicate never costs a node.
+        if self.contains(data.ordering_key()) {
+            return Err(Error::AlreadyExists);
+        }
+        if self.head == NULL {
+            self.head = self.alloc()?;
+        }
+        if self.storage.node(self.head).is_full() {
+            // The tree grows at the top: the full head becomes the only child of a new head, and is split below it.
+            let head = self.alloc()?;
+            self.storage.node_mut(head).children[0] = self.head;
+            if let Err(e) = self.split_child(head, 0) {
+                self.release(head);
+                return Err(e);
+            }
+            self.head = head;
+        }
+
+        let key = data.ordering_key();
+        let mut index = self.head;
+        while !self.storage.node(index).is_leaf() {
+            let mut position = self.storage.node(index).child_position(key);
+            let child = self.storage.node(index).children[position];
+            if self.storage.node(child).is_full() {
+                self.split_child(index, position)?;
+                if key >= self.storage.node(index).elements()[position].ordering_key() {
+                    position += 1;
+                }
+            }
+            index = self.storage.node(index).children[position];
+        }
+
+        let leaf = self.storage.node_mut(index);
+        let Err(position) = leaf.search(key) else {
+            return Err(Error::AlreadyExists);
+        };
+        leaf.insert_element(position, data);
+        self.length += 1;
+        Ok(())
+    }
+
+    /// Splits the full child at `position` of `parent` in two, adding the separator between the halves to `parent`,
+    /// which must not be full. A leaf keeps all of its elements and hands a copy of the first one in the right half
+    /// up; an internal node moves its middle separator up instead.
+    fn split_child(&mut self, parent: u16, position: usize) -> Result<()> {
+        let half = B / 2;
+        let right = self.alloc()?;
+        let child = self.storage.node(parent).children[position];
+        let (left_node, right_node) = self.storage.pair(child, right);
+
+        let separator = if left_node.is_leaf() {
+            right_node.elements[..half - 1].copy_from_slice(&left_node.elements[half..B - 1]);
+            right_node.len = (half - 1) as u16;
+            left_node.len = half as u16;
+            right_node.prev = child;
+            right_node.next = left_node.next;
+            left_node.next = right;
+            right_node.elements()[0]
+        } else {
+            right_node.elements[..half - 1].copy_from_slice(&left_node.elements[half..B - 1]);
+            right_node.children[..half].copy_from_slice(&left_node.children[half..]);
+            left_node.children[half..].fill(NULL);
+            right_node.len = (half - 1) as u16;
+            left_node.len = half as u16;
+            left_node.remove_element(half - 1)
+        };
+        let next = right_node.next;
+        if right_node.is_leaf() && next != NULL {
+            self.storage.node_mut(next).prev = right;
+        }
+
+        let parent = self.storage.node_mut(parent);
+        parent.insert_element(position, separator);
+        parent.insert_child(position + 1, right);
+        Ok(())
+    }
+
+    /// Returns true if an element with the given key is in the tree.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_ref(key).is_some()
+    }
+
+    /// Returns a reference to the element with the given key.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        let leaf = self.storage.node(self.leaf_for(key)?);
+        leaf.search(key)
+            .ok()
+            .map(|position| &leaf.elements()[position])
+    }
+
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
+    }
+
+    /// Returns the leaf that holds `key` if it is in the tree.
+    fn leaf_for(&self, key: &D::Key) -> Option<u16> {
+        let mut index = self.head;
+        if index == NULL {
+            return None;
+        }
+        while !self.storage.node(index).is_leaf() {
+            let node = self.storage.node(index);
+            index = node.children[node.child_position(key)];
+        }
+        Some(index)
+    }
+
+    pub fn delete(&mut self, data: D) -> Result<()> {
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the tree, returning it. Returns `None` if no element has the key.
+    /// Before descending into a child, the child is topped up to at least `B / 2` entries, so it can lose one without
+    /// dropping below the minimum.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        if self.head == NULL {
+            return None;
+        }
+        let mut index = self.head;
+        while !self.storage.node(index).is_leaf() {
+            let position = self.storage.node(index).child_position(key);
+            let position = self.ensure_spare(index, position);
+            index = self.storage.node(index).children[position];
+        }
+        let leaf = self.storage.node_mut(index);
+        let removed = leaf
+            .search(key)
+            .ok()
+            .map(|position| leaf.remove_element(position));
+
+        let head = self.storage.node(self.head);
+        if head.len == 0 {
+            // The head gave up its last entry, so its only child, if it has one, takes its place.
+            let old_head = self.head;
+            self.head = head.children[0];
+            self.release(old_head);
+        }
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+
+    /// Makes sure the child at `position` of node `index` holds at least `B / 2` entries, taking one from a sibling
+    /// or merging it with one. Returns the position of the child afterwards.
+    fn ensure_spare(&mut self, index: u16, position: usize) -> usize {
+        let half = B / 2;
+        let node = self.storage.node(index);
+        let len = node.len();
+        let child = node.children[position];
+        let left = position.checked_sub(1).map(|left| node.children[left]);
+        let right = (position < len).then(|| node.children[position + 1]);
+
+        if self.storage.node(child).len() >= half {
+            position
+        } else if left.is_some_and(|left| self.storage.node(left).len() >= half) {
+            self.rotate_right(index, position - 1);
+            position
+        } else if right.is_some_and(|right| self.storage.node(right).len() >= half) {
+            self.rotate_left(index, position);
+            position
+        } else if right.is_some() {
+            self.merge_children(index, position);
+            position
+        } else {
+            self.merge_children(index, position - 1);
+            position - 1
+        }
+    }
+
+    /// Moves the last entry of the child left of `separator` over to the child right of it. Between leaves the
+    /// element moves and the separator becomes a copy of it; between internal nodes the separator moves down and
+    /// the last separator of the left child takes its place.
+    fn rotate_right(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node(index);
+        let (left, right) = (node.children[separator], node.children[separator + 1]);
+
+        let left_node = self.storage.node_mut(left);
+        let last = left_node.len() - 1;
+        let data = left_node.remove_element(last);
+        let child = (!left_node.is_leaf()).then(|| left_node.remove_child(last + 1));
+
+        let parent = &mut self.storage.node_mut(index).elements_mut()[separator];
+        let data = match child {
+            Some(_) => core::mem::replace(parent, data),
+            None => {
+                *parent = data;
+                data
+            }
+        };
+        let right_node = self.storage.node_mut(right);
+        right_node.insert_element(0, data);
+        if let Some(child) = child {
+            right_node.insert_child(0, child);
+        }
+    }
+
+    /// Moves the first entry of the child right of `separator` over to the child left of it, mirroring
+    /// [Self::rotate_right].
+    fn rotate_left(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node(index);
+        let (left, right) = (node.children[separator], node.children[separator + 1]);
+
+        let right_node = self.storage.node_mut(right);
+        let data = right_node.remove_element(0);
+        let child = (!right_node.is_leaf()).then(|| right_node.remove_child(0));
+        let first = right_node.elements().first().copied();
+
+        let parent = &mut self.storage.node_mut(index).elements_mut()[separator];
+        let data = match child {
+            Some(_) => core::mem::replace(parent, data),
+            None => {
+                // The separator has to stay above the moved element, so it becomes the new first element on the right.
+                *parent = first.expect("Leaf that can spare an element is empty");
+                data
+            }
+        };
+        let left_node = self.storage.node_mut(left);
+        left_node.insert_element(left_node.len(), data);
+        if let Some(child) = child {
+            left_node.insert_child(left_node.len(), child);
+        }
+    }
+
+    /// Merges the children on either side of `separator` into the left one. Leaves are concatenated and the right one
+    /// is unlinked from the list; internal nodes take the separator down between their entries.
+    fn merge_children(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node_mut(index);
+        let data = node.remove_element(separator);
+        let right = node.remove_child(separator + 1);
+        let left = node.children[separator];
+
+        let (left_node, right_node) = self.storage.pair(left, right);
+        if !left_node.is_leaf() {
+            left_node.insert_element(left_node.len(), data);
+        }
+        let start = left_node.len();
+        let moved = right_node.len();
+        left_node.elements[start..][..moved].copy_from_slice(&right_node.elements[..moved]);
+        left_node.children[start..][..=moved].copy_from_slice(&right_node.children[..=moved]);
+        left_node.len += moved as u16;
+        left_node.next = right_node.next;
+        let next = right_node.next;
+        if left_node.is_leaf() && next != NULL {
+            self.storage.node_mut(next).prev = left;
+        }
+        self.release(right);
+    }
+
+    /// Checks every B+ tree invariant and the links between the leaves, returning the height of the tree, or a
+    /// description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        if self.head == NULL {
+            return Ok(0);
+        }
+        let mut count = 0;
+        let mut previous_leaf = NULL;
+        let height = self.validate_node(self.head, None, None, &mut count, &mut previous_leaf)?;
+        if self.storage.node(previous_leaf).next != NULL {
+            return Err("last leaf links to another");
+        }
+        if count != self.length {
+            return Err("length does not match the number of elements");
+        }
+        Ok(height)
+    }
+
+    /// Checks the subtree rooted at `index`, whose keys must be at least `lower` and less than `upper`, adds its
+    /// elements to `count` and checks that its leaves follow `previous_leaf` in the list.
+    fn validate_node(
+        &self,
+        index: u16,
+        lower: Option<&D::Key>,
+        upper: Option<&D::Key>,
+        count: &mut usize,
+        previous_leaf: &mut u16,
+    ) -> core::result::Result<usize, &'static str> {
+        let node = self.storage.node(index);
+        if !self.storage.is_occupied(index as usize) {
+            return Err("node is in a free slot");
+        }
+        if node.len() >= B {
+            return Err("node length is out of range");
+        }
+        if node.len == 0 || (index != self.head && node.len() < B / 2 - 1) {
+            return Err("node holds too few entries");
+        }
+        let keys = node.elements().iter().map(|data| data.ordering_key());
+        if lower.is_some_and(|lower| {
+            node.elements()
+                .first()
+                .is_some_and(|first| first.ordering_key() < lower)
+        }) {
+            return Err("key is below the separator to its left");
+        }
+        let mut previous = None;
+        for key in keys.chain(upper) {
+            if previous.is_some_and(|previous| previous >= key) {
+                return Err("keys are not in ascending order");
+            }
+            previous = Some(key);
+        }
+
+        if node.is_leaf() {
+            if node.prev != *previous_leaf {
+                return Err("leaf does not link back to the previous leaf");
+            }
+            if *previous_leaf != NULL && self.storage.node(*previous_leaf).next != index {
+                return Err("leaf is not linked from the previous leaf");
+            }
+            *previous_leaf = index;
+            *count += node.len();
+            return Ok(1);
+        }
+
+        let mut height = None;
+        for (position, &child) in node.children[..=node.len()].iter().enumerate() {
+            if child == NULL {
+                return Err("internal node is missing a child");
+            }
+            let lower = position
+                .checked_sub(1)
+                .map(|left| node.elements()[left].ordering_key())
+                .or(lower);
+            let upper = node
+                .elements()
+                .get(position)
+                .map(|data| data.ordering_key())
+                .or(upper);
+            let child_height = self.validate_node(child, lower, upper, count, previous_leaf)?;
+            if height.is_some_and(|height| height != child_height) {
+                return Err("leaves are not all at the same depth");
+            }
+            height = Some(child_height);
+        }
+        Ok(height.unwrap() + 1)
+    }
+
+    /// Returns an iterator over the elements in ascending order.
+    pub fn iter(&self) -> Range<'_, D, B> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over the elements whose keys lie in `range`, in ascending order. Each end takes one descent
+    /// from the head; the elements in between are found by following the leaf links.
+    pub fn range<R: RangeBounds<D::Key>>(&self, range: R) -> Range<'_, D, B> {
+        let front = match range.start_bound() {
+            Bound::Included(lo) => self.seek(|key| key < lo),
+            Bound::Excluded(lo) => self.seek(|key| key <= lo),
+            Bound::Unbounded => self.seek(|_| false),
+        };
+        let after_back = match range.end_bound() {
+            Bound::Included(hi) => self.seek(|key| key <= hi),
+            Bound::Excluded(hi) => self.seek(|key| key < hi),
+            Bound::Unbounded => (NULL, 0),
+        };
+        let back = self.step_back(after_back);
+
+        let mut range = Range {
+            storage: &self.storage,
+            front,
+            back,
+        };
+        // An empty range leaves the front past the back, in which case neither end is walked.
+        match (range.get(front), range.get(back)) {
+            (Some(front), Some(back)) if front.ordering_key() <= back.ordering_key() => {}
+            _ => {
+                range.front = (NULL, 0);
+                range.back = (NULL, 0);
+            }
+        }
+        range
+    }
+
+    /// Returns the position of the first element whose key is not `before`, or `(NULL, 0)` if there is none.
+    /// `before` must hold for every key up to some point and for none after it.
+    fn seek(&self, before: impl Fn(&D::Key) -> bool) -> (u16, u16) {
+        let mut index = self.head;
+        if index == NULL {
+            return (NULL, 0);
+        }
+        loop {
+            let node = self.storage.node(index);
+            let position = node
+                .elements()
+                .partition_point(|data| before(data.ordering_key()));
+            if node.is_leaf() {
+                return match position < node.len() {
+                    true => (index, position as u16),
+                    // Every element here is before, so the first one of the next leaf is not.
+                    false => (node.next, 0),
+                };
+            }
+            index = node.children[position];
+        }
+    }
+
+    /// Returns the position of the element before `position`, where `(NULL, 0)` stands for the position past the last
+    /// element.
+    fn step_back(&self, (index, position): (u16, u16)) -> (u16, u16) {
+        if position > 0 {
+            return (index, position - 1);
+        }
+        let previous = match index {
+            NULL => {
+                let mut index = self.head;
+                while index != NULL && !self.storage.node(index).is_leaf() {
+                    let node = self.storage.node(index);
+                    index = node.children[node.len()];
+                }
+                index
+            }
+            index => self.storage.node(index).prev,
+        };
+        match previous {
+            NULL => (NULL, 0),
+            previous => (previous, self.storage.node(previous).len - 1),
+        }
+    }
+}
+
+impl<'b, D, const B: usize> IntoIterator for &'b BPlusTree<'_, D, B>
+where
+    D: Copy + OrderedKey,
+{
+    type Item = &'b D;
+    type IntoIter = Range<'b, D, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over a range of the elements of a [BPlusTree], created by [BPlusTree::range]. Both ends walk along the
+/// leaf links, and the iterator ends once they meet.
+pub struct Range<'a, D, const B: usize> {
+    storage: &'a Storage<'a, Node<D, B>, 0>,
+    /// The leaf and position of the next element from the front, with a leaf of [NULL] once the range is exhausted.
+    front: (u16, u16),
+    /// The leaf and position of the next element from the back.
+    back: (u16, u16),
+}
+
+impl<'a, D, const B: usize> Range<'a, D, B>
+where
+    D: Copy,
+{
+    fn get(&self, (index, position): (u16, u16)) -> Option<&'a D> {
+        let storage = self.storage;
+        (index != NULL).then(|| &storage.node(index).elements()[position as usize])
+    }
+
+    /// Returns the element at `position` and ends the range if it was the last one in it.
+    fn take_at(&mut self, position: (u16, u16)) -> Option<&'a D> {
+        let data = self.get(position);
+        if self.front == self.back {
+            self.front = (NULL, 0);
+            self.back = (NULL, 0);
+        }
+        data
+    }
+}
+
+impl<'a, D, const B: usize> Iterator for Range<'a, D, B>
+where
+    D: Copy,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, position) = self.front;
+        if index == NULL {
+            return None;
+        }
+        let data = self.take_at(self.front);
+        if self.front.0 != NULL {
+            let node = self.storage.node(index);
+            self.front = match position as usize + 1 < node.len() {
+                true => (index, position + 1),
+                false => (node.next, 0),
+            };
+        }
+        data
+    }
+}
+
+impl<'a, D, const B: usize> DoubleEndedIterator for Range<'a, D, B>
+where
+    D: Copy,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (index, position) = self.back;
+        if index == NULL {
+            return None;
+        }
+        let data = self.take_at(self.back);
+        if self.back.0 != NULL {
+            self.back = match position {
+                0 => {
+                    let previous = self.storage.node(index).prev;
+                    (previous, self.storage.node(previous).len - 1)
+                }
+                _ => (index, position - 1),
+            };
+        }
+        data
+    }
+}
+
+/// A node holds up to `B - 1` entries in ascending order: the elements themselves in a leaf, or separators with one
+/// more child than separators in an internal node. The entry array is `B` long so it can be declared without const
+/// arithmetic; its last slot is never used.
+struct Node<D, const B: usize> {
+    /// The number of entries.
+    len: u16,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    /// Slot indices of the children, all [NULL] in a leaf.
+    children: [u16; B],
+    /// The next leaf in ascending order. A free slot links to the next free slot through it.
+    next: u16,
+    /// The previous leaf in ascending order.
+    prev: u16,
+    elements: [MaybeUninit<D>; B],
+}
+
+impl<D, const B: usize> Slot for Node<D, B> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).next).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).next).write(index) };
+    }
+}
+
+impl<D, const B: usize> Node<D, B>
+where
+    D: Copy + OrderedKey,
+{
+    /// Finds `key` among the entries, like `slice::binary_search`.
+    fn search(&self, key: &D::Key) -> core::result::Result<usize, usize> {
+        self.elements()
+            .binary_search_by(|data| data.ordering_key().cmp(key))
+    }
+
+    /// Returns the position of the child whose keys cover `key`. Keys equal to a separator live to its right.
+    fn child_position(&self, key: &D::Key) -> usize {
+        self.elements()
+            .partition_point(|data| data.ordering_key() <= key)
+    }
+}
+
+impl<D, const B: usize> Node<D, B>
+where
+    D: Copy,
+{
+    /// Returns an empty leaf that is not linked to any other, to be written to slot `index`.
+    fn new(index: u16) -> Self {
+        Node {
+            len: 0,
+            index,
+            children: [NULL; B],
+            next: NULL,
+            prev: NULL,
+            elements: [MaybeUninit::uninit(); B],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NULL
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == B - 1
+    }
+
+    fn elements(&self) -> &[D] {
+        unsafe { slice::from_raw_parts(self.elements.as_ptr().cast(), self.len()) }
+    }
+
+    fn elements_mut(&mut self) -> &mut [D] {
+        unsafe { slice::from_raw_parts_mut(self.elements.as_mut_ptr().cast(), self.len()) }
+    }
+
+    /// Inserts `data` at `position`, shifting the entries after it to the right.
+    fn insert_element(&mut self, position: usize, data: D) {
+        let len = self.len();
+        self.elements.copy_within(position..len, position + 1);
+        self.elements[position] = MaybeUninit::new(data);
+        self.len += 1;
+    }
+
+    /// Removes the entry at `position`, shifting the entries after it to the left.
+    fn remove_element(&mut self, position: usize) -> D {
+        let data = self.elements()[position];
+        let len = self.len();
+        self.elements.copy_within(position + 1..len, position);
+        self.len -= 1;
+        data
+    }
+
+    /// Inserts `child` at `position`. Called after inserting the separator in front of it, so the length already
+    /// accounts for it.
+    fn insert_child(&mut self, position: usize, child: u16) {
+        let len = self.len();
+        self.children.copy_within(position..len, position + 1);
+        self.children[position] = child;
+    }
+
+    /// Removes the child at `position`. Called after removing the separator in front of it.
+    fn remove_child(&mut self, position: usize) -> u16 {
+        let len = self.len();
+        let child = self.children[position];
+        self.children.copy_within(position + 1..len + 2, position);
+        self.children[len + 1] = NULL;
+        child
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, BPlusTree};
+    use crate::testing::{self, impl_test_set};
+    use crate::{AlignedBuffer, Error};
+    use std::vec::Vec;
+
+    impl_test_set!([const B: usize] BPlusTree<'_, u32, B>);
+
+    fn check_against_set<const B: usize>() {
+        let mut mem = AlignedBuffer([0; 512 * node_size::<u32, 8>()]);
+        testing::check_against_set(&mut BPlusTree::<u32, B>::new(&mut mem));
+    }
+
+    #[test]
+    fn test_insert_search_remove() {
+        check_against_set::<4>();
+        check_against_set::<6>();
+        check_against_set::<8>();
+
+        let mut mem = AlignedBuffer([0; 128 * node_size::<u32, 4>()]);
+        let mut tree = BPlusTree::<u32, 4>::new(&mut mem);
+        for value in 0..100 {
+            tree.insert(value).unwrap();
+        }
+        assert!(matches!(tree.insert(50), Err(Error::AlreadyExists)));
+        assert_eq!(tree.len(), 100);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().count(), 0);
+        tree.insert(7).unwrap();
+        assert!(tree.contains(&7));
+    }
+
+    #[test]
+    fn test_range() {
+        let mut mem = AlignedBuffer([0; 256 * node_size::<u32, 4>()]);
+        let mut tree = BPlusTree::<u32, 4>::new(&mut mem);
+        for value in (0..200).map(|i| (i * 37) % 200 * 2) {
+            tree.insert(value).unwrap();
+        }
+        let values: Vec<u32> = (0..200).map(|i| i * 2).collect();
+
+        assert!(tree.range(10..20).copied().eq([10, 12, 14, 16, 18]));
+        assert!(tree.range(11..=20).copied().eq([12, 14, 16, 18, 20]));
+        assert!(tree
+            .range((
+                core::ops::Bound::Excluded(10),
+                core::ops::Bound::Excluded(16)
+            ))
+            .copied()
+            .eq([12, 14]));
+        assert!(tree.range(..5).copied().eq([0, 2, 4]));
+        assert!(tree.range(390..).copied().eq([390, 392, 394, 396, 398]));
+        assert!(tree.range(..).eq(values.iter()));
+        assert!(tree.range(..).rev().eq(values.iter().rev()));
+        assert_eq!(tree.range(11..12).count(), 0);
+        assert_eq!(
+            tree.range((
+                core::ops::Bound::Included(20),
+                core::ops::Bound::Excluded(10)
+            ))
+            .count(),
+            0
+        );
+        assert_eq!(tree.range(1000..).count(), 0);
+
+        // Both ends walk towards each other and stop once they meet.
+        let mut range = tree.range(100..=110);
+        assert_eq!(range.next(), Some(&100));
+        assert_eq!(range.next_back(), Some(&110));
+        assert!(range.copied().eq([102, 104, 106, 108]));
+
+        let mut range = tree.range(100..=104);
+        assert_eq!(range.next_back(), Some(&104));
+        assert_eq!(range.next(), Some(&100));
+        assert_eq!(range.next_back(), Some(&102));
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+    }
+
+    #[test]
+    fn test_duplicate_when_full() {
+        let mut mem = AlignedBuffer([0; node_size::<u32, 4>()]);
+        testing::check_duplicate_when_full(&mut BPlusTree::<u32, 4>::new(&mut mem));
+    }
+
+    #[test]
+    fn test_out_of_space() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32, 4>()]);
+        let mut tree = BPlusTree::<u32, 4>::new(&mut mem);
+        assert_eq!(tree.node_capacity(), 4);
+        testing::check_out_of_space(&mut tree);
+    }
+}
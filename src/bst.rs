@@ -1,154 +1,603 @@
 extern crate alloc;
-use core::ptr::null_mut;
+use core::ptr::{self, null_mut};
 use core::{
-    mem::size_of,
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
     panic, slice,
-    sync::atomic::{AtomicPtr, Ordering},
+    sync::atomic::{AtomicPtr, AtomicU16, AtomicU32, Ordering},
 };
 
-use super::{Error, Result};
+use super::{Error, OrderedKey, Result};
+use crate::pool::{self, NodePool, PoolHandle, Slot};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
 
 pub const fn node_size<D: core::cmp::PartialOrd>() -> usize {
-    size_of::<(bool, Node<D>)>()
+    size_of::<Node<D>>()
 }
 
-pub trait BstKey {
-    type Key: Ord;
-    fn ordering_key(&self) -> &Self::Key;
+/// Alias of [OrderedKey], kept so existing implementations continue to compile.
+pub use crate::OrderedKey as BstKey;
+
+/// The pool a [Bst] draws its nodes from unless it is created with another one.
+pub type Storage<'a, D, const SIZE: usize> = pool::Storage<'a, Node<D>, SIZE>;
+
+/// A buffer with the size and alignment to back a [Bst] of up to `SIZE` elements of type `D`, for use with
+/// [Bst::new_in]. Its slots start out uninitialized, so it can be declared as a `static` without spelling out a byte
+/// count.
+pub struct AlignedStorage<D, const SIZE: usize>
+where
+    D: PartialOrd,
+{
+    slots: [MaybeUninit<Node<D>>; SIZE],
 }
 
-impl<T> BstKey for T
+impl<D, const SIZE: usize> AlignedStorage<D, SIZE>
 where
-    T: Ord,
+    D: PartialOrd,
 {
-    type Key = Self;
-    fn ordering_key(&self) -> &T {
-        self
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { MaybeUninit::uninit() }; SIZE],
+        }
     }
 }
 
-pub struct Storage<'a, D, const SIZE: usize>
+impl<D, const SIZE: usize> Default for AlignedStorage<D, SIZE>
 where
     D: PartialOrd,
 {
-    pub data: &'a mut [(bool, Node<D>)],
-    pub length: usize,
-    free_indices: arrayvec::ArrayVec<u16, SIZE>,
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A binary search tree that can hold up to `SIZE` nodes, or as many as fit in its buffer if `SIZE` is 0. The nodes
+/// can also come from any other [NodePool] through [Self::with_pool], in which case `SIZE` is unused. `H` is the type
+/// of the handler set with [Self::with_full_handler].
+pub struct Bst<'a, D, const SIZE: usize, P = Storage<'a, D, SIZE>, H = fn()>
+where
+    D: PartialOrd + OrderedKey,
+{
+    pub storage: P,
+    pub head: AtomicPtr<Node<D>>,
+    full_handler: Option<H>,
+    full_handler_fired: bool,
+    /// Orders the keys on every descent. This is `Ord::cmp` unless the tree was created with [Bst::new_by].
+    order: fn(&D::Key, &D::Key) -> core::cmp::Ordering,
+    lifetime: PhantomData<&'a mut [Node<D>]>,
+}
+
+/// A [Bst] that holds as many nodes as fit in the buffer it is created with, for regions whose size is only known at
+/// run time. The free list is kept in the buffer, so nothing else depends on the capacity.
+pub type BstDyn<'a, D> = Bst<'a, D, 0>;
+
+/// A [pool::SharedPool] of up to `SIZE` nodes for any number of [Bst]s over elements of type `D`.
+pub type SharedPool<D, const SIZE: usize> = pool::SharedPool<Node<D>, SIZE>;
+
+/// A [Bst] that draws its nodes from a [SharedPool], created with [Bst::with_pool] from [pool::SharedPool::handle].
+pub type SharedBst<'p, D, const SIZE: usize> = Bst<'p, D, 0, PoolHandle<'p, Node<D>, SIZE>>;
+
+/// A [Bst] that owns its nodes on the heap, created with [Bst::with_capacity].
+#[cfg(feature = "alloc")]
+pub type HeapBst<D> = Bst<'static, D, 0, pool::HeapPool<Node<D>>>;
+
+/// A key type whose values can be summed without wrapping, as [Bst::find_pair_summing_to] does.
+pub trait CheckedAdd: Sized {
+    /// Returns `self + other`, or the side of the range of the type that the sum fell past if it does not fit.
+    fn checked_sum(self, other: Self) -> core::result::Result<Self, core::cmp::Ordering>;
+}
+
+macro_rules! impl_checked_add {
+    ($($ty:ty),*) => {
+        $(
+            impl CheckedAdd for $ty {
+                fn checked_sum(self, other: Self) -> core::result::Result<Self, core::cmp::Ordering> {
+                    match self.overflowing_add(other) {
+                        (sum, false) => Ok(sum),
+                        // A sum that wrapped past the top lands below `self`, and one past the bottom above it.
+                        (sum, true) if sum < self => Err(core::cmp::Ordering::Greater),
+                        (_, true) => Err(core::cmp::Ordering::Less),
+                    }
+                }
+            }
+        )*
+    };
 }
 
-impl<'a, D, const SIZE: usize> Storage<'a, D, { SIZE }>
+impl_checked_add!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl<'a, D, const SIZE: usize> Bst<'a, D, { SIZE }>
 where
-    D: PartialOrd + core::fmt::Debug,
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
 {
-    /// Create a new storage container.
-    fn new(slice: &'a mut [u8]) -> Storage<'a, D, SIZE> {
-        Storage {
-            data: unsafe {
-                slice::from_raw_parts_mut::<'a, (bool, Node<D>)>(
-                    slice as *mut [u8] as *mut (bool, Node<D>),
-                    SIZE,
-                )
-            },
-            length: 0,
-            free_indices: arrayvec::ArrayVec::from(array_init::array_init(|i| i as u16)),
+    /// Creates an empty tree backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<D>()` bytes or
+    /// is not aligned for the nodes.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_by(slice, Ord::cmp)
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Storage::<D, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice))
+    }
+
+    /// Creates an empty tree backed by `slice` that orders keys with `order` instead of their `Ord` implementation.
+    /// A closure that captures nothing, such as `|a, b| b.cmp(a)`, coerces to the function pointer. Panics like
+    /// [Self::new].
+    pub fn new_by(slice: &'a mut [u8], order: fn(&D::Key, &D::Key) -> core::cmp::Ordering) -> Self {
+        Self {
+            order,
+            ..Self::new_uninit(crate::as_uninit(slice))
         }
     }
 
-    /// Add a new node to the storage container, returning a mutable reference to the node.
-    fn add(&mut self, data: D) -> Result<&mut Node<D>> {
-        if let Some(index) = self.free_indices.pop() {
-            self.data[index as usize] = (true, Node::new(data));
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self::with_pool(Storage::new(slice))
+    }
+
+    /// Creates an empty tree backed by `storage`, which always has the right size and alignment.
+    pub fn new_in(storage: &'a mut AlignedStorage<D, SIZE>) -> Self {
+        let len = SIZE * node_size::<D>();
+        Self::new_uninit(unsafe {
+            slice::from_raw_parts_mut(storage.slots.as_mut_ptr().cast(), len)
+        })
+    }
+
+    /// Creates an empty tree over the `len` bytes starting at `addr`, such as a reserved memory region. Returns
+    /// `Error::BadBuffer` if the region cannot hold `SIZE` nodes or `addr` is not aligned for them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the region for the lifetime `'a`: it must be valid for writes and not accessed through any
+    /// other pointer while the tree is alive. Its contents may be uninitialized.
+    pub unsafe fn new_at(addr: *mut u8, len: usize) -> Result<Self> {
+        let slice = unsafe { slice::from_raw_parts_mut(addr as *mut MaybeUninit<u8>, len) };
+        Storage::<D, SIZE>::check(slice)?;
+        Ok(Self::new_uninit(slice))
+    }
 
-            let (_, node) = self.data.get_mut(index as usize).unwrap();
-            self.length += 1;
-            return Ok(node);
+    /// Wraps a buffer that holds the storage of another tree, which may have been copied from a different address.
+    /// Links between nodes are stored as slot indices, so the tree is usable once every node is pointed at the new
+    /// buffer and the head is found again.
+    ///
+    /// # Safety
+    ///
+    /// `slice` must hold the bytes of the storage of a tree of the same type, such as the buffer of a tree that is no
+    /// longer used or a copy of it, created with [Self::new]. The nodes are used as-is. The restored tree drops the
+    /// elements it holds, so the tree the bytes came from must be forgotten with `core::mem::forget` rather than
+    /// dropped if `D` has a destructor.
+    pub unsafe fn restore(slice: &'a mut [u8]) -> Self {
+        let (storage, head) = Storage::restore(slice);
+        // The nodes still point at slot 0 of the buffer they were written in.
+        for node in storage.occupied() {
+            node.base.store(storage.base(), Ordering::Release);
+        }
+        Self {
+            storage,
+            head: AtomicPtr::new(head),
+            full_handler: None,
+            full_handler_fired: false,
+            order: Ord::cmp,
+            lifetime: PhantomData,
         }
-        Err(Error::OutOfSpace)
     }
 
-    /// Delete a node from the storage container.
-    fn delete(&mut self, ptr: *mut Node<D>) {
-        // Calculate the index of the node in the storage container based off the pointer.
-        let index =
-            (ptr as usize - self.data.as_ptr() as usize) / core::mem::size_of::<(bool, Node<D>)>();
-        self.data[index].0 = false;
-        self.length -= 1;
-        self.free_indices.push(index as u16);
+    /// Returns true if every storage slot is either live or on the free list, never both and never twice.
+    pub fn validate_free_list(&self) -> bool {
+        // A slot freed twice links the list back onto itself, which shows up as more free slots than there are.
+        let mut free = 0;
+        for index in self.storage.free_slots() {
+            if index >= self.capacity() || self.storage.is_occupied(index) {
+                return false;
+            }
+            free += 1;
+        }
+        let live = self.storage.occupied().count();
+        live == self.storage.len() && live + free == self.capacity()
     }
 }
 
-pub struct Bst<'a, D, const SIZE: usize>
+#[cfg(feature = "alloc")]
+impl<D> Bst<'static, D, 0, pool::HeapPool<Node<D>>>
 where
-    D: PartialOrd,
+    D: PartialOrd + core::fmt::Debug + OrderedKey + 'static,
 {
-    pub storage: Storage<'a, D, SIZE>,
-    pub head: AtomicPtr<Node<D>>,
+    /// Creates an empty tree that owns a heap buffer of `capacity` nodes and frees it when dropped.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_pool(pool::HeapPool::new(capacity))
+    }
 }
 
-impl<'a, D, const SIZE: usize> Bst<'a, D, { SIZE }>
+impl<'a, D, const SIZE: usize, P> Bst<'a, D, SIZE, P>
 where
-    D: PartialOrd + Copy + core::fmt::Debug + BstKey,
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
 {
-    pub fn new(slice: &'a mut [u8]) -> Self {
+    /// Creates an empty tree that draws its nodes from `pool`, which must be empty.
+    pub fn with_pool(pool: P) -> Self {
+        Self::with_pool_by(pool, Ord::cmp)
+    }
+
+    /// Creates an empty tree that draws its nodes from `pool`, which must be empty, and orders keys with `order`
+    /// like [Bst::new_by].
+    pub fn with_pool_by(pool: P, order: fn(&D::Key, &D::Key) -> core::cmp::Ordering) -> Self {
+        assert!(pool.is_empty(), "The pool of a new tree must be empty");
         Self {
-            storage: Storage::new(slice),
+            storage: pool,
             head: AtomicPtr::default(),
+            full_handler: None,
+            full_handler_fired: false,
+            order,
+            lifetime: PhantomData,
+        }
+    }
+}
+
+impl<'a, D, const SIZE: usize, P, H> Bst<'a, D, SIZE, P, H>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+    H: FnMut(),
+{
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of elements the tree can hold. This is `SIZE`, unless `SIZE` is 0 and the capacity
+    /// was taken from the length of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Returns the number of elements that can still be inserted before the tree runs out of space.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Removes every element from the tree. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.head.store(null_mut(), Ordering::Release);
+        self.storage.clear();
+        self.full_handler_fired = false;
+    }
+
+    /// Registers a handler that is called the first time an insert fails because the tree is full. The handler is
+    /// re-armed once a delete frees up space. A closure that captures nothing can be set on any tree; one that does is
+    /// set with [Self::with_full_handler].
+    pub fn set_full_handler(&mut self, handler: H) {
+        self.full_handler = Some(handler);
+        self.full_handler_fired = false;
+    }
+
+    /// Returns this tree with `handler` registered like [Self::set_full_handler], replacing any handler it had. The
+    /// tree takes the type of `handler`, so the handler can capture state of its own.
+    pub fn with_full_handler<G: FnMut()>(self, handler: G) -> Bst<'a, D, SIZE, P, G> {
+        Bst {
+            storage: self.storage,
+            head: self.head,
+            full_handler: Some(handler),
+            full_handler_fired: false,
+            order: self.order,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Swaps the backing storage and contents of this tree with `other` in O(1). Each tree keeps pointing into its
+    /// own buffer, so no nodes need to be relocated. The comparators move with the contents they ordered, while
+    /// registered full handlers stay with their tree.
+    pub fn swap_backing(&mut self, other: &mut Bst<'a, D, SIZE, P, H>) {
+        core::mem::swap(&mut self.storage, &mut other.storage);
+        core::mem::swap(&mut self.order, &mut other.order);
+        let head = other
+            .head
+            .swap(self.head.load(Ordering::Acquire), Ordering::AcqRel);
+        self.head.store(head, Ordering::Release);
+        self.full_handler_fired = false;
+        other.full_handler_fired = false;
+    }
+
+    /// Calls the full handler if storage has no free slots left and the handler has not fired yet.
+    fn notify_if_full(&mut self) {
+        if self.storage.len() < self.capacity() || self.full_handler_fired {
+            return;
+        }
+        if let Some(handler) = self.full_handler.as_mut() {
+            handler();
+            self.full_handler_fired = true;
         }
     }
 
     pub fn head(&self) -> Option<&Node<D>> {
-        let head_ptr = self.head.load(Ordering::SeqCst);
+        let head_ptr = self.head.load(Ordering::Acquire);
         if head_ptr.is_null() {
             return None;
         }
         Some(unsafe { &*head_ptr })
     }
 
+    fn compare(&self, a: &D::Key, b: &D::Key) -> core::cmp::Ordering {
+        (self.order)(a, b)
+    }
+
     pub fn insert(&mut self, data: D) -> Result<()> {
-        let node = self.storage.add(data)?;
+        match self.search_slot(data.ordering_key()) {
+            Ok(_) => Err(Error::AlreadyExists),
+            Err((parent, left)) => self.link_leaf(data, parent, left).map(|_| ()),
+        }
+    }
+
+    /// Returns an iterator over the elements in ascending key order. The walk follows parent pointers, so it does not
+    /// allocate.
+    pub fn iter(&self) -> Iter<'_, D> {
+        Iter {
+            next: self.min_node(),
+            back: self.max_node(),
+        }
+    }
+
+    /// Returns an iterator over the elements whose keys fall within `lo..=hi`, in ascending key order. Each end is
+    /// found with one descent, and the walk follows parent pointers from either side.
+    pub fn range(&self, lo: &D::Key, hi: &D::Key) -> Range<'_, D> {
+        let next = self.bound_node(lo, true, false);
+        let back = self.bound_node(hi, false, false);
+        // An empty range leaves the front past the back, in which case neither end is walked.
+        let (next, back) = match (next, back) {
+            (Some(next), Some(back))
+                if self
+                    .compare(next.data.ordering_key(), back.data.ordering_key())
+                    .is_le() =>
+            {
+                (Some(next), Some(back))
+            }
+            _ => (None, None),
+        };
+        Range {
+            inner: Iter { next, back },
+        }
+    }
+
+    /// Writes the tree sideways, one node per line with the right subtree above its parent and the left subtree
+    /// below, indented four spaces per level. Each line shows the node's value.
+    #[cfg(any(test, feature = "fmt-tree"))]
+    pub fn fmt_tree(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Self::fmt_subtree(self.head(), 0, f)
+    }
 
-        if self.head.load(Ordering::SeqCst).is_null() {
-            self.head.store(node.as_mut_ptr(), Ordering::SeqCst);
+    #[cfg(any(test, feature = "fmt-tree"))]
+    fn fmt_subtree(
+        node: Option<&Node<D>>,
+        depth: usize,
+        f: &mut core::fmt::Formatter<'_>,
+    ) -> core::fmt::Result {
+        let Some(node) = node else {
             return Ok(());
+        };
+        Self::fmt_subtree(node.right(), depth + 1, f)?;
+        writeln!(f, "{:indent$}{:?}", "", node.data, indent = depth * 4)?;
+        Self::fmt_subtree(node.left(), depth + 1, f)
+    }
+
+    fn min_node(&self) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        while let Some(left) = current.left() {
+            current = left;
         }
+        Some(current)
+    }
 
-        let head = unsafe { &*self.head.load(Ordering::SeqCst) };
-        let mut current = head;
-        loop {
-            if node.data < current.data {
-                match current.left() {
-                    Some(left) => current = left,
-                    None => {
-                        current.set_left(node.as_mut_ptr());
-                        node.set_parent(current);
-                        return Ok(());
-                    }
-                }
-            } else if node.data > current.data {
-                match current.right() {
-                    Some(right) => current = right,
-                    None => {
-                        current.set_right(node.as_mut_ptr());
-                        node.set_parent(current);
-                        return Ok(());
-                    }
-                }
+    fn max_node(&self) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        while let Some(right) = current.right() {
+            current = right;
+        }
+        Some(current)
+    }
+
+    /// Inserts `data` into the tree, recording every node visited during the descent into `log`, followed by the
+    /// address of the new node. Returns the number of entries written.
+    ///
+    /// Nothing is modified if `log` is too small to hold the full path, in which case `Error::OutOfSpace` is
+    /// returned. The log can be handed to [Self::undo_insert] to reverse the insert.
+    pub fn insert_logged(&mut self, data: D, log: &mut [*const ()]) -> Result<usize> {
+        let mut count = 0;
+        let mut current = self.head();
+        let mut left = false;
+        while let Some(node) = current {
+            *log.get_mut(count).ok_or(Error::OutOfSpace)? = node.as_mut_ptr() as *const ();
+            count += 1;
+            left = self
+                .compare(data.ordering_key(), node.data.ordering_key())
+                .is_lt();
+            if left {
+                current = node.left();
+            } else if self
+                .compare(data.ordering_key(), node.data.ordering_key())
+                .is_gt()
+            {
+                current = node.right();
             } else {
-                panic!("Duplicate data found in the tree");
+                return Err(Error::AlreadyExists);
             }
         }
+        if count == log.len() {
+            return Err(Error::OutOfSpace);
+        }
+
+        let parent = count
+            .checked_sub(1)
+            .map(|i| unsafe { &*(log[i] as *const Node<D>) });
+        self.notify_if_full();
+        let base = self.storage.base();
+        let node: &Node<D> = self.storage.add(|index| Node::new(data, index, base))?;
+        match parent {
+            Some(parent) if left => parent.set_left(node.as_mut_ptr()),
+            Some(parent) => parent.set_right(node.as_mut_ptr()),
+            None => self.head.store(node.as_mut_ptr(), Ordering::Release),
+        }
+        if let Some(parent) = parent {
+            node.set_parent(parent);
+        }
+        log[count] = node.as_mut_ptr() as *const ();
+        Ok(count + 1)
     }
 
-    pub fn search(&self, key: &D::Key) -> Option<D> {
-        self.search_node(key).map(|node| node.data)
+    /// Reverses an insert recorded by [Self::insert_logged].
+    ///
+    /// # Safety
+    ///
+    /// `log` must be the log filled by the most recent call to [Self::insert_logged] on this tree, and the tree
+    /// must not have been modified since.
+    pub unsafe fn undo_insert(&mut self, log: &[*const ()]) {
+        let Some(node) = log.last() else {
+            return;
+        };
+        let node = *node as *mut Node<D>;
+        Self::replace_node(&self.head, node, null_mut());
+        self.storage.delete(Node::index_of(node));
+        self.full_handler_fired = false;
+    }
+
+    /// Returns an [Entry] for `key`, which finds or inserts the element stored under it in a single descent.
+    pub fn entry<'b>(&'b mut self, key: &'b D::Key) -> Entry<'b, 'a, D, SIZE, P, H> {
+        Entry { tree: self, key }
+    }
+
+    /// Returns a reference to the element stored under `key`, or inserts the element built by `f` if the key is
+    /// absent. `f` is only called on a miss, and the element it returns must have an ordering key equal to `key`.
+    /// Both cases take a single descent from the head. Returns `Error::OutOfSpace` if an element has to be inserted
+    /// but the tree is full.
+    pub fn get_or_insert_with<F: FnOnce() -> D>(&mut self, key: &D::Key, f: F) -> Result<&D> {
+        let node = match self.search_slot(key) {
+            Ok(node) => node,
+            Err((parent, left)) => {
+                let data = f();
+                debug_assert!(self.compare(key, data.ordering_key()).is_eq());
+                self.link_leaf(data, parent, left)?
+            }
+        };
+        Ok(unsafe { &(*node).data })
+    }
+
+    /// Inserts `data`, replacing the element stored under the same key if there is one. Returns the replaced element,
+    /// or None if `data` was newly inserted. Returns `Error::OutOfSpace` if `data` has to be inserted but the tree is
+    /// full.
+    pub fn insert_or_replace(&mut self, data: D) -> Result<Option<D>> {
+        match self.search_slot(data.ordering_key()) {
+            Ok(node) => Ok(Some(unsafe { ptr::replace(&mut (*node).data, data) })),
+            Err((parent, left)) => {
+                self.link_leaf(data, parent, left)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Descends from the head looking for `key`. Returns the node holding it, or the parent a new node for `key`
+    /// would be linked under (null for an empty tree) and whether it would be its left child.
+    fn search_slot(
+        &self,
+        key: &D::Key,
+    ) -> core::result::Result<*mut Node<D>, (*mut Node<D>, bool)> {
+        let mut parent = ptr::null_mut();
+        let mut left = false;
+        let mut current = self.head();
+        while let Some(node) = current {
+            let ordering = self.compare(key, node.data.ordering_key());
+            if ordering.is_eq() {
+                return Ok(node.as_mut_ptr());
+            }
+            left = ordering.is_lt();
+            parent = node.as_mut_ptr();
+            current = if left { node.left() } else { node.right() };
+        }
+        Err((parent, left))
+    }
+
+    /// Stores `data` in a new node linked as a child of `parent`, as returned by [Self::search_slot].
+    fn link_leaf(&mut self, data: D, parent: *mut Node<D>, left: bool) -> Result<*mut Node<D>> {
+        self.notify_if_full();
+        let base = self.storage.base();
+        let node: &Node<D> = self.storage.add(|index| Node::new(data, index, base))?;
+        match unsafe { parent.as_ref() } {
+            Some(parent) if left => parent.set_left(node.as_mut_ptr()),
+            Some(parent) => parent.set_right(node.as_mut_ptr()),
+            None => self.head.store(node.as_mut_ptr(), Ordering::Release),
+        }
+        node.set_parent(parent);
+        Ok(node.as_mut_ptr())
+    }
+
+    /// Returns true if an element with the given key is stored. Nothing is copied out of the tree.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_node(key).is_some()
+    }
+
+    /// Returns a reference to the element with the given key. Unlike `search`, this does not require `D: Copy`.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        self.search_node(key).map(|node| &node.data)
+    }
+
+    /// Like [Self::min], but returns a reference.
+    pub fn min_ref(&self) -> Option<&D> {
+        self.min_node().map(|node| &node.data)
+    }
+
+    /// Like [Self::max], but returns a reference.
+    pub fn max_ref(&self) -> Option<&D> {
+        self.max_node().map(|node| &node.data)
+    }
+
+    /// Like [Self::floor], but returns a reference.
+    pub fn floor_ref(&self, key: &D::Key) -> Option<&D> {
+        self.bound_node(key, false, false).map(|node| &node.data)
+    }
+
+    /// Like [Self::ceil], but returns a reference.
+    pub fn ceil_ref(&self, key: &D::Key) -> Option<&D> {
+        self.bound_node(key, true, false).map(|node| &node.data)
+    }
+
+    /// Like [Self::successor], but returns a reference.
+    pub fn successor_ref(&self, key: &D::Key) -> Option<&D> {
+        let node = self.search_node_or_last(key)?;
+        if self.compare(node.data.ordering_key(), key).is_gt() {
+            return Some(&node.data);
+        }
+        node.next_node().map(|node| &node.data)
+    }
+
+    /// Like [Self::predecessor], but returns a reference.
+    pub fn predecessor_ref(&self, key: &D::Key) -> Option<&D> {
+        let node = self.search_node_or_last(key)?;
+        if self.compare(node.data.ordering_key(), key).is_lt() {
+            return Some(&node.data);
+        }
+        node.prev_node().map(|node| &node.data)
+    }
+
+    /// Returns a mutable reference to the element with the given key, so fields other than its ordering key can be
+    /// updated in place. The ordering key must not be changed through the returned reference.
+    pub fn get_mut(&mut self, key: &D::Key) -> Option<&mut D> {
+        let node = self.search_node(key)?.as_mut_ptr();
+        Some(unsafe { &mut (*node).data })
     }
 
     fn search_node(&self, key: &D::Key) -> Option<&Node<D>> {
         let mut current = self.head();
         while let Some(node) = current {
-            if key < node.data.ordering_key() {
+            if self.compare(key, node.data.ordering_key()).is_lt() {
                 current = node.left();
-            } else if key > node.data.ordering_key() {
+            } else if self.compare(key, node.data.ordering_key()).is_gt() {
                 current = node.right();
             } else {
                 return Some(node);
@@ -157,6 +606,45 @@ where
         None
     }
 
+    /// Descends from the head towards `key`, returning the node holding it or the last node visited, which is the
+    /// in-order neighbour on one side of where `key` would be inserted.
+    fn search_node_or_last(&self, key: &D::Key) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        loop {
+            let next = match self.compare(key, current.data.ordering_key()) {
+                core::cmp::Ordering::Less => current.left(),
+                core::cmp::Ordering::Greater => current.right(),
+                core::cmp::Ordering::Equal => return Some(current),
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Some(current),
+            }
+        }
+    }
+
+    /// Descends once from the head, tracking the closest node above (`above`) or below `key`. A node whose key
+    /// equals `key` is returned immediately unless `strict` is set.
+    fn bound_node(&self, key: &D::Key, above: bool, strict: bool) -> Option<&Node<D>> {
+        let mut best = None;
+        let mut current = self.head();
+        while let Some(node) = current {
+            let ordering = self.compare(key, node.data.ordering_key());
+            if ordering.is_eq() && !strict {
+                return Some(node);
+            }
+            if (above && ordering.is_lt()) || (!above && ordering.is_gt()) {
+                best = Some(node);
+            }
+            current = if ordering.is_lt() || (ordering.is_eq() && !above) {
+                node.left()
+            } else {
+                node.right()
+            };
+        }
+        best
+    }
+
     fn replace_node(head: &AtomicPtr<Node<D>>, old: *mut Node<D>, new: *mut Node<D>) {
         if let Some(parent) = unsafe { &*old }.parent() {
             if parent.left_ptr() == old {
@@ -171,8 +659,8 @@ where
                 unsafe { &*new }.set_parent(parent);
             }
         // If the old node has no parent, it is the head of the tree
-        } else if !new.is_null() {
-            head.store(new, Ordering::SeqCst);
+        } else {
+            head.store(new, Ordering::Release);
             if !new.is_null() {
                 unsafe { &*new }.set_parent(null_mut());
             }
@@ -180,9 +668,46 @@ where
     }
 
     pub fn delete(&mut self, data: D) -> Result<()> {
-        let Some(to_delete) = self.search_node(data.ordering_key()) else {
-            return Err(Error::NotFound);
-        };
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the tree, returning it. Returns `None` if no element has the key.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        let node_ptr = self.search_node(key)?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes and returns the element with the smallest key, or `None` if the tree is empty. The node is taken from
+    /// the end of the leftmost spine, so no search by key is needed.
+    pub fn pop_min(&mut self) -> Option<D> {
+        let node_ptr = self.min_node()?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes and returns the element with the largest key, or `None` if the tree is empty.
+    pub fn pop_max(&mut self) -> Option<D> {
+        let node_ptr = self.max_node()?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes every element for which `f` returns false, visiting the elements in ascending key order. Removing a
+    /// node only relinks the nodes around it, so the walk continues from the successor found before the removal.
+    pub fn retain<F: FnMut(&D) -> bool>(&mut self, mut f: F) {
+        let mut current = self.min_node().map(Node::as_mut_ptr);
+        while let Some(node_ptr) = current {
+            let node = unsafe { &*node_ptr };
+            current = node.next_node().map(Node::as_mut_ptr);
+            if !f(&node.data) {
+                self.remove_node(node_ptr);
+            }
+        }
+    }
+
+    /// Unlinks the node at `node_ptr`, which must be live in this tree, and moves its data out.
+    fn remove_node(&mut self, node_ptr: *mut Node<D>) -> D {
+        let to_delete = unsafe { &*node_ptr };
 
         let left = to_delete.left();
         let right = to_delete.right();
@@ -228,114 +753,1921 @@ where
             left.set_parent(successor);
         }
 
-        self.storage.delete(to_delete.as_mut_ptr());
-        Ok(())
+        let data = self.storage.delete(to_delete.index).data;
+        self.full_handler_fired = false;
+        data
     }
 
-    #[allow(dead_code)]
-    fn dfs(&self, node: Option<&Node<D>>, values: &mut alloc::vec::Vec<D>) {
-        if let Some(node) = node {
-            self.dfs(node.left(), values);
-            values.push(node.data);
-            self.dfs(node.right(), values);
+    /// Inserts `data`, or bumps the count of the element with the same key if one is already present. Returns the
+    /// count of the key after the insert. The stored element is kept as is when only the count changes.
+    pub fn insert_dup(&mut self, data: D) -> Result<u32> {
+        if let Some(node) = self.search_node(data.ordering_key()) {
+            return Ok(node.count.fetch_add(1, Ordering::AcqRel) + 1);
         }
+        self.insert(data)?;
+        Ok(1)
     }
-}
 
-#[derive(Debug)]
-pub struct Node<D>
-where
-    D: PartialOrd,
-{
-    data: D,
-    parent: AtomicPtr<Node<D>>,
-    left: AtomicPtr<Node<D>>,
-    right: AtomicPtr<Node<D>>,
-}
+    /// Decrements the count of the element with the given key, unlinking and dropping it once the count reaches
+    /// zero. Returns the remaining count, or `None` if no element has the key.
+    pub fn remove_one(&mut self, key: &D::Key) -> Option<u32> {
+        let node = self.search_node(key)?;
+        let count = node.count.load(Ordering::Acquire);
+        if count > 1 {
+            node.count.store(count - 1, Ordering::Release);
+            return Some(count - 1);
+        }
+        self.remove(key);
+        Some(0)
+    }
 
-impl<D> Node<D>
-where
-    D: PartialOrd,
-{
-    fn new(data: D) -> Self {
-        Node {
-            data,
-            parent: AtomicPtr::default(),
-            left: AtomicPtr::default(),
-            right: AtomicPtr::default(),
+    /// Returns how many times the key is held by the tree, 0 if it is absent.
+    pub fn count(&self, key: &D::Key) -> u32 {
+        self.search_node(key)
+            .map_or(0, |node| node.count.load(Ordering::Acquire))
+    }
+
+    /// Writes the ordering key of every element, in order, into `out`, returning the number of keys written. Returns
+    /// `Error::OutOfSpace` if `out` cannot hold every key.
+    pub fn keys_into(&self, out: &mut [D::Key]) -> Result<usize>
+    where
+        D::Key: Copy,
+    {
+        if out.len() < self.storage.len() {
+            return Err(Error::OutOfSpace);
         }
+        let mut count = 0;
+        self.visit_in_order(|node| {
+            out[count] = *node.data.ordering_key();
+            count += 1;
+        });
+        Ok(count)
     }
 
-    fn right(&self) -> Option<&Node<D>> {
-        let node = self.right.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
+    /// Calls `f` on every node in the tree, in order. The walk follows parent pointers, so its stack use does not
+    /// grow with the depth of the tree.
+    fn visit_in_order<F: FnMut(&Node<D>)>(&self, mut f: F) {
+        let mut current = self.min_node();
+        while let Some(node) = current {
+            f(node);
+            current = node.next_node();
         }
-        Some(unsafe { &*node })
     }
 
-    fn right_ptr(&self) -> *mut Node<D> {
-        self.right.load(Ordering::SeqCst)
+    /// Returns true if any two nodes in the tree share the same ordering key, as judged by the tree's comparator. The
+    /// walk compares each key against the next one in order, following parent pointers rather than recursing.
+    pub fn has_duplicates(&self) -> bool {
+        let mut current = self.min_node();
+        while let Some(node) = current {
+            let next = node.next_node();
+            if next.is_some_and(|next| {
+                self.compare(node.data.ordering_key(), next.data.ordering_key())
+                    .is_eq()
+            }) {
+                return true;
+            }
+            current = next;
+        }
+        false
     }
+}
 
-    fn set_right<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.right.store(node.into(), Ordering::SeqCst);
+impl<'a, D, const SIZE: usize, P, H> Bst<'a, D, SIZE, P, H>
+where
+    D: PartialOrd + Copy + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+    H: FnMut(),
+{
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
     }
 
-    fn left(&self) -> Option<&Node<D>> {
-        let node = self.left.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
+    /// Returns the element stored under the key of `data`, or inserts `data` and returns it if the key is absent.
+    /// Both cases take a single descent from the head. Returns `Error::OutOfSpace` if `data` has to be inserted but
+    /// the tree is full.
+    pub fn get_or_insert(&mut self, data: D) -> Result<D> {
+        self.get_or_insert_with(data.ordering_key(), || data)
+            .copied()
+    }
+
+    /// Returns the element that a new element with `key` would be linked under, without inserting it. Returns None if
+    /// the tree is empty or already contains `key`.
+    pub fn would_insert_under(&self, key: &D::Key) -> Option<D> {
+        let mut current = self.head()?;
+        loop {
+            let next = if self.compare(key, current.data.ordering_key()).is_lt() {
+                current.left()
+            } else if self.compare(key, current.data.ordering_key()).is_gt() {
+                current.right()
+            } else {
+                return None;
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Some(current.data),
+            }
         }
-        Some(unsafe { &*node })
     }
 
-    fn left_ptr(&self) -> *mut Node<D> {
-        self.left.load(Ordering::SeqCst)
+    /// Returns the element with the smallest key, or `None` if the tree is empty.
+    pub fn min(&self) -> Option<D> {
+        self.min_ref().copied()
     }
 
-    fn set_left<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.left.store(node.into(), Ordering::SeqCst);
+    /// Returns the element with the largest key, or `None` if the tree is empty.
+    pub fn max(&self) -> Option<D> {
+        self.max_ref().copied()
     }
 
-    fn parent(&self) -> Option<&Node<D>> {
-        let node = self.parent.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
-        }
-        Some(unsafe { &*node })
+    /// Returns the element with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &D::Key) -> Option<D> {
+        self.floor_ref(key).copied()
     }
 
-    #[allow(dead_code)]
-    fn parent_ptr(&self) -> *mut Node<D> {
-        self.parent.load(Ordering::SeqCst)
+    /// Returns the element with the smallest key greater than or equal to `key`.
+    pub fn ceil(&self, key: &D::Key) -> Option<D> {
+        self.ceil_ref(key).copied()
     }
 
-    fn set_parent<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.parent.store(node.into(), Ordering::SeqCst);
+    /// Returns the element with the smallest key strictly greater than `key`, which need not be in the tree. Walks
+    /// from the node at (or next to) `key` through parent pointers rather than rescanning from the head.
+    pub fn successor(&self, key: &D::Key) -> Option<D> {
+        self.successor_ref(key).copied()
     }
 
-    pub fn as_mut_ptr(&self) -> *mut Node<D> {
-        self as *const _ as *mut _
+    /// Returns the element with the largest key strictly less than `key`, which need not be in the tree. Walks from
+    /// the node at (or next to) `key` through parent pointers rather than rescanning from the head.
+    pub fn predecessor(&self, key: &D::Key) -> Option<D> {
+        self.predecessor_ref(key).copied()
     }
-}
 
-impl<D> From<&Node<D>> for *mut Node<D>
-where
-    D: PartialOrd,
-{
-    fn from(node: &Node<D>) -> *mut Node<D> {
-        node.as_mut_ptr()
+    /// Returns the element with the smallest key strictly greater than `key`.
+    pub fn min_key_greater(&self, key: &D::Key) -> Option<D> {
+        self.bound_node(key, true, true).map(|node| node.data)
     }
-}
 
-#[cfg(test)]
-mod tests {}
+    /// Returns the element with the largest key strictly less than `key`.
+    pub fn max_key_less(&self, key: &D::Key) -> Option<D> {
+        self.bound_node(key, false, true).map(|node| node.data)
+    }
+
+    /// Inserts `data` into the tree. If the tree is already full, the largest (`evict_max`) or smallest element among
+    /// the stored elements and `data` is evicted instead and returned. Returns `Error::AlreadyExists`, with nothing
+    /// evicted, if the key of `data` is already stored.
+    pub fn push_bounded(&mut self, data: D, evict_max: bool) -> Result<Option<D>> {
+        if self.search_ref(data.ordering_key()).is_some() {
+            return Err(Error::AlreadyExists);
+        }
+        if self.storage.len() < self.capacity() {
+            self.insert(data)?;
+            return Ok(None);
+        }
+
+        let extreme = if evict_max {
+            self.max_node()
+        } else {
+            self.min_node()
+        };
+        let Some(extreme) = extreme.map(|node| node.data) else {
+            return Err(Error::OutOfSpace);
+        };
+        let ordering = self.compare(data.ordering_key(), extreme.ordering_key());
+        if (evict_max && ordering.is_gt()) || (!evict_max && ordering.is_lt()) {
+            return Ok(Some(data));
+        }
+
+        self.delete(extreme)?;
+        self.insert(data)?;
+        Ok(Some(extreme))
+    }
+
+    /// Finds two distinct elements whose keys sum to `target`, sweeping inwards from both ends of the tree. The keys
+    /// are summed as numbers, so the tree must order them either ascending or descending. A sum that does not fit in
+    /// the key type is never `target`, and steers the sweep like any sum on the same side of it.
+    pub fn find_pair_summing_to(&self, target: D::Key) -> Option<(D, D)>
+    where
+        D::Key: Copy + CheckedAdd,
+    {
+        let mut low = self.min_node()?;
+        let mut high = self.max_node()?;
+        while low.as_mut_ptr() != high.as_mut_ptr() {
+            let ordering = match low
+                .data
+                .ordering_key()
+                .checked_sum(*high.data.ordering_key())
+            {
+                Ok(sum) => self.compare(&sum, &target),
+                // The overflow is known in numeric order, which a descending tree sweeps the other way.
+                Err(ordering) => {
+                    let (low_key, high_key) = (low.data.ordering_key(), high.data.ordering_key());
+                    match self.compare(low_key, high_key) == low_key.cmp(high_key) {
+                        true => ordering,
+                        false => ordering.reverse(),
+                    }
+                }
+            };
+            if ordering.is_eq() {
+                return Some((low.data, high.data));
+            } else if ordering.is_lt() {
+                low = low.next_node()?;
+            } else {
+                high = high.prev_node()?;
+            }
+        }
+        None
+    }
+
+    /// Draws up to `k` keys uniformly at random from the tree into `out` with a single in-order pass, returning the
+    /// number of keys sampled. `rng` must return uniformly distributed random numbers.
+    pub fn sample_into<R: FnMut() -> u64>(&self, k: usize, mut rng: R, out: &mut [D]) -> usize {
+        let k = k.min(out.len());
+        let mut seen = 0;
+        self.visit_in_order(|node| {
+            if seen < k {
+                out[seen] = node.data;
+            } else {
+                let index = (rng() % (seen as u64 + 1)) as usize;
+                if index < k {
+                    out[index] = node.data;
+                }
+            }
+            seen += 1;
+        });
+        seen.min(k)
+    }
+
+    #[allow(dead_code)]
+    fn dfs(&self, node: Option<&Node<D>>, values: &mut alloc::vec::Vec<D>) {
+        if let Some(node) = node {
+            self.dfs(node.left(), values);
+            values.push(node.data);
+            self.dfs(node.right(), values);
+        }
+    }
+}
+
+/// An iterator over the elements of a [Bst] in ascending key order, created by [Bst::iter].
+pub struct Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    next: Option<&'a Node<D>>,
+    /// The next node to yield from the back. Both ends are cleared once they meet, so no node is yielded twice.
+    back: Option<&'a Node<D>>,
+}
+
+impl<'a, D> Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    /// Clears both ends if `node` is the last one between them.
+    fn close_if_last(&mut self, node: &'a Node<D>) -> bool {
+        let last = self.next.is_some_and(|next| ptr::eq(next, node))
+            && self.back.is_some_and(|back| ptr::eq(back, node));
+        if last {
+            self.next = None;
+            self.back = None;
+        }
+        last
+    }
+}
+
+impl<'a, D> Iterator for Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<&'a D> {
+        let node = self.next?;
+        if !self.close_if_last(node) {
+            self.next = node.next_node();
+        }
+        Some(&node.data)
+    }
+}
+
+impl<'a, D> DoubleEndedIterator for Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    fn next_back(&mut self) -> Option<&'a D> {
+        let node = self.back?;
+        if !self.close_if_last(node) {
+            self.back = node.prev_node();
+        }
+        Some(&node.data)
+    }
+}
+
+impl<'b, 'a, D, const SIZE: usize, P, H> IntoIterator for &'b Bst<'a, D, SIZE, P, H>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+    H: FnMut(),
+{
+    type Item = &'b D;
+    type IntoIter = Iter<'b, D>;
+
+    fn into_iter(self) -> Iter<'b, D> {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a [Bst] within a range of keys, created by [Bst::range].
+pub struct Range<'a, D>
+where
+    D: PartialOrd,
+{
+    inner: Iter<'a, D>,
+}
+
+impl<'a, D> Iterator for Range<'a, D>
+where
+    D: PartialOrd,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<&'a D> {
+        self.inner.next()
+    }
+}
+
+impl<'a, D> DoubleEndedIterator for Range<'a, D>
+where
+    D: PartialOrd,
+{
+    fn next_back(&mut self) -> Option<&'a D> {
+        self.inner.next_back()
+    }
+}
+
+/// A view into the slot for a single key of a [Bst], created by [Bst::entry].
+pub struct Entry<'b, 'a, D, const SIZE: usize, P = Storage<'a, D, SIZE>, H = fn()>
+where
+    D: PartialOrd + OrderedKey,
+{
+    tree: &'b mut Bst<'a, D, SIZE, P, H>,
+    key: &'b D::Key,
+}
+
+impl<'b, 'a, D, const SIZE: usize, P, H> Entry<'b, 'a, D, SIZE, P, H>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+    H: FnMut(),
+{
+    /// Returns the element stored under the key, or inserts the element built by `f` if there is none. See
+    /// [Bst::get_or_insert_with].
+    pub fn or_insert_with<F: FnOnce() -> D>(self, f: F) -> Result<&'b D> {
+        self.tree.get_or_insert_with(self.key, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct Node<D>
+where
+    D: PartialOrd,
+{
+    data: D,
+    /// The slot this node is stored in. Links are slot indices, so the storage stays valid wherever its bytes are
+    /// moved once `base` is pointed at the new slot 0. Free slots hold [NULL] instead.
+    index: u16,
+    /// Slot 0 of the storage. Neighbours are reached from it rather than from the address of this node, which only
+    /// grants access to this node.
+    base: AtomicPtr<Node<D>>,
+    /// How many times the key has been inserted through `insert_dup`. Plain `insert` leaves it at 1.
+    count: AtomicU32,
+    parent: AtomicU16,
+    left: AtomicU16,
+    right: AtomicU16,
+}
+
+// Links are loaded with `Acquire` and stored with `Release`. Every mutation goes through `&mut self` on the tree, so
+// there is a single writer, and a reader that observes a link also observes the node written before it was linked.
+// Nothing relies on a single total order across different links, which is all `SeqCst` would add.
+impl<D> Node<D>
+where
+    D: PartialOrd,
+{
+    fn new(data: D, index: u16, base: *mut Node<D>) -> Self {
+        Node {
+            data,
+            index,
+            base: AtomicPtr::new(base),
+            count: AtomicU32::new(1),
+            parent: AtomicU16::new(NULL),
+            left: AtomicU16::new(NULL),
+            right: AtomicU16::new(NULL),
+        }
+    }
+
+    fn right(&self) -> Option<&Node<D>> {
+        unsafe { self.right_ptr().as_ref() }
+    }
+
+    fn right_ptr(&self) -> *mut Node<D> {
+        self.slot(self.right.load(Ordering::Acquire))
+    }
+
+    fn set_right<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.right
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    fn left(&self) -> Option<&Node<D>> {
+        unsafe { self.left_ptr().as_ref() }
+    }
+
+    fn left_ptr(&self) -> *mut Node<D> {
+        self.slot(self.left.load(Ordering::Acquire))
+    }
+
+    fn set_left<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.left
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    fn parent(&self) -> Option<&Node<D>> {
+        unsafe { self.parent_ptr().as_ref() }
+    }
+
+    #[allow(dead_code)]
+    fn parent_ptr(&self) -> *mut Node<D> {
+        self.slot(self.parent.load(Ordering::Acquire))
+    }
+
+    fn set_parent<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.parent
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    /// Returns the in-order successor of this node by following parent pointers.
+    fn next_node(&self) -> Option<&Node<D>> {
+        if let Some(mut current) = self.right() {
+            while let Some(left) = current.left() {
+                current = left;
+            }
+            return Some(current);
+        }
+        let mut child = self;
+        while let Some(parent) = child.parent() {
+            if parent.left_ptr() == child.as_mut_ptr() {
+                return Some(parent);
+            }
+            child = parent;
+        }
+        None
+    }
+
+    /// Returns the in-order predecessor of this node by following parent pointers.
+    fn prev_node(&self) -> Option<&Node<D>> {
+        if let Some(mut current) = self.left() {
+            while let Some(right) = current.right() {
+                current = right;
+            }
+            return Some(current);
+        }
+        let mut child = self;
+        while let Some(parent) = child.parent() {
+            if parent.right_ptr() == child.as_mut_ptr() {
+                return Some(parent);
+            }
+            child = parent;
+        }
+        None
+    }
+
+    /// Returns the node stored in slot `index` of the same storage, or null for [NULL].
+    fn slot(&self, index: u16) -> *mut Node<D> {
+        if index == NULL {
+            return ptr::null_mut();
+        }
+        unsafe { self.base.load(Ordering::Acquire).add(index as usize) }
+    }
+
+    fn index_of(node: *mut Node<D>) -> u16 {
+        unsafe { node.as_ref() }.map_or(NULL, |node| node.index)
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut Node<D> {
+        // Taken from `base` rather than `self`, so that the tree may write through it to this node later on.
+        self.slot(self.index)
+    }
+}
+
+impl<D> Slot for Node<D>
+where
+    D: PartialOrd,
+{
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { (*ptr::addr_of!((*slot).parent)).load(Ordering::Acquire) }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).parent).write(AtomicU16::new(index)) };
+    }
+}
+
+impl<D> From<&Node<D>> for *mut Node<D>
+where
+    D: PartialOrd,
+{
+    fn from(node: &Node<D>) -> *mut Node<D> {
+        node.as_mut_ptr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, AlignedStorage, Bst, BstDyn, Node, OrderedKey};
+    use crate::{AlignedBuffer, Error};
+    use arrayvec::ArrayVec;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::rc::Rc;
+
+    const BST_MAX_SIZE: usize = 16;
+
+    #[test]
+    #[should_panic(expected = "too small")]
+    fn test_new_undersized_buffer() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>() - 1]);
+        Bst::<u32, 4>::new(&mut mem);
+    }
+
+    #[test]
+    #[should_panic(expected = "not aligned")]
+    fn test_new_misaligned_buffer() {
+        #[repr(C, align(16))]
+        struct Aligned([u8; 4 * node_size::<u32>() + 1]);
+
+        let mut mem = Aligned([0; 4 * node_size::<u32>() + 1]);
+        Bst::<u32, 4>::new(&mut mem.0[1..]);
+    }
+
+    #[test]
+    fn test_len_and_capacity() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>()]);
+        let mut tree = Bst::<u32, 4>::new(&mut mem);
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.capacity(), 4);
+        assert_eq!(tree.remaining(), 4);
+
+        for num in [3, 1, 2, 4] {
+            tree.insert(num).unwrap();
+        }
+        assert!(!tree.is_empty());
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.remaining(), 0);
+        assert!(matches!(tree.insert(5), Err(Error::OutOfSpace)));
+
+        tree.delete(2).unwrap();
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.capacity(), 4);
+        assert_eq!(tree.remaining(), 1);
+    }
+
+    #[test]
+    fn test_clear() {
+        const SIZE: usize = 8;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        for num in [5, 3, 8, 1, 4] {
+            tree.insert(num).unwrap();
+        }
+        let used_slots = |tree: &Bst<u32, SIZE>| -> [bool; SIZE] {
+            core::array::from_fn(|i| tree.storage.is_occupied(i))
+        };
+        let first_slots = used_slots(&tree);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.search(&5), None);
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(used_slots(&tree), [false; SIZE]);
+
+        for num in [2, 9, 7, 6, 0] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(used_slots(&tree), first_slots);
+        assert!(tree.iter().copied().eq([0, 2, 6, 7, 9]));
+
+        // Every slot is available again.
+        for num in 10..13 {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(13), Err(Error::OutOfSpace)));
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut tree = Bst::<i32, BST_MAX_SIZE>::new(&mut mem);
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.max(), None);
+
+        for num in [50, 30, 70, 20, 40, 60, 80] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.min(), Some(20));
+        assert_eq!(tree.max(), Some(80));
+
+        tree.delete(20).unwrap();
+        tree.delete(80).unwrap();
+        assert_eq!(tree.min(), Some(30));
+        assert_eq!(tree.max(), Some(70));
+
+        for num in [30, 40, 60, 70] {
+            tree.delete(num).unwrap();
+        }
+        assert_eq!(tree.min(), Some(50));
+        assert_eq!(tree.max(), Some(50));
+
+        tree.delete(50).unwrap();
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.max(), None);
+    }
+
+    #[test]
+    fn test_range() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut input = ArrayVec::<u32, SIZE>::new();
+        while !input.is_full() {
+            let num = rng.gen_range(0..200);
+            if tree.insert(num).is_ok() {
+                input.push(num);
+            }
+        }
+        input.sort();
+
+        for (lo, hi) in [(0, 200), (50, 120), (37, 37), (120, 50), (201, 300), (0, 0)] {
+            let expected = input.iter().filter(|num| (lo..=hi).contains(*num));
+            assert!(
+                tree.range(&lo, &hi).eq(expected.clone()),
+                "range {}..={}",
+                lo,
+                hi
+            );
+            assert!(
+                tree.range(&lo, &hi).rev().eq(expected.rev()),
+                "range {}..={} reversed",
+                lo,
+                hi
+            );
+        }
+        for num in input.iter() {
+            assert!(tree.range(num, num).eq([num]));
+        }
+    }
+
+    #[test]
+    fn test_floor_ceil() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.ceil(&5), None);
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut input = ArrayVec::<u32, SIZE>::new();
+        while !input.is_full() {
+            let num = rng.gen_range(10..1_000);
+            if tree.insert(num).is_ok() {
+                input.push(num);
+            }
+        }
+
+        for key in 0..1_010 {
+            let floor = input.iter().filter(|num| **num <= key).max().copied();
+            let ceil = input.iter().filter(|num| **num >= key).min().copied();
+            assert_eq!(tree.floor(&key), floor, "floor of {}", key);
+            assert_eq!(tree.ceil(&key), ceil, "ceil of {}", key);
+        }
+        let (min, max) = (tree.min().unwrap(), tree.max().unwrap());
+        assert_eq!(tree.floor(&(min - 1)), None);
+        assert_eq!(tree.ceil(&(max + 1)), None);
+        assert_eq!((tree.floor(&max), tree.ceil(&max)), (Some(max), Some(max)));
+    }
+
+    #[test]
+    fn test_successor_predecessor() {
+        const SIZE: usize = 8;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        assert_eq!(tree.successor(&5), None);
+        assert_eq!(tree.predecessor(&5), None);
+        for num in [40, 20, 60, 10, 30, 50, 70] {
+            tree.insert(num).unwrap();
+        }
+
+        // Leaf, internal, root, min and max starting nodes.
+        assert_eq!(
+            (tree.predecessor(&30), tree.successor(&30)),
+            (Some(20), Some(40))
+        );
+        assert_eq!(
+            (tree.predecessor(&20), tree.successor(&20)),
+            (Some(10), Some(30))
+        );
+        assert_eq!(
+            (tree.predecessor(&40), tree.successor(&40)),
+            (Some(30), Some(50))
+        );
+        assert_eq!(
+            (tree.predecessor(&10), tree.successor(&10)),
+            (None, Some(20))
+        );
+        assert_eq!(
+            (tree.predecessor(&70), tree.successor(&70)),
+            (Some(60), None)
+        );
+
+        // Keys that are not in the tree.
+        assert_eq!(
+            (tree.predecessor(&35), tree.successor(&35)),
+            (Some(30), Some(40))
+        );
+        assert_eq!(
+            (tree.predecessor(&45), tree.successor(&45)),
+            (Some(40), Some(50))
+        );
+        assert_eq!((tree.predecessor(&0), tree.successor(&0)), (None, Some(10)));
+        assert_eq!(
+            (tree.predecessor(&99), tree.successor(&99)),
+            (Some(70), None)
+        );
+
+        let mut key = 0;
+        for expected in [10, 20, 30, 40, 50, 60, 70] {
+            key = tree.successor(&key).unwrap();
+            assert_eq!(key, expected);
+        }
+    }
+
+    #[test]
+    fn test_new_by_reverse_order() {
+        const SIZE: usize = 16;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new_by(&mut mem, |a, b| b.cmp(a));
+        for num in [5, 3, 8, 1, 9, 7, 2] {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(8), Err(Error::AlreadyExists)));
+        assert!(tree.iter().eq([9, 8, 7, 5, 3, 2, 1].iter()));
+        assert!(tree.range(&8, &3).eq([8, 7, 5, 3].iter()));
+        assert_eq!((tree.min(), tree.max()), (Some(9), Some(1)));
+        assert_eq!(tree.search(&7), Some(7));
+        assert_eq!(tree.search(&6), None);
+        assert_eq!(tree.successor(&6), Some(5));
+
+        tree.delete(8).unwrap();
+        assert!(tree.iter().eq([9, 7, 5, 3, 2, 1].iter()));
+    }
+
+    #[test]
+    fn test_non_copy_payload() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Named {
+            key: u32,
+            name: std::string::String,
+        }
+
+        impl OrderedKey for Named {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let named = |key: u32| Named {
+            key,
+            name: std::format!("node {}", key),
+        };
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<Named>()]);
+        let mut tree = Bst::<Named, BST_MAX_SIZE>::new(&mut mem);
+        for key in [4, 2, 6, 1, 3, 5, 7] {
+            tree.insert(named(key)).unwrap();
+        }
+        assert_eq!(tree.search_ref(&3), Some(&named(3)));
+        assert_eq!(tree.search_ref(&8), None);
+        assert!(tree.contains(&3));
+        assert!(!tree.contains(&8));
+
+        tree.delete(named(4)).unwrap();
+        assert_eq!(tree.search_ref(&4), None);
+        assert!(tree
+            .iter()
+            .map(|n| n.name.as_str())
+            .eq(["node 1", "node 2", "node 3", "node 5", "node 6", "node 7"]));
+    }
+
+    #[test]
+    fn test_remove() {
+        #[derive(Debug)]
+        struct Entry {
+            key: u32,
+            token: Rc<()>,
+        }
+
+        impl PartialEq for Entry {
+            fn eq(&self, other: &Self) -> bool {
+                self.key == other.key
+            }
+        }
+
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                self.key.partial_cmp(&other.key)
+            }
+        }
+
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let token = Rc::new(());
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<Entry>()]);
+        let mut tree = Bst::<Entry, BST_MAX_SIZE>::new(&mut mem);
+        for key in [4, 2, 6, 1, 3, 5, 7] {
+            let token = token.clone();
+            tree.insert(Entry { key, token }).unwrap();
+        }
+        assert_eq!(Rc::strong_count(&token), 8);
+
+        let removed = tree.remove(&4).unwrap();
+        assert_eq!(removed.key, 4);
+        assert!(Rc::ptr_eq(&removed.token, &token));
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.storage.occupied().count(), 6);
+        assert!(tree.remove(&4).is_none());
+        assert!(tree.iter().map(|e| e.key).eq([1, 2, 3, 5, 6, 7]));
+
+        // The removed value owns its data, and clearing the tree drops what is left.
+        drop(removed);
+        assert_eq!(Rc::strong_count(&token), 7);
+        tree.clear();
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn test_insert_dup_remove_one() {
+        const SIZE: usize = 4;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        tree.insert(1).unwrap();
+        tree.insert(9).unwrap();
+
+        assert_eq!(tree.insert_dup(5).unwrap(), 1);
+        assert_eq!(tree.insert_dup(5).unwrap(), 2);
+        assert_eq!(tree.insert_dup(5).unwrap(), 3);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.count(&5), 3);
+
+        assert_eq!(tree.remove_one(&5), Some(2));
+        assert_eq!(tree.remove_one(&5), Some(1));
+        assert_eq!(tree.search_ref(&5), Some(&5));
+        assert_eq!(tree.count(&5), 1);
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.remove_one(&5), Some(0));
+        assert_eq!(tree.search_ref(&5), None);
+        assert_eq!(tree.count(&5), 0);
+        assert_eq!(tree.remove_one(&5), None);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.storage.occupied().count(), 2);
+        assert_eq!(tree.remaining(), 2);
+    }
+
+    #[test]
+    fn test_packed_storage() {
+        const SIZE: usize = 4;
+        assert_eq!(node_size::<u64>(), core::mem::size_of::<Node<u64>>());
+        let mut mem = [0xAA; SIZE * node_size::<u64>() + 8];
+        let offset = mem
+            .as_ptr()
+            .align_offset(core::mem::align_of::<Node<u64>>());
+        let mut tree = Bst::<u64, SIZE>::new(&mut mem[offset..offset + SIZE * node_size::<u64>()]);
+        assert!((0..SIZE).all(|i| !tree.storage.is_occupied(i)));
+
+        for num in [20, 10, 30] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.storage.occupied().count(), 3);
+        assert_eq!(tree.storage.free_slots().count(), 1);
+        tree.delete(20).unwrap();
+        assert_eq!(tree.storage.occupied().count(), 2);
+        assert!(tree.storage.occupied().all(|node| node.data != 20));
+
+        // The freed slot is reused, and every slot can be filled.
+        for num in [40, 50] {
+            tree.insert(num).unwrap();
+        }
+        assert!((0..SIZE).all(|i| tree.storage.is_occupied(i)));
+        assert!(tree.iter().eq([10, 30, 40, 50].iter()));
+    }
+
+    #[test]
+    fn test_retain() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        for num in (0..SIZE as u32).map(|i| (i * 37) % SIZE as u32) {
+            tree.insert(num).unwrap();
+        }
+
+        let mut visited = std::vec::Vec::new();
+        tree.retain(|&x| {
+            visited.push(x);
+            x % 2 == 0
+        });
+        assert!(visited
+            .iter()
+            .eq((0..SIZE as u32).collect::<std::vec::Vec<_>>().iter()));
+        assert!(tree.iter().copied().eq((0..SIZE as u32).step_by(2)));
+        assert_eq!(tree.len(), SIZE / 2);
+        assert_eq!(tree.storage.occupied().count(), SIZE / 2);
+
+        tree.retain(|_| false);
+        assert!(tree.is_empty());
+        assert_eq!(tree.storage.free_slots().count(), SIZE);
+    }
+
+    #[test]
+    fn test_fmt_tree() {
+        struct Tree<'b, 'a>(&'b Bst<'a, u32, 8>);
+        impl core::fmt::Display for Tree<'_, '_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                self.0.fmt_tree(f)
+            }
+        }
+
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u32>()]);
+        let mut tree = Bst::<u32, 8>::new(&mut mem);
+        assert_eq!(std::format!("{}", Tree(&tree)), "");
+        for num in [4, 2, 6, 1, 3, 5, 7] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(
+            std::format!("{}", Tree(&tree)),
+            "        7\n    6\n        5\n4\n        3\n    2\n        1\n"
+        );
+    }
+
+    #[test]
+    fn test_pop_min_max() {
+        const SIZE: usize = 32;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut mem);
+        assert_eq!((tree.pop_min(), tree.pop_max()), (None, None));
+        for num in (0..SIZE as u32).map(|i| (i * 13) % SIZE as u32) {
+            tree.insert(num).unwrap();
+        }
+
+        for expected in 0..SIZE as u32 / 2 {
+            assert_eq!(tree.pop_min(), Some(expected));
+            assert_eq!(tree.pop_max(), Some(SIZE as u32 - 1 - expected));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.storage.free_slots().count(), SIZE);
+        assert_eq!((tree.pop_min(), tree.pop_max()), (None, None));
+    }
+
+    #[test]
+    fn test_new_at() {
+        const SIZE: usize = 8;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>()]);
+        static mut REGION: Aligned = Aligned([0; SIZE * node_size::<u32>()]);
+
+        let addr = unsafe { core::ptr::addr_of_mut!(REGION.0) } as *mut u8;
+        let len = SIZE * node_size::<u32>();
+        assert!(matches!(
+            unsafe { Bst::<u32, SIZE>::new_at(addr, len - 1) },
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            unsafe { Bst::<u32, SIZE>::new_at(addr.add(1), len - 1) },
+            Err(Error::BadBuffer)
+        ));
+        let mut tree = unsafe { Bst::<u32, SIZE>::new_at(addr, len).unwrap() };
+        for num in [5, 3, 8, 1] {
+            tree.insert(num).unwrap();
+        }
+        assert!(tree.iter().copied().eq([1, 3, 5, 8]));
+
+        // Every node lives inside the region.
+        let region = addr as usize..addr as usize + SIZE * node_size::<u32>();
+        let mut node = tree.head();
+        while let Some(current) = node {
+            assert!(region.contains(&(current.as_mut_ptr() as usize)));
+            node = current.left();
+        }
+    }
+
+    #[test]
+    fn test_restore_relocated() {
+        const SIZE: usize = 32;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>()]);
+
+        let mut first = Aligned([0; SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new(&mut first.0);
+        for num in 0..20 {
+            tree.insert((num * 7) % 20).unwrap();
+        }
+        tree.delete(5).unwrap();
+        // The restored tree takes over the elements, so the original must not drop them.
+        core::mem::forget(tree);
+
+        // Move the bytes to a different address and wipe the original.
+        let mut second = Aligned([0; SIZE * node_size::<u32>()]);
+        second.0.copy_from_slice(&first.0);
+        first.0.fill(0);
+
+        let mut tree = unsafe { Bst::<u32, SIZE>::restore(&mut second.0) };
+        assert_eq!(tree.len(), 19);
+        for num in 0..20 {
+            assert_eq!(tree.search(&num), (num != 5).then_some(num));
+        }
+        assert!(tree.iter().copied().eq((0..20).filter(|num| *num != 5)));
+
+        // The free slots were recovered as well.
+        for num in 20..33 {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(33), Err(Error::OutOfSpace)));
+    }
+
+    #[test]
+    fn test_new_uninit() {
+        const SIZE: usize = 8;
+        #[repr(C, align(16))]
+        struct Aligned([core::mem::MaybeUninit<u8>; SIZE * node_size::<u32>()]);
+
+        let mut mem = Aligned([core::mem::MaybeUninit::uninit(); SIZE * node_size::<u32>()]);
+        let mut tree = Bst::<u32, SIZE>::new_uninit(&mut mem.0);
+        assert!(tree.is_empty());
+        for num in [4, 2, 6, 1] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.remove(&2), Some(2));
+        assert!(tree.iter().eq([1, 4, 6].iter()));
+    }
+
+    #[test]
+    fn test_dyn_capacity() {
+        #[repr(C, align(16))]
+        struct Aligned([u8; 5 * node_size::<u32>() + 3]);
+
+        // The trailing bytes are too few for another node.
+        let mut mem = Aligned([0; 5 * node_size::<u32>() + 3]);
+        let mut tree = BstDyn::<u32>::new(&mut mem.0);
+        assert_eq!(tree.capacity(), 5);
+        for num in [3, 1, 4, 5, 2] {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(6), Err(Error::OutOfSpace)));
+        tree.delete(4).unwrap();
+        tree.insert(6).unwrap();
+        assert!(tree.iter().eq([1, 2, 3, 5, 6].iter()));
+        tree.clear();
+        assert_eq!(tree.remaining(), 5);
+    }
+
+    #[test]
+    fn test_new_in() {
+        static mut STORAGE: AlignedStorage<u64, 16> = AlignedStorage::new();
+
+        let mut tree = Bst::<u64, 16>::new_in(unsafe { &mut *core::ptr::addr_of_mut!(STORAGE) });
+        for num in (0..16).rev() {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(16), Err(Error::OutOfSpace)));
+        assert!(tree.iter().copied().eq(0..16));
+
+        let mut storage = AlignedStorage::<u8, 2>::default();
+        let mut tree = Bst::<u8, 2>::new_in(&mut storage);
+        tree.insert(7).unwrap();
+        assert_eq!(tree.search(&7), Some(7));
+    }
+
+    #[test]
+    fn test_try_new_bad_buffer() {
+        const SIZE: usize = 4;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>() + 1]);
+
+        let mut mem = Aligned([0; SIZE * node_size::<u32>() + 1]);
+        assert!(matches!(
+            Bst::<u32, SIZE>::try_new(&mut mem.0[..SIZE * node_size::<u32>() - 1]),
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            Bst::<u32, SIZE>::try_new(&mut mem.0[1..]),
+            Err(Error::BadBuffer)
+        ));
+        let mut tree = Bst::<u32, SIZE>::try_new(&mut mem.0).unwrap();
+        tree.insert(1).unwrap();
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_get_or_insert() {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Block {
+            start: u32,
+            pages: u32,
+        }
+
+        impl OrderedKey for Block {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.start
+            }
+        }
+
+        let block = |start, pages| Block { start, pages };
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Block>()]);
+        let mut tree = Bst::<Block, 4>::new(&mut mem);
+
+        // Misses insert the new block.
+        for start in [20, 10, 30] {
+            assert_eq!(
+                tree.get_or_insert(block(start, 1)).unwrap(),
+                block(start, 1)
+            );
+        }
+        assert_eq!(tree.len(), 3);
+
+        // A hit returns the stored block and adds nothing.
+        assert_eq!(tree.get_or_insert(block(10, 5)).unwrap(), block(10, 1));
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.get_or_insert(block(40, 1)).unwrap(), block(40, 1));
+        assert_eq!(tree.len(), 4);
+        assert!(matches!(
+            tree.get_or_insert(block(50, 1)),
+            Err(Error::OutOfSpace)
+        ));
+        assert_eq!(tree.get_or_insert(block(30, 2)).unwrap(), block(30, 1));
+        assert!(tree.iter().map(|b| b.start).eq([10, 20, 30, 40]));
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut mem = AlignedBuffer([0; 3 * node_size::<u32>()]);
+        let mut tree = Bst::<u32, 3>::new(&mut mem);
+        let mut calls = 0;
+
+        for key in [2, 1, 3] {
+            let value = tree.get_or_insert_with(&key, || {
+                calls += 1;
+                key
+            });
+            assert_eq!(*value.unwrap(), key);
+        }
+        assert_eq!(calls, 3);
+
+        // A hit never builds a new element, even when the tree is full.
+        assert_eq!(*tree.get_or_insert_with(&1, || unreachable!()).unwrap(), 1);
+        assert_eq!(
+            *tree.entry(&3).or_insert_with(|| unreachable!()).unwrap(),
+            3
+        );
+        assert!(matches!(
+            tree.entry(&4).or_insert_with(|| 4),
+            Err(Error::OutOfSpace)
+        ));
+        assert!(tree.iter().eq([1, 2, 3].iter()));
+    }
+
+    #[test]
+    fn test_insert_or_replace() {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Block {
+            start: u32,
+            pages: u32,
+        }
+
+        impl OrderedKey for Block {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.start
+            }
+        }
+
+        let block = |start, pages| Block { start, pages };
+        let mut mem = AlignedBuffer([0; 2 * node_size::<Block>()]);
+        let mut tree = Bst::<Block, 2>::new(&mut mem);
+
+        assert_eq!(tree.insert_or_replace(block(10, 1)).unwrap(), None);
+        assert_eq!(tree.insert_or_replace(block(20, 1)).unwrap(), None);
+        assert_eq!(
+            tree.insert_or_replace(block(10, 4)).unwrap(),
+            Some(block(10, 1))
+        );
+        assert!(matches!(
+            tree.insert_or_replace(block(30, 1)),
+            Err(Error::OutOfSpace)
+        ));
+
+        // A replacement reuses the existing node, so it succeeds even when the tree is full.
+        assert_eq!(
+            tree.insert_or_replace(block(20, 2)).unwrap(),
+            Some(block(20, 1))
+        );
+        assert_eq!(tree.len(), 2);
+        assert!(tree.iter().eq([block(10, 4), block(20, 2)].iter()));
+    }
+
+    #[test]
+    fn test_get_mut() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Region {
+            base: u64,
+            attributes: u64,
+        }
+
+        impl OrderedKey for Region {
+            type Key = u64;
+            fn ordering_key(&self) -> &u64 {
+                &self.base
+            }
+        }
+
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Region>()]);
+        let mut tree = Bst::<Region, 4>::new(&mut mem);
+        for base in [0x2000, 0x1000, 0x3000] {
+            tree.insert(Region {
+                base,
+                attributes: 0,
+            })
+            .unwrap();
+        }
+
+        tree.get_mut(&0x1000).unwrap().attributes = 0x8;
+        assert!(tree.get_mut(&0x4000).is_none());
+        assert_eq!(tree.search_ref(&0x1000).unwrap().attributes, 0x8);
+        assert_eq!(tree.search_ref(&0x2000).unwrap().attributes, 0);
+    }
+
+    #[test]
+    fn test_non_copy_elements() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Handle {
+            id: u32,
+            state: core::cell::Cell<u32>,
+        }
+
+        impl OrderedKey for Handle {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.id
+            }
+        }
+
+        let handle = |id| Handle {
+            id,
+            state: core::cell::Cell::new(0),
+        };
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Handle>()]);
+        let mut tree = Bst::<Handle, 4>::new(&mut mem);
+        for id in [3, 1, 5] {
+            tree.insert(handle(id)).unwrap();
+        }
+
+        tree.search_ref(&3).unwrap().state.set(7);
+        assert_eq!(tree.floor_ref(&4).unwrap().state.get(), 7);
+        assert_eq!(tree.ceil_ref(&4).unwrap().id, 5);
+        assert_eq!(tree.successor_ref(&3).unwrap().id, 5);
+        assert_eq!(tree.predecessor_ref(&3).unwrap().id, 1);
+        assert_eq!(tree.min_ref().unwrap().id, 1);
+        assert_eq!(tree.max_ref().unwrap().id, 5);
+
+        // Removing moves the element out of the tree.
+        let removed = tree.remove(&3).unwrap();
+        assert_eq!(removed.state.into_inner(), 7);
+        assert!(tree.iter().map(|h| h.id).eq([1, 5]));
+    }
+
+    #[test]
+    fn test_drop_elements() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Tracked<'c> {
+            id: u32,
+            drops: &'c core::cell::Cell<u32>,
+        }
+
+        impl OrderedKey for Tracked<'_> {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.id
+            }
+        }
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let drops = core::cell::Cell::new(0);
+        let tracked = |id| Tracked { id, drops: &drops };
+        let mut mem = AlignedBuffer([0; 8 * node_size::<Tracked>()]);
+        let mut tree = Bst::<Tracked, 8>::new(&mut mem);
+        for id in 0..6 {
+            tree.insert(tracked(id)).unwrap();
+        }
+
+        // A removed element is moved out and dropped by the caller, exactly once.
+        drop(tree.remove(&2));
+        assert_eq!(drops.get(), 1);
+        assert!(matches!(tree.insert(tracked(3)), Err(Error::AlreadyExists)));
+        assert_eq!(drops.get(), 2);
+
+        tree.clear();
+        assert_eq!(drops.get(), 7);
+
+        for id in 0..4 {
+            tree.insert(tracked(id)).unwrap();
+        }
+        drop(tree);
+        assert_eq!(drops.get(), 11);
+    }
+
+    #[test]
+    fn test_insert_duplicate() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        for num in [50, 30, 70] {
+            bst.insert(num).unwrap();
+        }
+
+        for num in [50, 30, 70] {
+            assert!(matches!(bst.insert(num), Err(Error::AlreadyExists)));
+        }
+        assert_eq!(bst.storage.length, 3);
+        assert!(bst.validate_free_list());
+
+        let mut keys = [0; BST_MAX_SIZE];
+        assert_eq!(bst.keys_into(&mut keys).unwrap(), 3);
+        assert_eq!(keys[..3], [30, 50, 70]);
+
+        let mut log = [core::ptr::null(); BST_MAX_SIZE];
+        assert!(matches!(
+            bst.insert_logged(30, &mut log),
+            Err(Error::AlreadyExists)
+        ));
+        assert_eq!(bst.storage.length, 3);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert_eq!(bst.iter().next(), None);
+
+        let input = [50, 30, 70, 20, 40, 60, 80, 35, 45, 65];
+        for num in input {
+            bst.insert(num).unwrap();
+        }
+        let mut expected = ArrayVec::<i32, BST_MAX_SIZE>::from_iter(input);
+        expected.sort();
+        let values: ArrayVec<i32, BST_MAX_SIZE> = bst.iter().copied().collect();
+        assert_eq!(values, expected);
+        assert!(bst.iter().rev().eq(expected.iter().rev()));
+
+        // Both ends stop where they meet, whichever side reaches it first.
+        let mut iter = bst.iter();
+        assert_eq!(iter.next(), Some(&20));
+        assert_eq!(iter.next_back(), Some(&80));
+        let middle: ArrayVec<i32, BST_MAX_SIZE> = iter.by_ref().copied().collect();
+        assert_eq!(middle[..], expected[1..expected.len() - 1]);
+        assert_eq!((iter.next(), iter.next_back()), (None, None));
+
+        // Borrowing the tree iterates it the same way.
+        let mut values = ArrayVec::<i32, BST_MAX_SIZE>::new();
+        for value in &bst {
+            values.push(*value);
+        }
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_has_duplicates() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert!(!bst.has_duplicates());
+
+        for num in [50, 30, 70, 20, 40, 60, 80] {
+            bst.insert(num).unwrap();
+        }
+        assert!(!bst.has_duplicates());
+
+        // Overwrite 40 with 50 so the in-order walk sees 50 twice.
+        let index = (0..BST_MAX_SIZE)
+            .find(|i| bst.storage.is_occupied(*i) && bst.storage.node(*i as u16).data == 40)
+            .unwrap();
+        let node = bst.storage.node_mut(index as u16);
+        node.data = 50;
+        assert!(bst.has_duplicates());
+    }
+
+    #[test]
+    fn test_has_duplicates_by() {
+        // Keys in the same decade compare equal, even though they differ.
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> =
+            Bst::new_by(&mut mem, |a: &i32, b: &i32| (a / 10).cmp(&(b / 10)));
+        for num in [20, 10, 30] {
+            bst.insert(num).unwrap();
+        }
+        assert!(!bst.has_duplicates());
+
+        // 11 lands next to 10 in the walk and is equal to it under the comparator.
+        let index = (0..BST_MAX_SIZE)
+            .find(|i| bst.storage.is_occupied(*i) && bst.storage.node(*i as u16).data == 20)
+            .unwrap();
+        bst.storage.node_mut(index as u16).data = 11;
+        assert!(bst.has_duplicates());
+    }
+
+    #[test]
+    fn test_sample_into() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+
+        let mut out = [0; 4];
+        assert_eq!(bst.sample_into(4, || 0, &mut out), 0);
+
+        for num in [8, 4, 12, 2, 6, 10, 14, 1, 3, 5, 7, 9, 11, 13, 15] {
+            bst.insert(num).unwrap();
+        }
+
+        // A simple xorshift generator keeps the test deterministic.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let rng = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        assert_eq!(bst.sample_into(4, rng, &mut out), 4);
+        for (i, num) in out.iter().enumerate() {
+            assert!(bst.search(num).is_some());
+            assert!(!out[i + 1..].contains(num));
+        }
+
+        // Asking for more keys than the tree holds returns every key.
+        let mut out = [0; 32];
+        assert_eq!(bst.sample_into(32, || 0, &mut out), 15);
+        let mut sampled = out[..15].to_vec();
+        sampled.sort();
+        assert_eq!(sampled, (1..=15).collect::<std::vec::Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_bounded() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<i32>()]);
+        let mut bst: Bst<i32, 4> = Bst::new(&mut mem);
+
+        // Not yet full, nothing is evicted.
+        for num in [40, 20, 60, 10] {
+            assert_eq!(bst.push_bounded(num, true).unwrap(), None);
+        }
+        assert_eq!(bst.storage.length, 4);
+
+        // Keep the 4 smallest values by evicting the max.
+        assert_eq!(bst.push_bounded(30, true).unwrap(), Some(60));
+        assert_eq!(bst.push_bounded(50, true).unwrap(), Some(50));
+        assert!(bst.search(&60).is_none());
+        assert!(bst.search(&30).is_some());
+        assert_eq!(bst.storage.length, 4);
+
+        // Keep the 4 largest values by evicting the min.
+        assert_eq!(bst.push_bounded(35, false).unwrap(), Some(10));
+        assert_eq!(bst.push_bounded(5, false).unwrap(), Some(5));
+        assert!(bst.search(&10).is_none());
+        assert!(bst.search(&35).is_some());
+        assert_eq!(bst.storage.length, 4);
+
+        let mut values = std::vec::Vec::new();
+        bst.dfs(bst.head(), &mut values);
+        assert_eq!(values, [20, 30, 35, 40]);
+    }
+
+    #[test]
+    fn test_push_bounded_duplicate_when_full() {
+        let mut mem = AlignedBuffer([0; 3 * node_size::<i32>()]);
+        let mut bst: Bst<i32, 3> = Bst::new(&mut mem);
+        for num in [1, 2, 3] {
+            bst.insert(num).unwrap();
+        }
+
+        // A key that is already stored is rejected before anything is evicted.
+        assert!(matches!(
+            bst.push_bounded(2, true),
+            Err(Error::AlreadyExists)
+        ));
+        assert!(matches!(
+            bst.push_bounded(2, false),
+            Err(Error::AlreadyExists)
+        ));
+        assert_eq!(bst.storage.length, 3);
+        assert!(bst.iter().copied().eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_ordering_key_differs_from_ord() {
+        // The derived `PartialOrd` compares `rank` first, which runs opposite to `key`.
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Entry {
+            rank: u32,
+            key: u32,
+        }
+
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let entry = |key| Entry {
+            rank: 100 - key,
+            key,
+        };
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<Entry>()]);
+        let mut tree: Bst<Entry, BST_MAX_SIZE> = Bst::new(&mut mem);
+        let keys = [7, 3, 11, 1, 5, 9, 13];
+        for key in keys {
+            tree.insert(entry(key)).unwrap();
+        }
+        for key in keys {
+            assert_eq!(tree.search(&key), Some(entry(key)));
+        }
+        assert!(tree.iter().map(|e| e.key).eq([1, 3, 5, 7, 9, 11, 13]));
+
+        tree.delete(entry(7)).unwrap();
+        tree.delete(entry(1)).unwrap();
+        assert_eq!(tree.search(&7), None);
+        assert!(tree.iter().map(|e| e.key).eq([3, 5, 9, 11, 13]));
+    }
+
+    #[test]
+    fn test_keys_into() {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Entry {
+            key: u32,
+            payload: [u64; 4],
+        }
+
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<Entry>()]);
+        let mut bst: Bst<Entry, BST_MAX_SIZE> = Bst::new(&mut mem);
+        let keys = [7, 3, 11, 1, 5, 9, 13];
+        for key in keys {
+            bst.insert(Entry {
+                key,
+                payload: [key as u64; 4],
+            })
+            .unwrap();
+        }
+
+        let mut out = [0u32; BST_MAX_SIZE];
+        assert_eq!(bst.keys_into(&mut out).unwrap(), keys.len());
+        let mut sorted = keys;
+        sorted.sort();
+        assert_eq!(out[..keys.len()], sorted);
+
+        assert!(bst.keys_into(&mut out[..keys.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_find_pair_summing_to() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert_eq!(bst.find_pair_summing_to(10), None);
+
+        for num in [6, 1, 9, 4] {
+            bst.insert(num).unwrap();
+        }
+        let pair = bst.find_pair_summing_to(10);
+        assert!(pair == Some((1, 9)) || pair == Some((4, 6)));
+        assert_eq!(bst.find_pair_summing_to(15), Some((6, 9)));
+
+        // A key is never paired with itself.
+        assert_eq!(bst.find_pair_summing_to(12), None);
+        assert_eq!(bst.find_pair_summing_to(100), None);
+    }
+
+    #[test]
+    fn test_find_pair_summing_to_overflow() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<u8>()]);
+        let mut bst: Bst<u8, BST_MAX_SIZE> = Bst::new(&mut mem);
+        for num in [100, 200] {
+            bst.insert(num).unwrap();
+        }
+        // 100 + 200 does not fit in a u8, so the sweep moves past 200 instead of wrapping.
+        assert_eq!(bst.find_pair_summing_to(50), None);
+        bst.insert(60).unwrap();
+        assert_eq!(bst.find_pair_summing_to(160), Some((60, 100)));
+
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i8>()]);
+        let mut bst: Bst<i8, BST_MAX_SIZE> = Bst::new(&mut mem);
+        for num in [-100, -40, -30] {
+            bst.insert(num).unwrap();
+        }
+        // -100 + -30 falls below i8::MIN, so the sweep moves past -100.
+        assert_eq!(bst.find_pair_summing_to(-70), Some((-40, -30)));
+    }
+
+    #[test]
+    fn test_find_pair_summing_to_descending() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i8>()]);
+        let mut bst: Bst<i8, BST_MAX_SIZE> = Bst::new_by(&mut mem, |a: &i8, b: &i8| b.cmp(a));
+        for num in [120, 100, 30, 10] {
+            bst.insert(num).unwrap();
+        }
+        // The sweep starts from 120 and 10, whose sum overflows. In a descending tree that means moving past 120.
+        assert_eq!(bst.find_pair_summing_to(40), Some((30, 10)));
+        assert_eq!(bst.find_pair_summing_to(110), Some((100, 10)));
+        assert_eq!(bst.find_pair_summing_to(125), None);
+    }
+
+    #[test]
+    fn test_strict_bounds() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert_eq!(bst.min_key_greater(&0), None);
+        assert_eq!(bst.max_key_less(&0), None);
+
+        for num in [40, 20, 60, 10, 30, 50, 70] {
+            bst.insert(num).unwrap();
+        }
+
+        // The query key is present: the exact search finds it, the strict bounds skip it.
+        for num in [10, 20, 30, 40, 50, 60, 70] {
+            assert_eq!(bst.search(&num), Some(num));
+            assert_eq!(bst.min_key_greater(&num), (num < 70).then_some(num + 10));
+            assert_eq!(bst.max_key_less(&num), (num > 10).then_some(num - 10));
+        }
+
+        // The query key is absent.
+        assert_eq!(bst.min_key_greater(&35), Some(40));
+        assert_eq!(bst.max_key_less(&35), Some(30));
+        assert_eq!(bst.min_key_greater(&5), Some(10));
+        assert_eq!(bst.max_key_less(&75), Some(70));
+    }
+
+    #[test]
+    fn test_full_handler() {
+        let mut calls = 0;
+        let mut mem = AlignedBuffer([0; 4 * node_size::<i32>()]);
+        let mut bst = Bst::<i32, 4>::new(&mut mem).with_full_handler(|| calls += 1);
+
+        for num in [1, 2, 3, 4] {
+            bst.insert(num).unwrap();
+        }
+        assert!(matches!(bst.insert(5), Err(Error::OutOfSpace)));
+        assert!(matches!(bst.insert(6), Err(Error::OutOfSpace)));
+
+        // Freeing a slot re-arms the handler.
+        bst.delete(4).unwrap();
+        bst.insert(5).unwrap();
+        assert!(matches!(bst.insert(6), Err(Error::OutOfSpace)));
+
+        drop(bst);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Bst<u32, 4>>();
+    }
+
+    #[test]
+    fn test_full_handler_duplicate() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let mut mem = AlignedBuffer([0; 4 * node_size::<i32>()]);
+        let mut bst: Bst<i32, 4> = Bst::new(&mut mem);
+        bst.set_full_handler(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+        });
+
+        for num in [1, 2, 3, 4] {
+            bst.insert(num).unwrap();
+        }
+        // A key that is already stored needs no slot, so a full tree reports the duplicate without notifying.
+        assert!(matches!(bst.insert(3), Err(Error::AlreadyExists)));
+        let mut log = [core::ptr::null(); 4];
+        assert!(matches!(
+            bst.insert_logged(2, &mut log),
+            Err(Error::AlreadyExists)
+        ));
+        assert_eq!(bst.len(), 4);
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_would_insert_under() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert_eq!(bst.would_insert_under(&10), None);
+
+        for num in [50, 30, 70, 20, 40, 60, 80] {
+            bst.insert(num).unwrap();
+        }
+        assert_eq!(bst.would_insert_under(&50), None);
+
+        for num in [10, 25, 35, 45, 55, 65, 75, 85] {
+            let predicted = bst.would_insert_under(&num).unwrap();
+            bst.insert(num).unwrap();
+            let parent = bst.search_node(&num).unwrap().parent().unwrap();
+            assert_eq!(predicted, parent.data);
+        }
+    }
+
+    #[test]
+    fn test_swap_backing() {
+        let mut live_mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut scratch_mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut live: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut live_mem);
+        let mut scratch: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut scratch_mem);
+
+        for num in [2, 1, 3] {
+            live.insert(num).unwrap();
+        }
+        for num in [20, 10, 30, 40] {
+            scratch.insert(num).unwrap();
+        }
+        let scratch_data = scratch.storage.slot(0);
+
+        live.swap_backing(&mut scratch);
+        assert_eq!(live.storage.slot(0), scratch_data);
+
+        let mut values = std::vec::Vec::new();
+        live.dfs(live.head(), &mut values);
+        assert_eq!(values, [10, 20, 30, 40]);
+        assert_eq!(live.storage.length, 4);
+
+        let mut values = std::vec::Vec::new();
+        scratch.dfs(scratch.head(), &mut values);
+        assert_eq!(values, [1, 2, 3]);
+        assert_eq!(scratch.storage.length, 3);
+
+        // Both trees keep working independently after the swap.
+        live.insert(50).unwrap();
+        scratch.delete(2).unwrap();
+        assert!(live.search(&50).is_some());
+        assert!(scratch.search(&50).is_none());
+        assert!(scratch.search(&2).is_none());
+    }
+
+    #[test]
+    fn test_swap_backing_order() {
+        const SIZE: usize = 16;
+        let mut forward_mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut reverse_mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut forward = Bst::<u32, SIZE>::new(&mut forward_mem);
+        let mut reverse = Bst::<u32, SIZE>::new_by(&mut reverse_mem, |a, b| b.cmp(a));
+        for num in [5, 3, 8] {
+            forward.insert(num).unwrap();
+            reverse.insert(num + 1).unwrap();
+        }
+
+        forward.swap_backing(&mut reverse);
+        for num in [1, 7, 10] {
+            forward.insert(num).unwrap();
+            reverse.insert(num).unwrap();
+        }
+        assert!(forward.iter().eq([10, 9, 7, 6, 4, 1].iter()));
+        assert!(reverse.iter().eq([1, 3, 5, 7, 8, 10].iter()));
+        assert_eq!(forward.search(&4), Some(4));
+        assert_eq!(reverse.search(&8), Some(8));
+        assert!(matches!(forward.insert(9), Err(Error::AlreadyExists)));
+    }
+
+    #[test]
+    fn test_validate_free_list() {
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+        assert!(bst.validate_free_list());
+
+        for num in [5, 3, 8, 1, 4] {
+            bst.insert(num).unwrap();
+        }
+        bst.delete(3).unwrap();
+        assert!(bst.validate_free_list());
+
+        // Freeing a slot that is still live.
+        let live = (0..BST_MAX_SIZE)
+            .position(|i| bst.storage.is_occupied(i))
+            .unwrap();
+        // The free list is chained through the parent link, so keep the live node's link to put it back.
+        let parent = bst
+            .storage
+            .node(live as u16)
+            .parent
+            .load(core::sync::atomic::Ordering::Acquire);
+        bst.storage.push_free(live);
+        assert!(!bst.validate_free_list());
+        bst.storage.pop_free();
+        bst.storage
+            .node(live as u16)
+            .parent
+            .store(parent, core::sync::atomic::Ordering::Release);
+        assert!(bst.validate_free_list());
+
+        // Freeing the same slot twice.
+        let free = bst.storage.free_slots().next().unwrap();
+        bst.storage.push_free(free);
+        assert!(!bst.validate_free_list());
+    }
+
+    #[test]
+    fn test_insert_logged_and_undo() {
+        use std::vec::Vec;
+
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
+        let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
+
+        // Logging an insert into an empty tree records only the new node.
+        let mut log = [core::ptr::null(); 8];
+        assert_eq!(bst.insert_logged(50, &mut log).unwrap(), 1);
+        unsafe { bst.undo_insert(&log[..1]) };
+        assert!(bst.head().is_none());
+        assert_eq!(bst.storage.length, 0);
+
+        for num in [50, 30, 70, 20, 40] {
+            bst.insert(num).unwrap();
+        }
+        let mut before = Vec::new();
+        bst.dfs(bst.head(), &mut before);
+
+        // 35 is linked under 50 -> 30 -> 40.
+        let count = bst.insert_logged(35, &mut log).unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(bst.storage.length, 6);
+        assert!(bst.search(&35).is_some());
+
+        unsafe { bst.undo_insert(&log[..count]) };
+        let mut after = Vec::new();
+        bst.dfs(bst.head(), &mut after);
+        assert_eq!(before, after);
+        assert_eq!(bst.storage.length, 5);
+        assert!(bst.search(&35).is_none());
+
+        // A log too short for the path leaves the tree untouched.
+        assert!(bst.insert_logged(35, &mut log[..3]).is_err());
+        assert_eq!(bst.storage.length, 5);
+        assert!(bst.search(&35).is_none());
+
+        // Undoing the insert that filled the tree frees a slot, which re-arms the full handler like a delete.
+        let mut calls = 0;
+        let mut mem = AlignedBuffer([0; 2 * node_size::<i32>()]);
+        let mut bst = Bst::<i32, 2>::new(&mut mem).with_full_handler(|| calls += 1);
+        bst.insert(1).unwrap();
+        for _ in 0..2 {
+            let count = bst.insert_logged(2, &mut log).unwrap();
+            assert!(matches!(bst.insert(3), Err(Error::OutOfSpace)));
+            unsafe { bst.undo_insert(&log[..count]) };
+        }
+        drop(bst);
+        assert_eq!(calls, 2);
+    }
+}
 
 #[cfg(test)]
 mod fuzz_tests {
     extern crate std;
     use super::{node_size, Bst};
+    use crate::AlignedBuffer;
     use rand::seq::SliceRandom;
     use rand::Rng;
     use std::collections::HashSet;
@@ -346,7 +2678,7 @@ mod fuzz_tests {
     #[test]
     fn fuzz_insert() {
         for _ in 0..100 {
-            let mut mem = [0; BST_MAX_SIZE * node_size::<i32>()];
+            let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
             let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
             let mut rng = rand::thread_rng();
             let min = 1;
@@ -377,7 +2709,7 @@ mod fuzz_tests {
 
     #[test]
     fn fuzz_search() {
-        let mut mem = [0; BST_MAX_SIZE * node_size::<i32>()];
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<i32>()]);
         let mut bst: Bst<i32, BST_MAX_SIZE> = Bst::new(&mut mem);
         let mut rng = rand::thread_rng();
         let min = 50_000;
@@ -417,7 +2749,7 @@ mod fuzz_tests {
 
     #[test]
     fn fuzz_delete() {
-        let mut mem = [0; BST_MAX_SIZE * node_size::<i32>()];
+        let mut mem = AlignedBuffer([0; BST_MAX_SIZE * node_size::<usize>()]);
         let mut rbt: Bst<usize, BST_MAX_SIZE> = Bst::new(&mut mem);
         let mut rng = rand::thread_rng();
         let min = 1;
@@ -442,7 +2774,7 @@ mod fuzz_tests {
         while let Some(num) = random_numbers.pop() {
             match rbt.delete(num) {
                 Ok(_) => (),
-                Err(e) => assert!(false, "{:?}", e),
+                Err(e) => panic!("{:?}", e),
             }
         }
 
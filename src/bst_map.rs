@@ -0,0 +1,23 @@
+//! A key-value map on top of [Bst], for payloads that have no ordering of their own.
+use crate::bst::{self, Bst};
+use crate::map::{impl_map, KeyValue};
+
+/// Returns the number of bytes a single entry of a [BstMap] occupies in its storage.
+pub const fn node_size<K, V>() -> usize
+where
+    K: Ord,
+{
+    bst::node_size::<KeyValue<K, V>>()
+}
+
+/// A map from keys of type `K` to values of type `V`, backed by a [Bst] holding up to `SIZE` entries. Only the keys
+/// need to be `Ord`; the values are stored alongside them untouched. The tree is never rebalanced, so this suits keys
+/// inserted in random order; use [crate::rbt_map::RbtMap] when they may arrive sorted.
+pub struct BstMap<'a, K, V, const SIZE: usize>
+where
+    K: Ord,
+{
+    tree: Bst<'a, KeyValue<K, V>, SIZE>,
+}
+
+impl_map!(BstMap, Bst);
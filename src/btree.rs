@@ -0,0 +1,724 @@
+//! A B-tree whose nodes are fixed-size slabs carved out of a caller-provided buffer.
+//!
+//! Every node holds up to `B - 1` elements side by side, so a search touches a handful of nodes instead of one node
+//! per level of a binary tree. This pays off for large keys, where the binary trees spread every comparison over a
+//! separate cache line.
+use core::mem::{self, size_of, MaybeUninit};
+use core::{ptr, slice};
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::{Error, OrderedKey, Result};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
+
+/// The deepest a tree can grow. Every node but the head has at least two children, so a taller tree would need more
+/// nodes than a `u16` slot index can address.
+const MAX_DEPTH: usize = 16;
+
+/// Returns the number of bytes a single node of a [BTree] with fanout `B` occupies in its storage.
+pub const fn node_size<D, const B: usize>() -> usize {
+    size_of::<Node<D, B>>()
+}
+
+/// A B-tree holding elements of type `D` in nodes of up to `B - 1` elements and `B` children each. `B` must be an
+/// even number of at least 4, so a full node splits into two halves that are both at least half full.
+///
+/// The tree takes as many nodes as fit in the buffer it is created with. How many elements that is depends on how
+/// full the nodes are: at least `B / 2 - 1` per node, and up to `B - 1`.
+pub struct BTree<'a, D, const B: usize>
+where
+    D: OrderedKey,
+{
+    storage: Storage<'a, Node<D, B>, 0>,
+    /// The slot of the head node, or [NULL] if the tree is empty.
+    head: u16,
+    length: usize,
+}
+
+/// What [BTree::remove_from] looks for on its way down.
+#[derive(Clone, Copy)]
+enum Target<'k, K> {
+    Key(&'k K),
+    Min,
+    Max,
+}
+
+impl<'a, D, const B: usize> BTree<'a, D, B>
+where
+    D: OrderedKey,
+{
+    /// Creates an empty tree backed by `slice`. Panics if `slice` is not aligned for the nodes, or if `B` is odd or
+    /// less than 4.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice))
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Storage::<Node<D, B>, 0>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice))
+    }
+
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        assert!(
+            B >= 4 && B.is_multiple_of(2),
+            "Fanout must be an even number of at least 4, not {}",
+            B
+        );
+        Self {
+            storage: Storage::new(slice),
+            head: NULL,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of nodes the buffer holds.
+    pub fn node_capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Removes every element from the tree. The backing storage is kept, and all of its nodes can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.head = NULL;
+        self.length = 0;
+    }
+
+    /// Takes a free slot and turns it into an empty leaf.
+    fn alloc(&mut self) -> Result<u16> {
+        Ok(self.storage.add(Node::new)?.index)
+    }
+
+    /// Gives slot `index` back to the storage. Its elements must already have been moved out or dropped.
+    fn release(&mut self, index: u16) {
+        self.storage.delete(index);
+    }
+
+    /// Inserts `data` into the tree. Full nodes are split on the way down, so the insert never has to walk back up.
+    /// Returns `Error::AlreadyExists` if an element with the same key is in the tree, and `Error::OutOfSpace` if a
+    /// node had to be split but no free node was left. The tree stays valid either way.
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        // Check for the key before growing or splitting anything, so a duplicate never costs a node.
+        if self.contains(data.ordering_key()) {
+            return Err(Error::AlreadyExists);
+        }
+        if self.head == NULL {
+            let head = self.alloc()?;
+            self.storage.node_mut(head).insert_element(0, data);
+            self.head = head;
+            self.length += 1;
+            return Ok(());
+        }
+        if self.storage.node(self.head).is_full() {
+            // The tree grows at the top: the full head becomes the only child of a new head, and is split below it.
+            let head = self.alloc()?;
+            self.storage.node_mut(head).children[0] = self.head;
+            if let Err(e) = self.split_child(head, 0) {
+                self.release(head);
+                return Err(e);
+            }
+            self.head = head;
+        }
+
+        let mut index = self.head;
+        loop {
+            let node = self.storage.node(index);
+            let Err(mut position) = node.search(data.ordering_key()) else {
+                return Err(Error::AlreadyExists);
+            };
+            if node.is_leaf() {
+                self.storage.node_mut(index).insert_element(position, data);
+                self.length += 1;
+                return Ok(());
+            }
+            if self.storage.node(node.children[position]).is_full() {
+                self.split_child(index, position)?;
+                match data
+                    .ordering_key()
+                    .cmp(self.storage.node(index).elements()[position].ordering_key())
+                {
+                    core::cmp::Ordering::Less => {}
+                    core::cmp::Ordering::Equal => return Err(Error::AlreadyExists),
+                    core::cmp::Ordering::Greater => position += 1,
+                }
+            }
+            index = self.storage.node(index).children[position];
+        }
+    }
+
+    /// Splits the full child at `position` of `parent` in two, moving its middle element up into `parent`, which
+    /// must not be full.
+    fn split_child(&mut self, parent: u16, position: usize) -> Result<()> {
+        let half = B / 2;
+        let right = self.alloc()?;
+        let child = self.storage.node(parent).children[position];
+        let (left_node, right_node) = self.storage.pair(child, right);
+
+        // The child holds `B - 1` elements: the first `half - 1` stay, the next one moves up and the rest move right.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                left_node.elements.as_ptr().add(half),
+                right_node.elements.as_mut_ptr(),
+                half - 1,
+            )
+        };
+        right_node.children[..half].copy_from_slice(&left_node.children[half..]);
+        left_node.children[half..].fill(NULL);
+        right_node.len = (half - 1) as u16;
+        left_node.len = half as u16;
+        let middle = left_node.remove_element(half - 1);
+
+        let parent = self.storage.node_mut(parent);
+        parent.insert_element(position, middle);
+        parent.insert_child(position + 1, right);
+        Ok(())
+    }
+
+    /// Returns true if an element with the given key is in the tree.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_ref(key).is_some()
+    }
+
+    /// Returns a reference to the element with the given key.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        let mut index = self.head;
+        while index != NULL {
+            let node = self.storage.node(index);
+            match node.search(key) {
+                Ok(position) => return Some(&node.elements()[position]),
+                // A leaf has no children, so this ends the search.
+                Err(position) => index = node.children[position],
+            }
+        }
+        None
+    }
+
+    pub fn delete(&mut self, data: D) -> Result<()> {
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the tree, returning it. Returns `None` if no element has the key.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        if self.head == NULL {
+            return None;
+        }
+        let removed = self.remove_from(self.head, Target::Key(key));
+        let head = self.storage.node(self.head);
+        if head.len == 0 {
+            // The head gave up its last element, so its only child, if it has one, takes its place.
+            let old_head = self.head;
+            self.head = head.children[0];
+            self.release(old_head);
+        }
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+
+    /// Removes `target` from the subtree rooted at `index`. Before descending into a child, the child is topped up to
+    /// at least `B / 2` elements, so it can lose one without dropping below the minimum.
+    fn remove_from(&mut self, mut index: u16, target: Target<D::Key>) -> Option<D> {
+        loop {
+            let node = self.storage.node(index);
+            let (position, found) = match target {
+                Target::Key(key) => match node.search(key) {
+                    Ok(position) => (position, true),
+                    Err(position) => (position, false),
+                },
+                Target::Min => (0, node.is_leaf()),
+                Target::Max if node.is_leaf() => (node.len() - 1, true),
+                Target::Max => (node.len(), false),
+            };
+            if found {
+                return Some(self.remove_at(index, position));
+            }
+            if node.is_leaf() {
+                return None;
+            }
+            let position = self.ensure_spare(index, position);
+            index = self.storage.node(index).children[position];
+        }
+    }
+
+    /// Removes the element at `position` of node `index`, which holds at least `B / 2` elements unless it is the head.
+    fn remove_at(&mut self, index: u16, position: usize) -> D {
+        let half = B / 2;
+        let node = self.storage.node(index);
+        if node.is_leaf() {
+            return self.storage.node_mut(index).remove_element(position);
+        }
+        let (left, right) = (node.children[position], node.children[position + 1]);
+        let replacement = if self.storage.node(left).len() >= half {
+            self.remove_from(left, Target::Max)
+        } else if self.storage.node(right).len() >= half {
+            self.remove_from(right, Target::Min)
+        } else {
+            // Neither side can spare its neighbour of the element, so both are merged around it and it is removed
+            // from the middle of the merged node.
+            self.merge_children(index, position);
+            return self.remove_at(left, half - 1);
+        };
+        let replacement = replacement.expect("Child of an internal node is empty");
+        mem::replace(
+            &mut self.storage.node_mut(index).elements_mut()[position],
+            replacement,
+        )
+    }
+
+    /// Makes sure the child at `position` of node `index` holds at least `B / 2` elements, taking one from a sibling
+    /// or merging it with one. Returns the position of the child afterwards.
+    fn ensure_spare(&mut self, index: u16, position: usize) -> usize {
+        let half = B / 2;
+        let node = self.storage.node(index);
+        let len = node.len();
+        let child = node.children[position];
+        let left = position.checked_sub(1).map(|left| node.children[left]);
+        let right = (position < len).then(|| node.children[position + 1]);
+
+        if self.storage.node(child).len() >= half {
+            position
+        } else if left.is_some_and(|left| self.storage.node(left).len() >= half) {
+            self.rotate_right(index, position - 1);
+            position
+        } else if right.is_some_and(|right| self.storage.node(right).len() >= half) {
+            self.rotate_left(index, position);
+            position
+        } else if right.is_some() {
+            self.merge_children(index, position);
+            position
+        } else {
+            self.merge_children(index, position - 1);
+            position - 1
+        }
+    }
+
+    /// Moves the last element of the child left of `separator` up into node `index`, and the separator down to the
+    /// front of the child right of it.
+    fn rotate_right(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node(index);
+        let (left, right) = (node.children[separator], node.children[separator + 1]);
+
+        let left_node = self.storage.node_mut(left);
+        let last = left_node.len() - 1;
+        let data = left_node.remove_element(last);
+        let child = (!left_node.is_leaf()).then(|| left_node.remove_child(last + 1));
+
+        let data = mem::replace(
+            &mut self.storage.node_mut(index).elements_mut()[separator],
+            data,
+        );
+        let right_node = self.storage.node_mut(right);
+        right_node.insert_element(0, data);
+        if let Some(child) = child {
+            right_node.insert_child(0, child);
+        }
+    }
+
+    /// Moves the first element of the child right of `separator` up into node `index`, and the separator down to the
+    /// end of the child left of it.
+    fn rotate_left(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node(index);
+        let (left, right) = (node.children[separator], node.children[separator + 1]);
+
+        let right_node = self.storage.node_mut(right);
+        let data = right_node.remove_element(0);
+        let child = (!right_node.is_leaf()).then(|| right_node.remove_child(0));
+
+        let data = mem::replace(
+            &mut self.storage.node_mut(index).elements_mut()[separator],
+            data,
+        );
+        let left_node = self.storage.node_mut(left);
+        left_node.insert_element(left_node.len(), data);
+        if let Some(child) = child {
+            left_node.insert_child(left_node.len(), child);
+        }
+    }
+
+    /// Merges the children on either side of `separator` into the left one, along with the separator itself. Both
+    /// children hold `B / 2 - 1` elements, so the merged node is full.
+    fn merge_children(&mut self, index: u16, separator: usize) {
+        let node = self.storage.node_mut(index);
+        let data = node.remove_element(separator);
+        let right = node.remove_child(separator + 1);
+        let left = node.children[separator];
+
+        let (left_node, right_node) = self.storage.pair(left, right);
+        let start = left_node.len();
+        let moved = right_node.len();
+        left_node.insert_element(start, data);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                right_node.elements.as_ptr(),
+                left_node.elements.as_mut_ptr().add(start + 1),
+                moved,
+            )
+        };
+        left_node.children[start + 1..][..=moved].copy_from_slice(&right_node.children[..=moved]);
+        left_node.len += moved as u16;
+        // The elements were moved out, so releasing the node must not drop them.
+        right_node.len = 0;
+        self.release(right);
+    }
+
+    /// Checks every B-tree invariant, returning the height of the tree, or a description of the first invariant that
+    /// does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        if self.head == NULL {
+            return Ok(0);
+        }
+        let mut count = 0;
+        let height = self.validate_node(self.head, None, None, &mut count)?;
+        if count != self.length {
+            return Err("length does not match the number of elements");
+        }
+        Ok(height)
+    }
+
+    /// Checks the subtree rooted at `index`, whose keys must lie strictly between `lower` and `upper`, and adds its
+    /// elements to `count`.
+    fn validate_node(
+        &self,
+        index: u16,
+        lower: Option<&D::Key>,
+        upper: Option<&D::Key>,
+        count: &mut usize,
+    ) -> core::result::Result<usize, &'static str> {
+        let node = self.storage.node(index);
+        if !self.storage.is_occupied(index as usize) {
+            return Err("node is in a free slot");
+        }
+        if node.len() >= B {
+            return Err("node length is out of range");
+        }
+        if node.len == 0 || (index != self.head && node.len() < B / 2 - 1) {
+            return Err("node holds too few elements");
+        }
+        let mut previous = lower;
+        for key in node
+            .elements()
+            .iter()
+            .map(|data| data.ordering_key())
+            .chain(upper)
+        {
+            if previous.is_some_and(|previous| previous >= key) {
+                return Err("keys are not in ascending order");
+            }
+            previous = Some(key);
+        }
+        *count += node.len();
+        if node.is_leaf() {
+            return Ok(1);
+        }
+
+        let mut height = None;
+        for (position, &child) in node.children[..=node.len()].iter().enumerate() {
+            if child == NULL {
+                return Err("internal node is missing a child");
+            }
+            let lower = position
+                .checked_sub(1)
+                .map(|left| node.elements()[left].ordering_key())
+                .or(lower);
+            let upper = node
+                .elements()
+                .get(position)
+                .map(|data| data.ordering_key())
+                .or(upper);
+            let child_height = self.validate_node(child, lower, upper, count)?;
+            if height.is_some_and(|height| height != child_height) {
+                return Err("leaves are not all at the same depth");
+            }
+            height = Some(child_height);
+        }
+        Ok(height.unwrap() + 1)
+    }
+
+    /// Returns an iterator over the elements in ascending order.
+    pub fn iter(&self) -> Iter<'_, D, B> {
+        let mut iter = Iter {
+            storage: &self.storage,
+            stack: [(NULL, 0); MAX_DEPTH],
+            depth: 0,
+        };
+        iter.descend(self.head);
+        iter
+    }
+}
+
+impl<D, const B: usize> BTree<'_, D, B>
+where
+    D: Copy + OrderedKey,
+{
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
+    }
+}
+
+impl<'b, D, const B: usize> IntoIterator for &'b BTree<'_, D, B>
+where
+    D: OrderedKey,
+{
+    type Item = &'b D;
+    type IntoIter = Iter<'b, D, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a [BTree] in ascending order.
+pub struct Iter<'a, D, const B: usize> {
+    storage: &'a Storage<'a, Node<D, B>, 0>,
+    /// The nodes on the path to the next element, each with the position of its next element.
+    stack: [(u16, u16); MAX_DEPTH],
+    depth: usize,
+}
+
+impl<D, const B: usize> Iter<'_, D, B> {
+    /// Pushes the path from `index` down to the leftmost leaf below it.
+    fn descend(&mut self, mut index: u16) {
+        while index != NULL {
+            self.stack[self.depth] = (index, 0);
+            self.depth += 1;
+            index = self.storage.node(index).children[0];
+        }
+    }
+}
+
+impl<'a, D, const B: usize> Iterator for Iter<'a, D, B> {
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.depth > 0 {
+            let (index, position) = self.stack[self.depth - 1];
+            let node = self.storage.node(index);
+            if (position as usize) < node.len() {
+                self.stack[self.depth - 1].1 += 1;
+                self.descend(node.children[position as usize + 1]);
+                return Some(&node.elements()[position as usize]);
+            }
+            self.depth -= 1;
+        }
+        None
+    }
+}
+
+/// A node holds up to `B - 1` elements in ascending order and, unless it is a leaf, one more child than elements. The
+/// element array is `B` long so it can be declared without const arithmetic; its last slot is never used.
+struct Node<D, const B: usize> {
+    /// The number of elements.
+    len: u16,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    /// Slot indices of the children, all [NULL] in a leaf. A free slot links to the next free slot through the first.
+    children: [u16; B],
+    elements: [MaybeUninit<D>; B],
+}
+
+impl<D, const B: usize> Slot for Node<D, B> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).children[0]).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).children[0]).write(index) };
+    }
+}
+
+impl<D, const B: usize> Drop for Node<D, B> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.elements_mut()) };
+    }
+}
+
+impl<D, const B: usize> Node<D, B>
+where
+    D: OrderedKey,
+{
+    /// Finds `key` among the elements, like `slice::binary_search`.
+    fn search(&self, key: &D::Key) -> core::result::Result<usize, usize> {
+        self.elements()
+            .binary_search_by(|data| data.ordering_key().cmp(key))
+    }
+}
+
+impl<D, const B: usize> Node<D, B> {
+    /// Returns an empty leaf, to be written to slot `index`.
+    fn new(index: u16) -> Self {
+        Node {
+            len: 0,
+            index,
+            children: [NULL; B],
+            elements: [const { MaybeUninit::uninit() }; B],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NULL
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == B - 1
+    }
+
+    fn elements(&self) -> &[D] {
+        unsafe { slice::from_raw_parts(self.elements.as_ptr().cast(), self.len()) }
+    }
+
+    fn elements_mut(&mut self) -> &mut [D] {
+        unsafe { slice::from_raw_parts_mut(self.elements.as_mut_ptr().cast(), self.len()) }
+    }
+
+    /// Inserts `data` at `position`, shifting the elements after it to the right.
+    fn insert_element(&mut self, position: usize, data: D) {
+        unsafe {
+            let slot = self.elements.as_mut_ptr().add(position);
+            ptr::copy(slot, slot.add(1), self.len() - position);
+            slot.write(MaybeUninit::new(data));
+        }
+        self.len += 1;
+    }
+
+    /// Moves the element at `position` out, shifting the elements after it to the left.
+    fn remove_element(&mut self, position: usize) -> D {
+        self.len -= 1;
+        unsafe {
+            let slot = self.elements.as_mut_ptr().add(position);
+            let data = slot.read().assume_init();
+            ptr::copy(slot.add(1), slot, self.len() - position);
+            data
+        }
+    }
+
+    /// Inserts `child` at `position`. Called after inserting the element that separates it from its neighbour, so
+    /// the length already accounts for it.
+    fn insert_child(&mut self, position: usize, child: u16) {
+        let len = self.len();
+        self.children.copy_within(position..len, position + 1);
+        self.children[position] = child;
+    }
+
+    /// Removes the child at `position`. Called after removing the element that separated it from its neighbour.
+    fn remove_child(&mut self, position: usize) -> u16 {
+        let len = self.len();
+        let child = self.children[position];
+        self.children.copy_within(position + 1..len + 2, position);
+        self.children[len + 1] = NULL;
+        child
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, BTree};
+    use crate::testing::{self, impl_test_set, Tracked};
+    use crate::{AlignedBuffer, Error};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::vec::Vec;
+
+    impl_test_set!([const B: usize] BTree<'_, u32, B>);
+
+    fn check_against_set<const B: usize>() {
+        let mut mem = AlignedBuffer([0; 512 * node_size::<u32, 8>()]);
+        testing::check_against_set(&mut BTree::<u32, B>::new(&mut mem));
+    }
+
+    #[test]
+    fn test_insert_search_remove() {
+        check_against_set::<4>();
+        check_against_set::<6>();
+        check_against_set::<8>();
+
+        let mut mem = AlignedBuffer([0; 128 * node_size::<u32, 4>()]);
+        let mut tree = BTree::<u32, 4>::new(&mut mem);
+        for value in 0..100 {
+            tree.insert(value).unwrap();
+        }
+        assert!(matches!(tree.insert(50), Err(Error::AlreadyExists)));
+        assert_eq!(tree.len(), 100);
+        assert!(tree.iter().copied().eq(0..100));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().count(), 0);
+        tree.insert(7).unwrap();
+        assert!(tree.contains(&7));
+    }
+
+    #[test]
+    fn test_duplicate_when_full() {
+        let mut mem = AlignedBuffer([0; node_size::<u32, 4>()]);
+        testing::check_duplicate_when_full(&mut BTree::<u32, 4>::new(&mut mem));
+    }
+
+    #[test]
+    fn test_out_of_space() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32, 4>()]);
+        let mut tree = BTree::<u32, 4>::new(&mut mem);
+        assert_eq!(tree.node_capacity(), 4);
+        testing::check_out_of_space(&mut tree);
+    }
+
+    #[test]
+    fn test_try_new_bad_buffer() {
+        let mut mem = [0u32; 8 * node_size::<u32, 4>() / 4 + 1];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        assert!(matches!(
+            BTree::<u32, 4>::try_new(&mut bytes[1..]),
+            Err(Error::BadBuffer)
+        ));
+        assert!(BTree::<u32, 4>::try_new(bytes).is_ok());
+    }
+
+    #[test]
+    fn test_drop_elements() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut mem = AlignedBuffer([0; 64 * node_size::<Tracked, 4>()]);
+        let mut tree = BTree::<Tracked, 4>::new(&mut mem);
+        for value in 0..40 {
+            tree.insert(Tracked(value, &DROPPED)).unwrap();
+        }
+        // Splits and merges move elements between nodes without dropping them.
+        let removed: Vec<_> = (0..40)
+            .step_by(2)
+            .map(|value| tree.remove(&value).unwrap())
+            .collect();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+        drop(removed);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 20);
+
+        drop(tree);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 40);
+    }
+}
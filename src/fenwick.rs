@@ -0,0 +1,255 @@
+//! A Fenwick tree (binary indexed tree) over a caller-provided buffer, for prefix sums under point updates.
+//!
+//! The tree is a flat array of partial sums: slot `i` (counting from 1) holds the sum of the `i & -i` values ending
+//! at `i`. Updates and prefix sums each touch O(log n) slots, and nothing but the array itself is stored.
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Add, Bound, RangeBounds, Sub};
+use core::slice;
+
+use crate::{Error, Result};
+
+/// A Fenwick tree over a fixed number of values of type `T`, all of which start out as `T::default()`, which must
+/// be zero for the sums to make sense. The number of values is however many fit in the buffer.
+pub struct Fenwick<'a, T> {
+    tree: &'a mut [T],
+}
+
+impl<'a, T> Fenwick<'a, T>
+where
+    T: Copy + Default + PartialOrd + Add<Output = T> + Sub<Output = T>,
+{
+    /// Creates a tree over as many values as fit in `slice`, all zero. The bytes of `slice` are overwritten. Panics if
+    /// `slice` is not aligned for `T`.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice))
+    }
+
+    /// Creates a tree like [Self::new], but returns `Error::BadBuffer` instead of panicking if `slice` is not aligned
+    /// for `T`.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        if !slice.as_ptr().cast::<T>().is_aligned() {
+            return Err(Error::BadBuffer);
+        }
+        Ok(Self::new(slice))
+    }
+
+    /// Creates a tree over as many values as fit in `slice`, whose bytes need not be initialized. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        assert!(
+            slice.as_ptr().cast::<T>().is_aligned(),
+            "Buffer is not aligned to the {} byte alignment of the values",
+            core::mem::align_of::<T>()
+        );
+        let len = slice.len() / size_of::<T>();
+        let tree =
+            unsafe { slice::from_raw_parts_mut(slice.as_mut_ptr().cast::<MaybeUninit<T>>(), len) };
+        for slot in tree.iter_mut() {
+            slot.write(T::default());
+        }
+        Self {
+            tree: unsafe { slice::from_raw_parts_mut(tree.as_mut_ptr().cast(), len) },
+        }
+    }
+
+    /// Creates a tree over `slice` holding `values` at the front, with any remaining values zero. Builds the partial
+    /// sums in O(n) rather than adding the values one by one. Returns `Error::OutOfSpace` if `slice` cannot hold
+    /// every value.
+    pub fn from_values(slice: &'a mut [u8], values: &[T]) -> Result<Self> {
+        let fenwick = Self::try_new(slice)?;
+        if values.len() > fenwick.len() {
+            return Err(Error::OutOfSpace);
+        }
+        fenwick.tree[..values.len()].copy_from_slice(values);
+        let len = fenwick.len();
+        for i in 1..=len {
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= len {
+                fenwick.tree[parent - 1] = fenwick.tree[parent - 1] + fenwick.tree[i - 1];
+            }
+        }
+        Ok(fenwick)
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets every value back to zero.
+    pub fn clear(&mut self) {
+        self.tree.fill(T::default());
+    }
+
+    /// Adds `delta` to the value at `index`. Panics if `index` is out of bounds.
+    pub fn add(&mut self, index: usize, delta: T) {
+        assert!(index < self.len(), "Index {} is out of bounds", index);
+        let mut i = index + 1;
+        while i <= self.len() {
+            self.tree[i - 1] = self.tree[i - 1] + delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Subtracts `delta` from the value at `index`, for types such as unsigned integers that cannot add a negative
+    /// delta. Panics if `index` is out of bounds.
+    pub fn sub(&mut self, index: usize, delta: T) {
+        assert!(index < self.len(), "Index {} is out of bounds", index);
+        let mut i = index + 1;
+        while i <= self.len() {
+            self.tree[i - 1] = self.tree[i - 1] - delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Replaces the value at `index` with `value`. Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) {
+        let old = self.get(index);
+        if value >= old {
+            self.add(index, value - old);
+        } else {
+            self.sub(index, old - value);
+        }
+    }
+
+    /// Returns the value at `index`. Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> T {
+        self.sum(index..=index)
+    }
+
+    /// Returns the sum of the values before `end`. Panics if `end` is greater than the number of values.
+    pub fn prefix_sum(&self, end: usize) -> T {
+        assert!(end <= self.len(), "End {} is out of bounds", end);
+        let mut sum = T::default();
+        let mut i = end;
+        while i > 0 {
+            sum = sum + self.tree[i - 1];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    /// Returns the sum of the values at the indices in `range`. Panics if the range is out of bounds.
+    pub fn sum<R: RangeBounds<usize>>(&self, range: R) -> T {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            start <= end,
+            "Range starts at {} but ends at {}",
+            start,
+            end
+        );
+        self.prefix_sum(end) - self.prefix_sum(start)
+    }
+
+    /// Returns the number of leading values whose sum is at most `target`, which is the index of the value that the
+    /// running total passes `target` in, or the number of values if it never does. With per-region page counts, this
+    /// finds the region that holds a given page. Requires every value to be non-negative.
+    pub fn upper_bound(&self, target: T) -> usize {
+        let mut position = 0;
+        let mut remaining = target;
+        let mut step = match self.len() {
+            0 => 0,
+            len => 1 << len.ilog2(),
+        };
+        while step > 0 {
+            if position + step <= self.len() && self.tree[position + step - 1] <= remaining {
+                position += step;
+                remaining = remaining - self.tree[position - 1];
+            }
+            step >>= 1;
+        }
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::Fenwick;
+    use crate::{AlignedBuffer, Error};
+    use core::mem::size_of;
+    use rand::Rng;
+
+    #[test]
+    fn test_against_array() {
+        let mut mem = AlignedBuffer([0; size_of::<[u64; 100]>()]);
+        let mut fenwick = Fenwick::<u64>::new(&mut mem);
+        assert_eq!(fenwick.len(), 100);
+        let mut values = [0u64; 100];
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..2000 {
+            let index = rng.gen_range(0..100);
+            match rng.gen_range(0..3) {
+                0 => {
+                    let delta = rng.gen_range(0..50);
+                    fenwick.add(index, delta);
+                    values[index] += delta;
+                }
+                1 => {
+                    let delta = rng.gen_range(0..=values[index]);
+                    fenwick.sub(index, delta);
+                    values[index] -= delta;
+                }
+                _ => {
+                    let value = rng.gen_range(0..50);
+                    fenwick.set(index, value);
+                    values[index] = value;
+                }
+            }
+            let start = rng.gen_range(0..=100);
+            let end = rng.gen_range(start..=100);
+            assert_eq!(fenwick.sum(start..end), values[start..end].iter().sum());
+            assert_eq!(fenwick.get(index), values[index]);
+        }
+        assert_eq!(fenwick.prefix_sum(100), values.iter().sum());
+
+        fenwick.clear();
+        assert_eq!(fenwick.sum(..), 0);
+    }
+
+    #[test]
+    fn test_from_values_and_upper_bound() {
+        // Page counts of five regions: pages 0..4 are in region 0, 4..4 in none, 4..10 in region 2, and so on.
+        let counts = [4u32, 0, 6, 1, 5];
+        let mut mem = AlignedBuffer([0; size_of::<[u32; 8]>()]);
+        let fenwick = Fenwick::from_values(&mut mem, &counts).unwrap();
+        assert_eq!(fenwick.len(), 8);
+        for (index, count) in counts.iter().enumerate() {
+            assert_eq!(fenwick.get(index), *count);
+        }
+        assert_eq!(fenwick.sum(..), 16);
+
+        assert_eq!(fenwick.upper_bound(0), 0);
+        assert_eq!(fenwick.upper_bound(3), 0);
+        assert_eq!(fenwick.upper_bound(4), 2);
+        assert_eq!(fenwick.upper_bound(9), 2);
+        assert_eq!(fenwick.upper_bound(10), 3);
+        assert_eq!(fenwick.upper_bound(11), 4);
+        assert_eq!(fenwick.upper_bound(15), 4);
+        assert_eq!(fenwick.upper_bound(16), 8);
+
+        let mut mem = AlignedBuffer([0; size_of::<[u32; 4]>()]);
+        assert!(matches!(
+            Fenwick::from_values(&mut mem, &counts),
+            Err(Error::OutOfSpace)
+        ));
+        assert!(matches!(
+            Fenwick::<u32>::try_new(&mut mem[1..1 + size_of::<u32>()]),
+            Err(Error::BadBuffer)
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+use core::{fmt::Debug, iter::Chain, mem, slice};
+
+use crate::sorted_slice::Error;
+use crate::OrderedKey;
+
+/// A sorted slice that keeps a movable gap of free slots inside the buffer.
+///
+/// Inserting or removing an element moves the gap to that position first, so only the elements between the old
+/// and new gap position are shifted. Clustered inserts near the previous insert are amortized O(1), where a plain
+/// [SortedSlice](crate::sorted_slice::SortedSlice) always shifts the whole tail.
+///
+/// Logical index `i` lives at physical index `i` when it is before the gap, and at `i + gap length` otherwise.
+pub struct GapSortedSlice<'a, T> {
+    slice: &'a mut [T],
+    gap_start: usize,
+    gap_end: usize,
+}
+
+impl<'a, T> GapSortedSlice<'a, T>
+where
+    T: Clone + Copy + OrderedKey + Sized,
+{
+    pub fn new(slice: &'a mut [u8]) -> GapSortedSlice<'a, T> {
+        let slice = unsafe {
+            slice::from_raw_parts_mut::<'a, T>(
+                slice as *mut [u8] as *mut T,
+                slice.len() / mem::size_of::<T>(),
+            )
+        };
+        Self {
+            gap_end: slice.len(),
+            slice,
+            gap_start: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.gap_start + (self.slice.len() - self.gap_end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slice.len()
+    }
+
+    /// Returns the number of elements that can still be added, which is the length of the gap.
+    pub fn remaining(&self) -> usize {
+        self.gap_len()
+    }
+
+    /// Returns the element at logical index `idx`.
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        if idx < self.gap_start {
+            Some(&self.slice[idx])
+        } else if idx < self.len() {
+            Some(&self.slice[idx + self.gap_len()])
+        } else {
+            None
+        }
+    }
+
+    pub fn add(&mut self, element: T) -> Result<usize, Error> {
+        if self.capacity() == self.len() {
+            return Err(Error::NotEnoughMemory);
+        }
+        let Err(idx) = self.search(element) else {
+            return Err(Error::ElementAlreadyInserted);
+        };
+
+        self.move_gap(idx);
+        self.slice[self.gap_start] = element;
+        self.gap_start += 1;
+        Ok(idx)
+    }
+
+    pub fn remove(&mut self, element: T) -> Result<usize, Error> {
+        let Ok(idx) = self.search(element) else {
+            return Err(Error::ElementNotFound);
+        };
+        self.remove_at_idx(idx);
+        Ok(idx)
+    }
+
+    pub fn remove_at_idx(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.len() {
+            return None;
+        }
+        self.move_gap(idx);
+        let item = self.slice[self.gap_end];
+        self.gap_end += 1;
+        Some(item)
+    }
+
+    pub fn search(&self, element: T) -> Result<usize, usize> {
+        self.search_idx_with_key(element.ordering_key())
+    }
+
+    pub fn search_with_key(&self, key: &T::Key) -> Option<&T> {
+        self.search_idx_with_key(key)
+            .ok()
+            .and_then(|idx| self.get(idx))
+    }
+
+    /// Binary searches both sides of the gap for `key`, returning the logical index of the match or of where it
+    /// would be inserted.
+    pub fn search_idx_with_key(&self, key: &T::Key) -> Result<usize, usize> {
+        let (before, after) = self.as_slices();
+        match before.binary_search_by_key(&key, |e| e.ordering_key()) {
+            Err(idx) if idx == before.len() => after
+                .binary_search_by_key(&key, |e| e.ordering_key())
+                .map(|idx| idx + before.len())
+                .map_err(|idx| idx + before.len()),
+            result => result,
+        }
+    }
+
+    /// Returns the elements before and after the gap. Both halves are sorted, and every element of the first half
+    /// is less than every element of the second.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        (&self.slice[..self.gap_start], &self.slice[self.gap_end..])
+    }
+
+    pub fn iter(&self) -> Chain<slice::Iter<'_, T>, slice::Iter<'_, T>> {
+        let (before, after) = self.as_slices();
+        before.iter().chain(after.iter())
+    }
+
+    fn gap_len(&self) -> usize {
+        self.gap_end - self.gap_start
+    }
+
+    /// Moves the gap so that it starts at logical index `idx`, shifting only the elements in between.
+    fn move_gap(&mut self, idx: usize) {
+        if idx < self.gap_start {
+            let count = self.gap_start - idx;
+            self.slice
+                .copy_within(idx..self.gap_start, self.gap_end - count);
+            self.gap_start -= count;
+            self.gap_end -= count;
+        } else if idx > self.gap_start {
+            let count = idx - self.gap_start;
+            self.slice
+                .copy_within(self.gap_end..self.gap_end + count, self.gap_start);
+            self.gap_start += count;
+            self.gap_end += count;
+        }
+    }
+}
+
+impl<T> core::fmt::Debug for GapSortedSlice<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GapSortedSlice")
+            .field("before_gap", &&self.slice[..self.gap_start])
+            .field("gap", &(self.gap_start..self.gap_end))
+            .field("after_gap", &&self.slice[self.gap_end..])
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::AlignedBuffer;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_add_and_remove_move_the_gap() {
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<usize>()]);
+        let mut gs = GapSortedSlice::<'_, usize>::new(&mut mem);
+        assert_eq!(10, gs.capacity());
+        assert!(gs.is_empty());
+
+        for e in [1, 4, 3, 2, 5, 8, 0, 6, 7] {
+            gs.add(e).unwrap();
+        }
+        assert_eq!(
+            (0..9).collect::<Vec<_>>(),
+            gs.iter().copied().collect::<Vec<_>>()
+        );
+        for i in 0..9 {
+            assert_eq!(Some(&i), gs.get(i));
+        }
+        assert_eq!(None, gs.get(9));
+
+        assert_eq!(Err(Error::ElementAlreadyInserted), gs.add(0));
+        assert_eq!(1, gs.remaining());
+        assert_eq!(Ok(9), gs.add(9));
+        assert_eq!(0, gs.remaining());
+        assert_eq!(Err(Error::NotEnoughMemory), gs.add(10));
+
+        assert_eq!(Ok(5), gs.remove(5));
+        assert_eq!(Err(Error::ElementNotFound), gs.remove(5));
+        assert_eq!(Some(0), gs.remove_at_idx(0));
+        assert_eq!(Ok(3), gs.search(4));
+        assert_eq!(Err(4), gs.search(5));
+        assert_eq!(Some(&9), gs.search_with_key(&9));
+        assert_eq!(
+            [1, 2, 3, 4, 6, 7, 8, 9],
+            gs.iter().copied().collect::<Vec<_>>()[..]
+        );
+    }
+
+    #[test]
+    fn fuzz_against_btree_set() {
+        const CAPACITY: usize = 512;
+        let mut mem = AlignedBuffer([0; CAPACITY * mem::size_of::<u32>()]);
+        let mut gs = GapSortedSlice::<'_, u32>::new(&mut mem);
+        let mut expected = BTreeSet::new();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..20_000 {
+            let value = rng.gen_range(0..1_000u32);
+            if rng.gen_bool(0.6) {
+                match gs.add(value) {
+                    Ok(_) => assert!(expected.insert(value)),
+                    Err(Error::ElementAlreadyInserted) => assert!(expected.contains(&value)),
+                    Err(Error::NotEnoughMemory) => assert_eq!(expected.len(), CAPACITY),
+                    Err(e) => panic!("unexpected error {:?}", e),
+                }
+            } else {
+                assert_eq!(gs.remove(value).is_ok(), expected.remove(&value));
+            }
+
+            assert_eq!(gs.len(), expected.len());
+            assert_eq!(
+                gs.search_with_key(&value).is_some(),
+                expected.contains(&value)
+            );
+        }
+        assert!(gs.iter().eq(expected.iter()));
+    }
+}
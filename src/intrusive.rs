@@ -0,0 +1,415 @@
+//! A red-black tree whose links live inside the elements it orders.
+//!
+//! The caller owns the elements, typically in a static array, and embeds a [TreeLinks] in each of them. The tree only
+//! ever rewrites those links: elements are never copied or moved, so they need not be `Copy`, and linking or
+//! unlinking one cannot run out of space.
+use core::cmp::Ordering;
+
+use crate::rb::{Forest, Links, SlotLinks, BLACK, NULL, RED};
+use crate::{Error, OrderedKey, Result};
+
+/// The links an element embeds to be part of an [IntrusiveRbt].
+pub struct TreeLinks {
+    links: Links,
+    linked: bool,
+}
+
+impl TreeLinks {
+    /// Returns the links of an element that is in no tree, for use in constant initializers.
+    pub const fn new() -> Self {
+        Self {
+            links: Links {
+                parent: NULL,
+                left: NULL,
+                right: NULL,
+                color: RED,
+            },
+            linked: false,
+        }
+    }
+
+    /// Returns true if the element is in a tree.
+    pub fn is_linked(&self) -> bool {
+        self.linked
+    }
+}
+
+impl Default for TreeLinks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gives an [IntrusiveRbt] access to the [TreeLinks] embedded in an element.
+pub trait Linked {
+    fn tree_links(&self) -> &TreeLinks;
+    fn tree_links_mut(&mut self) -> &mut TreeLinks;
+}
+
+/// A red-black tree over a caller-provided slice of elements, ordered by their [OrderedKey]. Elements are linked in
+/// and out by their position in the slice, and no two linked elements may have equal keys.
+pub struct IntrusiveRbt<'a, T>
+where
+    T: Linked + OrderedKey,
+{
+    elements: &'a mut [T],
+    /// The position of the head element, or [NULL] if the tree is empty.
+    head: u16,
+    length: usize,
+}
+
+impl<'a, T> IntrusiveRbt<'a, T>
+where
+    T: Linked + OrderedKey,
+{
+    /// Creates an empty tree over `elements`, resetting the links of every element. Panics if there are more
+    /// elements than can be addressed.
+    pub fn new(elements: &'a mut [T]) -> Self {
+        assert!(
+            elements.len() < NULL as usize,
+            "At most {} elements can be addressed",
+            NULL
+        );
+        for element in elements.iter_mut() {
+            *element.tree_links_mut() = TreeLinks::new();
+        }
+        Self {
+            elements,
+            head: NULL,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of linked elements.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements the tree was created over.
+    pub fn capacity(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Unlinks every element.
+    pub fn clear(&mut self) {
+        for element in self.elements.iter_mut() {
+            *element.tree_links_mut() = TreeLinks::new();
+        }
+        self.head = NULL;
+        self.length = 0;
+    }
+
+    /// Links the element at `index` into the tree. Returns `Error::AlreadyExists` if it is already linked or another
+    /// linked element has an equal key. Panics if `index` is out of bounds.
+    pub fn insert(&mut self, index: usize) -> Result<()> {
+        let key = self.elements[index].ordering_key();
+        if self.elements[index].tree_links().linked
+            || self.search_node(0, |other| key.cmp(other.ordering_key())) != NULL
+        {
+            return Err(Error::AlreadyExists);
+        }
+        self.link(0, index as u16);
+        self.elements[index].tree_links_mut().linked = true;
+        self.length += 1;
+        Ok(())
+    }
+
+    /// Unlinks the element at `index`. Returns `Error::NotFound` if it is not linked. Panics if `index` is out of
+    /// bounds.
+    pub fn unlink_at(&mut self, index: usize) -> Result<()> {
+        if !self.elements[index].tree_links().linked {
+            return Err(Error::NotFound);
+        }
+        self.unlink(0, index as u16);
+        *self.elements[index].tree_links_mut() = TreeLinks::new();
+        self.length -= 1;
+        Ok(())
+    }
+
+    /// Unlinks the element with key `key`, returning its position.
+    pub fn remove(&mut self, key: &T::Key) -> Option<usize> {
+        let index = self.search(key)?;
+        self.unlink_at(index).ok()?;
+        Some(index)
+    }
+
+    /// Returns the position of the linked element with key `key`.
+    pub fn search(&self, key: &T::Key) -> Option<usize> {
+        Self::position(self.search_node(0, |other| key.cmp(other.ordering_key())))
+    }
+
+    /// Returns the position of the largest linked element whose key is at most `key`.
+    pub fn floor(&self, key: &T::Key) -> Option<usize> {
+        Self::position(self.bound_node(0, |other| key.cmp(other.ordering_key()), false))
+    }
+
+    /// Returns the position of the smallest linked element whose key is at least `key`.
+    pub fn ceil(&self, key: &T::Key) -> Option<usize> {
+        Self::position(self.bound_node(0, |other| key.cmp(other.ordering_key()), true))
+    }
+
+    /// Returns the element at `index`, whether or not it is linked.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.elements.get(index)
+    }
+
+    /// Returns the element at `index` if it is not linked. A linked element is refused, since writing through the
+    /// reference could change its key or its [TreeLinks]; unlink it first.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.elements
+            .get_mut(index)
+            .filter(|element| !element.tree_links().linked)
+    }
+
+    /// Returns true if the element at `index` is linked.
+    pub fn is_linked(&self, index: usize) -> bool {
+        self.elements
+            .get(index)
+            .is_some_and(|element| element.tree_links().linked)
+    }
+
+    /// Returns an iterator over the positions and linked elements in ascending order of key.
+    pub fn iter(&self) -> Iter<'_, 'a, T> {
+        Iter {
+            tree: self,
+            next: self.min_node(0, self.head),
+        }
+    }
+
+    /// Ends the tree and gives back the elements. Their links are left as they are, so a new tree over them starts
+    /// empty.
+    pub fn into_inner(self) -> &'a mut [T] {
+        self.elements
+    }
+
+    /// Checks every red-black invariant, that the linked elements are in ascending order of key, and that the length
+    /// matches the number of linked elements, returning the black height, or a description of the first invariant
+    /// that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        if self.head != NULL {
+            if self.links(0, self.head).parent != NULL {
+                return Err("head has a parent");
+            }
+            if self.links(0, self.head).color != BLACK {
+                return Err("head is red");
+            }
+        }
+        let height = self.validate_node(0, self.head)?;
+        let mut count = 0;
+        let mut previous: Option<&T> = None;
+        for (_, element) in self.iter() {
+            if !element.tree_links().linked {
+                return Err("element in the tree is not marked as linked");
+            }
+            if previous.is_some_and(|previous| previous.ordering_key() >= element.ordering_key()) {
+                return Err("elements are not in ascending order");
+            }
+            previous = Some(element);
+            count += 1;
+            if count > self.len() {
+                return Err("tree holds more elements than the length");
+            }
+        }
+        let linked = self
+            .elements
+            .iter()
+            .filter(|element| element.tree_links().linked)
+            .count();
+        if count != self.len() || linked != self.len() {
+            return Err("length does not match the number of linked elements");
+        }
+        Ok(height)
+    }
+
+    fn position(node: u16) -> Option<usize> {
+        match node {
+            NULL => None,
+            node => Some(node as usize),
+        }
+    }
+}
+
+impl<T> SlotLinks for IntrusiveRbt<'_, T>
+where
+    T: Linked + OrderedKey,
+{
+    fn links(&self, _index: usize, node: u16) -> &Links {
+        &self.elements[node as usize].tree_links().links
+    }
+
+    fn links_mut(&mut self, _index: usize, node: u16) -> &mut Links {
+        &mut self.elements[node as usize].tree_links_mut().links
+    }
+}
+
+impl<T> Forest for IntrusiveRbt<'_, T>
+where
+    T: Linked + OrderedKey,
+{
+    type Element = T;
+
+    fn element(&self, node: u16) -> &T {
+        &self.elements[node as usize]
+    }
+
+    fn head(&self, _index: usize) -> u16 {
+        self.head
+    }
+
+    fn set_head(&mut self, _index: usize, node: u16) {
+        self.head = node;
+    }
+
+    fn compare(&self, _index: usize, a: u16, b: u16) -> Ordering {
+        self.element(a)
+            .ordering_key()
+            .cmp(self.element(b).ordering_key())
+    }
+}
+
+/// An iterator over the positions and linked elements of an [IntrusiveRbt] in ascending order of key.
+pub struct Iter<'b, 'a, T>
+where
+    T: Linked + OrderedKey,
+{
+    tree: &'b IntrusiveRbt<'a, T>,
+    next: u16,
+}
+
+impl<'b, T> Iterator for Iter<'b, '_, T>
+where
+    T: Linked + OrderedKey,
+{
+    type Item = (usize, &'b T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NULL {
+            return None;
+        }
+        let node = self.next;
+        self.next = self.tree.next_node(0, node);
+        Some((node as usize, self.tree.element(node)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntrusiveRbt, Linked, TreeLinks};
+    use crate::{Error, OrderedKey};
+    use rand::seq::SliceRandom;
+
+    /// A region descriptor that is not `Copy`, as firmware would keep in a static table.
+    struct Region {
+        base: u64,
+        name: [u8; 8],
+        links: TreeLinks,
+    }
+
+    impl Region {
+        const fn new() -> Self {
+            Self {
+                base: 0,
+                name: [0; 8],
+                links: TreeLinks::new(),
+            }
+        }
+    }
+
+    impl Linked for Region {
+        fn tree_links(&self) -> &TreeLinks {
+            &self.links
+        }
+
+        fn tree_links_mut(&mut self) -> &mut TreeLinks {
+            &mut self.links
+        }
+    }
+
+    impl OrderedKey for Region {
+        type Key = u64;
+        fn ordering_key(&self) -> &u64 {
+            &self.base
+        }
+    }
+
+    #[test]
+    fn test_link_and_unlink() {
+        let mut regions = [const { Region::new() }; 8];
+        for (index, region) in regions.iter_mut().enumerate() {
+            region.base = 0x1000 * (8 - index as u64);
+            region.name[0] = index as u8;
+        }
+        let mut tree = IntrusiveRbt::new(&mut regions);
+
+        for index in [3, 0, 5, 7] {
+            tree.insert(index).unwrap();
+        }
+        assert!(matches!(tree.insert(3), Err(Error::AlreadyExists)));
+        assert_eq!(tree.validate(), Ok(3));
+        assert!(tree.iter().map(|(index, _)| index).eq([7, 5, 3, 0]));
+        assert_eq!(tree.search(&0x5000), Some(3));
+        assert_eq!(tree.search(&0x6000), None);
+        assert_eq!(tree.floor(&0x6fff), Some(3));
+        assert_eq!(tree.ceil(&0x3001), Some(3));
+        assert_eq!(tree.ceil(&0x8001), None);
+
+        // A linked element cannot be borrowed mutably, since that would expose its key and links.
+        assert!(tree.get_mut(3).is_none());
+        assert!(tree.get_mut(1).is_some());
+
+        assert_eq!(tree.remove(&0x5000), Some(3));
+        assert!(!tree.is_linked(3));
+        assert!(matches!(tree.unlink_at(3), Err(Error::NotFound)));
+        tree.unlink_at(7).unwrap();
+        assert!(tree.iter().map(|(index, _)| index).eq([5, 0]));
+
+        // An unlinked element can be rekeyed and linked again.
+        tree.get_mut(7).unwrap().base = 0x8800;
+        tree.insert(7).unwrap();
+        assert!(tree.iter().map(|(index, _)| index).eq([5, 0, 7]));
+        assert_eq!(tree.validate(), Ok(2));
+
+        let regions = tree.into_inner();
+        let mut tree = IntrusiveRbt::new(regions);
+        assert!(tree.is_empty());
+        tree.insert(7).unwrap();
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_random_order() {
+        let mut regions: [Region; 500] = core::array::from_fn(|index| Region {
+            base: index as u64 * 0x1000,
+            name: [0; 8],
+            links: TreeLinks::new(),
+        });
+        let mut order: [usize; 500] = core::array::from_fn(|index| index);
+        let mut rng = rand::thread_rng();
+        let mut tree = IntrusiveRbt::new(&mut regions);
+
+        order.shuffle(&mut rng);
+        for index in order {
+            tree.insert(index).unwrap();
+        }
+        tree.validate().unwrap();
+        assert!(tree.iter().map(|(index, _)| index).eq(0..500));
+
+        order.shuffle(&mut rng);
+        for (removed, index) in order.iter().enumerate() {
+            assert_eq!(tree.remove(&(*index as u64 * 0x1000)), Some(*index));
+            if removed % 50 == 0 {
+                tree.validate().unwrap();
+            }
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.validate(), Ok(1));
+        tree.insert(42).unwrap();
+        tree.clear();
+        assert!(!tree.is_linked(42));
+        assert_eq!(tree.validate(), Ok(1));
+    }
+}
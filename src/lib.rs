@@ -1,20 +1,247 @@
 #![no_std]
 #![feature(let_chains)]
 #![feature(is_sorted)]
+pub mod alloc_map;
+pub mod bplus_tree;
 pub mod bst;
+pub mod bst_map;
+pub mod btree;
+pub mod fenwick;
+pub mod gap_sorted_slice;
+pub mod intrusive;
+pub mod multi_rbt;
+pub mod pool;
+pub mod radix;
+pub mod range_map;
+pub mod range_set;
 pub mod rbt;
+pub mod rbt_map;
+pub mod segtree;
+pub mod skiplist;
 pub mod sorted_slice;
+pub mod treap;
+
+mod map;
+mod rb;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Views an initialized byte buffer as possibly uninitialized bytes, for the constructors that accept either.
+pub(crate) fn as_uninit(slice: &mut [u8]) -> &mut [core::mem::MaybeUninit<u8>] {
+    // SAFETY: `MaybeUninit<u8>` has the layout of `u8`, and every initialized byte is a valid `MaybeUninit<u8>`.
+    unsafe { &mut *(slice as *mut [u8] as *mut [core::mem::MaybeUninit<u8>]) }
+}
+
+/// A byte buffer aligned for every node type in the crate, for tests that lend a container a stack array.
+#[cfg(test)]
+#[repr(C, align(16))]
+pub(crate) struct AlignedBuffer<const N: usize>(pub(crate) [u8; N]);
+
+#[cfg(test)]
+impl<const N: usize> core::ops::Deref for AlignedBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> core::ops::DerefMut for AlignedBuffer<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+/// Random sources, drop counting and the differential test against `BTreeSet` that the container tests share.
+#[cfg(test)]
+pub(crate) mod testing {
+    extern crate std;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use rand::Rng;
+    use std::collections::BTreeSet;
+    use std::vec::Vec;
+
+    /// A xorshift generator, the kind of random source a firmware build would pass in.
+    pub(crate) fn xorshift(mut state: u32) -> impl FnMut() -> u32 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        }
+    }
+
+    /// An element keyed by its `u32` that counts its drops. Each test passes its own counter, so tests running at the
+    /// same time do not see each other's drops.
+    #[derive(Debug)]
+    pub(crate) struct Tracked(pub(crate) u32, pub(crate) &'static AtomicUsize);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl crate::OrderedKey for Tracked {
+        type Key = u32;
+        fn ordering_key(&self) -> &u32 {
+            &self.0
+        }
+    }
+
+    /// Calls `step` 4000 times with a random key below `keys`, and whether to insert it (six times in ten) or remove
+    /// it.
+    pub(crate) fn random_ops(keys: u32, mut step: impl FnMut(u32, bool)) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..4000 {
+            let key = rng.gen_range(0..keys);
+            step(key, rng.gen_bool(0.6));
+        }
+    }
+
+    /// A set of `u32` that [check_against_set] can hold up against a `BTreeSet`. Implemented with [impl_test_set] for
+    /// containers whose own methods have these names.
+    pub(crate) trait TestSet {
+        fn insert(&mut self, value: u32) -> crate::Result<()>;
+        fn remove(&mut self, value: &u32) -> Option<u32>;
+        fn delete(&mut self, value: u32) -> crate::Result<()>;
+        fn search(&self, value: &u32) -> Option<u32>;
+        fn len(&self) -> usize;
+        fn values(&self) -> impl Iterator<Item = u32> + '_;
+        fn validate(&self) -> core::result::Result<usize, &'static str>;
+    }
+
+    macro_rules! impl_test_set {
+        ([$($generics:tt)*] $type:ty) => {
+            impl<$($generics)*> $crate::testing::TestSet for $type {
+                fn insert(&mut self, value: u32) -> $crate::Result<()> {
+                    <$type>::insert(self, value)
+                }
+
+                fn remove(&mut self, value: &u32) -> Option<u32> {
+                    <$type>::remove(self, value)
+                }
+
+                fn delete(&mut self, value: u32) -> $crate::Result<()> {
+                    <$type>::delete(self, value)
+                }
+
+                fn search(&self, value: &u32) -> Option<u32> {
+                    <$type>::search(self, value)
+                }
+
+                fn len(&self) -> usize {
+                    <$type>::len(self)
+                }
+
+                fn values(&self) -> impl Iterator<Item = u32> + '_ {
+                    <$type>::iter(self).copied()
+                }
+
+                fn validate(&self) -> core::result::Result<usize, &'static str> {
+                    <$type>::validate(self)
+                }
+            }
+        };
+    }
+    pub(crate) use impl_test_set;
+
+    /// Runs `set` and a `BTreeSet` through the same random inserts and removes of keys below 500, validating `set`
+    /// after every step, and checks that both end up with the same keys. Then empties both the same way.
+    pub(crate) fn check_against_set(set: &mut impl TestSet) {
+        let mut expected = BTreeSet::new();
+        random_ops(500, |value, insert| {
+            if insert {
+                assert_eq!(set.insert(value).is_ok(), expected.insert(value));
+            } else {
+                assert_eq!(set.remove(&value), expected.take(&value));
+            }
+            set.validate().unwrap();
+        });
+        assert_eq!(set.len(), expected.len());
+        assert!(set.values().eq(expected.iter().copied()));
+        for value in 0..500 {
+            assert_eq!(set.search(&value), expected.get(&value).copied());
+        }
+
+        while let Some(value) = expected.pop_first() {
+            set.delete(value).unwrap();
+            set.validate().unwrap();
+        }
+        assert_eq!(set.len(), 0);
+        assert!(matches!(set.delete(1), Err(crate::Error::NotFound)));
+    }
+
+    /// Inserts 0, 1, 2, ... into `set` until it runs out of space, and returns the values that went in.
+    pub(crate) fn fill(set: &mut impl TestSet) -> Vec<u32> {
+        let mut inserted = Vec::new();
+        for value in 0.. {
+            match set.insert(value) {
+                Ok(()) => inserted.push(value),
+                Err(crate::Error::OutOfSpace) => break,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        inserted
+    }
+
+    /// Fills `set` and checks that a key it already holds is reported as such, even though a new one would need
+    /// space that is not there.
+    pub(crate) fn check_duplicate_when_full(set: &mut impl TestSet) {
+        let inserted = fill(set);
+        for value in &inserted {
+            assert!(matches!(
+                set.insert(*value),
+                Err(crate::Error::AlreadyExists)
+            ));
+        }
+        assert_eq!(set.len(), inserted.len());
+        set.validate().unwrap();
+    }
+
+    /// Fills `set` and checks that the insert that failed left it valid, and that removing every element makes room
+    /// for all of them again.
+    pub(crate) fn check_out_of_space(set: &mut impl TestSet) {
+        let inserted = fill(set);
+        set.validate().unwrap();
+        assert!(set.values().eq(inserted.iter().copied()));
+
+        for value in &inserted {
+            set.remove(value).unwrap();
+        }
+        for value in &inserted {
+            set.insert(*value).unwrap();
+        }
+        set.validate().unwrap();
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     OutOfSpace,
     NotFound,
     AlreadyExists,
+    ValidationFailed,
+    BadBuffer,
 }
 
-pub trait SortedSliceKey {
+/// Gives access to the key a container orders its elements by. Types that are `Ord` themselves are their own key;
+/// anything else orders by a field, such as the start address of a memory range.
+pub trait OrderedKey {
     type Key: Ord;
     fn ordering_key(&self) -> &Self::Key;
 }
+
+impl<T> OrderedKey for T
+where
+    T: Ord,
+{
+    type Key = Self;
+    fn ordering_key(&self) -> &T {
+        self
+    }
+}
+
+/// Alias of [OrderedKey], kept so existing implementations continue to compile.
+pub use OrderedKey as SortedSliceKey;
@@ -0,0 +1,99 @@
+//! The entries and methods that the key-value maps over the trees share, such as [RbtMap](crate::rbt_map::RbtMap)
+//! and [BstMap](crate::bst_map::BstMap), and that [RangeSet](crate::range_set::RangeSet) and
+//! [RangeMap](crate::range_map::RangeMap) store their ranges as.
+use crate::OrderedKey;
+
+/// A key and its value as stored in a tree. Ordering and equality only look at the key.
+#[derive(Debug)]
+pub(crate) struct KeyValue<K, V> {
+    pub(crate) key: K,
+    pub(crate) value: V,
+}
+
+impl<K: Ord, V> PartialEq for KeyValue<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> PartialOrd for KeyValue<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.key.cmp(&other.key))
+    }
+}
+
+impl<K: Ord, V> OrderedKey for KeyValue<K, V> {
+    type Key = K;
+    fn ordering_key(&self) -> &K {
+        &self.key
+    }
+}
+
+/// Implements the map methods for `$map`, a struct with a single `tree` field holding a `$tree` of [KeyValue]
+/// entries. Every tree the maps are built on has the methods used here.
+macro_rules! impl_map {
+    ($map:ident, $tree:ident) => {
+        impl<'a, K, V, const SIZE: usize> $map<'a, K, V, { SIZE }>
+        where
+            K: Ord + core::fmt::Debug,
+            V: core::fmt::Debug,
+        {
+            /// Creates an empty map backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<K, V>()`
+            /// bytes or is not aligned for the entries.
+            pub fn new(slice: &'a mut [u8]) -> Self {
+                Self {
+                    tree: $tree::new(slice),
+                }
+            }
+
+            /// Returns the number of entries in the map.
+            pub fn len(&self) -> usize {
+                self.tree.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.tree.is_empty()
+            }
+
+            /// Removes every entry, keeping the storage.
+            pub fn clear(&mut self) {
+                self.tree.clear();
+            }
+
+            /// Inserts `value` under `key`. Returns the value previously stored under `key`, or None if the key is
+            /// new. Returns `Error::OutOfSpace` if the key is new and the map is full.
+            pub fn insert(&mut self, key: K, value: V) -> $crate::Result<Option<V>> {
+                let replaced = self
+                    .tree
+                    .insert_or_replace($crate::map::KeyValue { key, value })?;
+                Ok(replaced.map(|entry| entry.value))
+            }
+
+            /// Returns a reference to the value stored under `key`.
+            pub fn get(&self, key: &K) -> Option<&V> {
+                self.tree.search_ref(key).map(|entry| &entry.value)
+            }
+
+            /// Returns a mutable reference to the value stored under `key`.
+            pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+                self.tree.get_mut(key).map(|entry| &mut entry.value)
+            }
+
+            /// Returns true if a value is stored under `key`.
+            pub fn contains_key(&self, key: &K) -> bool {
+                self.tree.contains(key)
+            }
+
+            /// Removes the entry stored under `key` and returns its value.
+            pub fn remove(&mut self, key: &K) -> Option<V> {
+                self.tree.remove(key).map(|entry| entry.value)
+            }
+
+            /// Returns an iterator over the entries in ascending order of their keys.
+            pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+                self.tree.iter().map(|entry| (&entry.key, &entry.value))
+            }
+        }
+    };
+}
+pub(crate) use impl_map;
@@ -0,0 +1,444 @@
+//! Red-black trees over a caller-provided buffer that share one pool of nodes, each ordering the same elements its
+//! own way.
+//!
+//! Every node holds its element once, along with one set of links per index, and all of the indices draw their nodes
+//! from the same free list. An allocator can keep its free blocks ordered by address and by size without storing each
+//! block twice, and without the two copies ever disagreeing.
+use core::cmp::Ordering;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::rb::{Forest, Links, SlotLinks, BLACK, NULL, RED};
+use crate::{Error, Result};
+
+/// Returns the number of bytes a single node of a [MultiRbt] with `N` indices occupies in its storage.
+pub const fn node_size<D, const N: usize>() -> usize {
+    size_of::<Node<D, N>>()
+}
+
+struct Node<D, const N: usize> {
+    data: D,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    links: [Links; N],
+}
+
+impl<D, const N: usize> Node<D, N> {
+    /// Returns a node holding `data` that is in none of the indices yet, to be written to slot `index`.
+    fn new(data: D, index: u16) -> Self {
+        let links = Links {
+            parent: NULL,
+            left: NULL,
+            right: NULL,
+            color: RED,
+        };
+        Node {
+            data,
+            index,
+            links: [links; N],
+        }
+    }
+}
+
+/// Free slots are chained through the parent link of their first index.
+impl<D, const N: usize> Slot for Node<D, N> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).links[0].parent).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).links[0].parent).write(index) };
+    }
+}
+
+/// `N` red-black trees over the same elements of type `D`, holding up to `SIZE` of them, or as many as fit in its
+/// buffer if `SIZE` is 0.
+///
+/// Index `i` orders the elements by the `i`th function passed to [MultiRbt::new], and no two elements may be equal
+/// under any of them. Elements are found through one index by a probe that compares the sought key against an
+/// element, so each index can be searched by its own key type; a removal through one index unlinks the element from
+/// all of them.
+pub struct MultiRbt<'a, D, const N: usize, const SIZE: usize> {
+    storage: Storage<'a, Node<D, N>, SIZE>,
+    /// The slot of the head node of each index, or [NULL] if the tree is empty.
+    heads: [u16; N],
+    orders: [fn(&D, &D) -> Ordering; N],
+}
+
+/// A [MultiRbt] that holds as many nodes as fit in the buffer it is created with.
+pub type MultiRbtDyn<'a, D, const N: usize> = MultiRbt<'a, D, N, 0>;
+
+impl<'a, D, const N: usize, const SIZE: usize> MultiRbt<'a, D, N, SIZE> {
+    /// Creates an empty tree backed by `slice`, with index `i` ordered by `orders[i]`. Panics if `slice` is shorter
+    /// than `SIZE * node_size::<D, N>()` bytes or is not aligned for the nodes, or if `N` is 0.
+    pub fn new(slice: &'a mut [u8], orders: [fn(&D, &D) -> Ordering; N]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice), orders)
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8], orders: [fn(&D, &D) -> Ordering; N]) -> Result<Self> {
+        Storage::<Node<D, N>, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice, orders))
+    }
+
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(
+        slice: &'a mut [MaybeUninit<u8>],
+        orders: [fn(&D, &D) -> Ordering; N],
+    ) -> Self {
+        assert!(N > 0, "A tree needs at least one index");
+        Self {
+            storage: Storage::new(slice),
+            heads: [NULL; N],
+            orders,
+        }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of elements the tree can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Returns the number of elements that can still be inserted before the tree runs out of space.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Removes every element from the tree. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.heads = [NULL; N];
+    }
+
+    /// Inserts `data` into every index. Returns `Error::AlreadyExists` if an element equal to it under any of the
+    /// orderings is in the tree, and `Error::OutOfSpace` if the tree is full.
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        for index in 0..N {
+            let order = self.orders[index];
+            if self.search_node(index, |other| order(&data, other)) != NULL {
+                return Err(Error::AlreadyExists);
+            }
+        }
+        let node = self.storage.add(|index| Node::new(data, index))?.index;
+        for index in 0..N {
+            self.link(index, node);
+        }
+        Ok(())
+    }
+
+    /// Returns the element that `probe` finds in index `index`. The probe compares the sought key against an
+    /// element, the way `key.cmp(element_key)` would. Panics if `index` is not less than `N`.
+    pub fn search_by<F: Fn(&D) -> Ordering>(&self, index: usize, probe: F) -> Option<&D> {
+        self.data(self.search_node(index, probe))
+    }
+
+    /// Returns the largest element in index `index` that is at most the key of `probe`.
+    pub fn floor_by<F: Fn(&D) -> Ordering>(&self, index: usize, probe: F) -> Option<&D> {
+        self.data(self.bound_node(index, probe, false))
+    }
+
+    /// Returns the smallest element in index `index` that is at least the key of `probe`.
+    pub fn ceil_by<F: Fn(&D) -> Ordering>(&self, index: usize, probe: F) -> Option<&D> {
+        self.data(self.bound_node(index, probe, true))
+    }
+
+    /// Returns the smallest element in index `index`.
+    pub fn min_by(&self, index: usize) -> Option<&D> {
+        self.data(self.min_node(index, self.heads[index]))
+    }
+
+    /// Returns the largest element in index `index`.
+    pub fn max_by(&self, index: usize) -> Option<&D> {
+        let mut node = self.heads[index];
+        while node != NULL && self.links(index, node).right != NULL {
+            node = self.links(index, node).right;
+        }
+        self.data(node)
+    }
+
+    /// Removes the element that `probe` finds in index `index` from every index, returning it.
+    pub fn remove_by<F: Fn(&D) -> Ordering>(&mut self, index: usize, probe: F) -> Option<D> {
+        let node = self.search_node(index, probe);
+        if node == NULL {
+            return None;
+        }
+        for index in 0..N {
+            self.unlink(index, node);
+        }
+        Some(self.storage.delete(node).data)
+    }
+
+    /// Returns an iterator over the elements in the order of index `index`.
+    pub fn iter(&self, index: usize) -> Iter<'_, 'a, D, N, SIZE> {
+        Iter {
+            tree: self,
+            index,
+            next: self.min_node(index, self.heads[index]),
+        }
+    }
+
+    /// Returns an iterator over the elements in the order of index `index`, starting at the smallest that is at least
+    /// the key of `probe`.
+    pub fn iter_from<F: Fn(&D) -> Ordering>(
+        &self,
+        index: usize,
+        probe: F,
+    ) -> Iter<'_, 'a, D, N, SIZE> {
+        Iter {
+            tree: self,
+            index,
+            next: self.bound_node(index, probe, true),
+        }
+    }
+
+    /// Checks every red-black invariant of every index, and that each index holds every element once in order,
+    /// returning the largest black height, or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        let mut height = 0;
+        for index in 0..N {
+            let head = self.heads[index];
+            if head != NULL {
+                if self.links(index, head).parent != NULL {
+                    return Err("head has a parent");
+                }
+                if self.links(index, head).color != BLACK {
+                    return Err("head is red");
+                }
+            }
+            height = height.max(self.validate_node(index, head)?);
+            let mut count = 0;
+            let mut previous: Option<&D> = None;
+            for data in self.iter(index) {
+                if previous.is_some_and(|previous| (self.orders[index])(previous, data).is_ge()) {
+                    return Err("elements are not in ascending order");
+                }
+                previous = Some(data);
+                count += 1;
+                if count > self.len() {
+                    return Err("index holds more nodes than the length");
+                }
+            }
+            if count != self.len() {
+                return Err("length does not match the number of nodes in an index");
+            }
+        }
+        Ok(height)
+    }
+
+    fn data(&self, node: u16) -> Option<&D> {
+        match node {
+            NULL => None,
+            node => Some(&self.storage.node(node).data),
+        }
+    }
+}
+
+impl<D, const N: usize, const SIZE: usize> SlotLinks for MultiRbt<'_, D, N, SIZE> {
+    fn links(&self, index: usize, node: u16) -> &Links {
+        &self.storage.node(node).links[index]
+    }
+
+    fn links_mut(&mut self, index: usize, node: u16) -> &mut Links {
+        &mut self.storage.node_mut(node).links[index]
+    }
+}
+
+impl<D, const N: usize, const SIZE: usize> Forest for MultiRbt<'_, D, N, SIZE> {
+    type Element = D;
+
+    fn element(&self, node: u16) -> &D {
+        &self.storage.node(node).data
+    }
+
+    fn head(&self, index: usize) -> u16 {
+        self.heads[index]
+    }
+
+    fn set_head(&mut self, index: usize, node: u16) {
+        self.heads[index] = node;
+    }
+
+    fn compare(&self, index: usize, a: u16, b: u16) -> Ordering {
+        (self.orders[index])(self.element(a), self.element(b))
+    }
+}
+
+/// An iterator over the elements of a [MultiRbt] in the order of one of its indices.
+pub struct Iter<'b, 'a, D, const N: usize, const SIZE: usize> {
+    tree: &'b MultiRbt<'a, D, N, SIZE>,
+    index: usize,
+    next: u16,
+}
+
+impl<'b, D, const N: usize, const SIZE: usize> Iterator for Iter<'b, '_, D, N, SIZE> {
+    type Item = &'b D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NULL {
+            return None;
+        }
+        let node = self.next;
+        self.next = self.tree.next_node(self.index, node);
+        Some(&self.tree.storage.node(node).data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, MultiRbt, MultiRbtDyn};
+    use crate::testing::{self, Tracked};
+    use crate::Error;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Block {
+        base: u32,
+        size: u32,
+    }
+
+    const BY_BASE: usize = 0;
+    const BY_SIZE: usize = 1;
+    const ORDERS: [fn(&Block, &Block) -> core::cmp::Ordering; 2] = [
+        |a, b| a.base.cmp(&b.base),
+        |a, b| (a.size, a.base).cmp(&(b.size, b.base)),
+    ];
+
+    #[test]
+    fn test_two_indices() {
+        let mut mem = [0u64; 500 * node_size::<Block, 2>() / 8];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        let mut tree = MultiRbt::<Block, 2, 500>::new(bytes, ORDERS);
+        let mut by_base = BTreeSet::new();
+        let mut by_size = BTreeSet::new();
+        let mut rng = rand::thread_rng();
+
+        testing::random_ops(600, |base, insert| {
+            if insert {
+                let size = rng.gen_range(1..50);
+                let inserted = !by_base.contains(&base);
+                match tree.insert(Block { base, size }) {
+                    Ok(()) => {
+                        assert!(inserted);
+                        by_base.insert(base);
+                        by_size.insert((size, base));
+                    }
+                    Err(Error::AlreadyExists) => assert!(!inserted),
+                    Err(Error::OutOfSpace) => assert_eq!(by_base.len(), 500),
+                    Err(error) => panic!("{:?}", error),
+                }
+            } else {
+                let removed = tree.remove_by(BY_BASE, |block| base.cmp(&block.base));
+                assert_eq!(removed.is_some(), by_base.remove(&base));
+                if let Some(block) = removed {
+                    assert!(by_size.remove(&(block.size, block.base)));
+                }
+            }
+            tree.validate().unwrap();
+        });
+
+        assert_eq!(tree.len(), by_base.len());
+        assert!(tree
+            .iter(BY_BASE)
+            .map(|block| block.base)
+            .eq(by_base.iter().copied()));
+        assert!(tree
+            .iter(BY_SIZE)
+            .map(|block| (block.size, block.base))
+            .eq(by_size.iter().copied()));
+
+        // The first block of at least 20 bytes, found by size and removed through the other index.
+        let fit = *tree
+            .ceil_by(BY_SIZE, |block| (20, 0).cmp(&(block.size, block.base)))
+            .unwrap();
+        assert_eq!(
+            Some((fit.size, fit.base)),
+            by_size.range((20, 0)..).next().copied()
+        );
+        assert_eq!(
+            tree.remove_by(BY_BASE, |block| fit.base.cmp(&block.base)),
+            Some(fit)
+        );
+        assert!(tree
+            .search_by(BY_SIZE, |block| (fit.size, fit.base)
+                .cmp(&(block.size, block.base)))
+            .is_none());
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut mem = [0u64; 8 * node_size::<Block, 2>() / 8];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        let mut tree = MultiRbtDyn::<Block, 2>::new(bytes, ORDERS);
+        assert_eq!(tree.capacity(), 8);
+        for (base, size) in [(10, 5), (20, 1), (30, 5), (40, 3)] {
+            tree.insert(Block { base, size }).unwrap();
+        }
+        assert!(matches!(
+            tree.insert(Block { base: 10, size: 2 }),
+            Err(Error::AlreadyExists)
+        ));
+        assert!(matches!(tree.insert(Block { base: 50, size: 5 }), Ok(())));
+        assert!(matches!(
+            tree.insert(Block { base: 50, size: 6 }),
+            Err(Error::AlreadyExists)
+        ));
+
+        let base = |block: Option<&Block>| block.map(|block| block.base);
+        assert_eq!(base(tree.floor_by(BY_BASE, |b| 25.cmp(&b.base))), Some(20));
+        assert_eq!(base(tree.ceil_by(BY_BASE, |b| 25.cmp(&b.base))), Some(30));
+        assert_eq!(base(tree.floor_by(BY_BASE, |b| 5.cmp(&b.base))), None);
+        assert_eq!(base(tree.ceil_by(BY_BASE, |b| 55.cmp(&b.base))), None);
+        assert_eq!(base(tree.min_by(BY_SIZE)), Some(20));
+        assert_eq!(base(tree.max_by(BY_SIZE)), Some(50));
+        assert!(tree
+            .iter_from(BY_SIZE, |b| (4, 0).cmp(&(b.size, b.base)))
+            .map(|block| block.base)
+            .eq([10, 30, 50]));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.remaining(), 8);
+        assert_eq!(tree.validate(), Ok(1));
+    }
+
+    #[test]
+    fn test_drop_elements() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut mem = [0u64; 10 * node_size::<Tracked, 1>() / 8 + 1];
+        let (_, bytes, _) = unsafe { mem.align_to_mut::<u8>() };
+        {
+            let mut tree = MultiRbtDyn::<Tracked, 1>::new(bytes, [|a, b| a.0.cmp(&b.0)]);
+            for value in 0..5 {
+                tree.insert(Tracked(value, &DROPS)).unwrap();
+            }
+            drop(tree.remove_by(0, |tracked| 2.cmp(&tracked.0)));
+            assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        }
+        assert_eq!(DROPS.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,821 @@
+//! The node pools that [Rbt](crate::rbt::Rbt) and [Bst](crate::bst::Bst) draw their nodes from.
+//!
+//! A tree only ever asks its pool for a fresh slot, gives a slot back, and asks how many slots are in use. Nodes link
+//! to each other by slot index and find a slot from a pointer to slot 0, so whatever a pool is backed by, its slots
+//! must form one array that stays put while nodes live in it. [Storage] is the pool the trees use unless told otherwise: a
+//! caller-provided buffer whose free slots are chained through the buffer itself. The other trees in the crate keep
+//! their nodes in a [Storage] too, and look them up in it by slot index. [SharedPool] spreads one budget of
+//! slots over several trees, and with the `alloc` feature, [HeapPool] allocates the slots on the heap.
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+#[cfg(feature = "alloc")]
+use core::mem::ManuallyDrop;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+#[cfg(feature = "alloc")]
+use core::slice;
+
+use crate::{Error, Result};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
+
+/// Hands out the node slots of a tree.
+///
+/// # Safety
+///
+/// The slots must be the elements of a single array of `N`, so that slot `i` lives `i * size_of::<N>()` bytes past
+/// slot 0, and the array must not move while any node is in it. [Self::add] must return a slot that holds no live
+/// node, whose index is below `u16::MAX`, and pass that index to the constructor. A live node must stay untouched
+/// until it is given back through [Self::delete] or [Self::clear], and the pool must drop the nodes still live when
+/// it is dropped itself.
+pub unsafe trait NodePool<N> {
+    /// Writes the node that `new` builds for the index of a free slot into that slot and returns it. Returns
+    /// `Error::OutOfSpace` if no slot is free.
+    fn add<F: FnOnce(u16) -> N>(&mut self, new: F) -> Result<&mut N>;
+
+    /// Moves the node out of slot `index` and frees the slot. The slot must hold a live node.
+    fn delete(&mut self, index: u16) -> N;
+
+    /// Returns the number of live nodes.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of slots.
+    fn capacity(&self) -> usize;
+
+    /// Drops every live node, making all slots available again.
+    fn clear(&mut self);
+
+    /// Returns a pointer to slot 0, from which every other slot is reached. A pointer to a single node only grants
+    /// access to that node, so nodes keep this one to find their neighbours.
+    fn base(&self) -> *mut N;
+}
+
+/// A node that [Storage] can keep track of from the bytes of its slot alone. Implemented by the nodes of the trees
+/// in this crate. A free slot holds only its index and parent link, so both are read and written through raw
+/// pointers rather than through a reference to a whole node.
+pub trait Slot {
+    /// Returns the index of the slot the node in `slot` was written to, or `u16::MAX` if the slot is free.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for reads, and its index must have been written.
+    unsafe fn slot_index(slot: *const Self) -> u16;
+
+    /// Writes the slot index of the node in `slot`, whose other bytes may be uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for writes.
+    unsafe fn write_slot_index(slot: *mut Self, index: u16);
+
+    /// Returns the parent link of `slot`, which free slots reuse to chain the free list. Nodes without a parent link
+    /// give up another link that a free slot has no use for.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for reads, and its parent link must have been written.
+    unsafe fn parent_link(slot: *const Self) -> u16;
+
+    /// Writes the parent link of `slot`, whose other bytes may be uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for writes.
+    unsafe fn set_parent_link(slot: *mut Self, index: u16);
+}
+
+/// A pool over a caller-provided buffer that holds up to `SIZE` nodes, or as many as fit if `SIZE` is 0.
+pub struct Storage<'a, N, const SIZE: usize>
+where
+    N: Slot,
+{
+    /// Slot 0 of the buffer. Every slot is reached through this pointer, so the nodes it hands out stay valid while
+    /// other slots are used, and no reference is made to a slot that holds no node.
+    base: *mut N,
+    capacity: usize,
+    pub(crate) length: usize,
+    /// The most recently freed slot. Free slots are chained through their parent link, so the free list lives in
+    /// the buffer itself and holds however many slots the buffer has.
+    free: u16,
+    buffer: PhantomData<&'a mut [MaybeUninit<N>]>,
+}
+
+// SAFETY: The storage owns its nodes through the buffer it borrows mutably, like a `&mut [N]` would.
+unsafe impl<N, const SIZE: usize> Send for Storage<'_, N, SIZE> where N: Slot + Send {}
+unsafe impl<N, const SIZE: usize> Sync for Storage<'_, N, SIZE> where N: Slot + Sync {}
+
+impl<'a, N, const SIZE: usize> Storage<'a, N, { SIZE }>
+where
+    N: Slot,
+{
+    /// Create a new storage container. The bytes of `slice` may be uninitialized: only the index of each slot is
+    /// written here, marking it free, and `add` writes a whole node before a slot is used.
+    ///
+    /// Panics if `slice` is shorter than `SIZE * size_of::<N>()` bytes or is not aligned for the nodes.
+    pub fn new(slice: &'a mut [MaybeUninit<u8>]) -> Storage<'a, N, SIZE> {
+        let mut storage = Self::wrap(slice);
+        for index in 0..storage.capacity() {
+            unsafe { N::write_slot_index(storage.slot(index), NULL) };
+            storage.push_free(index);
+        }
+        storage
+    }
+
+    /// Returns the number of slots for a buffer of `len` bytes: `SIZE`, or as many nodes as fit if `SIZE` is 0.
+    pub(crate) fn capacity_for(len: usize) -> usize {
+        match SIZE {
+            0 => (len / size_of::<N>()).min(NULL as usize - 1),
+            _ => SIZE,
+        }
+    }
+
+    /// Returns `Error::BadBuffer` if `slice` is too short to hold `SIZE` nodes, `SIZE` is more than a slot index can
+    /// address, or the slice is not aligned for the nodes.
+    pub(crate) fn check(slice: &[MaybeUninit<u8>]) -> Result<()> {
+        let capacity = Self::capacity_for(slice.len());
+        if capacity >= NULL as usize
+            || slice.len() < capacity * size_of::<N>()
+            || !slice.as_ptr().cast::<N>().is_aligned()
+        {
+            return Err(Error::BadBuffer);
+        }
+        Ok(())
+    }
+
+    /// Checks and casts `slice` into node slots, leaving their bytes as they are. The free list starts out empty.
+    fn wrap(slice: &'a mut [MaybeUninit<u8>]) -> Storage<'a, N, SIZE> {
+        let capacity = Self::capacity_for(slice.len());
+        assert!(
+            slice.len() >= capacity * size_of::<N>(),
+            "Buffer of {} bytes is too small to hold {} nodes of {} bytes",
+            slice.len(),
+            capacity,
+            size_of::<N>()
+        );
+        assert!(
+            capacity < NULL as usize,
+            "At most {} nodes can be addressed",
+            NULL
+        );
+        assert!(
+            slice.as_ptr().cast::<N>().is_aligned(),
+            "Buffer is not aligned to the {} byte alignment of the nodes",
+            core::mem::align_of::<N>()
+        );
+        Storage {
+            base: slice.as_mut_ptr().cast(),
+            capacity,
+            length: 0,
+            free: NULL,
+            buffer: PhantomData,
+        }
+    }
+
+    /// Wrap a buffer that already holds the nodes of a tree, rebuilding the length and free list from the index each
+    /// slot holds. Returns the storage along with the node that has no parent, which is the head of the tree.
+    pub(crate) fn restore(slice: &'a mut [u8]) -> (Storage<'a, N, SIZE>, *mut N) {
+        let mut storage = Self::wrap(crate::as_uninit(slice));
+        let mut head = ptr::null_mut();
+        for index in 0..storage.capacity() {
+            if !storage.is_occupied(index) {
+                storage.push_free(index);
+                continue;
+            }
+            storage.length += 1;
+            if unsafe { N::parent_link(storage.slot(index)) } == NULL {
+                head = storage.slot(index);
+            }
+        }
+        (storage, head)
+    }
+
+    /// Returns a pointer to slot `index`, which may or may not hold a node. Panics if there is no such slot.
+    pub(crate) fn slot(&self, index: usize) -> *mut N {
+        assert!(index < self.capacity, "Slot {} is out of bounds", index);
+        unsafe { self.base.add(index) }
+    }
+
+    /// Returns the node in slot `index`. Panics if the slot holds no node.
+    pub(crate) fn node(&self, index: u16) -> &N {
+        assert!(self.is_occupied(index as usize), "Slot {} is free", index);
+        unsafe { &*self.slot(index as usize) }
+    }
+
+    /// Returns the node in slot `index`. Panics if the slot holds no node.
+    pub(crate) fn node_mut(&mut self, index: u16) -> &mut N {
+        assert!(self.is_occupied(index as usize), "Slot {} is free", index);
+        unsafe { &mut *self.slot(index as usize) }
+    }
+
+    /// Returns two distinct nodes at once.
+    pub(crate) fn pair(&mut self, a: u16, b: u16) -> (&mut N, &mut N) {
+        assert_ne!(a, b);
+        let a = self.node_mut(a) as *mut N;
+        let b = self.node_mut(b) as *mut N;
+        // SAFETY: The slots are distinct, so the two nodes do not overlap.
+        unsafe { (&mut *a, &mut *b) }
+    }
+
+    /// Returns true if slot `index` holds a live node. A free slot holds [NULL] as its index, so this is known from
+    /// the bytes of the slot alone.
+    pub fn is_occupied(&self, index: usize) -> bool {
+        unsafe { N::slot_index(self.slot(index)) == index as u16 }
+    }
+
+    /// Returns the live nodes in slot order.
+    pub fn occupied(&self) -> impl Iterator<Item = &N> {
+        (0..self.capacity)
+            .filter(|index| self.is_occupied(*index))
+            .map(|index| unsafe { &*self.slot(index) })
+    }
+
+    /// Puts slot `index` on top of the free list.
+    pub(crate) fn push_free(&mut self, index: usize) {
+        unsafe { N::set_parent_link(self.slot(index), self.free) };
+        self.free = index as u16;
+    }
+
+    /// Takes the most recently freed slot off the free list.
+    pub(crate) fn pop_free(&mut self) -> Option<u16> {
+        let index = self.free;
+        if index == NULL {
+            return None;
+        }
+        self.free = unsafe { N::parent_link(self.slot(index as usize)) };
+        Some(index)
+    }
+
+    /// Returns the slots on the free list, most recently freed first. A corrupted list is cut off after more entries
+    /// than there are slots.
+    pub(crate) fn free_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut next = self.free;
+        core::iter::from_fn(move || {
+            let index = (next != NULL).then_some(next as usize)?;
+            next = match index < self.capacity {
+                true => unsafe { N::parent_link(self.slot(index)) },
+                false => NULL,
+            };
+            Some(index)
+        })
+        .take(self.capacity() + 1)
+    }
+}
+
+unsafe impl<N, const SIZE: usize> NodePool<N> for Storage<'_, N, { SIZE }>
+where
+    N: Slot,
+{
+    fn add<F: FnOnce(u16) -> N>(&mut self, new: F) -> Result<&mut N> {
+        let index = self.pop_free().ok_or(Error::OutOfSpace)?;
+        let slot = self.slot(index as usize);
+        // The slot may hold stale or zeroed bytes, so write over it without dropping the old contents.
+        unsafe { slot.write(new(index)) };
+        self.length += 1;
+        Ok(unsafe { &mut *slot })
+    }
+
+    fn delete(&mut self, index: u16) -> N {
+        assert!(self.is_occupied(index as usize), "Slot {} is free", index);
+        let index = index as usize;
+        // Mark the slot free before reading the node out, so the node is never read or dropped through it again.
+        let node = unsafe { self.slot(index).read() };
+        unsafe { N::write_slot_index(self.slot(index), NULL) };
+        self.length -= 1;
+        self.push_free(index);
+        node
+    }
+
+    fn len(&self) -> usize {
+        self.length
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn base(&self) -> *mut N {
+        self.base
+    }
+
+    fn clear(&mut self) {
+        self.free = NULL;
+        for index in 0..self.capacity() {
+            if self.is_occupied(index) {
+                unsafe { ptr::drop_in_place(self.slot(index)) };
+            }
+            unsafe { N::write_slot_index(self.slot(index), NULL) };
+            self.push_free(index);
+        }
+        self.length = 0;
+    }
+}
+
+impl<N, const SIZE: usize> Drop for Storage<'_, N, { SIZE }>
+where
+    N: Slot,
+{
+    /// Drops the node in every occupied slot. Nodes moved out by `delete` are already marked free.
+    fn drop(&mut self) {
+        if !core::mem::needs_drop::<N>() {
+            return;
+        }
+        for index in 0..self.capacity() {
+            if self.is_occupied(index) {
+                unsafe { ptr::drop_in_place(self.slot(index)) };
+            }
+        }
+    }
+}
+
+/// A fixed budget of `SIZE` node slots that several trees draw from, so that they share one capacity instead of each
+/// being sized for its worst case. Each tree takes a [PoolHandle] from [Self::handle] as its [NodePool]. The pool
+/// records which handle owns each slot, so clearing or dropping a tree frees only its own nodes.
+///
+/// Every handle borrows the pool, so the pool outlives and stays in place under the trees that use it.
+pub struct SharedPool<N, const SIZE: usize>
+where
+    N: Slot,
+{
+    slots: [UnsafeCell<MaybeUninit<N>>; SIZE],
+    /// The handle that owns each slot, or [NULL] if the slot is free.
+    owners: [Cell<u16>; SIZE],
+    /// The most recently freed slot. Freed slots are chained through their parent link.
+    free: Cell<u16>,
+    /// The first slot that has never been handed out. It and every slot after it are free but not on the free list,
+    /// so the pool can be created in a constant.
+    unused: Cell<u16>,
+    length: Cell<usize>,
+    handles: Cell<u16>,
+}
+
+impl<N, const SIZE: usize> SharedPool<N, SIZE>
+where
+    N: Slot,
+{
+    pub const fn new() -> Self {
+        assert!(SIZE < NULL as usize, "Too many nodes to address");
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; SIZE],
+            owners: [const { Cell::new(NULL) }; SIZE],
+            free: Cell::new(NULL),
+            unused: Cell::new(0),
+            length: Cell::new(0),
+            handles: Cell::new(0),
+        }
+    }
+
+    /// Returns a new, empty share of the pool for a tree to draw its nodes from. Panics if the pool has already
+    /// handed out `u16::MAX - 1` handles.
+    pub fn handle(&self) -> PoolHandle<'_, N, SIZE> {
+        let id = self.handles.get();
+        assert!(id < NULL, "At most {} handles can be handed out", NULL);
+        self.handles.set(id + 1);
+        PoolHandle {
+            pool: self,
+            id,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of live nodes across every tree drawing from the pool.
+    pub fn len(&self) -> usize {
+        self.length.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// Returns the number of nodes that can still be added, by any of the trees, before the pool runs out of space.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    fn slot(&self, index: u16) -> *mut N {
+        assert!((index as usize) < SIZE, "Slot {} is out of bounds", index);
+        unsafe { self.base().add(index as usize) }
+    }
+
+    /// Returns a pointer to slot 0. The slots sit in an `UnsafeCell`, so the pointer may be written through.
+    fn base(&self) -> *mut N {
+        UnsafeCell::raw_get(self.slots.as_ptr()).cast()
+    }
+
+    /// Takes a free slot, preferring the most recently freed one. The slot stays unowned until [Self::own].
+    fn take(&self) -> Option<u16> {
+        let index = self.free.get();
+        if index != NULL {
+            self.free.set(unsafe { N::parent_link(self.slot(index)) });
+            return Some(index);
+        }
+        let index = self.unused.get();
+        if index as usize == SIZE {
+            return None;
+        }
+        self.unused.set(index + 1);
+        Some(index)
+    }
+
+    fn own(&self, index: u16, owner: u16) {
+        self.owners[index as usize].set(owner);
+        self.length.set(self.length.get() + 1);
+    }
+
+    /// Puts slot `index`, whose node has been moved out or dropped, on top of the free list.
+    fn release(&self, index: u16) {
+        self.owners[index as usize].set(NULL);
+        self.length.set(self.length.get() - 1);
+        // The node was live, so its bytes are initialized even though it no longer owns anything.
+        unsafe { N::set_parent_link(self.slot(index), self.free.get()) };
+        self.free.set(index);
+    }
+}
+
+impl<N, const SIZE: usize> Default for SharedPool<N, SIZE>
+where
+    N: Slot,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The share of a [SharedPool] that one tree draws its nodes from.
+pub struct PoolHandle<'p, N, const SIZE: usize>
+where
+    N: Slot,
+{
+    pool: &'p SharedPool<N, SIZE>,
+    /// Tags the slots this handle owns.
+    id: u16,
+    length: usize,
+}
+
+unsafe impl<N, const SIZE: usize> NodePool<N> for PoolHandle<'_, N, SIZE>
+where
+    N: Slot,
+{
+    fn add<F: FnOnce(u16) -> N>(&mut self, new: F) -> Result<&mut N> {
+        let index = self.pool.take().ok_or(Error::OutOfSpace)?;
+        let slot = self.pool.slot(index);
+        // The slot is only owned once it holds a node, so a panicking `new` leaves nothing for `clear` to drop.
+        unsafe { slot.write(new(index)) };
+        self.pool.own(index, self.id);
+        self.length += 1;
+        Ok(unsafe { &mut *slot })
+    }
+
+    fn delete(&mut self, index: u16) -> N {
+        debug_assert_eq!(self.pool.owners[index as usize].get(), self.id);
+        let node = unsafe { self.pool.slot(index).read() };
+        self.pool.release(index);
+        self.length -= 1;
+        node
+    }
+
+    fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Returns the nodes of this handle plus the slots still free in the pool, which is as many nodes as the tree
+    /// could hold if no other tree added any.
+    fn capacity(&self) -> usize {
+        self.length + self.pool.remaining()
+    }
+
+    fn clear(&mut self) {
+        for index in 0..self.pool.unused.get() {
+            if self.pool.owners[index as usize].get() == self.id {
+                unsafe { ptr::drop_in_place(self.pool.slot(index)) };
+                self.pool.release(index);
+            }
+        }
+        self.length = 0;
+    }
+
+    fn base(&self) -> *mut N {
+        self.pool.base()
+    }
+}
+
+impl<N, const SIZE: usize> Drop for PoolHandle<'_, N, SIZE>
+where
+    N: Slot,
+{
+    /// Drops the nodes of this handle and gives their slots back to the pool.
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// A pool that owns a heap buffer of a fixed number of slots, for host-side tools and tests that have no buffer of
+/// their own to lend a tree. The slots never move once allocated, so the tree that owns the pool can move freely.
+#[cfg(feature = "alloc")]
+pub struct HeapPool<N>
+where
+    N: Slot + 'static,
+{
+    /// Borrows the buffer for as long as the pool lives, and is dropped before the buffer is freed.
+    storage: ManuallyDrop<Storage<'static, N, 0>>,
+    buffer: *mut [MaybeUninit<N>],
+}
+
+#[cfg(feature = "alloc")]
+impl<N> HeapPool<N>
+where
+    N: Slot + 'static,
+{
+    /// Allocates a pool of `capacity` slots. Panics if more slots are asked for than can be addressed.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity < NULL as usize,
+            "At most {} nodes can be addressed",
+            NULL
+        );
+        let buffer = Box::into_raw(Box::<[N]>::new_uninit_slice(capacity));
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(buffer.cast::<MaybeUninit<u8>>(), capacity * size_of::<N>())
+        };
+        Self {
+            storage: ManuallyDrop::new(Storage::new(bytes)),
+            buffer,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<N> NodePool<N> for HeapPool<N>
+where
+    N: Slot + 'static,
+{
+    fn add<F: FnOnce(u16) -> N>(&mut self, new: F) -> Result<&mut N> {
+        self.storage.add(new)
+    }
+
+    fn delete(&mut self, index: u16) -> N {
+        self.storage.delete(index)
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    fn clear(&mut self) {
+        self.storage.clear();
+    }
+
+    fn base(&self) -> *mut N {
+        self.storage.base()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<N> Drop for HeapPool<N>
+where
+    N: Slot + 'static,
+{
+    /// Drops the live nodes, then frees the buffer.
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.storage);
+            drop(Box::from_raw(self.buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodePool;
+    use crate::{bst::Bst, rbt, rbt::Rbt, Error, Result};
+    use core::marker::PhantomData;
+    use core::mem::MaybeUninit;
+    use core::ptr;
+
+    /// A pool that hands out each slot of a borrowed array once, in order, and never reuses a freed one.
+    struct Bump<'a, N> {
+        /// Slot 0 of the array. Slots are only reached through it, so the nodes handed out stay valid.
+        slots: *mut N,
+        capacity: usize,
+        /// Bit `i` is set while slot `i` holds a live node.
+        live: u64,
+        next: usize,
+        array: PhantomData<&'a mut [MaybeUninit<N>]>,
+    }
+
+    impl<'a, N> Bump<'a, N> {
+        fn new(slots: &'a mut [MaybeUninit<N>]) -> Self {
+            assert!(slots.len() <= 64);
+            Self {
+                slots: slots.as_mut_ptr().cast(),
+                capacity: slots.len(),
+                live: 0,
+                next: 0,
+                array: PhantomData,
+            }
+        }
+    }
+
+    unsafe impl<N> NodePool<N> for Bump<'_, N> {
+        fn add<F: FnOnce(u16) -> N>(&mut self, new: F) -> Result<&mut N> {
+            let index = self.next;
+            if index == self.capacity {
+                return Err(Error::OutOfSpace);
+            }
+            self.next += 1;
+            self.live |= 1 << index;
+            let slot = unsafe { self.slots.add(index) };
+            unsafe { slot.write(new(index as u16)) };
+            Ok(unsafe { &mut *slot })
+        }
+
+        fn delete(&mut self, index: u16) -> N {
+            self.live &= !(1 << index);
+            unsafe { self.slots.add(index as usize).read() }
+        }
+
+        fn len(&self) -> usize {
+            self.live.count_ones() as usize
+        }
+
+        fn capacity(&self) -> usize {
+            self.capacity
+        }
+
+        fn clear(&mut self) {
+            for index in 0..self.next {
+                if self.live & (1 << index) != 0 {
+                    unsafe { ptr::drop_in_place(self.slots.add(index)) };
+                }
+            }
+            self.live = 0;
+            self.next = 0;
+        }
+
+        fn base(&self) -> *mut N {
+            self.slots
+        }
+    }
+
+    impl<N> Drop for Bump<'_, N> {
+        fn drop(&mut self) {
+            self.clear();
+        }
+    }
+
+    #[test]
+    fn test_custom_pool() {
+        let mut slots: [MaybeUninit<rbt::Node<u32>>; 16] = [const { MaybeUninit::uninit() }; 16];
+        let mut tree = Rbt::<u32, 0, _>::with_pool(Bump::new(&mut slots));
+        for value in [8, 3, 12, 1, 5, 10, 14, 4] {
+            tree.insert(value).unwrap();
+        }
+        tree.validate().unwrap();
+        assert_eq!(tree.remove(&3), Some(3));
+        assert_eq!(tree.remove(&12), Some(12));
+        assert!(tree.iter().copied().eq([1, 4, 5, 8, 10, 14]));
+
+        // Freed slots are never handed out again, so the pool runs out after 16 inserts in all.
+        for value in 20..28 {
+            tree.insert(value).unwrap();
+        }
+        assert!(matches!(tree.insert(30), Err(Error::OutOfSpace)));
+        assert_eq!(tree.len(), 14);
+        tree.validate().unwrap();
+
+        tree.clear();
+        assert!(tree.is_empty());
+        tree.insert(7).unwrap();
+        assert_eq!(tree.max(), Some(7));
+    }
+
+    #[test]
+    fn test_custom_pool_bst() {
+        let mut slots: [MaybeUninit<crate::bst::Node<u32>>; 8] =
+            [const { MaybeUninit::uninit() }; 8];
+        let mut tree = Bst::<u32, 0, _>::with_pool(Bump::new(&mut slots));
+        for value in [5, 2, 8, 1, 9] {
+            tree.insert(value).unwrap();
+        }
+        assert_eq!(tree.remove(&5), Some(5));
+        assert!(tree.iter().copied().eq([1, 2, 8, 9]));
+        assert_eq!(tree.floor(&7), Some(2));
+        assert_eq!(tree.capacity(), 8);
+    }
+
+    #[test]
+    fn test_shared_pool() {
+        let pool = rbt::SharedPool::<u32, 8>::new();
+        let mut a: rbt::SharedRbt<u32, 8> = Rbt::with_pool(pool.handle());
+        let mut b: rbt::SharedRbt<u32, 8> = Rbt::with_pool(pool.handle());
+
+        for value in 0..5 {
+            a.insert(value).unwrap();
+        }
+        for value in 10..13 {
+            b.insert(value).unwrap();
+        }
+        assert_eq!(pool.len(), 8);
+        assert!(matches!(a.insert(5), Err(Error::OutOfSpace)));
+        assert!(matches!(b.insert(13), Err(Error::OutOfSpace)));
+        assert_eq!(a.remaining(), 0);
+
+        // A node freed by one tree can be used by the other.
+        assert_eq!(a.remove(&0), Some(0));
+        assert_eq!(b.remaining(), 1);
+        b.insert(13).unwrap();
+        assert_eq!((a.len(), b.len()), (4, 4));
+
+        // Clearing a tree frees only its own nodes.
+        a.clear();
+        assert_eq!(pool.len(), 4);
+        assert!(b.iter().copied().eq(10..14));
+        for value in 20..24 {
+            a.insert(value).unwrap();
+        }
+        a.validate().unwrap();
+        b.validate().unwrap();
+
+        drop(b);
+        assert_eq!(pool.len(), 4);
+        let mut c: rbt::SharedRbt<u32, 8> = Rbt::with_pool(pool.handle());
+        for value in 30..34 {
+            c.insert(value).unwrap();
+        }
+        assert!(a.iter().copied().eq(20..24));
+        assert!(c.iter().copied().eq(30..34));
+    }
+
+    #[test]
+    fn test_shared_pool_drops_elements() {
+        extern crate std;
+        use std::rc::Rc;
+
+        #[derive(PartialEq, PartialOrd, Debug)]
+        struct Tracked(u32, Rc<()>);
+
+        impl crate::OrderedKey for Tracked {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.0
+            }
+        }
+
+        let counter = Rc::new(());
+        let pool = crate::bst::SharedPool::<Tracked, 6>::new();
+        let mut a: crate::bst::SharedBst<Tracked, 6> = Bst::with_pool(pool.handle());
+        let mut b: crate::bst::SharedBst<Tracked, 6> = Bst::with_pool(pool.handle());
+        for value in 0..3 {
+            a.insert(Tracked(value, counter.clone())).unwrap();
+            b.insert(Tracked(value, counter.clone())).unwrap();
+        }
+        assert_eq!(Rc::strong_count(&counter), 7);
+        drop(a.remove(&1));
+        assert_eq!(Rc::strong_count(&counter), 6);
+        b.clear();
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(a);
+        assert_eq!(Rc::strong_count(&counter), 1);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_heap_pool() {
+        extern crate std;
+
+        let mut tree = Rbt::with_capacity(100);
+        for value in (0..100u32).rev() {
+            tree.insert(value).unwrap();
+        }
+        assert!(matches!(tree.insert(100), Err(Error::OutOfSpace)));
+        tree.validate().unwrap();
+
+        // The nodes stay in the heap buffer, so the tree can be moved while it holds them.
+        let boxed = std::boxed::Box::new(tree);
+        assert!(boxed.iter().copied().eq(0..100));
+
+        let mut tree: crate::bst::HeapBst<u32> = Bst::with_capacity(4);
+        tree.insert(2).unwrap();
+        tree.insert(1).unwrap();
+        assert_eq!(tree.remove(&2), Some(2));
+        assert_eq!(tree.remaining(), 3);
+    }
+}
@@ -0,0 +1,704 @@
+//! A compressed binary trie over a caller-provided buffer, keyed on fixed-width unsigned integers.
+//!
+//! Every node stands for a prefix of a key: its leading `len` bits. The child on either side extends that prefix by
+//! at least the one bit that picks the side, and runs of bits shared by everything below a node are skipped over
+//! rather than spelled out one node per bit. A lookup therefore follows at most one node per bit where stored keys
+//! differ, compares no keys against each other, and its cost depends on the key width rather than the element count.
+//! Entries can be whole keys or shorter prefixes, which makes the tree a natural fit for longest-prefix matching of
+//! addresses against regions.
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::{Error, Result};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
+
+/// Returns the number of bytes a single node of a [RadixTree] occupies in its storage. A tree holding `n` entries
+/// needs at most `2n - 1` nodes, since every node without a value of its own joins two others.
+pub const fn node_size<K, V>() -> usize {
+    size_of::<Node<K, V>>()
+}
+
+/// A fixed-width unsigned integer that a [RadixTree] can walk bit by bit, starting at the most significant bit.
+pub trait RadixKey: Copy + Eq {
+    const BITS: u32;
+    /// Returns bit `index`, counting from the most significant bit.
+    fn bit(self, index: u32) -> bool;
+    /// Keeps the `len` most significant bits and clears the rest.
+    fn prefix(self, len: u32) -> Self;
+    /// Returns the number of leading bits that `self` and `other` have in common.
+    fn common_prefix(self, other: Self) -> u32;
+}
+
+macro_rules! impl_radix_key {
+    ($($ty:ty),*) => {
+        $(
+            impl RadixKey for $ty {
+                const BITS: u32 = <$ty>::BITS;
+
+                fn bit(self, index: u32) -> bool {
+                    (self >> (Self::BITS - 1 - index)) & 1 == 1
+                }
+
+                fn prefix(self, len: u32) -> Self {
+                    match len {
+                        0 => 0,
+                        _ => self & (<$ty>::MAX << (Self::BITS - len)),
+                    }
+                }
+
+                fn common_prefix(self, other: Self) -> u32 {
+                    (self ^ other).leading_zeros()
+                }
+            }
+        )*
+    };
+}
+
+impl_radix_key!(u8, u16, u32, u64, u128, usize);
+
+struct Node<K, V> {
+    /// The prefix this node stands for, with every bit past `len` cleared.
+    key: K,
+    len: u8,
+    has_value: bool,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    parent: u16,
+    /// The children whose next bit is 0 and 1.
+    children: [u16; 2],
+    value: MaybeUninit<V>,
+}
+
+impl<K, V> Node<K, V> {
+    /// Returns an unlinked node for the first `len` bits of `key`, to be written to slot `index`.
+    fn new(key: K, len: u32, value: Option<V>, index: u16) -> Self {
+        Node {
+            key,
+            len: len as u8,
+            has_value: value.is_some(),
+            index,
+            parent: NULL,
+            children: [NULL, NULL],
+            value: match value {
+                Some(value) => MaybeUninit::new(value),
+                None => MaybeUninit::uninit(),
+            },
+        }
+    }
+
+    /// Moves the value out of the node, which must have one.
+    fn take_value(&mut self) -> V {
+        self.has_value = false;
+        // The flag is cleared, so the value is never read or dropped through the node again.
+        unsafe { self.value.assume_init_read() }
+    }
+}
+
+impl<K, V> Slot for Node<K, V> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).parent).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).parent).write(index) };
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        if self.has_value {
+            unsafe { self.value.assume_init_drop() };
+        }
+    }
+}
+
+/// A map from prefixes of keys of type `K` to values of type `V`, backed by up to `SIZE` nodes, or as many as fit
+/// in its buffer if `SIZE` is 0.
+///
+/// An entry is stored under the first `len` bits of a key, where `len` runs from 0 to `K::BITS`; [RadixTree::insert]
+/// and the other methods without `prefix` in their name use the whole key. Entries under different lengths of the
+/// same bits are distinct, so a tree can hold a region and the addresses inside it at once.
+pub struct RadixTree<'a, K, V, const SIZE: usize>
+where
+    K: RadixKey,
+{
+    storage: Storage<'a, Node<K, V>, SIZE>,
+    /// The slot of the head node, or [NULL] if the tree is empty.
+    head: u16,
+    /// The number of nodes that hold a value.
+    length: usize,
+}
+
+/// A [RadixTree] that holds as many nodes as fit in the buffer it is created with.
+pub type RadixTreeDyn<'a, K, V> = RadixTree<'a, K, V, 0>;
+
+impl<'a, K, V, const SIZE: usize> RadixTree<'a, K, V, SIZE>
+where
+    K: RadixKey,
+{
+    /// Creates an empty tree backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<K, V>()` bytes
+    /// or is not aligned for the nodes.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice))
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Storage::<Node<K, V>, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice))
+    }
+
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self {
+            storage: Storage::new(slice),
+            head: NULL,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of nodes the tree can hold. Entries that share no prefix with another take one node each,
+    /// and each place two entries diverge may take one more.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Removes every entry from the tree. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.head = NULL;
+        self.length = 0;
+    }
+
+    /// Writes a new unlinked node for the first `len` bits of `key` into a free slot and returns its index.
+    fn add(&mut self, key: K, len: u32, value: Option<V>) -> Result<u16> {
+        Ok(self
+            .storage
+            .add(|index| Node::new(key, len, value, index))?
+            .index)
+    }
+
+    /// Inserts `value` under the whole of `key`. Returns the value previously stored under it, or None if the key is
+    /// new. Returns `Error::OutOfSpace` if the key is new and no node is free for it.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        self.insert_prefix(key, K::BITS, value)
+    }
+
+    /// Inserts `value` under the first `len` bits of `key`, ignoring the rest. Returns the value previously stored
+    /// under that prefix, or None if it is new. Returns `Error::OutOfSpace` if the prefix is new and there are not
+    /// enough free nodes to hold it, in which case the tree is left unchanged. Panics if `len` is greater than
+    /// `K::BITS`.
+    pub fn insert_prefix(&mut self, key: K, len: u32, value: V) -> Result<Option<V>> {
+        assert!(len <= K::BITS, "Prefix length {} is out of bounds", len);
+        let key = key.prefix(len);
+        let mut parent = NULL;
+        let mut index = self.head;
+        while index != NULL {
+            let node = self.storage.node(index);
+            let node_len = node.len as u32;
+            let common = key.common_prefix(node.key).min(len).min(node_len);
+            if common < node_len {
+                // The node goes past the new prefix or branches off it, so it moves below a node for the shared bits.
+                return self
+                    .split(parent, index, common, key, len, value)
+                    .map(|_| None);
+            }
+            if node_len == len {
+                if node.has_value {
+                    let old = core::mem::replace(
+                        unsafe { self.storage.node_mut(index).value.assume_init_mut() },
+                        value,
+                    );
+                    return Ok(Some(old));
+                }
+                let node = self.storage.node_mut(index);
+                node.value.write(value);
+                node.has_value = true;
+                self.length += 1;
+                return Ok(None);
+            }
+            parent = index;
+            index = node.children[key.bit(node_len) as usize];
+        }
+
+        let index = self.add(key, len, Some(value))?;
+        self.attach(parent, index);
+        self.length += 1;
+        Ok(None)
+    }
+
+    /// Places the entry for the first `len` bits of `key` above node `index`, whose first `common` bits are the
+    /// most it shares with the entry. If the entry covers just those bits it becomes the parent of `index`;
+    /// otherwise a node without a value for the shared bits takes the place of `index` with both below it.
+    fn split(
+        &mut self,
+        parent: u16,
+        index: u16,
+        common: u32,
+        key: K,
+        len: u32,
+        value: V,
+    ) -> Result<()> {
+        let needed = if common == len { 1 } else { 2 };
+        if self.storage.capacity() - self.storage.len() < needed {
+            return Err(Error::OutOfSpace);
+        }
+        let top = if common == len {
+            self.add(key, len, Some(value))?
+        } else {
+            let branch = self.add(key.prefix(common), common, None)?;
+            let leaf = self.add(key, len, Some(value))?;
+            self.attach(branch, leaf);
+            branch
+        };
+        self.attach(top, index);
+        self.replace_child(parent, index, top);
+        self.length += 1;
+        Ok(())
+    }
+
+    /// Links node `child` below `parent` on the side picked by its next bit, or as the head if `parent` is [NULL].
+    fn attach(&mut self, parent: u16, child: u16) {
+        self.storage.node_mut(child).parent = parent;
+        if parent == NULL {
+            self.head = child;
+            return;
+        }
+        let side = self
+            .storage
+            .node(child)
+            .key
+            .bit(self.storage.node(parent).len as u32);
+        self.storage.node_mut(parent).children[side as usize] = child;
+    }
+
+    /// Points the link of `parent` that leads to `old` at `new` instead, or the head if `parent` is [NULL].
+    fn replace_child(&mut self, parent: u16, old: u16, new: u16) {
+        if new != NULL {
+            self.storage.node_mut(new).parent = parent;
+        }
+        if parent == NULL {
+            self.head = new;
+            return;
+        }
+        let children = &mut self.storage.node_mut(parent).children;
+        let side = (children[1] == old) as usize;
+        children[side] = new;
+    }
+
+    /// Returns the node for exactly the first `len` bits of `key`, whether or not it holds a value.
+    fn search_node(&self, key: K, len: u32) -> Option<u16> {
+        let mut index = self.head;
+        while index != NULL {
+            let node = self.storage.node(index);
+            let node_len = node.len as u32;
+            if node_len > len || key.prefix(node_len) != node.key {
+                return None;
+            }
+            if node_len == len {
+                return Some(index);
+            }
+            index = node.children[key.bit(node_len) as usize];
+        }
+        None
+    }
+
+    /// Returns a reference to the value stored under the whole of `key`.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_prefix(key, K::BITS)
+    }
+
+    /// Returns a mutable reference to the value stored under the whole of `key`.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_prefix_mut(key, K::BITS)
+    }
+
+    /// Returns a reference to the value stored under the first `len` bits of `key`. Panics if `len` is greater than
+    /// `K::BITS`.
+    pub fn get_prefix(&self, key: &K, len: u32) -> Option<&V> {
+        assert!(len <= K::BITS, "Prefix length {} is out of bounds", len);
+        let node = self.storage.node(self.search_node(*key, len)?);
+        match node.has_value {
+            true => Some(unsafe { node.value.assume_init_ref() }),
+            false => None,
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under the first `len` bits of `key`. Panics if `len` is
+    /// greater than `K::BITS`.
+    pub fn get_prefix_mut(&mut self, key: &K, len: u32) -> Option<&mut V> {
+        assert!(len <= K::BITS, "Prefix length {} is out of bounds", len);
+        let node = self.storage.node_mut(self.search_node(*key, len)?);
+        match node.has_value {
+            true => Some(unsafe { node.value.assume_init_mut() }),
+            false => None,
+        }
+    }
+
+    /// Returns true if a value is stored under the whole of `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the entry with the longest prefix that `key` starts with, as the prefix, its length and its value.
+    /// With regions stored under their base address and the number of fixed address bits, this finds the smallest
+    /// region holding an address.
+    pub fn longest_prefix_match(&self, key: &K) -> Option<(K, u32, &V)> {
+        let mut found = None;
+        let mut index = self.head;
+        while index != NULL {
+            let node = self.storage.node(index);
+            let node_len = node.len as u32;
+            if key.prefix(node_len) != node.key {
+                break;
+            }
+            if node.has_value {
+                found = Some(index);
+            }
+            if node_len == K::BITS {
+                break;
+            }
+            index = node.children[key.bit(node_len) as usize];
+        }
+        let node = self.storage.node(found?);
+        Some((node.key, node.len as u32, unsafe {
+            node.value.assume_init_ref()
+        }))
+    }
+
+    /// Removes the entry stored under the whole of `key` and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_prefix(key, K::BITS)
+    }
+
+    /// Removes the entry stored under the first `len` bits of `key` and returns its value. Nodes left with neither a
+    /// value nor two children are unlinked, so the tree never holds more nodes than its entries need. Panics if `len`
+    /// is greater than `K::BITS`.
+    pub fn remove_prefix(&mut self, key: &K, len: u32) -> Option<V> {
+        assert!(len <= K::BITS, "Prefix length {} is out of bounds", len);
+        let index = self.search_node(*key, len)?;
+        if !self.storage.node(index).has_value {
+            return None;
+        }
+        let value = self.storage.node_mut(index).take_value();
+        self.length -= 1;
+        let parent = self.storage.node(index).parent;
+        if self.prune(index) && parent != NULL && !self.storage.node(parent).has_value {
+            // The parent had two children and has one left, so it no longer marks a place where prefixes diverge.
+            self.prune(parent);
+        }
+        Some(value)
+    }
+
+    /// Unlinks node `index`, which has no value, if it has fewer than two children, moving its only child into its
+    /// place. Returns true if the node was unlinked.
+    fn prune(&mut self, index: u16) -> bool {
+        let node = self.storage.node(index);
+        let child = match node.children {
+            [NULL, NULL] => NULL,
+            [child, NULL] | [NULL, child] => child,
+            _ => return false,
+        };
+        self.replace_child(node.parent, index, child);
+        self.storage.delete(index);
+        true
+    }
+
+    /// Checks that every child extends the prefix of its parent on the side its next bit picks, that every node
+    /// without a value has two children, and the parent links, returning the depth of the tree, or a description of
+    /// the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        if self.head != NULL && self.storage.node(self.head).parent != NULL {
+            return Err("head has a parent");
+        }
+        let mut depth = 0;
+        let mut nodes = 0;
+        let mut values = 0;
+        let mut next = self.head;
+        while next != NULL {
+            let node = self.storage.node(next);
+            if node.key.prefix(node.len as u32) != node.key {
+                return Err("key has bits set past its prefix length");
+            }
+            if !node.has_value && node.children.contains(&NULL) {
+                return Err("node without a value has fewer than two children");
+            }
+            for (side, child) in node.children.into_iter().enumerate() {
+                if child == NULL {
+                    continue;
+                }
+                let child_node = self.storage.node(child);
+                if child_node.parent != next {
+                    return Err("child does not point back to its parent");
+                }
+                if child_node.len <= node.len
+                    || child_node.key.prefix(node.len as u32) != node.key
+                    || child_node.key.bit(node.len as u32) as usize != side
+                {
+                    return Err("child does not extend the prefix of its parent on its side");
+                }
+            }
+            let mut node_depth = 1;
+            let mut ancestor = node.parent;
+            while ancestor != NULL && node_depth <= self.capacity() {
+                node_depth += 1;
+                ancestor = self.storage.node(ancestor).parent;
+            }
+            depth = depth.max(node_depth);
+            nodes += 1;
+            values += node.has_value as usize;
+            if nodes > self.storage.len() {
+                return Err("tree holds more nodes than are in use");
+            }
+            next = self.next_index(next);
+        }
+        if nodes != self.storage.len() {
+            return Err("number of nodes in use does not match the tree");
+        }
+        if values != self.len() {
+            return Err("length does not match the number of values");
+        }
+        Ok(depth)
+    }
+
+    /// Returns the node after `index` in pre-order, which visits a prefix before the longer ones below it and the
+    /// 0 side before the 1 side.
+    fn next_index(&self, index: u16) -> u16 {
+        let node = self.storage.node(index);
+        for child in node.children {
+            if child != NULL {
+                return child;
+            }
+        }
+        let mut child = index;
+        let mut parent = node.parent;
+        while parent != NULL {
+            let [_, right] = self.storage.node(parent).children;
+            if right != NULL && right != child {
+                return right;
+            }
+            child = parent;
+            parent = self.storage.node(parent).parent;
+        }
+        NULL
+    }
+
+    /// Returns an iterator over the entries as their prefix, its length and their value. The entries come in
+    /// ascending order of their keys, with a prefix before the longer prefixes and keys that start with it.
+    pub fn iter(&self) -> Iter<'_, 'a, K, V, SIZE> {
+        Iter {
+            tree: self,
+            next: self.head,
+        }
+    }
+}
+
+/// An iterator over the entries of a [RadixTree] in ascending order.
+pub struct Iter<'b, 'a, K, V, const SIZE: usize>
+where
+    K: RadixKey,
+{
+    tree: &'b RadixTree<'a, K, V, SIZE>,
+    next: u16,
+}
+
+impl<'b, K, V, const SIZE: usize> Iterator for Iter<'b, '_, K, V, SIZE>
+where
+    K: RadixKey,
+{
+    type Item = (K, u32, &'b V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next != NULL {
+            let index = self.next;
+            self.next = self.tree.next_index(index);
+            let node = self.tree.storage.node(index);
+            if node.has_value {
+                return Some((node.key, node.len as u32, unsafe {
+                    node.value.assume_init_ref()
+                }));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, RadixKey, RadixTree, RadixTreeDyn};
+    use crate::testing::{self, Tracked};
+    use crate::{AlignedBuffer, Error};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use rand::Rng;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_radix_key() {
+        assert!(0x80u8.bit(0));
+        assert!(!0x80u8.bit(1));
+        assert!(1u64.bit(63));
+        assert_eq!(0xabcd_u16.prefix(0), 0);
+        assert_eq!(0xabcd_u16.prefix(4), 0xa000);
+        assert_eq!(0xabcd_u16.prefix(16), 0xabcd);
+        assert_eq!(0xff00_u16.common_prefix(0xf000), 4);
+        assert_eq!(7u32.common_prefix(7), 32);
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut mem = AlignedBuffer([0; 1000 * node_size::<u64, u32>()]);
+        let mut tree = RadixTree::<u64, u32, 1000>::new(&mut mem);
+        let mut map = BTreeMap::new();
+        let mut rng = rand::thread_rng();
+
+        testing::random_ops(500, |key, insert| {
+            // Keep the keys close together so that they share long prefixes.
+            let key = 0xfff0_0000_0000 + key as u64 * 0x1000;
+            if insert {
+                let value = rng.gen_range(0..1000);
+                assert_eq!(tree.insert(key, value).unwrap(), map.insert(key, value));
+            } else {
+                assert_eq!(tree.remove(&key), map.remove(&key));
+            }
+            tree.validate().unwrap();
+        });
+        assert_eq!(tree.len(), map.len());
+        assert!(tree
+            .iter()
+            .map(|(key, len, value)| (key, len, *value))
+            .eq(map.iter().map(|(key, value)| (*key, 64, *value))));
+        for key in 0..500 {
+            let key = 0xfff0_0000_0000 + key * 0x1000;
+            assert_eq!(tree.get(&key), map.get(&key));
+            assert!(!tree.contains_key(&(key + 1)));
+        }
+
+        while let Some((key, value)) = map.pop_first() {
+            assert_eq!(tree.remove(&key), Some(value));
+        }
+        tree.validate().unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.storage.length, 0);
+    }
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let mut mem = AlignedBuffer([0; 16 * node_size::<u32, &str>()]);
+        let mut tree = RadixTreeDyn::<u32, &str>::new(&mut mem);
+        assert_eq!(tree.capacity(), 16);
+
+        tree.insert_prefix(0, 0, "everything").unwrap();
+        tree.insert_prefix(0x8000_0000, 1, "upper half").unwrap();
+        tree.insert_prefix(0xfee0_0000, 12, "local apic").unwrap();
+        tree.insert(0xfee0_0020, "apic id").unwrap();
+        tree.insert_prefix(0x1000_0000, 4, "low region").unwrap();
+        tree.validate().unwrap();
+
+        assert_eq!(
+            tree.longest_prefix_match(&0x0000_1000),
+            Some((0, 0, &"everything"))
+        );
+        assert_eq!(
+            tree.longest_prefix_match(&0x1234_5678),
+            Some((0x1000_0000, 4, &"low region"))
+        );
+        assert_eq!(
+            tree.longest_prefix_match(&0x9000_0000),
+            Some((0x8000_0000, 1, &"upper half"))
+        );
+        assert_eq!(
+            tree.longest_prefix_match(&0xfee0_0300),
+            Some((0xfee0_0000, 12, &"local apic"))
+        );
+        assert_eq!(
+            tree.longest_prefix_match(&0xfee0_0020),
+            Some((0xfee0_0020, 32, &"apic id"))
+        );
+
+        // Prefixes of different lengths over the same bits are separate entries.
+        assert_eq!(tree.get_prefix(&0xfee0_1234, 12), Some(&"local apic"));
+        assert_eq!(tree.get_prefix(&0xfee0_1234, 11), None);
+        assert_eq!(tree.get(&0xfee0_0000), None);
+        *tree.get_prefix_mut(&0x8000_0000, 1).unwrap() = "high";
+        assert!(tree.iter().map(|(_, len, _)| len).eq([0, 4, 1, 12, 32]));
+
+        assert_eq!(tree.remove_prefix(&0xfee0_0000, 12), Some("local apic"));
+        assert_eq!(
+            tree.longest_prefix_match(&0xfee0_0300),
+            Some((0x8000_0000, 1, &"high"))
+        );
+        assert_eq!(tree.remove_prefix(&0, 0), Some("everything"));
+        assert_eq!(tree.longest_prefix_match(&0x0000_1000), None);
+        tree.validate().unwrap();
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn test_out_of_space() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u8, u8>()]);
+        let mut tree = RadixTree::<u8, u8, 4>::new(&mut mem);
+        tree.insert(0b0000_0000, 0).unwrap();
+        tree.insert(0b1000_0000, 1).unwrap();
+        // A key diverging from both needs a node for itself and one for where it branches off.
+        assert!(matches!(
+            tree.insert(0b0100_0000, 2),
+            Err(Error::OutOfSpace)
+        ));
+        tree.validate().unwrap();
+        assert_eq!(tree.len(), 2);
+        // Any shorter prefix of an existing one fits in the remaining node.
+        assert_eq!(tree.insert_prefix(0, 1, 3).unwrap(), None);
+        assert_eq!(tree.insert(0, 4).unwrap(), Some(0));
+        tree.validate().unwrap();
+
+        tree.clear();
+        assert!(tree.is_empty());
+        tree.insert(0b0100_0000, 2).unwrap();
+        drop(tree);
+        assert!(matches!(
+            RadixTree::<u8, u8, 4>::try_new(&mut mem[1..]),
+            Err(Error::BadBuffer)
+        ));
+    }
+
+    #[test]
+    fn test_drop_values() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut mem = AlignedBuffer([0; 16 * node_size::<u16, Tracked>()]);
+        {
+            let mut tree = RadixTreeDyn::<u16, Tracked>::new(&mut mem);
+            for key in 0..6 {
+                assert!(tree.insert(key, Tracked(0, &DROPS)).unwrap().is_none());
+            }
+            drop(tree.insert(0, Tracked(0, &DROPS)).unwrap());
+            assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+            drop(tree.remove(&1));
+            assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+        }
+        assert_eq!(DROPS.load(Ordering::Relaxed), 7);
+    }
+}
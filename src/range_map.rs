@@ -0,0 +1,384 @@
+//! A map from disjoint half-open ranges to attributes on top of [Rbt], in the manner of the UEFI GCD memory map.
+//!
+//! Like [crate::range_set::RangeSet], the map keeps the fewest ranges it can: setting the attributes of part of a
+//! range splits it at the boundaries, and ranges that touch and end up with equal attributes are merged back into
+//! one.
+use core::ops::Range;
+
+use crate::map::KeyValue;
+use crate::rbt::{self, Rbt};
+use crate::{Error, Result};
+
+/// Returns the number of bytes a single range of a [RangeMap] occupies in its storage.
+pub const fn node_size<T, A>() -> usize
+where
+    T: Ord,
+{
+    rbt::node_size::<KeyValue<T, Span<T, A>>>()
+}
+
+/// The end of a range and the attributes of the values in it, stored under its start.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Span<T, A> {
+    end: T,
+    attributes: A,
+}
+
+/// A map from values of type `T` to attributes of type `A`, stored as up to `SIZE` disjoint ranges, or as many as fit
+/// in the buffer if `SIZE` is 0. Values outside every range have no attributes. Two ranges that touch always have
+/// different attributes, since equal ones are merged.
+pub struct RangeMap<'a, T, A, const SIZE: usize>
+where
+    T: Ord,
+{
+    tree: Rbt<'a, KeyValue<T, Span<T, A>>, SIZE>,
+}
+
+/// A [RangeMap] that holds as many ranges as fit in the buffer it is created with.
+pub type RangeMapDyn<'a, T, A> = RangeMap<'a, T, A, 0>;
+
+/// The ranges that an update replaces or touches, as found before anything is changed.
+struct Overlap<T, A> {
+    /// The number of ranges that overlap the update.
+    count: usize,
+    /// The part of the first overlapping range before the update, if it starts earlier.
+    before: Option<(T, A)>,
+    /// The part of the last overlapping range after the update, if it ends later.
+    after: Option<(T, A)>,
+    /// Every value in the update is in some range.
+    covered: bool,
+    /// The start and attributes of the range that ends exactly where the update starts.
+    touching_before: Option<(T, A)>,
+    /// The end and attributes of the range that starts exactly where the update ends.
+    touching_after: Option<(T, A)>,
+}
+
+impl<'a, T, A, const SIZE: usize> RangeMap<'a, T, A, SIZE>
+where
+    T: Ord + Copy + core::fmt::Debug,
+    A: Copy + PartialEq + core::fmt::Debug,
+{
+    /// Creates an empty map backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<T, A>()` bytes
+    /// or is not aligned for the ranges.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self {
+            tree: Rbt::new(slice),
+        }
+    }
+
+    /// Creates an empty map like [Self::new], but returns `Error::BadBuffer` instead of panicking if `slice` is too
+    /// short or not aligned for the ranges.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Ok(Self {
+            tree: Rbt::try_new(slice)?,
+        })
+    }
+
+    /// Returns the number of disjoint ranges in the map.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the maximum number of disjoint ranges the map can hold.
+    pub fn capacity(&self) -> usize {
+        self.tree.capacity()
+    }
+
+    /// Removes every range, keeping the storage.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Gives every value in `range` the attributes `attributes`, whether or not it had any before. The ranges it
+    /// overlaps are cut back to where it starts and ends, and it is merged with the ranges on either side if they
+    /// have the same attributes. Returns `Error::OutOfSpace` if the map cannot hold the resulting ranges, in which
+    /// case it is left unchanged.
+    pub fn insert(&mut self, range: Range<T>, attributes: A) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        self.assign(range, Some(attributes))
+    }
+
+    /// Gives every value in `range` the attributes `attributes`, like [Self::insert], but only if every value in it
+    /// already has attributes, as when changing the attributes of memory that has been added to the memory map.
+    /// Returns `Error::NotFound` if any value in `range` has none, and `Error::OutOfSpace` like [Self::insert].
+    pub fn set_attributes(&mut self, range: Range<T>, attributes: A) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        if !self.overlap(&range).covered {
+            return Err(Error::NotFound);
+        }
+        self.assign(range, Some(attributes))
+    }
+
+    /// Takes the values in `range` out of the map, cutting back the ranges it overlaps. Returns `Error::OutOfSpace` if
+    /// a range has to be split and the map is full, in which case it is left unchanged.
+    pub fn remove(&mut self, range: Range<T>) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        self.assign(range, None)
+    }
+
+    /// Returns the range of the map that holds `value`, along with its attributes.
+    pub fn get(&self, value: &T) -> Option<(Range<T>, A)> {
+        self.tree
+            .floor_ref(value)
+            .filter(|range| range.value.end > *value)
+            .map(|range| (range.key..range.value.end, range.value.attributes))
+    }
+
+    /// Returns an iterator over the ranges and their attributes in ascending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (Range<T>, A)> + '_ {
+        self.tree
+            .iter()
+            .map(|range| (range.key..range.value.end, range.value.attributes))
+    }
+
+    /// Checks that every range is non-empty, that no two ranges overlap, and that ranges that touch have different
+    /// attributes, returning the number of ranges, or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        self.tree.validate()?;
+        let mut previous: Option<(T, A)> = None;
+        for (range, attributes) in self.iter() {
+            if range.is_empty() {
+                return Err("range is empty");
+            }
+            if let Some((end, previous_attributes)) = previous {
+                if end > range.start {
+                    return Err("ranges overlap");
+                }
+                if end == range.start && previous_attributes == attributes {
+                    return Err("touching ranges have the same attributes");
+                }
+            }
+            previous = Some((range.end, attributes));
+        }
+        Ok(self.len())
+    }
+
+    /// Returns the first range that overlaps `start..end`, as its start and span.
+    fn first_overlap(&self, start: T, end: T) -> Option<(T, Span<T, A>)> {
+        let range = self
+            .tree
+            .floor_ref(&start)
+            .filter(|range| range.value.end > start)
+            .or_else(|| self.tree.ceil_ref(&start).filter(|range| range.key < end))?;
+        Some((range.key, range.value))
+    }
+
+    /// Finds the ranges that an update of `range` replaces, and the ranges on either side that it may merge with.
+    fn overlap(&self, range: &Range<T>) -> Overlap<T, A> {
+        let mut overlap = Overlap {
+            count: 0,
+            before: None,
+            after: None,
+            covered: true,
+            touching_before: self
+                .tree
+                .predecessor_ref(&range.start)
+                .filter(|before| before.value.end == range.start)
+                .map(|before| (before.key, before.value.attributes)),
+            touching_after: self
+                .tree
+                .search_ref(&range.end)
+                .map(|after| (after.value.end, after.value.attributes)),
+        };
+        let mut cursor = range.start;
+        let first = self.first_overlap(range.start, range.end);
+        let rest = self
+            .tree
+            .range(range.start..range.end)
+            .map(|range| (range.key, range.value))
+            .filter(|(start, _)| Some(*start) != first.map(|(first, _)| first));
+        for (start, span) in first.into_iter().chain(rest) {
+            if overlap.count == 0 && start < range.start {
+                overlap.before = Some((start, span.attributes));
+            }
+            if span.end > range.end {
+                overlap.after = Some((span.end, span.attributes));
+            }
+            overlap.covered &= start <= cursor;
+            cursor = span.end;
+            overlap.count += 1;
+        }
+        overlap.covered &= cursor >= range.end;
+        overlap
+    }
+
+    /// Replaces whatever the map holds for `range` with `attributes`, or with nothing if `attributes` is None.
+    fn assign(&mut self, range: Range<T>, attributes: Option<A>) -> Result<()> {
+        let overlap = self.overlap(&range);
+        let mut start = range.start;
+        let mut end = range.end;
+        // Work out the ranges the update leaves before touching the tree, so that running out of space changes nothing.
+        let mut removed = overlap.count;
+        let mut added = 0;
+        let mut before = overlap.before;
+        let mut after = overlap.after;
+        if let Some(attributes) = attributes {
+            added += 1;
+            match before.or(overlap.touching_before) {
+                Some((before_start, before_attributes)) if before_attributes == attributes => {
+                    start = before_start;
+                    if before.is_none() {
+                        removed += 1;
+                    }
+                    before = None;
+                }
+                _ => {}
+            }
+            match after.or(overlap.touching_after) {
+                Some((after_end, after_attributes)) if after_attributes == attributes => {
+                    end = after_end;
+                    if after.is_none() {
+                        removed += 1;
+                    }
+                    after = None;
+                }
+                _ => {}
+            }
+        }
+        added += before.is_some() as usize + after.is_some() as usize;
+        if added > removed + self.tree.remaining() {
+            return Err(Error::OutOfSpace);
+        }
+
+        while let Some((overlap_start, _)) = self.first_overlap(start, end) {
+            self.tree.remove(&overlap_start);
+        }
+        if let Some((before_start, before_attributes)) = before {
+            self.insert_span(before_start, range.start, before_attributes)?;
+        }
+        if let Some((after_end, after_attributes)) = after {
+            self.insert_span(range.end, after_end, after_attributes)?;
+        }
+        if let Some(attributes) = attributes {
+            self.insert_span(start, end, attributes)?;
+        }
+        Ok(())
+    }
+
+    fn insert_span(&mut self, start: T, end: T, attributes: A) -> Result<()> {
+        self.tree.insert(KeyValue {
+            key: start,
+            value: Span { end, attributes },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{node_size, RangeMap, RangeMapDyn};
+    use crate::{AlignedBuffer, Error};
+    use rand::Rng;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Memory {
+        Reserved,
+        System { cacheable: bool },
+        Mmio,
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u64, Memory>()]);
+        let mut map = RangeMap::<u64, Memory, 4>::new(&mut mem);
+        let system = Memory::System { cacheable: true };
+        let uncached = Memory::System { cacheable: false };
+
+        map.insert(0x0000..0x8000, system).unwrap();
+        map.insert(0x8000..0x9000, system).unwrap();
+        map.insert(0xa000..0xb000, Memory::Mmio).unwrap();
+        assert!(map
+            .iter()
+            .eq([(0x0000..0x9000, system), (0xa000..0xb000, Memory::Mmio)]));
+
+        // Changing the middle of a range splits it in three, and changing it back merges them again.
+        map.set_attributes(0x2000..0x3000, uncached).unwrap();
+        assert!(map.iter().eq([
+            (0x0000..0x2000, system),
+            (0x2000..0x3000, uncached),
+            (0x3000..0x9000, system),
+            (0xa000..0xb000, Memory::Mmio),
+        ]));
+        assert_eq!(map.get(&0x2800), Some((0x2000..0x3000, uncached)));
+        assert_eq!(map.get(&0x9800), None);
+        assert!(matches!(
+            map.set_attributes(0x4000..0x5000, Memory::Reserved),
+            Err(Error::OutOfSpace)
+        ));
+        assert!(matches!(
+            map.set_attributes(0x8000..0xa800, Memory::Mmio),
+            Err(Error::NotFound)
+        ));
+        map.set_attributes(0x2000..0x3000, system).unwrap();
+        assert_eq!(map.validate(), Ok(2));
+
+        // Filling the gap with the attributes of the range after it merges the two.
+        map.insert(0x9000..0xa000, Memory::Mmio).unwrap();
+        assert!(map
+            .iter()
+            .eq([(0x0000..0x9000, system), (0x9000..0xb000, Memory::Mmio)]));
+        map.insert(0x1000..0xa000, Memory::Reserved).unwrap();
+        assert!(map.iter().eq([
+            (0x0000..0x1000, system),
+            (0x1000..0xa000, Memory::Reserved),
+            (0xa000..0xb000, Memory::Mmio),
+        ]));
+
+        map.remove(0x800..0xa800).unwrap();
+        assert!(map
+            .iter()
+            .eq([(0x0000..0x800, system), (0xa800..0xb000, Memory::Mmio)]));
+        assert_eq!(map.validate(), Ok(2));
+    }
+
+    #[test]
+    fn test_against_array() {
+        let mut mem = AlignedBuffer([0; 100 * node_size::<u32, u8>()]);
+        let mut map = RangeMapDyn::<u32, u8>::new(&mut mem);
+        assert_eq!(map.capacity(), 100);
+        // Touching ranges may differ in attributes, so with fewer values than ranges the map never runs out of space.
+        let mut values = [None; 90];
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..4000 {
+            let start = rng.gen_range(0..90);
+            let end = rng.gen_range(start..=(start + 10).min(90));
+            let attributes = rng.gen_range(0..3);
+            let range = start as usize..end as usize;
+            match rng.gen_range(0..3) {
+                0 => {
+                    map.insert(start..end, attributes).unwrap();
+                    values[range].fill(Some(attributes));
+                }
+                1 => {
+                    let covered = values[range.clone()].iter().all(Option::is_some);
+                    match map.set_attributes(start..end, attributes) {
+                        Ok(()) => values[range].fill(Some(attributes)),
+                        Err(Error::NotFound) => assert!(!covered),
+                        Err(error) => panic!("{:?}", error),
+                    }
+                }
+                _ => {
+                    map.remove(start..end).unwrap();
+                    values[range].fill(None);
+                }
+            }
+            map.validate().unwrap();
+            for (value, expected) in values.iter().enumerate() {
+                assert_eq!(
+                    map.get(&(value as u32)).map(|(_, attributes)| attributes),
+                    *expected
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! A set of disjoint half-open ranges on top of [Rbt], merged and split as ranges are added and taken away.
+use core::ops::Range;
+
+use crate::map::KeyValue;
+use crate::rbt::{self, Rbt};
+use crate::{Error, Result};
+
+/// Returns the number of bytes a single range of a [RangeSet] occupies in its storage.
+pub const fn node_size<T>() -> usize
+where
+    T: Ord,
+{
+    rbt::node_size::<KeyValue<T, T>>()
+}
+
+/// A set of values of type `T`, stored as up to `SIZE` disjoint ranges, or as many as fit in the buffer if `SIZE` is
+/// 0. Ranges that overlap or touch are merged as they are inserted, so the set always holds the fewest ranges that
+/// cover its values, and removing the middle of a range splits it in two.
+pub struct RangeSet<'a, T, const SIZE: usize>
+where
+    T: Ord,
+{
+    /// The ranges as start to end.
+    tree: Rbt<'a, KeyValue<T, T>, SIZE>,
+}
+
+/// A [RangeSet] that holds as many ranges as fit in the buffer it is created with.
+pub type RangeSetDyn<'a, T> = RangeSet<'a, T, 0>;
+
+impl<'a, T, const SIZE: usize> RangeSet<'a, T, SIZE>
+where
+    T: Ord + Copy + core::fmt::Debug,
+{
+    /// Creates an empty set backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<T>()` bytes or is
+    /// not aligned for the ranges.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self {
+            tree: Rbt::new(slice),
+        }
+    }
+
+    /// Creates an empty set like [Self::new], but returns `Error::BadBuffer` instead of panicking if `slice` is too
+    /// short or not aligned for the ranges.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Ok(Self {
+            tree: Rbt::try_new(slice)?,
+        })
+    }
+
+    /// Returns the number of disjoint ranges in the set.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the maximum number of disjoint ranges the set can hold.
+    pub fn capacity(&self) -> usize {
+        self.tree.capacity()
+    }
+
+    /// Removes every range, keeping the storage.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Adds the values in `range` to the set, merging it with every range it overlaps or touches. Returns
+    /// `Error::OutOfSpace` if it touches no range and the set is full, in which case the set is left unchanged.
+    pub fn insert(&mut self, range: Range<T>) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let before = self
+            .tree
+            .floor_ref(&range.start)
+            .filter(|before| before.value >= range.start);
+        let touches = before.is_some()
+            || self
+                .tree
+                .ceil_ref(&range.start)
+                .is_some_and(|after| after.key <= range.end);
+        if !touches && self.tree.remaining() == 0 {
+            return Err(Error::OutOfSpace);
+        }
+
+        let start = before.map_or(range.start, |before| before.key);
+        let mut end = range.end;
+        while let Some(next) = self.tree.ceil_ref(&start) {
+            if next.key > end {
+                break;
+            }
+            end = end.max(next.value);
+            let key = next.key;
+            self.tree.remove(&key);
+        }
+        self.tree.insert(KeyValue {
+            key: start,
+            value: end,
+        })
+    }
+
+    /// Takes the values in `range` out of the set, trimming the ranges it overlaps and splitting the one it falls
+    /// inside of. Returns `Error::OutOfSpace` if a range has to be split and the set is full, in which case the set is
+    /// left unchanged.
+    pub fn remove(&mut self, range: Range<T>) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let before = self
+            .tree
+            .floor_ref(&range.start)
+            .filter(|before| before.key < range.start && before.value > range.start)
+            .map(|before| (before.key, before.value));
+        if before.is_some_and(|(_, end)| end > range.end) && self.tree.remaining() == 0 {
+            return Err(Error::OutOfSpace);
+        }
+
+        if let Some((start, end)) = before {
+            self.tree.get_mut(&start).unwrap().value = range.start;
+            if end > range.end {
+                return self.tree.insert(KeyValue {
+                    key: range.end,
+                    value: end,
+                });
+            }
+        }
+        while let Some(next) = self.tree.ceil_ref(&range.start) {
+            if next.key >= range.end {
+                break;
+            }
+            let (start, end) = (next.key, next.value);
+            self.tree.remove(&start);
+            if end > range.end {
+                // The rest of the range starts at the end of the removed values, so the loop does not see it again.
+                self.tree.insert(KeyValue {
+                    key: range.end,
+                    value: end,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if `value` is in the set.
+    pub fn contains(&self, value: &T) -> bool {
+        self.get(value).is_some()
+    }
+
+    /// Returns the range of the set that holds `value`.
+    pub fn get(&self, value: &T) -> Option<Range<T>> {
+        self.tree
+            .floor_ref(value)
+            .filter(|range| range.value > *value)
+            .map(|range| range.key..range.value)
+    }
+
+    /// Returns true if every value in `range` is in the set, which is when a single range of the set covers it.
+    pub fn contains_range(&self, range: Range<T>) -> bool {
+        range.is_empty()
+            || self
+                .get(&range.start)
+                .is_some_and(|found| found.end >= range.end)
+    }
+
+    /// Returns true if any value in `range` is in the set.
+    pub fn overlaps(&self, range: Range<T>) -> bool {
+        !range.is_empty()
+            && (self.contains(&range.start)
+                || self
+                    .tree
+                    .ceil_ref(&range.start)
+                    .is_some_and(|after| after.key < range.end))
+    }
+
+    /// Returns an iterator over the ranges in ascending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Range<T>> + '_ {
+        self.tree.iter().map(|range| range.key..range.value)
+    }
+
+    /// Checks that every range is non-empty and that no two ranges overlap or touch, returning the number of ranges,
+    /// or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        self.tree.validate()?;
+        let mut previous_end = None;
+        for range in self.iter() {
+            if range.is_empty() {
+                return Err("range is empty");
+            }
+            if previous_end.is_some_and(|end| end >= range.start) {
+                return Err("ranges overlap or touch");
+            }
+            previous_end = Some(range.end);
+        }
+        Ok(self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{node_size, RangeSet, RangeSetDyn};
+    use crate::{AlignedBuffer, Error};
+    use rand::Rng;
+
+    #[test]
+    fn test_merge_and_split() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u64>()]);
+        let mut set = RangeSet::<u64, 4>::new(&mut mem);
+
+        set.insert(10..20).unwrap();
+        set.insert(30..40).unwrap();
+        set.insert(20..25).unwrap();
+        assert!(set.iter().eq([10..25, 30..40]));
+        set.insert(24..30).unwrap();
+        assert!(set.iter().eq(core::iter::once(10..40)));
+        set.insert(0..5).unwrap();
+        set.insert(50..60).unwrap();
+        set.insert(45..47).unwrap();
+        assert!(matches!(set.insert(70..80), Err(Error::OutOfSpace)));
+        // Covering several ranges merges them into one.
+        set.insert(3..46).unwrap();
+        assert!(set.iter().eq([0..47, 50..60]));
+        assert_eq!(set.validate(), Ok(2));
+
+        set.remove(10..20).unwrap();
+        set.remove(40..55).unwrap();
+        assert!(set.iter().eq([0..10, 20..40, 55..60]));
+        assert_eq!(set.get(&25), Some(20..40));
+        assert_eq!(set.get(&40), None);
+        assert!(set.contains(&0));
+        assert!(!set.contains(&15));
+        assert!(set.contains_range(21..40));
+        assert!(!set.contains_range(5..25));
+        assert!(set.overlaps(5..25));
+        assert!(set.overlaps(45..56));
+        assert!(!set.overlaps(40..55));
+
+        set.insert(70..80).unwrap();
+        assert!(matches!(set.remove(25..30), Err(Error::OutOfSpace)));
+        assert!(set.iter().eq([0..10, 20..40, 55..60, 70..80]));
+        set.remove(5..75).unwrap();
+        assert!(set.iter().eq([0..5, 75..80]));
+        assert_eq!(set.validate(), Ok(2));
+    }
+
+    #[test]
+    fn test_against_bitmap() {
+        let mut mem = AlignedBuffer([0; 100 * node_size::<u32>()]);
+        let mut set = RangeSetDyn::<u32>::new(&mut mem);
+        assert_eq!(set.capacity(), 100);
+        // Fewer than twice as many values as ranges, so the set can never run out of space.
+        let mut values = [false; 150];
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..4000 {
+            let start = rng.gen_range(0..150);
+            let end = rng.gen_range(start..=(start + 10).min(150));
+            let insert = rng.gen_bool(0.5);
+            match insert {
+                true => set.insert(start..end).unwrap(),
+                false => set.remove(start..end).unwrap(),
+            }
+            values[start as usize..end as usize].fill(insert);
+            set.validate().unwrap();
+            for (value, expected) in values.iter().enumerate() {
+                assert_eq!(set.contains(&(value as u32)), *expected);
+            }
+        }
+
+        set.clear();
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+//! The red-black tree algorithms shared by the trees in the crate. They work on nodes through handles, which are slot
+//! indices for the trees that keep one set of [Links] per index in each node, and may keep a value computed from each
+//! subtree up to date as they restructure.
+use core::cmp::Ordering;
+
+pub(crate) const RED: bool = false;
+pub(crate) const BLACK: bool = true;
+/// The slot index that marks a missing link.
+pub(crate) const NULL: u16 = u16::MAX;
+
+/// The place of a node in one index.
+#[derive(Clone, Copy)]
+pub(crate) struct Links {
+    pub(crate) parent: u16,
+    pub(crate) left: u16,
+    pub(crate) right: u16,
+    pub(crate) color: bool,
+}
+
+/// Reads and rewrites the links of the nodes in one or more red-black trees, given a handle to a node and the index
+/// of the tree.
+pub(crate) trait Linkage {
+    /// A handle to a node.
+    type Node: Copy + Eq;
+    /// The handle that marks a missing link.
+    const NIL: Self::Node;
+    /// Whether [Linkage::update] keeps anything, so the algorithms can skip walking up to call it.
+    const AUGMENTED: bool = false;
+
+    fn parent(&self, index: usize, node: Self::Node) -> Self::Node;
+    fn left(&self, index: usize, node: Self::Node) -> Self::Node;
+    fn right(&self, index: usize, node: Self::Node) -> Self::Node;
+    /// Returns the color of `node`, which must not be missing.
+    fn node_color(&self, index: usize, node: Self::Node) -> bool;
+    fn set_parent(&mut self, index: usize, node: Self::Node, parent: Self::Node);
+    fn set_left(&mut self, index: usize, node: Self::Node, left: Self::Node);
+    fn set_right(&mut self, index: usize, node: Self::Node, right: Self::Node);
+    fn set_color(&mut self, index: usize, node: Self::Node, color: bool);
+
+    /// Recomputes the value kept for the subtree rooted at `node` from its children, which are already up to date.
+    fn update(&mut self, _index: usize, _node: Self::Node) {}
+}
+
+/// Gives the [Links] of each index in a node addressed by slot index, which provides its [Linkage].
+pub(crate) trait SlotLinks {
+    fn links(&self, index: usize, node: u16) -> &Links;
+    fn links_mut(&mut self, index: usize, node: u16) -> &mut Links;
+}
+
+impl<T: SlotLinks> Linkage for T {
+    type Node = u16;
+    const NIL: u16 = NULL;
+
+    fn parent(&self, index: usize, node: u16) -> u16 {
+        self.links(index, node).parent
+    }
+
+    fn left(&self, index: usize, node: u16) -> u16 {
+        self.links(index, node).left
+    }
+
+    fn right(&self, index: usize, node: u16) -> u16 {
+        self.links(index, node).right
+    }
+
+    fn node_color(&self, index: usize, node: u16) -> bool {
+        self.links(index, node).color
+    }
+
+    fn set_parent(&mut self, index: usize, node: u16, parent: u16) {
+        self.links_mut(index, node).parent = parent;
+    }
+
+    fn set_left(&mut self, index: usize, node: u16, left: u16) {
+        self.links_mut(index, node).left = left;
+    }
+
+    fn set_right(&mut self, index: usize, node: u16, right: u16) {
+        self.links_mut(index, node).right = right;
+    }
+
+    fn set_color(&mut self, index: usize, node: u16, color: bool) {
+        self.links_mut(index, node).color = color;
+    }
+}
+
+/// One or more red-black trees over the same nodes, each with its own head and its own links in every node. The
+/// implementor says where the elements and heads live and how index `index` orders two nodes; the tree operations
+/// are provided.
+pub(crate) trait Forest: Linkage {
+    type Element;
+
+    fn element(&self, node: Self::Node) -> &Self::Element;
+    fn head(&self, index: usize) -> Self::Node;
+    fn set_head(&mut self, index: usize, node: Self::Node);
+    /// Orders nodes `a` and `b` in index `index`.
+    fn compare(&self, index: usize, a: Self::Node, b: Self::Node) -> Ordering;
+
+    /// Returns the black height of the subtree of index `index` rooted at `node`.
+    fn validate_node(
+        &self,
+        index: usize,
+        node: Self::Node,
+    ) -> core::result::Result<usize, &'static str> {
+        if node == Self::NIL {
+            return Ok(1);
+        }
+        let (left, right) = (self.left(index, node), self.right(index, node));
+        for child in [left, right] {
+            if child == Self::NIL {
+                continue;
+            }
+            if self.parent(index, child) != node {
+                return Err("child does not point back to its parent");
+            }
+            if self.node_color(index, node) == RED && self.node_color(index, child) == RED {
+                return Err("red node has a red child");
+            }
+        }
+        let left = self.validate_node(index, left)?;
+        let right = self.validate_node(index, right)?;
+        if left != right {
+            return Err("paths hold different numbers of black nodes");
+        }
+        Ok(left + (self.node_color(index, node) == BLACK) as usize)
+    }
+
+    /// Returns the color of `node` in index `index`, where missing nodes are black.
+    fn color(&self, index: usize, node: Self::Node) -> bool {
+        node == Self::NIL || self.node_color(index, node)
+    }
+
+    fn search_node<F: Fn(&Self::Element) -> Ordering>(&self, index: usize, probe: F) -> Self::Node {
+        let mut node = self.head(index);
+        while node != Self::NIL {
+            node = match probe(self.element(node)) {
+                Ordering::Equal => return node,
+                Ordering::Less => self.left(index, node),
+                Ordering::Greater => self.right(index, node),
+            };
+        }
+        Self::NIL
+    }
+
+    /// Returns the smallest node at least the key of `probe` if `ceil`, or else the largest at most it.
+    fn bound_node<F: Fn(&Self::Element) -> Ordering>(
+        &self,
+        index: usize,
+        probe: F,
+        ceil: bool,
+    ) -> Self::Node {
+        let mut found = Self::NIL;
+        let mut node = self.head(index);
+        while node != Self::NIL {
+            let ordering = probe(self.element(node));
+            if ordering.is_eq() {
+                return node;
+            }
+            if ordering.is_lt() == ceil {
+                found = node;
+            }
+            node = match ordering {
+                Ordering::Less => self.left(index, node),
+                _ => self.right(index, node),
+            };
+        }
+        found
+    }
+
+    fn min_node(&self, index: usize, mut node: Self::Node) -> Self::Node {
+        while node != Self::NIL && self.left(index, node) != Self::NIL {
+            node = self.left(index, node);
+        }
+        node
+    }
+
+    /// Returns the in-order successor of `node` in index `index` by following parent links.
+    fn next_node(&self, index: usize, node: Self::Node) -> Self::Node {
+        let right = self.right(index, node);
+        if right != Self::NIL {
+            return self.min_node(index, right);
+        }
+        let mut child = node;
+        let mut parent = self.parent(index, node);
+        while parent != Self::NIL && self.right(index, parent) == child {
+            child = parent;
+            parent = self.parent(index, parent);
+        }
+        parent
+    }
+
+    /// Calls [Linkage::update] on `node` and each of its ancestors in index `index`, if the implementor keeps anything.
+    fn update_path(&mut self, index: usize, mut node: Self::Node) {
+        if !Self::AUGMENTED {
+            return;
+        }
+        while node != Self::NIL {
+            self.update(index, node);
+            node = self.parent(index, node);
+        }
+    }
+
+    /// Attaches the new node `node` to index `index` as a red leaf and restores the red-black invariants.
+    fn link(&mut self, index: usize, node: Self::Node) {
+        let mut parent = Self::NIL;
+        let mut left = false;
+        let mut current = self.head(index);
+        while current != Self::NIL {
+            parent = current;
+            left = self.compare(index, node, current).is_lt();
+            current = match left {
+                true => self.left(index, current),
+                false => self.right(index, current),
+            };
+        }
+        self.attach(index, node, parent, left);
+    }
+
+    /// Attaches the new node `node` to index `index` as a red leaf under `parent`, on its left if `left`, or as the
+    /// head if `parent` is missing, and restores the red-black invariants.
+    fn attach(&mut self, index: usize, mut node: Self::Node, parent: Self::Node, left: bool) {
+        self.set_parent(index, node, parent);
+        self.set_left(index, node, Self::NIL);
+        self.set_right(index, node, Self::NIL);
+        self.set_color(index, node, RED);
+        if parent == Self::NIL {
+            self.set_head(index, node);
+        } else if left {
+            self.set_left(index, parent, node);
+        } else {
+            self.set_right(index, parent, node);
+        }
+        self.update_path(index, node);
+
+        while self.color(index, self.parent(index, node)) == RED {
+            let parent = self.parent(index, node);
+            // A red parent is never the head, so the grandparent exists.
+            let grandparent = self.parent(index, parent);
+            let parent_is_left = self.left(index, grandparent) == parent;
+            let uncle = match parent_is_left {
+                true => self.right(index, grandparent),
+                false => self.left(index, grandparent),
+            };
+            if self.color(index, uncle) == RED {
+                self.set_color(index, parent, BLACK);
+                self.set_color(index, uncle, BLACK);
+                self.set_color(index, grandparent, RED);
+                node = grandparent;
+                continue;
+            }
+            let inner = match parent_is_left {
+                true => self.right(index, parent) == node,
+                false => self.left(index, parent) == node,
+            };
+            if inner {
+                node = parent;
+                self.rotate(index, node, parent_is_left);
+            }
+            let parent = self.parent(index, node);
+            self.set_color(index, parent, BLACK);
+            self.set_color(index, grandparent, RED);
+            self.rotate(index, grandparent, !parent_is_left);
+        }
+        let head = self.head(index);
+        self.set_color(index, head, BLACK);
+    }
+
+    /// Rotates the subtree of index `index` rooted at `node` to the left if `left`, or else to the right, so that
+    /// the child on the opposite side takes its place.
+    fn rotate(&mut self, index: usize, node: Self::Node, left: bool) {
+        let child = match left {
+            true => self.right(index, node),
+            false => self.left(index, node),
+        };
+        let moved = match left {
+            true => self.left(index, child),
+            false => self.right(index, child),
+        };
+        match left {
+            true => self.set_right(index, node, moved),
+            false => self.set_left(index, node, moved),
+        }
+        if moved != Self::NIL {
+            self.set_parent(index, moved, node);
+        }
+        self.transplant(index, node, child);
+        match left {
+            true => self.set_left(index, child, node),
+            false => self.set_right(index, child, node),
+        }
+        self.set_parent(index, node, child);
+        if Self::AUGMENTED {
+            self.update(index, node);
+            self.update(index, child);
+        }
+    }
+
+    /// Puts `new` in the place of `old` under the parent of `old` in index `index`.
+    fn transplant(&mut self, index: usize, old: Self::Node, new: Self::Node) {
+        let parent = self.parent(index, old);
+        if parent == Self::NIL {
+            self.set_head(index, new);
+        } else if self.left(index, parent) == old {
+            self.set_left(index, parent, new);
+        } else {
+            self.set_right(index, parent, new);
+        }
+        if new != Self::NIL {
+            self.set_parent(index, new, parent);
+        }
+    }
+
+    /// Detaches `node` from index `index` and restores the red-black invariants.
+    fn unlink(&mut self, index: usize, node: Self::Node) {
+        let (left, right) = (self.left(index, node), self.right(index, node));
+        let mut removed_color = self.node_color(index, node);
+        let (child, mut parent);
+        if left == Self::NIL || right == Self::NIL {
+            child = match left == Self::NIL {
+                true => right,
+                false => left,
+            };
+            parent = self.parent(index, node);
+            self.transplant(index, node, child);
+        } else {
+            // Move the successor into the place of the node, and fix up from where the successor was.
+            let successor = self.min_node(index, right);
+            removed_color = self.node_color(index, successor);
+            child = self.right(index, successor);
+            if self.parent(index, successor) == node {
+                parent = successor;
+            } else {
+                parent = self.parent(index, successor);
+                self.transplant(index, successor, child);
+                self.set_right(index, successor, right);
+                self.set_parent(index, right, successor);
+            }
+            self.transplant(index, node, successor);
+            self.set_left(index, successor, left);
+            self.set_parent(index, left, successor);
+            self.set_color(index, successor, self.node_color(index, node));
+        }
+        self.update_path(index, parent);
+        if removed_color == RED {
+            return;
+        }
+
+        // `child` is missing a black node on its paths, which is fixed by recoloring or rotating around its sibling.
+        let mut child = child;
+        while child != self.head(index) && self.color(index, child) == BLACK {
+            let child_is_left = self.left(index, parent) == child;
+            let sibling_of = |tree: &Self| match child_is_left {
+                true => tree.right(index, parent),
+                false => tree.left(index, parent),
+            };
+            let mut sibling = sibling_of(self);
+            if self.color(index, sibling) == RED {
+                self.set_color(index, sibling, BLACK);
+                self.set_color(index, parent, RED);
+                self.rotate(index, parent, child_is_left);
+                sibling = sibling_of(self);
+            }
+            let (near, far) = match child_is_left {
+                true => (self.left(index, sibling), self.right(index, sibling)),
+                false => (self.right(index, sibling), self.left(index, sibling)),
+            };
+            if self.color(index, near) == BLACK && self.color(index, far) == BLACK {
+                self.set_color(index, sibling, RED);
+                child = parent;
+                parent = self.parent(index, child);
+                continue;
+            }
+            if self.color(index, far) == BLACK {
+                self.set_color(index, near, BLACK);
+                self.set_color(index, sibling, RED);
+                self.rotate(index, sibling, !child_is_left);
+                sibling = sibling_of(self);
+            }
+            let far = match child_is_left {
+                true => self.right(index, sibling),
+                false => self.left(index, sibling),
+            };
+            self.set_color(index, sibling, self.node_color(index, parent));
+            self.set_color(index, parent, BLACK);
+            self.set_color(index, far, BLACK);
+            self.rotate(index, parent, child_is_left);
+            child = self.head(index);
+        }
+        if child != Self::NIL {
+            self.set_color(index, child, BLACK);
+        }
+    }
+}
@@ -1,788 +1,2964 @@
 extern crate alloc;
 
-use crate::bst::BstKey;
-
-use super::{Error, Result};
-use core::mem::size_of;
-use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use super::{Error, OrderedKey, Result};
+use crate::pool::{self, NodePool, PoolHandle, Slot, Storage};
+use crate::rb::{Forest, Linkage, BLACK, NULL, RED};
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU16, AtomicU32, Ordering};
 use core::{ptr, slice};
 
-const RED: bool = false;
-const BLACK: bool = true;
+/// Identifies a snapshot written by [Rbt::save_to].
+const SNAPSHOT_MAGIC: u32 = u32::from_le_bytes(*b"RBTS");
+/// The version of the snapshot format. Bumped whenever the layout changes, so older snapshots are rejected.
+const SNAPSHOT_VERSION: u32 = 2;
+/// The number of bytes [Rbt::save_to] writes ahead of the elements: the magic, the format version, the size of an
+/// element and the number of elements, each as a little-endian `u32`.
+pub const SNAPSHOT_HEADER_SIZE: usize = 4 * size_of::<u32>();
+
+/// An element that [Rbt::save_to] can write as bytes and [Rbt::load_from] can read back. Elements are written field by
+/// field, so no padding ends up in a snapshot and no bytes are taken for a value they do not hold.
+pub trait Snapshot: Sized {
+    /// The number of bytes an element takes in a snapshot.
+    const SIZE: usize;
+    /// Writes the element into `out`, which is [Self::SIZE] bytes long.
+    fn write_to(&self, out: &mut [u8]);
+    /// Reads an element from `bytes`, which are [Self::SIZE] bytes long, or returns `None` if they do not hold one.
+    fn read_from(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_snapshot {
+    ($($ty:ty),*) => {
+        $(
+            impl Snapshot for $ty {
+                const SIZE: usize = size_of::<$ty>();
 
-pub const fn node_size<D: core::cmp::PartialOrd>() -> usize {
-    size_of::<(bool, Node<D>)>()
+                fn write_to(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_from(bytes: &[u8]) -> Option<Self> {
+                    Some(<$ty>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
 }
 
-pub trait RbtKey {
-    type Key: Ord;
-    fn ordering_key(&self) -> &Self::Key;
+impl_snapshot!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Snapshot for bool {
+    const SIZE: usize = 1;
+
+    fn write_to(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn read_from(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
 }
 
-impl<T> RbtKey for T
-where
-    T: Ord,
-{
-    type Key = Self;
-    fn ordering_key(&self) -> &T {
-        self
+impl<A: Snapshot, B: Snapshot> Snapshot for (A, B) {
+    const SIZE: usize = A::SIZE + B::SIZE;
+
+    fn write_to(&self, out: &mut [u8]) {
+        let (a, b) = out.split_at_mut(A::SIZE);
+        self.0.write_to(a);
+        self.1.write_to(b);
+    }
+
+    fn read_from(bytes: &[u8]) -> Option<Self> {
+        let (a, b) = bytes.split_at(A::SIZE);
+        Some((A::read_from(a)?, B::read_from(b)?))
     }
 }
 
-/// A on-stack storage container for the nodes of a red-black tree.
-struct Storage<'a, D, const SIZE: usize>
+pub const fn node_size<D: core::cmp::PartialOrd>() -> usize {
+    size_of::<Node<D>>()
+}
+
+/// Declares a statically allocated [Rbt] named `$name` that can hold `$size` nodes of type `$d`.
+///
+/// The macro expands to a static [AlignedStorage] buffer along with a unit struct whose `take` function lazily
+/// initializes the tree on first call and hands out the only `&'static mut` reference to it. Every later call to
+/// `take` returns `None`.
+///
+/// ```ignore
+/// rbt_static!(MEMORY_MAP, u32, 256);
+///
+/// let rbt = MEMORY_MAP::take().unwrap();
+/// rbt.insert(5).unwrap();
+/// ```
+#[macro_export]
+macro_rules! rbt_static {
+    ($vis:vis $name:ident, $d:ty, $size:expr) => {
+        #[allow(non_camel_case_types)]
+        $vis struct $name;
+
+        impl $name {
+            pub fn take() -> Option<&'static mut $crate::rbt::Rbt<'static, $d, { $size }>> {
+                use core::mem::MaybeUninit;
+                use core::sync::atomic::{AtomicBool, Ordering};
+
+                static TAKEN: AtomicBool = AtomicBool::new(false);
+                static mut BUFFER: $crate::rbt::AlignedStorage<$d, { $size }> =
+                    $crate::rbt::AlignedStorage::new();
+                static mut TREE: MaybeUninit<$crate::rbt::Rbt<'static, $d, { $size }>> =
+                    MaybeUninit::uninit();
+
+                if TAKEN
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+                {
+                    return None;
+                }
+
+                // SAFETY: The TAKEN flag guarantees this block runs at most once, so the references created here
+                // are the only references to BUFFER and TREE that will ever exist.
+                unsafe {
+                    let storage = &mut *core::ptr::addr_of_mut!(BUFFER);
+                    let tree = &mut *core::ptr::addr_of_mut!(TREE);
+                    Some(tree.write($crate::rbt::Rbt::new_in(storage)))
+                }
+            }
+        }
+    };
+}
+
+/// Alias of [OrderedKey], kept so existing implementations continue to compile.
+pub use crate::OrderedKey as RbtKey;
+
+/// A buffer with the size and alignment to back a [Rbt] of up to `SIZE` elements of type `D`, for use with
+/// [Rbt::new_in]. Its slots start out uninitialized, so it can be declared as a `static` without spelling out a byte
+/// count.
+pub struct AlignedStorage<D, const SIZE: usize>
 where
     D: PartialOrd,
 {
-    data: &'a mut [(bool, Node<D>)],
-    length: usize,
-    free_indices: arrayvec::ArrayVec<u16, SIZE>,
+    slots: [MaybeUninit<Node<D>>; SIZE],
 }
 
-impl<'a, D, const SIZE: usize> Storage<'a, D, { SIZE }>
+impl<D, const SIZE: usize> AlignedStorage<D, SIZE>
 where
     D: PartialOrd,
 {
-    /// Create a new storage container.
-    fn new(slice: &'a mut [u8]) -> Storage<'a, D, SIZE> {
-        Storage {
-            data: unsafe {
-                slice::from_raw_parts_mut::<'a, (bool, Node<D>)>(
-                    slice as *mut [u8] as *mut (bool, Node<D>),
-                    SIZE,
-                )
-            },
-            length: 0,
-            free_indices: arrayvec::ArrayVec::from(array_init::array_init(|i| i as u16)),
-        }
-    }
-
-    fn len(&self) -> usize {
-        self.length
-    }
-
-    /// Add a new node to the storage container, returning a mutable reference to the node.
-    fn add(&mut self, data: D) -> Result<&mut Node<D>> {
-        if let Some(index) = self.free_indices.pop() {
-            self.data[index as usize] = (true, Node::new(data));
-            let (_, node) = self.data.get_mut(index as usize).unwrap();
-            self.length += 1;
-            return Ok(node);
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { MaybeUninit::uninit() }; SIZE],
         }
-        Err(Error::OutOfSpace)
     }
+}
 
-    /// Delete a node from the storage container.
-    fn delete(&mut self, ptr: *mut Node<D>) {
-        // Calculate the index of the node in the storage container based off the pointer.
-        let index =
-            (ptr as usize - self.data.as_ptr() as usize) / core::mem::size_of::<(bool, Node<D>)>();
-        self.data[index].0 = false;
-        self.length -= 1;
-        self.free_indices.push(index as u16);
+impl<D, const SIZE: usize> Default for AlignedStorage<D, SIZE>
+where
+    D: PartialOrd,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 /// A red-black tree that can hold up to `SIZE` nodes.
 ///
-/// The tree is implemented using the [AtomicPtr] structure, so the target must support atomic operations.
+/// Nodes link to each other through atomic slot indices, so the target must support atomic operations. Because the
+/// links do not depend on where the storage lives, its bytes can be moved and picked up again with [Self::restore].
 /// The storage is allocated on the stack with [Self::new] or statically at any address using [Self::new_at].
-/// TODO: storage probably needs to be stored differently as we want to allocate it at a specific address.
-pub struct Rbt<'a, D, const SIZE: usize>
+///
+/// A `SIZE` of 0 takes the capacity from the length of the buffer instead; see [RbtDyn]. The nodes can also come from
+/// any other [NodePool] through [Self::with_pool], in which case `SIZE` is unused.
+pub struct Rbt<'a, D, const SIZE: usize, P = Storage<'a, Node<D>, SIZE>>
 where
-    D: PartialOrd,
+    D: PartialOrd + OrderedKey,
 {
-    storage: Storage<'a, D, SIZE>,
+    storage: P,
     head: AtomicPtr<Node<D>>,
+    /// Orders the keys on every descent. This is `Ord::cmp` unless the tree was created with [Rbt::new_by].
+    order: fn(&D::Key, &D::Key) -> core::cmp::Ordering,
+    lifetime: PhantomData<&'a mut [Node<D>]>,
 }
 
+/// A [Rbt] that holds as many nodes as fit in the buffer it is created with, for regions whose size is only known at
+/// run time. The free list is kept in the buffer, so nothing else depends on the capacity.
+pub type RbtDyn<'a, D> = Rbt<'a, D, 0>;
+
+/// A [pool::SharedPool] of up to `SIZE` nodes for any number of [Rbt]s over elements of type `D`.
+pub type SharedPool<D, const SIZE: usize> = pool::SharedPool<Node<D>, SIZE>;
+
+/// A [Rbt] that draws its nodes from a [SharedPool], created with [Rbt::with_pool] from [pool::SharedPool::handle].
+pub type SharedRbt<'p, D, const SIZE: usize> = Rbt<'p, D, 0, PoolHandle<'p, Node<D>, SIZE>>;
+
+/// A [Rbt] that owns its nodes on the heap, created with [Rbt::with_capacity].
+#[cfg(feature = "alloc")]
+pub type HeapRbt<D> = Rbt<'static, D, 0, pool::HeapPool<Node<D>>>;
+
 impl<'a, D, const SIZE: usize> Rbt<'a, D, { SIZE }>
 where
-    D: PartialOrd + Copy + core::fmt::Debug + BstKey,
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
 {
+    /// Creates an empty tree backed by `slice`. Panics if `slice` is shorter than `SIZE * node_size::<D>()` bytes or
+    /// is not aligned for the nodes.
     pub fn new(slice: &'a mut [u8]) -> Rbt<D, SIZE> {
-        Rbt {
-            storage: Storage::new(slice),
-            head: AtomicPtr::default(),
+        Self::new_by(slice, Ord::cmp)
+    }
+
+    /// Creates an empty tree backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        Storage::<Node<D>, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice))
+    }
+
+    /// Creates an empty tree backed by `slice` that orders keys with `order` instead of their `Ord` implementation.
+    /// A closure that captures nothing, such as `|a, b| b.cmp(a)`, coerces to the function pointer. Panics like
+    /// [Self::new].
+    pub fn new_by(slice: &'a mut [u8], order: fn(&D::Key, &D::Key) -> core::cmp::Ordering) -> Self {
+        Self {
+            order,
+            ..Self::new_uninit(crate::as_uninit(slice))
         }
     }
 
-    fn head(&self) -> Option<&Node<D>> {
-        let head_ptr = self.head.load(Ordering::SeqCst);
-        if head_ptr.is_null() {
-            return None;
+    /// Creates an empty tree backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self::with_pool(Storage::new(slice))
+    }
+
+    /// Creates an empty tree backed by `storage`, which always has the right size and alignment.
+    pub fn new_in(storage: &'a mut AlignedStorage<D, SIZE>) -> Self {
+        let len = SIZE * node_size::<D>();
+        Self::new_uninit(unsafe {
+            slice::from_raw_parts_mut(storage.slots.as_mut_ptr().cast(), len)
+        })
+    }
+
+    /// Creates an empty tree over the `len` bytes starting at `addr`, such as a reserved memory region. Returns
+    /// `Error::BadBuffer` if the region cannot hold `SIZE` nodes or `addr` is not aligned for them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the region for the lifetime `'a`: it must be valid for writes and not accessed through any
+    /// other pointer while the tree is alive. Its contents may be uninitialized.
+    pub unsafe fn new_at(addr: *mut u8, len: usize) -> Result<Self> {
+        let slice = unsafe { slice::from_raw_parts_mut(addr as *mut MaybeUninit<u8>, len) };
+        Storage::<Node<D>, SIZE>::check(slice)?;
+        Ok(Self::new_uninit(slice))
+    }
+
+    /// Wraps a buffer that holds the storage of another tree, which may have been copied from a different address.
+    /// Links between nodes are stored as slot indices, so the tree is usable once every node is pointed at the new
+    /// buffer and the head is found again.
+    ///
+    /// # Safety
+    ///
+    /// `slice` must hold the bytes of the storage of a tree of the same type, such as the buffer of a tree that is no
+    /// longer used or a copy of it, created with [Self::new]. The nodes are used as-is. The restored tree drops the
+    /// elements it holds, so the tree the bytes came from must be forgotten with `core::mem::forget` rather than
+    /// dropped if `D` has a destructor.
+    pub unsafe fn restore(slice: &'a mut [u8]) -> Self {
+        let (storage, head) = Storage::<Node<D>, SIZE>::restore(slice);
+        // The nodes still point at slot 0 of the buffer they were written in.
+        for node in storage.occupied() {
+            node.base.store(storage.base(), Ordering::Release);
+        }
+        Self {
+            storage,
+            head: AtomicPtr::new(head),
+            order: Ord::cmp,
+            lifetime: PhantomData,
         }
-        Some(unsafe { &*head_ptr })
     }
+}
 
-    pub fn insert(&mut self, data: D) -> Result<()> {
-        let node = self.storage.add(data).unwrap();
-        node.set_color(RED);
+#[cfg(feature = "alloc")]
+impl<D> Rbt<'static, D, 0, pool::HeapPool<Node<D>>>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey + 'static,
+{
+    /// Creates an empty tree that owns a heap buffer of `capacity` nodes and frees it when dropped.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_pool(pool::HeapPool::new(capacity))
+    }
+}
 
-        if self.head.load(Ordering::SeqCst).is_null() {
-            node.set_color(BLACK);
-            self.head.store(node, Ordering::SeqCst);
-            return Ok(());
+impl<'a, D, const SIZE: usize, P> Rbt<'a, D, SIZE, P>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+{
+    /// Creates an empty tree that draws its nodes from `pool`, which must be empty.
+    pub fn with_pool(pool: P) -> Self {
+        Self::with_pool_by(pool, Ord::cmp)
+    }
+
+    /// Creates an empty tree that draws its nodes from `pool`, which must be empty, and orders keys with `order`
+    /// like [Rbt::new_by].
+    pub fn with_pool_by(pool: P, order: fn(&D::Key, &D::Key) -> core::cmp::Ordering) -> Self {
+        assert!(pool.is_empty(), "The pool of a new tree must be empty");
+        Self {
+            storage: pool,
+            head: AtomicPtr::default(),
+            order,
+            lifetime: PhantomData,
         }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
 
-        let head = unsafe { &mut *self.head.load(Ordering::SeqCst) };
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        Self::insert_node(head, node);
-        Self::fixup_insert(&self.head, node);
-        head.set_color(BLACK);
+    /// Returns the maximum number of elements the tree can hold. This is `SIZE`, unless `SIZE` is 0 and the capacity
+    /// was taken from the length of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
 
-        return Ok(());
+    /// Returns the number of elements that can still be inserted before the tree runs out of space.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
     }
 
-    pub fn search(&self, key: &D::Key) -> Option<D> {
-        let mut current_idx = self.head();
-        while let Some(node) = current_idx {
-            if key == node.data.ordering_key() {
-                return Some(node.data);
-            } else if key < node.data.ordering_key() {
-                current_idx = node.left();
-            } else {
-                current_idx = node.right();
-            }
-        }
-        None
+    /// Removes every element from the tree. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.head.store(ptr::null_mut(), Ordering::Release);
+        self.storage.clear();
     }
 
-    pub fn delete(&mut self, data: D) -> Result<()> {
+    /// Checks every red-black invariant, returning the black height of the tree, or a description of the first
+    /// invariant that does not hold. The walk only uses the recursion stack, so it is cheap enough to call after
+    /// every operation while debugging.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
         let Some(head) = self.head() else {
-            return Err(Error::NotFound);
+            return Ok(0);
         };
-        let mut current = head;
-        loop {
-            if data == current.data {
-                break;
-            } else if data < current.data {
-                if let Some(left) = current.left() {
-                    current = left;
-                } else {
-                    return Err(Error::NotFound);
-                }
-            } else {
-                if let Some(right) = current.right() {
-                    current = right;
-                } else {
-                    return Err(Error::NotFound);
-                }
+        if head.is_red() {
+            return Err("head is red");
+        }
+        if head.parent().is_some() {
+            return Err("head has a parent");
+        }
+        let black_height = Self::validate_node(head)?;
+
+        let mut previous = self.min_node();
+        while let Some(node) = previous {
+            let next = node.next_node();
+            if next.is_some_and(|next| {
+                self.compare(next.data.ordering_key(), node.data.ordering_key())
+                    .is_le()
+            }) {
+                return Err("keys are not in ascending order");
             }
+            previous = next;
         }
+        Ok(black_height)
+    }
 
-        let color = current.is_red();
+    fn validate_node(node: &Node<D>) -> core::result::Result<usize, &'static str> {
+        let mut heights = [1; 2];
+        for (height, child) in heights.iter_mut().zip([node.left(), node.right()]) {
+            let Some(child) = child else {
+                continue;
+            };
+            if child.parent_ptr() != node.as_mut_ptr() {
+                return Err("child does not point back to its parent");
+            }
+            if node.is_red() && child.is_red() {
+                return Err("red node has a red child");
+            }
+            *height = Self::validate_node(child)?;
+        }
+        if node.size() != 1 + Node::subtree_size(node.left()) + Node::subtree_size(node.right()) {
+            return Err("subtree size does not match its children");
+        }
+        if heights[0] != heights[1] {
+            return Err("black height differs between paths");
+        }
+        Ok(heights[0] + node.is_black() as usize)
+    }
 
-        let moved_up = if current.left().is_none() | current.right().is_none() {
-            Self::delete_simple(head, current)
-        } else {
-            Self::delete_complex(current)
-        };
+    fn head(&self) -> Option<&Node<D>> {
+        let head_ptr = self.head.load(Ordering::Acquire);
+        if head_ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { &*head_ptr })
+    }
 
-        if let Some(node) = moved_up
-            && color == BLACK
-        {
-            Self::fixup_delete(&self.head, node);
+    fn compare(&self, a: &D::Key, b: &D::Key) -> core::cmp::Ordering {
+        (self.order)(a, b)
+    }
+
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        match self.search_slot(data.ordering_key()) {
+            Ok(_) => Err(Error::AlreadyExists),
+            Err((parent, left)) => self.link_leaf(data, parent, left).map(|_| ()),
         }
+    }
 
-        self.storage.delete(current.as_mut_ptr());
-        Ok(())
+    /// Returns an [Entry] for `key`, which finds or inserts the element stored under it in a single descent.
+    pub fn entry<'b>(&'b mut self, key: &'b D::Key) -> Entry<'b, 'a, D, SIZE, P> {
+        Entry { tree: self, key }
     }
 
-    // Deletes a node with 0 or 1 children.
-    fn delete_simple<'b>(head: &'b Node<D>, node: &'b Node<D>) -> Option<&'b Node<D>> {
-        let parent = match node.parent() {
-            Some(parent) => parent,
-            None => head,
-        };
-        if let Some(left) = node.left() {
-            left.set_parent(parent);
-            if parent.left_ptr() == node.as_mut_ptr() {
-                parent.set_left(left);
-            } else {
-                parent.set_right(left);
+    /// Returns a reference to the element stored under `key`, or inserts the element built by `f` if the key is
+    /// absent. `f` is only called on a miss, and the element it returns must have an ordering key equal to `key`.
+    /// Both cases take a single descent from the head. Returns `Error::OutOfSpace` if an element has to be inserted
+    /// but the tree is full.
+    pub fn get_or_insert_with<F: FnOnce() -> D>(&mut self, key: &D::Key, f: F) -> Result<&D> {
+        let node = match self.search_slot(key) {
+            Ok(node) => node,
+            Err((parent, left)) => {
+                let data = f();
+                debug_assert!(self.compare(key, data.ordering_key()).is_eq());
+                self.link_leaf(data, parent, left)?
             }
-            return Some(left);
-        } else if let Some(right) = node.right() {
-            right.set_parent(node);
-            if parent.left_ptr() == node.as_mut_ptr() {
-                parent.set_left(right);
-            } else {
-                parent.set_right(right);
+        };
+        Ok(unsafe { &(*node).data })
+    }
+
+    /// Inserts `data`, replacing the element stored under the same key if there is one. Returns the replaced element,
+    /// or None if `data` was newly inserted. Returns `Error::OutOfSpace` if `data` has to be inserted but the tree is
+    /// full.
+    pub fn insert_or_replace(&mut self, data: D) -> Result<Option<D>> {
+        match self.search_slot(data.ordering_key()) {
+            Ok(node) => Ok(Some(unsafe { ptr::replace(&mut (*node).data, data) })),
+            Err((parent, left)) => {
+                self.link_leaf(data, parent, left)?;
+                Ok(None)
             }
-            return Some(right);
-        } else {
-            if parent.left_ptr() == node.as_mut_ptr() {
-                parent.set_left(ptr::null_mut());
-            } else {
-                parent.set_right(ptr::null_mut());
+        }
+    }
+
+    /// Descends from the head looking for `key`. Returns the node holding it, or the parent a new node for `key`
+    /// would be linked under (null for an empty tree) and whether it would be its left child.
+    fn search_slot(
+        &self,
+        key: &D::Key,
+    ) -> core::result::Result<*mut Node<D>, (*mut Node<D>, bool)> {
+        let mut parent = ptr::null_mut();
+        let mut left = false;
+        let mut current = self.head();
+        while let Some(node) = current {
+            let ordering = self.compare(key, node.data.ordering_key());
+            if ordering.is_eq() {
+                return Ok(node.as_mut_ptr());
             }
-            return None;
+            left = ordering.is_lt();
+            parent = node.as_mut_ptr();
+            current = if left { node.left() } else { node.right() };
         }
+        Err((parent, left))
     }
 
-    // Deletes a node with 2 children.
-    fn delete_complex(node: &Node<D>) -> Option<&Node<D>> {
-        todo!()
+    /// Stores `data` in a new node linked as a child of `parent`, as returned by [Self::search_slot], and rebalances.
+    fn link_leaf(&mut self, data: D, parent: *mut Node<D>, left: bool) -> Result<*mut Node<D>> {
+        let base = self.storage.base();
+        let node = self
+            .storage
+            .add(|index| Node::new(data, index, base))?
+            .as_mut_ptr();
+        self.attach(0, node, parent, left);
+
+        debug_assert!(self.parents_consistent());
+        Ok(node)
     }
 
-    fn insert_node(start: &Node<D>, node: &Node<D>) {
-        let mut current = start;
-        loop {
-            if node.data < current.data {
-                match current.left() {
-                    Some(left) => current = left,
-                    None => {
-                        current.set_left(node);
-                        node.set_parent(current);
-                        return;
-                    }
-                }
-            } else if node.data > current.data {
-                match current.right() {
-                    Some(right) => current = right,
-                    None => {
-                        current.set_right(node);
-                        node.set_parent(current);
-                        return;
-                    }
-                }
-            } else {
-                panic!("Node already exists in the tree.");
-            }
+    /// Returns true if an element with the given key is stored. Nothing is copied out of the tree.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_node(key).is_some()
+    }
+
+    /// Returns a reference to the element with the given key. Unlike `search`, this does not require `D: Copy`.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        self.search_node(key).map(|node| &node.data)
+    }
+
+    /// Like [Self::floor], but returns a reference.
+    pub fn floor_ref(&self, key: &D::Key) -> Option<&D> {
+        self.bound_node(key, false, false).map(|node| &node.data)
+    }
+
+    /// Like [Self::ceil], but returns a reference.
+    pub fn ceil_ref(&self, key: &D::Key) -> Option<&D> {
+        self.bound_node(key, true, false).map(|node| &node.data)
+    }
+
+    /// Alias of [Self::ceil_ref].
+    pub fn ceiling_ref(&self, key: &D::Key) -> Option<&D> {
+        self.ceil_ref(key)
+    }
+
+    /// Like [Self::successor], but returns a reference.
+    pub fn successor_ref(&self, key: &D::Key) -> Option<&D> {
+        let node = self.search_node_or_last(key)?;
+        if self.compare(node.data.ordering_key(), key).is_gt() {
+            return Some(&node.data);
         }
+        node.next_node().map(|node| &node.data)
     }
 
-    fn rotate_left(head: &AtomicPtr<Node<D>>, node: &Node<D>) {
-        let right_child = node
-            .right()
-            .expect("Right Child should always exist when rotating.");
-        let parent_tmp = node.parent();
-        node.set_right(right_child.left_ptr());
-        if let Some(left) = right_child.left() {
-            left.set_parent(node);
+    /// Like [Self::predecessor], but returns a reference.
+    pub fn predecessor_ref(&self, key: &D::Key) -> Option<&D> {
+        let node = self.search_node_or_last(key)?;
+        if self.compare(node.data.ordering_key(), key).is_lt() {
+            return Some(&node.data);
         }
+        node.prev_node().map(|node| &node.data)
+    }
+
+    /// Like [Self::select], but returns a reference.
+    pub fn select_ref(&self, k: usize) -> Option<&D> {
+        self.select_node(k).map(|node| &node.data)
+    }
+
+    /// Like [Self::min], but returns a reference.
+    pub fn min_ref(&self) -> Option<&D> {
+        self.min_node().map(|node| &node.data)
+    }
+
+    /// Like [Self::max], but returns a reference.
+    pub fn max_ref(&self) -> Option<&D> {
+        self.max_node().map(|node| &node.data)
+    }
 
-        right_child.set_left(node);
-        node.set_parent(right_child);
+    /// Returns a mutable reference to the element with the given key, so fields other than its ordering key can be
+    /// updated in place. The ordering key must not be changed through the returned reference.
+    pub fn get_mut(&mut self, key: &D::Key) -> Option<&mut D> {
+        let node = self.search_node(key)?.as_mut_ptr();
+        Some(unsafe { &mut (*node).data })
+    }
 
-        if let Some(parent) = parent_tmp {
-            if parent.left_ptr() == node.as_mut_ptr() {
-                parent.set_left(right_child);
-                right_child.set_parent(parent);
-            } else if parent.right_ptr() == node.as_mut_ptr() {
-                parent.set_right(right_child);
-                right_child.set_parent(parent);
+    fn search_node(&self, key: &D::Key) -> Option<&Node<D>> {
+        let mut current_idx = self.head();
+        while let Some(node) = current_idx {
+            let ordering = self.compare(key, node.data.ordering_key());
+            if ordering.is_eq() {
+                return Some(node);
+            } else if ordering.is_lt() {
+                current_idx = node.left();
             } else {
-                panic!("Node is not a child of it's parents");
+                current_idx = node.right();
             }
-        } else {
-            head.store(right_child.as_mut_ptr(), Ordering::SeqCst);
-            right_child.set_parent(ptr::null_mut());
         }
+        None
     }
 
-    fn rotate_right(head: &AtomicPtr<Node<D>>, node: &Node<D>) {
-        let left_child = node.left().unwrap();
-        let parent_tmp = node.parent();
-        node.set_left(left_child.right_ptr());
-        if let Some(right) = left_child.right() {
-            right.set_parent(node);
+    /// Descends from the head towards `key`, returning the node holding it or the last node visited, which is the
+    /// in-order neighbour on one side of where `key` would be inserted.
+    fn search_node_or_last(&self, key: &D::Key) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        loop {
+            let next = match self.compare(key, current.data.ordering_key()) {
+                core::cmp::Ordering::Less => current.left(),
+                core::cmp::Ordering::Greater => current.right(),
+                core::cmp::Ordering::Equal => return Some(current),
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Some(current),
+            }
         }
+    }
 
-        left_child.set_right(node);
-        node.set_parent(left_child);
+    /// Returns the black height of the subtree rooted at `key`, counting the null leaves. Returns None if the key is
+    /// not in the tree or if two paths in the subtree pass through a different number of black nodes.
+    pub fn subtree_black_height(&self, key: &D::Key) -> Option<usize> {
+        let node = self.search_node(key)?;
+        Self::black_height(Some(node))
+    }
 
-        if let Some(parent) = parent_tmp {
-            if parent.left_ptr() == node.as_mut_ptr() {
-                parent.set_left(left_child);
-                left_child.set_parent(parent);
-            } else if parent.right_ptr() == node.as_mut_ptr() {
-                parent.set_right(left_child);
-                left_child.set_parent(parent);
-            } else {
-                panic!("Node is not a child of it's parents");
+    fn black_height(node: Option<&Node<D>>) -> Option<usize> {
+        let Some(node) = node else {
+            return Some(1);
+        };
+        let left = Self::black_height(node.left())?;
+        let right = Self::black_height(node.right())?;
+        (left == right).then_some(left + node.is_black() as usize)
+    }
+
+    pub fn delete(&mut self, data: D) -> Result<()> {
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the tree, returning it. Returns `None` if no element has the key.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        let node_ptr = self.search_node(key)?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes and returns the element with the smallest key, or `None` if the tree is empty. The node is taken from
+    /// the end of the leftmost spine, so no search by key is needed.
+    pub fn pop_min(&mut self) -> Option<D> {
+        let node_ptr = self.min_node()?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes and returns the element with the largest key, or `None` if the tree is empty.
+    pub fn pop_max(&mut self) -> Option<D> {
+        let node_ptr = self.max_node()?.as_mut_ptr();
+        Some(self.remove_node(node_ptr))
+    }
+
+    /// Removes every element for which `f` returns false, visiting the elements in ascending key order. Removing a
+    /// node only relinks the nodes around it, so the walk continues from the successor found before the removal.
+    pub fn retain<F: FnMut(&D) -> bool>(&mut self, mut f: F) {
+        let mut current = self.min_node().map(Node::as_mut_ptr);
+        while let Some(node_ptr) = current {
+            let node = unsafe { &*node_ptr };
+            current = node.next_node().map(Node::as_mut_ptr);
+            if !f(&node.data) {
+                self.remove_node(node_ptr);
             }
-        } else {
-            head.store(left_child.as_mut_ptr(), Ordering::SeqCst);
-            left_child.set_parent(ptr::null_mut());
         }
     }
 
-    fn fixup_insert(head: &AtomicPtr<Node<D>>, node: &Node<D>) {
-        // Case 1: The node is the root of the tree, no fixups needed.
-        let Some(mut parent) = node.parent() else {
-            node.set_color(BLACK);
-            return;
-        };
+    /// Unlinks the node at `node_ptr`, which must be live in this tree, and moves its data out.
+    fn remove_node(&mut self, node_ptr: *mut Node<D>) -> D {
+        self.unlink(0, node_ptr);
+        self.storage.delete(Node::index_of(node_ptr)).data
+    }
 
-        // The parent is black, no fixups needed.
-        if parent.is_black() {
-            return;
+    /// Inserts `data`, or bumps the count of the element with the same key if one is already present. Returns the
+    /// count of the key after the insert. The stored element is kept as is when only the count changes.
+    pub fn insert_dup(&mut self, data: D) -> Result<u32> {
+        if let Some(node) = self.search_node(data.ordering_key()) {
+            return Ok(node.count.fetch_add(1, Ordering::AcqRel) + 1);
         }
+        self.insert(data)?;
+        Ok(1)
+    }
 
-        // Case 2 is enforced by setting the parent to black. If the parent is red, the grandparent should exist.
-        let grandparent = parent
-            .parent()
-            .expect("Parent is red, grandparent should exist");
-        let uncle = Node::sibling(parent);
-
-        // Case 3: Uncle is red, recolor parent, grandparent, uncle
-        if let Some(uncle) = uncle
-            && uncle.is_red()
-        {
-            parent.set_color(BLACK);
-            grandparent.set_color(RED);
-            uncle.set_color(BLACK);
-
-            // Recursively fixup the grandparent
-            Self::fixup_insert(head, grandparent);
-        }
-        // Parent is left child of grandparent
-        else if parent.as_mut_ptr() == grandparent.left_ptr() {
-            // Case 4a: uncle is black and node is left->right "inner child" of it's grandparent
-            if node.as_mut_ptr() == parent.right_ptr() {
-                Self::rotate_left(head, parent);
-                parent = node;
-            }
-            // Case 5a: uncle is black and node is left->left "outer child" of it's grandparent
-            Self::rotate_right(head, grandparent); //todo, need updated parent??
-            parent.set_color(BLACK);
-            grandparent.set_color(RED);
-        }
-        // Parent is right child of grandparent
-        else if parent.as_mut_ptr() == grandparent.right_ptr() {
-            // Case 4b: uncle is black and node is right->left "inner child" of its grandparent
-            if node.as_mut_ptr() == parent.left_ptr() {
-                Self::rotate_right(head, parent);
-                parent = node;
-            }
-            Self::rotate_left(head, grandparent);
-
-            parent.set_color(BLACK);
-            grandparent.set_color(RED);
-        } else {
-            panic!("Parent is not a child of grandparent")
+    /// Decrements the count of the element with the given key, unlinking and dropping it once the count reaches
+    /// zero. Returns the remaining count, or `None` if no element has the key.
+    pub fn remove_one(&mut self, key: &D::Key) -> Option<u32> {
+        let node = self.search_node(key)?;
+        let count = node.count.load(Ordering::Acquire);
+        if count > 1 {
+            node.count.store(count - 1, Ordering::Release);
+            return Some(count - 1);
         }
+        self.remove(key);
+        Some(0)
     }
 
-    fn fixup_delete(head: &AtomicPtr<Node<D>>, node: &Node<D>) {
-        todo!()
+    /// Returns how many times the key is held by the tree, 0 if it is absent.
+    pub fn count(&self, key: &D::Key) -> u32 {
+        self.search_node(key)
+            .map_or(0, |node| node.count.load(Ordering::Acquire))
     }
 
-    fn dfs(&self, node: Option<&Node<D>>, values: &mut alloc::vec::Vec<D>) {
-        if let Some(node) = node {
-            self.dfs(node.left(), values);
-            values.push(node.data);
-            self.dfs(node.right(), values);
+    /// Returns how many stored keys are strictly less than `key`, using the subtree sizes to skip whole subtrees.
+    pub fn rank(&self, key: &D::Key) -> usize {
+        let mut rank = 0;
+        let mut current = self.head();
+        while let Some(node) = current {
+            if self.compare(key, node.data.ordering_key()).is_le() {
+                current = node.left();
+            } else {
+                rank += Node::subtree_size(node.left()) as usize + 1;
+                current = node.right();
+            }
         }
+        rank
     }
 
-    fn len(&self) -> usize {
-        self.storage.length
+    fn select_node(&self, k: usize) -> Option<&Node<D>> {
+        let mut k = k;
+        let mut current = self.head();
+        while let Some(node) = current {
+            let left_size = Node::subtree_size(node.left()) as usize;
+            if k < left_size {
+                current = node.left();
+            } else if k == left_size {
+                return Some(node);
+            } else {
+                k -= left_size + 1;
+                current = node.right();
+            }
+        }
+        None
     }
-}
-
-struct Node<D>
-where
-    D: PartialOrd,
-{
-    data: D,
-    color: AtomicBool,
-    parent: AtomicPtr<Node<D>>,
-    left: AtomicPtr<Node<D>>,
-    right: AtomicPtr<Node<D>>,
-}
 
-impl<D> Node<D>
-where
-    D: PartialOrd,
-{
-    fn new(data: D) -> Self {
-        Node {
-            data,
-            color: AtomicBool::new(RED),
-            parent: AtomicPtr::default(),
-            left: AtomicPtr::default(),
-            right: AtomicPtr::default(),
+    /// Returns true if the head has no parent and every child's parent pointer points back at the node linking to it.
+    pub fn parents_consistent(&self) -> bool {
+        match self.head() {
+            Some(head) => head.parent().is_none() && Self::children_consistent(head),
+            None => true,
         }
     }
 
-    fn set_color(&self, color: bool) {
-        self.color.store(color, Ordering::SeqCst);
+    fn children_consistent(node: &Node<D>) -> bool {
+        let child_consistent = |child: Option<&Node<D>>| match child {
+            Some(child) => {
+                child.parent_ptr() == node.as_mut_ptr() && Self::children_consistent(child)
+            }
+            None => true,
+        };
+        child_consistent(node.left()) && child_consistent(node.right())
     }
 
-    fn is_red(&self) -> bool {
-        self.color.load(Ordering::SeqCst) == RED
+    /// Returns true if every internal node has two children and every leaf is at the same depth.
+    pub fn is_perfect(&self) -> bool {
+        Self::perfect_height(self.head()).is_some()
     }
 
-    fn is_black(&self) -> bool {
-        self.color.load(Ordering::SeqCst) == BLACK
+    /// Returns true if every level is full except possibly the last, which is filled from the left.
+    pub fn is_complete(&self) -> bool {
+        Self::complete_from(self.head(), 0, self.len())
     }
 
-    #[inline(always)]
-    /// Used when you care whether or not the node is null.
-    fn right(&self) -> Option<&Node<D>> {
-        let node = self.right.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
-        }
-        Some(unsafe { &*node })
+    /// Writes the number of nodes at each depth into `out`, with the head at depth 0, and returns the number of levels
+    /// in the tree. Depths that do not fit in `out` are counted in the return value but not written.
+    pub fn depth_histogram_into(&self, out: &mut [usize]) -> usize {
+        out.fill(0);
+        Self::count_depths(self.head(), 0, out)
     }
 
-    /// Used when you don't care whether or not the node is null.
-    #[inline(always)]
-    fn right_ptr(&self) -> *mut Node<D> {
-        self.right.load(Ordering::SeqCst)
+    fn count_depths(node: Option<&Node<D>>, depth: usize, out: &mut [usize]) -> usize {
+        let Some(node) = node else {
+            return depth;
+        };
+        if let Some(count) = out.get_mut(depth) {
+            *count += 1;
+        }
+        let left = Self::count_depths(node.left(), depth + 1, out);
+        let right = Self::count_depths(node.right(), depth + 1, out);
+        left.max(right)
     }
 
-    #[inline(always)]
-    fn set_right<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.right.store(node.into(), Ordering::SeqCst);
+    /// Returns the height of the subtree if it is perfect, otherwise None.
+    fn perfect_height(node: Option<&Node<D>>) -> Option<usize> {
+        let Some(node) = node else {
+            return Some(0);
+        };
+        match (node.left(), node.right()) {
+            (None, None) => Some(1),
+            (Some(_), None) | (None, Some(_)) => None,
+            (left, right) => {
+                let left_height = Self::perfect_height(left)?;
+                let right_height = Self::perfect_height(right)?;
+                (left_height == right_height).then_some(left_height + 1)
+            }
+        }
     }
 
-    #[inline(always)]
-    fn left(&self) -> Option<&Node<D>> {
-        let node = self.left.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
+    /// A tree with `len` nodes is complete if every node's level-order index is less than `len`.
+    fn complete_from(node: Option<&Node<D>>, index: usize, len: usize) -> bool {
+        let Some(node) = node else {
+            return true;
+        };
+        if index >= len {
+            return false;
         }
-        Some(unsafe { &*node })
+        Self::complete_from(node.left(), 2 * index + 1, len)
+            && Self::complete_from(node.right(), 2 * index + 2, len)
     }
 
-    fn left_ptr(&self) -> *mut Node<D> {
-        self.left.load(Ordering::SeqCst)
+    /// Returns an iterator over the elements in ascending key order. The walk follows parent pointers, so it does not
+    /// allocate.
+    pub fn iter(&self) -> Iter<'_, D> {
+        Iter {
+            next: self.min_node(),
+            back: self.max_node(),
+        }
     }
 
-    #[inline(always)]
-    fn set_left<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.left.store(node.into(), Ordering::SeqCst);
+    /// Returns an iterator over the elements whose keys fall within `range`, in ascending key order. Each bound is
+    /// found with one descent, and the walk follows parent pointers from either side.
+    pub fn range<R: RangeBounds<D::Key>>(&self, range: R) -> Range<'_, D> {
+        let next = match range.start_bound() {
+            Bound::Included(lo) => self.bound_node(lo, true, false),
+            Bound::Excluded(lo) => self.bound_node(lo, true, true),
+            Bound::Unbounded => self.min_node(),
+        };
+        let back = match range.end_bound() {
+            Bound::Included(hi) => self.bound_node(hi, false, false),
+            Bound::Excluded(hi) => self.bound_node(hi, false, true),
+            Bound::Unbounded => self.max_node(),
+        };
+        // An empty range leaves the front past the back, in which case neither end is walked.
+        let (next, back) = match (next, back) {
+            (Some(next), Some(back))
+                if self
+                    .compare(next.data.ordering_key(), back.data.ordering_key())
+                    .is_le() =>
+            {
+                (Some(next), Some(back))
+            }
+            _ => (None, None),
+        };
+        Range {
+            inner: Iter { next, back },
+        }
     }
 
-    fn parent(&self) -> Option<&Node<D>> {
-        let node = self.parent.load(Ordering::SeqCst);
-        if node.is_null() {
-            return None;
+    /// Descends once from the head, tracking the closest node above (`above`) or below `key`. A node whose key
+    /// equals `key` is returned immediately unless `strict` is set.
+    fn bound_node(&self, key: &D::Key, above: bool, strict: bool) -> Option<&Node<D>> {
+        let mut best = None;
+        let mut current = self.head();
+        while let Some(node) = current {
+            let ordering = self.compare(key, node.data.ordering_key());
+            if ordering.is_eq() && !strict {
+                return Some(node);
+            }
+            if (above && ordering.is_lt()) || (!above && ordering.is_gt()) {
+                best = Some(node);
+            }
+            current = if ordering.is_lt() || (ordering.is_eq() && !above) {
+                node.left()
+            } else {
+                node.right()
+            };
         }
-        Some(unsafe { &*node })
+        best
     }
 
-    fn parent_ptr(&self) -> *mut Node<D> {
-        self.parent.load(Ordering::SeqCst)
+    /// Writes the tree sideways, one node per line with the right subtree above its parent and the left subtree
+    /// below, indented four spaces per level. Each line shows the node's value and its color, `R` or `B`.
+    #[cfg(any(test, feature = "fmt-tree"))]
+    pub fn fmt_tree(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Self::fmt_subtree(self.head(), 0, f)
     }
 
-    fn set_parent<N: Into<*mut Node<D>>>(&self, node: N) {
-        self.parent.store(node.into(), Ordering::SeqCst);
+    #[cfg(any(test, feature = "fmt-tree"))]
+    fn fmt_subtree(
+        node: Option<&Node<D>>,
+        depth: usize,
+        f: &mut core::fmt::Formatter<'_>,
+    ) -> core::fmt::Result {
+        let Some(node) = node else {
+            return Ok(());
+        };
+        Self::fmt_subtree(node.right(), depth + 1, f)?;
+        writeln!(
+            f,
+            "{:indent$}{:?} ({})",
+            "",
+            node.data,
+            if node.is_red() { 'R' } else { 'B' },
+            indent = depth * 4
+        )?;
+        Self::fmt_subtree(node.left(), depth + 1, f)
     }
 
-    #[inline(always)]
-    fn as_mut_ptr(&self) -> *mut Node<D> {
-        self as *const _ as *mut _
+    fn min_node(&self) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        while let Some(left) = current.left() {
+            current = left;
+        }
+        Some(current)
     }
 
-    fn sibling(node: &Node<D>) -> Option<&Node<D>> {
-        let parent = node.parent()?;
-        match node.as_mut_ptr() {
-            ptr if ptr == parent.left_ptr() => parent.right(),
-            ptr if ptr == parent.right_ptr() => parent.left(),
-            _ => panic!("Node is not a child of its parent."),
+    fn max_node(&self) -> Option<&Node<D>> {
+        let mut current = self.head()?;
+        while let Some(right) = current.right() {
+            current = right;
         }
+        Some(current)
     }
 }
 
-impl<D> core::fmt::Debug for Node<D>
+impl<'a, D, const SIZE: usize, P> Rbt<'a, D, SIZE, P>
 where
-    D: PartialOrd + core::fmt::Debug,
+    D: PartialOrd + Copy + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
 {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let color = if self.is_red() { "  RED" } else { "BLACK" };
-        write!(f, "Node {{ addr: {:?}, parent: {:12?}, left: {:12?}, right: {:12?}, color: {:?}, data: {:?} }}", self.as_mut_ptr(), self.parent_ptr(), self.left_ptr(), self.right_ptr(), color, self.data)
+    /// Links `len` elements, produced in ascending order by `element`, into a balanced tree and returns its head.
+    fn build_sorted(
+        storage: &mut P,
+        element: &impl Fn(usize) -> D,
+        len: usize,
+    ) -> Result<*mut Node<D>> {
+        let max_depth = len.checked_ilog2().unwrap_or(0) as usize;
+        Self::build_subtree(storage, element, 0..len, 0, max_depth)
+    }
+
+    /// Recursively links the middle of `range` as the root of each subtree. Every leaf ends up at `max_depth` or one
+    /// level above it, so coloring the nodes on the deepest level red keeps the black height equal on every path.
+    fn build_subtree(
+        storage: &mut P,
+        element: &impl Fn(usize) -> D,
+        range: core::ops::Range<usize>,
+        depth: usize,
+        max_depth: usize,
+    ) -> Result<*mut Node<D>> {
+        if range.is_empty() {
+            return Ok(ptr::null_mut());
+        }
+        let mid = range.start + range.len() / 2;
+        let left = Self::build_subtree(storage, element, range.start..mid, depth + 1, max_depth)?;
+        let right =
+            Self::build_subtree(storage, element, mid + 1..range.end, depth + 1, max_depth)?;
+
+        let base = storage.base();
+        let node = storage.add(|index| Node::new(element(mid), index, base))?;
+        node.set_color(if depth == max_depth && depth > 0 {
+            RED
+        } else {
+            BLACK
+        });
+        node.set_left(left);
+        node.set_right(right);
+        for child in [left, right] {
+            if let Some(child) = unsafe { child.as_ref() } {
+                child.set_parent(node.as_mut_ptr());
+            }
+        }
+        node.update_size();
+        Ok(node.as_mut_ptr())
+    }
+
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
+    }
+
+    /// Returns the element stored under the key of `data`, or inserts `data` and returns it if the key is absent.
+    /// Both cases take a single descent from the head. Returns `Error::OutOfSpace` if `data` has to be inserted but
+    /// the tree is full.
+    pub fn get_or_insert(&mut self, data: D) -> Result<D> {
+        self.get_or_insert_with(data.ordering_key(), || data)
+            .copied()
+    }
+
+    /// Returns the element with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &D::Key) -> Option<D> {
+        self.floor_ref(key).copied()
+    }
+
+    /// Returns the element with the smallest key greater than or equal to `key`.
+    pub fn ceil(&self, key: &D::Key) -> Option<D> {
+        self.ceil_ref(key).copied()
+    }
+
+    /// Alias of [Self::ceil].
+    pub fn ceiling(&self, key: &D::Key) -> Option<D> {
+        self.ceil(key)
+    }
+
+    /// Returns the element with the smallest key strictly greater than `key`, which need not be in the tree. Walks
+    /// from the node at (or next to) `key` through parent pointers rather than rescanning from the head.
+    pub fn successor(&self, key: &D::Key) -> Option<D> {
+        self.successor_ref(key).copied()
+    }
+
+    /// Returns the element with the largest key strictly less than `key`, which need not be in the tree. Walks from
+    /// the node at (or next to) `key` through parent pointers rather than rescanning from the head.
+    pub fn predecessor(&self, key: &D::Key) -> Option<D> {
+        self.predecessor_ref(key).copied()
+    }
+
+    /// Returns the `k`-th smallest element, counting from 0, or `None` if the tree holds `k` or fewer elements.
+    pub fn select(&self, k: usize) -> Option<D> {
+        self.select_ref(k).copied()
+    }
+
+    /// Returns the element with the smallest key, or `None` if the tree is empty.
+    pub fn min(&self) -> Option<D> {
+        self.min_ref().copied()
+    }
+
+    /// Returns the element with the largest key, or `None` if the tree is empty.
+    pub fn max(&self) -> Option<D> {
+        self.max_ref().copied()
+    }
+
+    /// Copies the tree into `out` in Eytzinger (breadth-first) order, returning a read-only view that can be
+    /// searched without chasing node pointers. Returns `Error::OutOfSpace` if `out` cannot hold every element.
+    pub fn freeze_into<'b>(&self, out: &'b mut [u8]) -> Result<FrozenRbt<'b, D>> {
+        let offset = out.as_ptr().align_offset(core::mem::align_of::<D>());
+        // Any buffer holds as many zero-sized elements as there are.
+        let fits = out
+            .len()
+            .saturating_sub(offset)
+            .checked_div(size_of::<D>())
+            .unwrap_or(usize::MAX);
+        if offset > out.len() || fits < self.len() {
+            return Err(Error::OutOfSpace);
+        }
+        let data = unsafe {
+            slice::from_raw_parts_mut::<'b, D>(out.as_mut_ptr().add(offset) as *mut D, self.len())
+        };
+        let mut next = self.min_node();
+        Self::fill_eytzinger(data, 0, &mut next);
+        Ok(FrozenRbt {
+            data,
+            order: self.order,
+        })
+    }
+
+    /// Writes the elements of the tree into `out` in ascending order, after a [SNAPSHOT_HEADER_SIZE] byte header,
+    /// returning the number of bytes written. The snapshot holds no links, so it can be kept anywhere, such as in
+    /// flash or across a warm reset, and turned back into a tree with [Self::load_from]. Returns `Error::OutOfSpace`
+    /// if `out` cannot hold the header and every element.
+    pub fn save_to(&self, out: &mut [u8]) -> Result<usize>
+    where
+        D: Snapshot,
+    {
+        let len = SNAPSHOT_HEADER_SIZE + self.len() * D::SIZE;
+        if out.len() < len {
+            return Err(Error::OutOfSpace);
+        }
+        let header = [
+            SNAPSHOT_MAGIC,
+            SNAPSHOT_VERSION,
+            D::SIZE as u32,
+            self.len() as u32,
+        ];
+        for (field, value) in out.chunks_exact_mut(4).zip(header) {
+            field.copy_from_slice(&value.to_le_bytes());
+        }
+        let elements = &mut out[SNAPSHOT_HEADER_SIZE..len];
+        for (index, data) in self.iter().enumerate() {
+            data.write_to(&mut elements[index * D::SIZE..][..D::SIZE]);
+        }
+        Ok(len)
+    }
+
+    /// Writes the nodes starting at `next` into `out` in order, placing them at the Eytzinger positions of the
+    /// subtree rooted at `index`.
+    fn fill_eytzinger(out: &mut [D], index: usize, next: &mut Option<&Node<D>>) {
+        if index >= out.len() {
+            return;
+        }
+        Self::fill_eytzinger(out, 2 * index + 1, next);
+        let node = next.expect("Tree holds fewer nodes than its length");
+        out[index] = node.data;
+        *next = node.next_node();
+        Self::fill_eytzinger(out, 2 * index + 2, next);
     }
 }
-impl<D> From<&Node<D>> for *mut Node<D>
+
+impl<'a, D, const SIZE: usize> Rbt<'a, D, { SIZE }>
+where
+    D: PartialOrd + Copy + core::fmt::Debug + OrderedKey,
+{
+    /// Builds a balanced tree from `sorted` in O(n), without the per-insert descents and rotations. Returns
+    /// `Error::OutOfSpace` if `sorted` holds more elements than the tree can, and `Error::AlreadyExists` if its keys
+    /// are not strictly ascending.
+    pub fn from_sorted(slice: &'a mut [u8], sorted: &[D]) -> Result<Self> {
+        if sorted.len() > Storage::<Node<D>, SIZE>::capacity_for(slice.len()) {
+            return Err(Error::OutOfSpace);
+        }
+        if sorted
+            .windows(2)
+            .any(|pair| pair[0].ordering_key() >= pair[1].ordering_key())
+        {
+            return Err(Error::AlreadyExists);
+        }
+        let mut rbt = Self::new(slice);
+        let head = Self::build_sorted(&mut rbt.storage, &|index| sorted[index], sorted.len())?;
+        rbt.head.store(head, Ordering::Release);
+        Ok(rbt)
+    }
+
+    /// Builds a balanced tree from `sorted` and validates it before returning it. Returns `Error::OutOfSpace` if
+    /// `sorted` holds more elements than the tree can, and `Error::ValidationFailed` if the resulting tree is not a
+    /// valid red-black tree, such as when `sorted` is not strictly ascending.
+    pub fn from_sorted_validated(slice: &'a mut [u8], sorted: &[D]) -> Result<Self> {
+        if sorted.len() > Storage::<Node<D>, SIZE>::capacity_for(slice.len()) {
+            return Err(Error::OutOfSpace);
+        }
+        let mut rbt = Self::new(slice);
+        let head = Self::build_sorted(&mut rbt.storage, &|index| sorted[index], sorted.len())?;
+        rbt.head.store(head, Ordering::Release);
+
+        rbt.validate().map_err(|_| Error::ValidationFailed)?;
+        Ok(rbt)
+    }
+
+    /// Builds a tree in `slice` from a snapshot written by [Self::save_to]. Returns `Error::BadBuffer` if the header
+    /// is missing, was written by another version of the format or for elements of another size, or does not match
+    /// the length of `bytes`, or if an element does not hold a valid `D`. Returns `Error::OutOfSpace` if the
+    /// snapshot holds more elements than the tree can, and `Error::ValidationFailed` if the elements are not strictly
+    /// ascending.
+    pub fn load_from(bytes: &[u8], slice: &'a mut [u8]) -> Result<Self>
+    where
+        D: Snapshot,
+    {
+        let Some((header, elements)) = bytes.split_at_checked(SNAPSHOT_HEADER_SIZE) else {
+            return Err(Error::BadBuffer);
+        };
+        let mut fields = header
+            .chunks_exact(4)
+            .map(|field| u32::from_le_bytes(field.try_into().unwrap()));
+        let [magic, version, element_size, len] = core::array::from_fn(|_| fields.next().unwrap());
+        let len = len as usize;
+        if magic != SNAPSHOT_MAGIC
+            || version != SNAPSHOT_VERSION
+            || element_size as usize != D::SIZE
+            || len.checked_mul(D::SIZE) != Some(elements.len())
+        {
+            return Err(Error::BadBuffer);
+        }
+        if len > Storage::<Node<D>, SIZE>::capacity_for(slice.len()) {
+            return Err(Error::OutOfSpace);
+        }
+        let element = |index: usize| D::read_from(&elements[index * D::SIZE..][..D::SIZE]);
+        if (0..len).any(|index| element(index).is_none()) {
+            return Err(Error::BadBuffer);
+        }
+        let mut rbt = Self::new(slice);
+        let head = Self::build_sorted(&mut rbt.storage, &|index| element(index).unwrap(), len)?;
+        rbt.head.store(head, Ordering::Release);
+
+        rbt.validate().map_err(|_| Error::ValidationFailed)?;
+        Ok(rbt)
+    }
+}
+
+/// An iterator over the elements of a [Rbt] in ascending key order, created by [Rbt::iter].
+pub struct Iter<'a, D>
 where
     D: PartialOrd,
 {
-    fn from(node: &Node<D>) -> *mut Node<D> {
-        node.as_mut_ptr()
+    next: Option<&'a Node<D>>,
+    /// The next node to yield from the back. Both ends are cleared once they meet, so no node is yielded twice.
+    back: Option<&'a Node<D>>,
+}
+
+// The shared red-black algorithms handle nodes by address and keep the subtree sizes up to date through `update`.
+// Every handle they pass in is a live node of this tree.
+impl<D, const SIZE: usize, P> Linkage for Rbt<'_, D, SIZE, P>
+where
+    D: PartialOrd + OrderedKey,
+{
+    type Node = *mut Node<D>;
+    const NIL: *mut Node<D> = ptr::null_mut();
+    const AUGMENTED: bool = true;
+
+    fn parent(&self, _index: usize, node: *mut Node<D>) -> *mut Node<D> {
+        unsafe { (*node).parent_ptr() }
+    }
+
+    fn left(&self, _index: usize, node: *mut Node<D>) -> *mut Node<D> {
+        unsafe { (*node).left_ptr() }
+    }
+
+    fn right(&self, _index: usize, node: *mut Node<D>) -> *mut Node<D> {
+        unsafe { (*node).right_ptr() }
+    }
+
+    fn node_color(&self, _index: usize, node: *mut Node<D>) -> bool {
+        unsafe { (*node).color.load(Ordering::Acquire) }
+    }
+
+    fn set_parent(&mut self, _index: usize, node: *mut Node<D>, parent: *mut Node<D>) {
+        unsafe { (*node).set_parent(parent) }
+    }
+
+    fn set_left(&mut self, _index: usize, node: *mut Node<D>, left: *mut Node<D>) {
+        unsafe { (*node).set_left(left) }
+    }
+
+    fn set_right(&mut self, _index: usize, node: *mut Node<D>, right: *mut Node<D>) {
+        unsafe { (*node).set_right(right) }
+    }
+
+    fn set_color(&mut self, _index: usize, node: *mut Node<D>, color: bool) {
+        unsafe { (*node).set_color(color) }
+    }
+
+    fn update(&mut self, _index: usize, node: *mut Node<D>) {
+        unsafe { (*node).update_size() }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    extern crate std;
-    use super::{node_size, Node, Rbt};
-    use core::{
-        ptr::null_mut,
-        sync::atomic::{AtomicPtr, Ordering},
-    };
-    use std::println;
+impl<D, const SIZE: usize, P> Forest for Rbt<'_, D, SIZE, P>
+where
+    D: PartialOrd + OrderedKey,
+{
+    type Element = D;
 
-    const RBT_MAX_SIZE: usize = 0x1000;
+    fn element(&self, node: *mut Node<D>) -> &D {
+        unsafe { &(*node).data }
+    }
 
-    #[test]
-    fn simple_test() {
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<i32>()];
-        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
-        assert!(rbt.insert(5).is_ok());
-        assert_eq!(rbt.storage.length, 1);
-        assert!(rbt.insert(3).is_ok());
-        assert!(rbt.insert(7).is_ok());
-        assert!(rbt.insert(2).is_ok());
-        assert!(rbt.insert(6).is_ok());
-        assert!(rbt.insert(8).is_ok());
-        assert!(rbt.insert(9).is_ok());
-        assert!(rbt.insert(10).is_ok());
-        assert_eq!(rbt.storage.length, 8);
+    fn head(&self, _index: usize) -> *mut Node<D> {
+        self.head.load(Ordering::Acquire)
+    }
 
-        let mut values = std::vec::Vec::new();
-        rbt.dfs(rbt.head(), &mut values);
-        println!("{:?}", values);
+    fn set_head(&mut self, _index: usize, node: *mut Node<D>) {
+        self.head.store(node, Ordering::Release);
+    }
 
-        for (initialized, node) in rbt.storage.data.iter() {
-            if *initialized {
-                println!("{:?}", node);
-            }
+    fn compare(&self, _index: usize, a: *mut Node<D>, b: *mut Node<D>) -> core::cmp::Ordering {
+        (self.order)(
+            self.element(a).ordering_key(),
+            self.element(b).ordering_key(),
+        )
+    }
+}
+
+impl<'a, D> Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    /// Clears both ends if `node` is the last one between them.
+    fn close_if_last(&mut self, node: &'a Node<D>) -> bool {
+        let last = self.next.is_some_and(|next| ptr::eq(next, node))
+            && self.back.is_some_and(|back| ptr::eq(back, node));
+        if last {
+            self.next = None;
+            self.back = None;
         }
+        last
     }
+}
 
-    #[test]
-    fn test_case_3() {
-        /* Update colors when parent and uncle nodes are red.
-            [17B]                  [17B]
-             /  \                  /   \
-          [09B] [19B] -------->  [09B] [19R] <- Updated
-                /   \                   /  \
-              [18R] [75R]  Updated -> [18B] [75B] <- Updated
-                      \                       \
-                      [81R]                  [81R]
-        */
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<i32>()];
-        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
-        rbt.insert(17).unwrap();
+impl<'a, D> Iterator for Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    type Item = &'a D;
 
-        // Head should be black
-        {
-            let head = rbt.head().unwrap();
-            assert!(head.is_black());
+    fn next(&mut self) -> Option<&'a D> {
+        let node = self.next?;
+        if !self.close_if_last(node) {
+            self.next = node.next_node();
         }
+        Some(&node.data)
+    }
+}
 
-        // Insert a node to the right, should be red
-        rbt.insert(19).unwrap();
-        {
-            let head = rbt.head().unwrap();
-            assert!(head.is_black());
-            let right = head.right().unwrap();
-            assert!(right.is_red());
+impl<'a, D> DoubleEndedIterator for Iter<'a, D>
+where
+    D: PartialOrd,
+{
+    fn next_back(&mut self) -> Option<&'a D> {
+        let node = self.back?;
+        if !self.close_if_last(node) {
+            self.back = node.prev_node();
         }
+        Some(&node.data)
+    }
+}
 
-        // Ensure no red-reds
-        rbt.insert(9).unwrap();
-        rbt.insert(18).unwrap();
-        rbt.insert(75).unwrap();
-        {
-            let head = rbt.head().unwrap();
-            assert!(head.is_black());
-            let right = head.right().unwrap();
-            assert!(right.is_black());
-            let right_l = right.left().unwrap();
-            assert!(right_l.is_red());
-            let right_r = right.right().unwrap();
-            assert!(right_r.is_red());
+impl<'b, 'a, D, const SIZE: usize, P> IntoIterator for &'b Rbt<'a, D, SIZE, P>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+{
+    type Item = &'b D;
+    type IntoIter = Iter<'b, D>;
+
+    fn into_iter(self) -> Iter<'b, D> {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a [Rbt] within a range of keys, created by [Rbt::range].
+pub struct Range<'a, D>
+where
+    D: PartialOrd,
+{
+    inner: Iter<'a, D>,
+}
+
+impl<'a, D> Iterator for Range<'a, D>
+where
+    D: PartialOrd,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<&'a D> {
+        self.inner.next()
+    }
+}
+
+impl<'a, D> DoubleEndedIterator for Range<'a, D>
+where
+    D: PartialOrd,
+{
+    fn next_back(&mut self) -> Option<&'a D> {
+        self.inner.next_back()
+    }
+}
+
+/// A view into the slot for a single key of a [Rbt], created by [Rbt::entry].
+pub struct Entry<'b, 'a, D, const SIZE: usize, P = Storage<'a, Node<D>, SIZE>>
+where
+    D: PartialOrd + OrderedKey,
+{
+    tree: &'b mut Rbt<'a, D, SIZE, P>,
+    key: &'b D::Key,
+}
+
+impl<'b, 'a, D, const SIZE: usize, P> Entry<'b, 'a, D, SIZE, P>
+where
+    D: PartialOrd + core::fmt::Debug + OrderedKey,
+    P: NodePool<Node<D>>,
+{
+    /// Returns the element stored under the key, or inserts the element built by `f` if there is none. See
+    /// [Rbt::get_or_insert_with].
+    pub fn or_insert_with<F: FnOnce() -> D>(self, f: F) -> Result<&'b D> {
+        self.tree.get_or_insert_with(self.key, f)
+    }
+}
+
+/// A read-only snapshot of an [Rbt] produced by [Rbt::freeze_into].
+///
+/// The elements are stored in Eytzinger order: the children of the element at index `i` live at `2i + 1` and
+/// `2i + 2`. The top levels of the tree share cache lines, which makes searches cheaper than walking the scattered
+/// nodes of the live tree.
+pub struct FrozenRbt<'a, D>
+where
+    D: OrderedKey,
+{
+    data: &'a [D],
+    order: fn(&D::Key, &D::Key) -> core::cmp::Ordering,
+}
+
+impl<D> FrozenRbt<'_, D>
+where
+    D: Copy + OrderedKey,
+{
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        // Descend without branching on the comparison, using 1-based indices so the children of `k` are `2k` and
+        // `2k + 1`. Every right turn appends a 1 bit, so stripping the trailing ones and the final left turn
+        // recovers the last node that was not less than `key`.
+        let mut k = 1;
+        while k <= self.data.len() {
+            k = 2 * k + (self.order)(self.data[k - 1].ordering_key(), key).is_lt() as usize;
+        }
+        k >>= k.trailing_ones() + 1;
+        let data = self.data.get(k.checked_sub(1)?)?;
+        (self.order)(data.ordering_key(), key)
+            .is_eq()
+            .then_some(*data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// A node of a [Rbt]. Its fields are private; the type is public so that a [NodePool] can be named for it.
+pub struct Node<D>
+where
+    D: PartialOrd,
+{
+    data: D,
+    color: AtomicBool,
+    /// The slot this node is stored in. Links are slot indices, so the storage stays valid wherever its bytes are
+    /// moved once `base` is pointed at the new slot 0. Free slots hold [NULL] instead.
+    index: u16,
+    /// Slot 0 of the storage. Neighbours are reached from it rather than from the address of this node, which only
+    /// grants access to this node.
+    base: AtomicPtr<Node<D>>,
+    /// How many times the key has been inserted through `insert_dup`. Plain `insert` leaves it at 1.
+    count: AtomicU32,
+    /// The number of nodes in the subtree rooted at this node, including itself.
+    size: AtomicU32,
+    parent: AtomicU16,
+    left: AtomicU16,
+    right: AtomicU16,
+}
+
+// Links are loaded with `Acquire` and stored with `Release`. Every mutation goes through `&mut self` on the tree, so
+// there is a single writer, and a reader that observes a link also observes the node written before it was linked.
+// Nothing relies on a single total order across different links, which is all `SeqCst` would add.
+impl<D> Node<D>
+where
+    D: PartialOrd,
+{
+    fn new(data: D, index: u16, base: *mut Node<D>) -> Self {
+        Node {
+            data,
+            index,
+            base: AtomicPtr::new(base),
+            count: AtomicU32::new(1),
+            size: AtomicU32::new(1),
+            color: AtomicBool::new(RED),
+            parent: AtomicU16::new(NULL),
+            left: AtomicU16::new(NULL),
+            right: AtomicU16::new(NULL),
+        }
+    }
+
+    fn set_color(&self, color: bool) {
+        self.color.store(color, Ordering::Release);
+    }
+
+    fn is_red(&self) -> bool {
+        self.color.load(Ordering::Acquire) == RED
+    }
+
+    fn is_black(&self) -> bool {
+        self.color.load(Ordering::Acquire) == BLACK
+    }
+
+    fn size(&self) -> u32 {
+        self.size.load(Ordering::Acquire)
+    }
+
+    fn subtree_size(node: Option<&Node<D>>) -> u32 {
+        node.map_or(0, Node::size)
+    }
+
+    /// Recomputes the subtree size from the children, which must already be up to date.
+    fn update_size(&self) {
+        let size = 1 + Self::subtree_size(self.left()) + Self::subtree_size(self.right());
+        self.size.store(size, Ordering::Release);
+    }
+
+    #[inline(always)]
+    /// Used when you care whether or not the node is null.
+    fn right(&self) -> Option<&Node<D>> {
+        unsafe { self.right_ptr().as_ref() }
+    }
+
+    /// Used when you don't care whether or not the node is null.
+    #[inline(always)]
+    fn right_ptr(&self) -> *mut Node<D> {
+        self.slot(self.right.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    fn set_right<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.right
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn left(&self) -> Option<&Node<D>> {
+        unsafe { self.left_ptr().as_ref() }
+    }
+
+    fn left_ptr(&self) -> *mut Node<D> {
+        self.slot(self.left.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    fn set_left<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.left
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    fn parent(&self) -> Option<&Node<D>> {
+        unsafe { self.parent_ptr().as_ref() }
+    }
+
+    fn parent_ptr(&self) -> *mut Node<D> {
+        self.slot(self.parent.load(Ordering::Acquire))
+    }
+
+    fn set_parent<N: Into<*mut Node<D>>>(&self, node: N) {
+        self.parent
+            .store(Self::index_of(node.into()), Ordering::Release);
+    }
+
+    /// Returns the in-order successor of this node by following parent pointers.
+    fn next_node(&self) -> Option<&Node<D>> {
+        if let Some(mut current) = self.right() {
+            while let Some(left) = current.left() {
+                current = left;
+            }
+            return Some(current);
+        }
+        let mut child = self;
+        while let Some(parent) = child.parent() {
+            if parent.left_ptr() == child.as_mut_ptr() {
+                return Some(parent);
+            }
+            child = parent;
+        }
+        None
+    }
+
+    /// Returns the in-order predecessor of this node by following parent pointers.
+    fn prev_node(&self) -> Option<&Node<D>> {
+        if let Some(mut current) = self.left() {
+            while let Some(right) = current.right() {
+                current = right;
+            }
+            return Some(current);
+        }
+        let mut child = self;
+        while let Some(parent) = child.parent() {
+            if parent.right_ptr() == child.as_mut_ptr() {
+                return Some(parent);
+            }
+            child = parent;
+        }
+        None
+    }
+
+    /// Returns the node stored in slot `index` of the same storage, or null for [NULL].
+    fn slot(&self, index: u16) -> *mut Node<D> {
+        if index == NULL {
+            return ptr::null_mut();
+        }
+        unsafe { self.base.load(Ordering::Acquire).add(index as usize) }
+    }
+
+    fn index_of(node: *mut Node<D>) -> u16 {
+        unsafe { node.as_ref() }.map_or(NULL, |node| node.index)
+    }
+
+    #[inline(always)]
+    fn as_mut_ptr(&self) -> *mut Node<D> {
+        // Taken from `base` rather than `self`, so that the tree may write through it to this node later on.
+        self.slot(self.index)
+    }
+}
+
+impl<D> Slot for Node<D>
+where
+    D: PartialOrd,
+{
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { (*ptr::addr_of!((*slot).parent)).load(Ordering::Acquire) }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).parent).write(AtomicU16::new(index)) };
+    }
+}
+
+impl<D> core::fmt::Debug for Node<D>
+where
+    D: PartialOrd + core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let color = if self.is_red() { "  RED" } else { "BLACK" };
+        write!(f, "Node {{ addr: {:?}, parent: {:12?}, left: {:12?}, right: {:12?}, color: {:?}, data: {:?} }}", self.as_mut_ptr(), self.parent_ptr(), self.left_ptr(), self.right_ptr(), color, self.data)
+    }
+}
+impl<D> From<&Node<D>> for *mut Node<D>
+where
+    D: PartialOrd,
+{
+    fn from(node: &Node<D>) -> *mut Node<D> {
+        node.as_mut_ptr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, AlignedStorage, Node, Rbt, RbtDyn, BLACK, RED, SNAPSHOT_HEADER_SIZE};
+    use crate::pool::NodePool;
+    use crate::rb::Forest;
+    use crate::OrderedKey;
+    use crate::{AlignedBuffer, Error};
+    use arrayvec::ArrayVec;
+    use core::{ops::Bound, sync::atomic::Ordering};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::println;
+    use std::rc::Rc;
+
+    const RBT_MAX_SIZE: usize = 0x1000;
+
+    #[test]
+    fn simple_test() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        assert!(rbt.insert(5).is_ok());
+        assert_eq!(rbt.storage.length, 1);
+        assert!(rbt.insert(3).is_ok());
+        assert!(rbt.insert(7).is_ok());
+        assert!(rbt.insert(2).is_ok());
+        assert!(rbt.insert(6).is_ok());
+        assert!(rbt.insert(8).is_ok());
+        assert!(rbt.insert(9).is_ok());
+        assert!(rbt.insert(10).is_ok());
+        assert_eq!(rbt.storage.length, 8);
+
+        let values: std::vec::Vec<_> = rbt.iter().copied().collect();
+        println!("{:?}", values);
+
+        for node in rbt.storage.occupied() {
+            println!("{:?}", node);
+        }
+    }
+
+    #[test]
+    fn test_case_3() {
+        /* Update colors when parent and uncle nodes are red.
+            [17B]                  [17B]
+             /  \                  /   \
+          [09B] [19B] -------->  [09B] [19R] <- Updated
+                /   \                   /  \
+              [18R] [75R]  Updated -> [18B] [75B] <- Updated
+                      \                       \
+                      [81R]                  [81R]
+        */
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        rbt.insert(17).unwrap();
+
+        // Head should be black
+        {
+            let head = rbt.head().unwrap();
+            assert!(head.is_black());
+        }
+
+        // Insert a node to the right, should be red
+        rbt.insert(19).unwrap();
+        {
+            let head = rbt.head().unwrap();
+            assert!(head.is_black());
+            let right = head.right().unwrap();
+            assert!(right.is_red());
+        }
+
+        // Ensure no red-reds
+        rbt.insert(9).unwrap();
+        rbt.insert(18).unwrap();
+        rbt.insert(75).unwrap();
+        {
+            let head = rbt.head().unwrap();
+            assert!(head.is_black());
+            let right = head.right().unwrap();
+            assert!(right.is_black());
+            let right_l = right.left().unwrap();
+            assert!(right_l.is_red());
+            let right_r = right.right().unwrap();
+            assert!(right_r.is_red());
+        }
+
+        // Adding a node off of 75 should cause a color change
+        rbt.insert(81).unwrap();
+        {
+            let head = rbt.head().unwrap();
+            assert!(head.is_black());
+            let right = head.right().unwrap();
+            assert!(right.is_red());
+            let right_l = right.left().unwrap();
+            assert!(right_l.is_black());
+            let right_r = right.right().unwrap();
+            assert!(right_r.is_black());
+            let right_r_r = right_r.right().unwrap();
+            assert!(right_r_r.is_red());
+        }
+    }
+
+    #[test]
+    fn test_case_4() {
+        /* Parent Node is red, uncle node is black, inserted node is Inner
+           grandchild should cause a rotation.
+
+          Final Expected State:
+                   [17B]
+                   /   \
+                [09B] [24B]
+                      /   \
+                    [19R] [75R]
+        */
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        rbt.insert(17).unwrap();
+        rbt.insert(9).unwrap();
+        rbt.insert(19).unwrap();
+        rbt.insert(75).unwrap();
+        rbt.insert(24).unwrap();
+
+        // Validate head (17)
+        let head = rbt.head().unwrap();
+        assert!(head.is_black());
+
+        // Validate left child (9)
+        let left = head.left().unwrap();
+        assert!(left.is_black());
+        assert_eq!(left.data, 9);
+        assert_eq!(left.parent_ptr(), head.as_mut_ptr());
+
+        // Validate right child(24)
+        let right = head.right().unwrap();
+        assert!(right.is_black());
+        assert_eq!(right.data, 24);
+        assert_eq!(right.parent_ptr(), head.as_mut_ptr());
+
+        // Validate right child's left child (19)
+        let right_l = right.left().unwrap();
+        assert!(right_l.is_red());
+        assert_eq!(right_l.data, 19);
+        assert_eq!(right_l.parent_ptr(), right.as_mut_ptr());
+
+        // Validate right child's right child (75)
+        let right_r = right.right().unwrap();
+        assert!(right_r.is_red());
+        assert_eq!(right_r.data, 75);
+    }
+
+    #[test]
+    fn test_parents_consistent() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        assert!(rbt.parents_consistent());
+
+        // 10 -> 30 -> 20 is a right-left zig-zag, forcing a double rotation in the insert fixup.
+        rbt.insert(10).unwrap();
+        rbt.insert(30).unwrap();
+        rbt.insert(20).unwrap();
+        assert!(rbt.parents_consistent());
+        assert_eq!(rbt.head().unwrap().data, 20);
+
+        // 5 -> 1 -> 3 under the left subtree is the mirrored left-right zig-zag.
+        rbt.insert(5).unwrap();
+        rbt.insert(1).unwrap();
+        rbt.insert(3).unwrap();
+        assert!(rbt.parents_consistent());
+
+        // Break a back-pointer by hand and make sure it is caught.
+        let head = rbt.head().unwrap();
+        head.right().unwrap().set_parent(head.left().unwrap());
+        assert!(!rbt.parents_consistent());
+    }
+
+    #[test]
+    fn test_subtree_black_height() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        assert_eq!(rbt.subtree_black_height(&1), None);
+
+        for num in 0..100 {
+            rbt.insert(num).unwrap();
+        }
+        for num in 0..100 {
+            assert!(rbt.subtree_black_height(&num).is_some());
+        }
+        let head = rbt.head().unwrap();
+        let height = rbt.subtree_black_height(&head.data).unwrap();
+        assert!(height > 1);
+
+        // Recoloring a black node red keeps its own subtree consistent, but not its parent's.
+        let broken = rbt
+            .storage
+            .occupied()
+            .find(|node| node.is_black() && node.parent().is_some())
+            .unwrap();
+        let broken_height = rbt.subtree_black_height(&broken.data).unwrap();
+        broken.set_color(super::RED);
+        assert_eq!(
+            rbt.subtree_black_height(&broken.data),
+            Some(broken_height - 1)
+        );
+        assert_eq!(
+            rbt.subtree_black_height(&broken.parent().unwrap().data),
+            None
+        );
+        assert_eq!(rbt.subtree_black_height(&head.data), None);
+    }
+
+    #[test]
+    fn test_freeze_into() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        let mut out = [0; 100 * core::mem::size_of::<u32>() + 7];
+
+        let frozen = rbt.freeze_into(&mut out).unwrap();
+        assert!(frozen.is_empty());
+        assert_eq!(frozen.search(&1), None);
+
+        for num in (0..100).map(|i| (i * 37) % 100 * 2) {
+            rbt.insert(num).unwrap();
+        }
+
+        assert!(rbt
+            .freeze_into(&mut out[..99 * core::mem::size_of::<u32>()])
+            .is_err());
+
+        // Start at an offset so the buffer is not aligned for u32.
+        let frozen = rbt.freeze_into(&mut out[1..]).unwrap();
+        assert_eq!(frozen.len(), 100);
+        for num in 0..200 {
+            assert_eq!(frozen.search(&num), rbt.search(&num));
+        }
+    }
+
+    #[test]
+    fn test_freeze_into_zero_sized() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<()>()]);
+        let mut rbt: Rbt<(), RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        rbt.insert(()).unwrap();
+
+        let frozen = rbt.freeze_into(&mut []).unwrap();
+        assert_eq!(frozen.len(), 1);
+        assert_eq!(frozen.search(&()), Some(()));
+    }
+
+    /// Places nodes holding `values` in consecutive slots, the way [Storage] lays them out, so they can be linked.
+    fn slots<const N: usize>(values: [i32; N]) -> [Node<i32>; N] {
+        core::array::from_fn(|i| Node::new(values[i], i as u16, core::ptr::null_mut()))
+    }
+
+    /// Points the nodes at slot 0 of `slots`, once they have been moved to where they stay.
+    fn anchored<const N: usize>(slots: &[Node<i32>; N]) -> &[Node<i32>; N] {
+        for node in slots {
+            node.base
+                .store(slots.as_ptr().cast_mut(), Ordering::Release);
+        }
+        slots
+    }
+
+    #[test]
+    fn test_rotate_right() {
+        /* Verifies that the rotate right function works as expected.
+             [50]              [75]
+             /  \              /  \
+           [10][75]    <--   [50][85]
+               /  \          /  \
+             [70][85]      [10][70]
+        */
+        let slots = slots([75, 50, 85, 10, 70]);
+        let [node, left, right, left_l, left_r] = anchored(&slots).each_ref();
+
+        left.set_left(left_l);
+        left_l.set_parent(left);
+        left.set_right(left_r);
+        left_r.set_parent(left);
+        node.set_left(left);
+        left.set_parent(node);
+        node.set_right(right);
+        right.set_parent(node);
+
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        rbt.rotate(0, node.as_mut_ptr(), false);
+
+        // left[50] took the place of node[75], and the sizes of both follow their new subtrees.
+        assert_eq!(rbt.head.load(Ordering::SeqCst), left.as_mut_ptr());
+        assert!(left.parent().is_none());
+        assert_eq!(node.size(), 3);
+        assert_eq!(left.size(), 5);
+
+        // Check left[50] <-> left_l[10] connection
+        assert_eq!(left.left().unwrap().as_mut_ptr(), left_l.as_mut_ptr());
+        assert_eq!(left_l.parent().unwrap().as_mut_ptr(), left.as_mut_ptr());
+
+        // check left[50] <-> left_r[70] connection
+        assert_eq!(left.right().unwrap().as_mut_ptr(), node.as_mut_ptr());
+        assert_eq!(node.parent().unwrap().as_mut_ptr(), left.as_mut_ptr());
+
+        // check left_l[10] has no children
+        assert!(left_l.left().is_none());
+        assert!(left_l.right().is_none());
+
+        // check node[75] <-> left_r[70] connection
+        assert_eq!(node.left().unwrap().as_mut_ptr(), left_r.as_mut_ptr());
+        assert_eq!(left_r.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+
+        // check node[75] <-> right[85] connection
+        assert_eq!(node.right().unwrap().as_mut_ptr(), right.as_mut_ptr());
+        assert_eq!(right.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+
+        // Check right_r[70] has no children
+        assert!(left_r.left().is_none());
+        assert!(left_r.right().is_none());
+
+        // Check right[85] has no children
+        assert!(right.left().is_none());
+        assert!(right.right().is_none());
+    }
+
+    #[test]
+    fn test_rotate_left() {
+        /* Verifies that the rotate left function works as expected.
+             [50]              [75]
+             /  \              /  \
+           [10][75]    -->   [50][85]
+               /  \          /  \
+             [70][85]      [10][70]
+        */
+        let slots = slots([50, 10, 75, 70, 85]);
+        let [node, left, right, right_l, right_r] = anchored(&slots).each_ref();
+
+        right.set_left(right_l);
+        right_l.set_parent(right);
+        right.set_right(right_r);
+        right_r.set_parent(right);
+        node.set_left(left);
+        left.set_parent(node);
+        node.set_right(right);
+        right.set_parent(node);
+
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        rbt.rotate(0, node.as_mut_ptr(), true);
+
+        // right[75] took the place of node[50], and the sizes of both follow their new subtrees.
+        assert_eq!(rbt.head.load(Ordering::SeqCst), right.as_mut_ptr());
+        assert!(right.parent().is_none());
+        assert_eq!(node.size(), 3);
+        assert_eq!(right.size(), 5);
+
+        // Check right[75] <-left-> node[50] connection
+        assert_eq!(right.left().unwrap().as_mut_ptr(), node.as_mut_ptr());
+        assert_eq!(node.parent().unwrap().as_mut_ptr(), right.as_mut_ptr());
+
+        // Check right[75] <-right-> right_r[85] connection
+        assert_eq!(right.right().unwrap().as_mut_ptr(), right_r.as_mut_ptr());
+        assert_eq!(right_r.parent().unwrap().as_mut_ptr(), right.as_mut_ptr());
+
+        // Check node[50] <-left-> left[10] connection
+        assert_eq!(node.left().unwrap().as_mut_ptr(), left.as_mut_ptr());
+        assert_eq!(left.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+
+        // Check node[50] <-right-> right_l[70] connection
+        assert_eq!(node.right().unwrap().as_mut_ptr(), right_l.as_mut_ptr());
+        assert_eq!(right_l.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+
+        // Check left[10] has no children
+        assert!(left.left().is_none());
+        assert!(left.right().is_none());
+
+        // Check right_r[85] has no children
+        assert!(right_r.left().is_none());
+        assert!(right_r.right().is_none());
+
+        // Check right_l[70] has no children
+        assert!(right_l.left().is_none());
+        assert!(right_l.right().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "too small")]
+    fn test_new_undersized_buffer() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>() - 1]);
+        Rbt::<u32, 4>::new(&mut mem);
+    }
+
+    #[test]
+    #[should_panic(expected = "not aligned")]
+    fn test_new_misaligned_buffer() {
+        #[repr(C, align(16))]
+        struct Aligned([u8; 4 * node_size::<u32>() + 1]);
+
+        let mut mem = Aligned([0; 4 * node_size::<u32>() + 1]);
+        Rbt::<u32, 4>::new(&mut mem.0[1..]);
+    }
+
+    #[test]
+    fn test_len_and_capacity() {
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, 4>::new(&mut mem);
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.capacity(), 4);
+        assert_eq!(tree.remaining(), 4);
+
+        for num in [3, 1, 2, 4] {
+            tree.insert(num).unwrap();
+        }
+        assert!(!tree.is_empty());
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.remaining(), 0);
+        assert!(matches!(tree.insert(5), Err(Error::OutOfSpace)));
+
+        tree.delete(2).unwrap();
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.capacity(), 4);
+        assert_eq!(tree.remaining(), 1);
+    }
+
+    #[test]
+    fn test_clear() {
+        const SIZE: usize = 8;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        for num in [5, 3, 8, 1, 4] {
+            tree.insert(num).unwrap();
+        }
+        let used_slots = |tree: &Rbt<u32, SIZE>| -> [bool; SIZE] {
+            core::array::from_fn(|i| tree.storage.is_occupied(i))
+        };
+        let first_slots = used_slots(&tree);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.search(&5), None);
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(used_slots(&tree), [false; SIZE]);
+
+        for num in [2, 9, 7, 6, 0] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(used_slots(&tree), first_slots);
+        assert!(tree.iter().copied().eq([0, 2, 6, 7, 9]));
+
+        // Every slot is available again.
+        for num in 10..13 {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(13), Err(Error::OutOfSpace)));
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut tree = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.max(), None);
+
+        for num in [50, 30, 70, 20, 40, 60, 80] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.min(), Some(20));
+        assert_eq!(tree.max(), Some(80));
+
+        tree.delete(20).unwrap();
+        tree.delete(80).unwrap();
+        assert_eq!(tree.min(), Some(30));
+        assert_eq!(tree.max(), Some(70));
+
+        for num in [30, 40, 60, 70] {
+            tree.delete(num).unwrap();
+        }
+        assert_eq!(tree.min(), Some(50));
+        assert_eq!(tree.max(), Some(50));
+
+        tree.delete(50).unwrap();
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.max(), None);
+    }
+
+    #[test]
+    fn test_range() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut input = ArrayVec::<u32, SIZE>::new();
+        while !input.is_full() {
+            let num = rng.gen_range(0..200);
+            if tree.insert(num).is_ok() {
+                input.push(num);
+            }
+        }
+        input.sort();
+
+        for (lo, hi) in [(0, 200), (50, 120), (37, 37), (120, 50), (201, 300), (0, 0)] {
+            let expected = input.iter().filter(|num| (lo..=hi).contains(*num));
+            assert!(tree.range(lo..=hi).eq(expected), "range {}..={}", lo, hi);
+            let expected = input.iter().filter(|num| (lo..hi).contains(*num));
+            assert!(tree.range(lo..hi).eq(expected), "range {}..{}", lo, hi);
+            let expected = input.iter().filter(|num| **num > lo && **num <= hi);
+            let bounds = (Bound::Excluded(lo), Bound::Included(hi));
+            assert!(tree.range(bounds).eq(expected), "range ({}, {}]", lo, hi);
+        }
+        for num in input.iter() {
+            assert!(tree.range(num..=num).eq([num]));
+            assert!(tree.range(..=num).eq(input.iter().filter(|n| *n <= num)));
+            assert!(tree.range(num..).eq(input.iter().filter(|n| *n >= num)));
+        }
+        assert!(tree.range(..).eq(input.iter()));
+        assert!(tree
+            .range(50..120)
+            .rev()
+            .eq(input.iter().filter(|n| (50..120).contains(*n)).rev()));
+        assert_eq!(
+            tree.range((Bound::Included(120), Bound::Excluded(50)))
+                .next_back(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_floor_ceil() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.ceil(&5), None);
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut input = ArrayVec::<u32, SIZE>::new();
+        while !input.is_full() {
+            let num = rng.gen_range(10..1_000);
+            if tree.insert(num).is_ok() {
+                input.push(num);
+            }
+        }
+
+        for key in 0..1_010 {
+            let floor = input.iter().filter(|num| **num <= key).max().copied();
+            let ceil = input.iter().filter(|num| **num >= key).min().copied();
+            assert_eq!(tree.floor(&key), floor, "floor of {}", key);
+            assert_eq!(tree.ceil(&key), ceil, "ceil of {}", key);
+            assert_eq!(tree.ceiling(&key), ceil, "ceiling of {}", key);
+        }
+        let (min, max) = (tree.min().unwrap(), tree.max().unwrap());
+        assert_eq!(tree.floor(&(min - 1)), None);
+        assert_eq!(tree.ceil(&(max + 1)), None);
+        assert_eq!((tree.floor(&max), tree.ceil(&max)), (Some(max), Some(max)));
+    }
+
+    #[test]
+    fn test_successor_predecessor() {
+        const SIZE: usize = 8;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        assert_eq!(tree.successor(&5), None);
+        assert_eq!(tree.predecessor(&5), None);
+        for num in [40, 20, 60, 10, 30, 50, 70] {
+            tree.insert(num).unwrap();
+        }
+
+        // Leaf, internal, root, min and max starting nodes.
+        assert_eq!(
+            (tree.predecessor(&30), tree.successor(&30)),
+            (Some(20), Some(40))
+        );
+        assert_eq!(
+            (tree.predecessor(&20), tree.successor(&20)),
+            (Some(10), Some(30))
+        );
+        assert_eq!(
+            (tree.predecessor(&40), tree.successor(&40)),
+            (Some(30), Some(50))
+        );
+        assert_eq!(
+            (tree.predecessor(&10), tree.successor(&10)),
+            (None, Some(20))
+        );
+        assert_eq!(
+            (tree.predecessor(&70), tree.successor(&70)),
+            (Some(60), None)
+        );
+
+        // Keys that are not in the tree.
+        assert_eq!(
+            (tree.predecessor(&35), tree.successor(&35)),
+            (Some(30), Some(40))
+        );
+        assert_eq!(
+            (tree.predecessor(&45), tree.successor(&45)),
+            (Some(40), Some(50))
+        );
+        assert_eq!((tree.predecessor(&0), tree.successor(&0)), (None, Some(10)));
+        assert_eq!(
+            (tree.predecessor(&99), tree.successor(&99)),
+            (Some(70), None)
+        );
+
+        let mut key = 0;
+        for expected in [10, 20, 30, 40, 50, 60, 70] {
+            key = tree.successor(&key).unwrap();
+            assert_eq!(key, expected);
+        }
+    }
+
+    #[test]
+    fn test_rank_select() {
+        const SIZE: usize = 256;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        assert_eq!(tree.rank(&5), 0);
+        assert_eq!(tree.select(0), None);
+
+        let mut rng = StdRng::seed_from_u64(17);
+        let mut expected = std::vec::Vec::new();
+        for _ in 0..2_000 {
+            let num = rng.gen_range(0..400);
+            if rng.gen_bool(0.6) {
+                if tree.insert(num).is_ok() {
+                    expected.push(num);
+                }
+            } else if tree.delete(num).is_ok() {
+                expected.retain(|e| *e != num);
+            }
+        }
+        tree.validate().unwrap();
+        expected.sort();
+
+        for (k, num) in expected.iter().enumerate() {
+            assert_eq!(tree.select(k), Some(*num));
+            assert_eq!(tree.rank(num), k);
+        }
+        assert_eq!(tree.select(expected.len()), None);
+        for key in 0..410 {
+            assert_eq!(tree.rank(&key), expected.partition_point(|e| *e < key));
+        }
+    }
+
+    #[test]
+    fn test_new_by_reverse_order() {
+        const SIZE: usize = 16;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new_by(&mut mem, |a, b| b.cmp(a));
+        for num in [5, 3, 8, 1, 9, 7, 2] {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(8), Err(Error::AlreadyExists)));
+        assert!(tree.iter().eq([9, 8, 7, 5, 3, 2, 1].iter()));
+        assert!(tree
+            .range((Bound::Included(8), Bound::Included(3)))
+            .eq([8, 7, 5, 3].iter()));
+        assert_eq!((tree.min(), tree.max()), (Some(9), Some(1)));
+        assert_eq!(tree.search(&7), Some(7));
+        assert_eq!(tree.search(&6), None);
+        assert_eq!(tree.successor(&6), Some(5));
+
+        tree.delete(8).unwrap();
+        assert!(tree.iter().eq([9, 7, 5, 3, 2, 1].iter()));
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn test_non_copy_payload() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Named {
+            key: u32,
+            name: std::string::String,
+        }
+
+        impl OrderedKey for Named {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let named = |key: u32| Named {
+            key,
+            name: std::format!("node {}", key),
+        };
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<Named>()]);
+        let mut tree = Rbt::<Named, RBT_MAX_SIZE>::new(&mut mem);
+        for key in [4, 2, 6, 1, 3, 5, 7] {
+            tree.insert(named(key)).unwrap();
+        }
+        assert_eq!(tree.search_ref(&3), Some(&named(3)));
+        assert_eq!(tree.search_ref(&8), None);
+        assert!(tree.contains(&3));
+        assert!(!tree.contains(&8));
+
+        tree.delete(named(4)).unwrap();
+        assert_eq!(tree.search_ref(&4), None);
+        assert!(tree
+            .iter()
+            .map(|n| n.name.as_str())
+            .eq(["node 1", "node 2", "node 3", "node 5", "node 6", "node 7"]));
+    }
+
+    #[test]
+    fn test_remove() {
+        #[derive(Debug)]
+        struct Entry {
+            key: u32,
+            token: Rc<()>,
+        }
+
+        impl PartialEq for Entry {
+            fn eq(&self, other: &Self) -> bool {
+                self.key == other.key
+            }
+        }
+
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                self.key.partial_cmp(&other.key)
+            }
+        }
+
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
+
+        let token = Rc::new(());
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<Entry>()]);
+        let mut tree = Rbt::<Entry, RBT_MAX_SIZE>::new(&mut mem);
+        for key in [4, 2, 6, 1, 3, 5, 7] {
+            let token = token.clone();
+            tree.insert(Entry { key, token }).unwrap();
+        }
+        assert_eq!(Rc::strong_count(&token), 8);
+
+        let removed = tree.remove(&4).unwrap();
+        assert_eq!(removed.key, 4);
+        assert!(Rc::ptr_eq(&removed.token, &token));
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.storage.occupied().count(), 6);
+        assert!(tree.remove(&4).is_none());
+        assert!(tree.iter().map(|e| e.key).eq([1, 2, 3, 5, 6, 7]));
+
+        // The removed value owns its data, and clearing the tree drops what is left.
+        drop(removed);
+        assert_eq!(Rc::strong_count(&token), 7);
+        tree.clear();
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn test_insert_dup_remove_one() {
+        const SIZE: usize = 4;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        tree.insert(1).unwrap();
+        tree.insert(9).unwrap();
+
+        assert_eq!(tree.insert_dup(5).unwrap(), 1);
+        assert_eq!(tree.insert_dup(5).unwrap(), 2);
+        assert_eq!(tree.insert_dup(5).unwrap(), 3);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.count(&5), 3);
+
+        assert_eq!(tree.remove_one(&5), Some(2));
+        assert_eq!(tree.remove_one(&5), Some(1));
+        assert_eq!(tree.search_ref(&5), Some(&5));
+        assert_eq!(tree.count(&5), 1);
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.remove_one(&5), Some(0));
+        assert_eq!(tree.search_ref(&5), None);
+        assert_eq!(tree.count(&5), 0);
+        assert_eq!(tree.remove_one(&5), None);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.storage.occupied().count(), 2);
+        assert_eq!(tree.remaining(), 2);
+    }
+
+    #[test]
+    fn test_packed_storage() {
+        const SIZE: usize = 4;
+        assert_eq!(node_size::<u64>(), core::mem::size_of::<Node<u64>>());
+        let mut mem = [0xAA; SIZE * node_size::<u64>() + 8];
+        let offset = mem
+            .as_ptr()
+            .align_offset(core::mem::align_of::<Node<u64>>());
+        let mut tree = Rbt::<u64, SIZE>::new(&mut mem[offset..offset + SIZE * node_size::<u64>()]);
+        assert!((0..SIZE).all(|i| !tree.storage.is_occupied(i)));
+
+        for num in [20, 10, 30] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.storage.occupied().count(), 3);
+        assert_eq!(tree.storage.free_slots().count(), 1);
+        tree.delete(20).unwrap();
+        assert_eq!(tree.storage.occupied().count(), 2);
+        assert!(tree.storage.occupied().all(|node| node.data != 20));
+
+        // The freed slot is reused, and every slot can be filled.
+        for num in [40, 50] {
+            tree.insert(num).unwrap();
+        }
+        assert!((0..SIZE).all(|i| tree.storage.is_occupied(i)));
+        assert!(tree.iter().eq([10, 30, 40, 50].iter()));
+    }
+
+    #[test]
+    fn test_retain() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        for num in (0..SIZE as u32).map(|i| (i * 37) % SIZE as u32) {
+            tree.insert(num).unwrap();
+        }
+
+        let mut visited = std::vec::Vec::new();
+        tree.retain(|&x| {
+            visited.push(x);
+            x % 2 == 0
+        });
+        assert!(visited
+            .iter()
+            .eq((0..SIZE as u32).collect::<std::vec::Vec<_>>().iter()));
+        assert!(tree.iter().copied().eq((0..SIZE as u32).step_by(2)));
+        assert_eq!(tree.len(), SIZE / 2);
+        assert_eq!(tree.storage.occupied().count(), SIZE / 2);
+        tree.validate().unwrap();
+
+        tree.retain(|_| false);
+        assert!(tree.is_empty());
+        assert_eq!(tree.storage.free_slots().count(), SIZE);
+    }
+
+    #[test]
+    fn test_fmt_tree() {
+        struct Tree<'b, 'a>(&'b Rbt<'a, u32, 8>);
+        impl core::fmt::Display for Tree<'_, '_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                self.0.fmt_tree(f)
+            }
+        }
+
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, 8>::new(&mut mem);
+        assert_eq!(std::format!("{}", Tree(&tree)), "");
+        for num in [1, 2, 3, 4] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(
+            std::format!("{}", Tree(&tree)),
+            "        4 (R)\n    3 (B)\n2 (B)\n    1 (B)\n"
+        );
+    }
+
+    #[test]
+    fn test_pop_min_max() {
+        const SIZE: usize = 32;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut mem);
+        assert_eq!((tree.pop_min(), tree.pop_max()), (None, None));
+        for num in (0..SIZE as u32).map(|i| (i * 13) % SIZE as u32) {
+            tree.insert(num).unwrap();
+        }
+
+        for expected in 0..SIZE as u32 / 2 {
+            assert_eq!(tree.pop_min(), Some(expected));
+            assert_eq!(tree.pop_max(), Some(SIZE as u32 - 1 - expected));
+            tree.validate().unwrap();
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.storage.free_slots().count(), SIZE);
+        assert_eq!((tree.pop_min(), tree.pop_max()), (None, None));
+    }
+
+    #[test]
+    fn test_new_at() {
+        const SIZE: usize = 8;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>()]);
+        static mut REGION: Aligned = Aligned([0; SIZE * node_size::<u32>()]);
+
+        let addr = unsafe { core::ptr::addr_of_mut!(REGION.0) } as *mut u8;
+        let len = SIZE * node_size::<u32>();
+        assert!(matches!(
+            unsafe { Rbt::<u32, SIZE>::new_at(addr, len - 1) },
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            unsafe { Rbt::<u32, SIZE>::new_at(addr.add(1), len - 1) },
+            Err(Error::BadBuffer)
+        ));
+        let mut tree = unsafe { Rbt::<u32, SIZE>::new_at(addr, len).unwrap() };
+        for num in [5, 3, 8, 1] {
+            tree.insert(num).unwrap();
+        }
+        assert!(tree.iter().copied().eq([1, 3, 5, 8]));
+
+        // Every node lives inside the region.
+        let region = addr as usize..addr as usize + SIZE * node_size::<u32>();
+        let mut node = tree.head();
+        while let Some(current) = node {
+            assert!(region.contains(&(current.as_mut_ptr() as usize)));
+            node = current.left();
+        }
+    }
+
+    #[test]
+    fn test_new_at_too_many_nodes() {
+        // Large enough for the nodes, but one of them would need the index reserved for null.
+        const SIZE: usize = u16::MAX as usize;
+        let len = SIZE * node_size::<u8>();
+        let mut region: std::vec::Vec<u128> = std::vec![0; len.div_ceil(16)];
+        let addr = region.as_mut_ptr() as *mut u8;
+        assert!(matches!(
+            unsafe { Rbt::<u8, SIZE>::new_at(addr, len) },
+            Err(Error::BadBuffer)
+        ));
+        assert!(unsafe { Rbt::<u8, { SIZE - 1 }>::new_at(addr, len) }.is_ok());
+    }
+
+    #[test]
+    fn test_restore_relocated() {
+        const SIZE: usize = 32;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>()]);
+
+        let mut first = Aligned([0; SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new(&mut first.0);
+        for num in 0..20 {
+            tree.insert((num * 7) % 20).unwrap();
+        }
+        tree.delete(5).unwrap();
+        // The restored tree takes over the elements, so the original must not drop them.
+        core::mem::forget(tree);
+
+        // Move the bytes to a different address and wipe the original.
+        let mut second = Aligned([0; SIZE * node_size::<u32>()]);
+        second.0.copy_from_slice(&first.0);
+        first.0.fill(0);
+
+        let mut tree = unsafe { Rbt::<u32, SIZE>::restore(&mut second.0) };
+        assert_eq!(tree.len(), 19);
+        for num in 0..20 {
+            assert_eq!(tree.search(&num), (num != 5).then_some(num));
+        }
+        assert!(tree.iter().copied().eq((0..20).filter(|num| *num != 5)));
+        assert!(tree.validate().is_ok());
+
+        // The free slots were recovered as well.
+        for num in 20..33 {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(33), Err(Error::OutOfSpace)));
+    }
+
+    #[test]
+    fn test_new_uninit() {
+        const SIZE: usize = 8;
+        #[repr(C, align(16))]
+        struct Aligned([core::mem::MaybeUninit<u8>; SIZE * node_size::<u32>()]);
+
+        let mut mem = Aligned([core::mem::MaybeUninit::uninit(); SIZE * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, SIZE>::new_uninit(&mut mem.0);
+        assert!(tree.is_empty());
+        for num in [4, 2, 6, 1] {
+            tree.insert(num).unwrap();
+        }
+        assert_eq!(tree.remove(&2), Some(2));
+        assert!(tree.iter().eq([1, 4, 6].iter()));
+    }
+
+    #[test]
+    fn test_dyn_capacity() {
+        #[repr(C, align(16))]
+        struct Aligned([u8; 5 * node_size::<u32>() + 3]);
+
+        // The trailing bytes are too few for another node.
+        let mut mem = Aligned([0; 5 * node_size::<u32>() + 3]);
+        let mut tree = RbtDyn::<u32>::new(&mut mem.0);
+        assert_eq!(tree.capacity(), 5);
+        for num in [3, 1, 4, 5, 2] {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(6), Err(Error::OutOfSpace)));
+        tree.delete(4).unwrap();
+        tree.insert(6).unwrap();
+        assert!(tree.iter().eq([1, 2, 3, 5, 6].iter()));
+        tree.clear();
+        assert_eq!(tree.remaining(), 5);
+
+        let mut mem = Aligned([0; 5 * node_size::<u32>() + 3]);
+        let tree = RbtDyn::<u32>::from_sorted(&mut mem.0, &[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(tree.remaining(), 0);
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_new_in() {
+        static mut STORAGE: AlignedStorage<u64, 16> = AlignedStorage::new();
+
+        let mut tree = Rbt::<u64, 16>::new_in(unsafe { &mut *core::ptr::addr_of_mut!(STORAGE) });
+        for num in (0..16).rev() {
+            tree.insert(num).unwrap();
+        }
+        assert!(matches!(tree.insert(16), Err(Error::OutOfSpace)));
+        assert!(tree.iter().copied().eq(0..16));
+
+        let mut storage = AlignedStorage::<u8, 2>::default();
+        let mut tree = Rbt::<u8, 2>::new_in(&mut storage);
+        tree.insert(7).unwrap();
+        assert_eq!(tree.search(&7), Some(7));
+    }
+
+    #[test]
+    fn test_try_new_bad_buffer() {
+        const SIZE: usize = 4;
+        #[repr(C, align(16))]
+        struct Aligned([u8; SIZE * node_size::<u32>() + 1]);
+
+        let mut mem = Aligned([0; SIZE * node_size::<u32>() + 1]);
+        assert!(matches!(
+            Rbt::<u32, SIZE>::try_new(&mut mem.0[..SIZE * node_size::<u32>() - 1]),
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            Rbt::<u32, SIZE>::try_new(&mut mem.0[1..]),
+            Err(Error::BadBuffer)
+        ));
+        let mut tree = Rbt::<u32, SIZE>::try_new(&mut mem.0).unwrap();
+        tree.insert(1).unwrap();
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_get_or_insert() {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Block {
+            start: u32,
+            pages: u32,
+        }
+
+        impl OrderedKey for Block {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.start
+            }
+        }
+
+        let block = |start, pages| Block { start, pages };
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Block>()]);
+        let mut tree = Rbt::<Block, 4>::new(&mut mem);
+
+        // Misses insert the new block.
+        for start in [20, 10, 30] {
+            assert_eq!(
+                tree.get_or_insert(block(start, 1)).unwrap(),
+                block(start, 1)
+            );
+        }
+        assert_eq!(tree.len(), 3);
+
+        // A hit returns the stored block and adds nothing.
+        assert_eq!(tree.get_or_insert(block(10, 5)).unwrap(), block(10, 1));
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.get_or_insert(block(40, 1)).unwrap(), block(40, 1));
+        assert_eq!(tree.len(), 4);
+        assert!(matches!(
+            tree.get_or_insert(block(50, 1)),
+            Err(Error::OutOfSpace)
+        ));
+        assert_eq!(tree.get_or_insert(block(30, 2)).unwrap(), block(30, 1));
+        assert!(tree.iter().map(|b| b.start).eq([10, 20, 30, 40]));
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut mem = AlignedBuffer([0; 3 * node_size::<u32>()]);
+        let mut tree = Rbt::<u32, 3>::new(&mut mem);
+        let mut calls = 0;
+
+        for key in [2, 1, 3] {
+            let value = tree.get_or_insert_with(&key, || {
+                calls += 1;
+                key
+            });
+            assert_eq!(*value.unwrap(), key);
+        }
+        assert_eq!(calls, 3);
+
+        // A hit never builds a new element, even when the tree is full.
+        assert_eq!(*tree.get_or_insert_with(&1, || unreachable!()).unwrap(), 1);
+        assert_eq!(
+            *tree.entry(&3).or_insert_with(|| unreachable!()).unwrap(),
+            3
+        );
+        assert!(matches!(
+            tree.entry(&4).or_insert_with(|| 4),
+            Err(Error::OutOfSpace)
+        ));
+        assert!(tree.iter().eq([1, 2, 3].iter()));
+    }
+
+    #[test]
+    fn test_insert_or_replace() {
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Block {
+            start: u32,
+            pages: u32,
         }
 
-        // Adding a node off of 75 should cause a color change
-        rbt.insert(81).unwrap();
-        {
-            let head = rbt.head().unwrap();
-            assert!(head.is_black());
-            let right = head.right().unwrap();
-            assert!(right.is_red());
-            let right_l = right.left().unwrap();
-            assert!(right_l.is_black());
-            let right_r = right.right().unwrap();
-            assert!(right_r.is_black());
-            let right_r_r = right_r.right().unwrap();
-            assert!(right_r_r.is_red());
+        impl OrderedKey for Block {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.start
+            }
         }
+
+        let block = |start, pages| Block { start, pages };
+        let mut mem = AlignedBuffer([0; 2 * node_size::<Block>()]);
+        let mut tree = Rbt::<Block, 2>::new(&mut mem);
+
+        assert_eq!(tree.insert_or_replace(block(10, 1)).unwrap(), None);
+        assert_eq!(tree.insert_or_replace(block(20, 1)).unwrap(), None);
+        assert_eq!(
+            tree.insert_or_replace(block(10, 4)).unwrap(),
+            Some(block(10, 1))
+        );
+        assert!(matches!(
+            tree.insert_or_replace(block(30, 1)),
+            Err(Error::OutOfSpace)
+        ));
+
+        // A replacement reuses the existing node, so it succeeds even when the tree is full.
+        assert_eq!(
+            tree.insert_or_replace(block(20, 2)).unwrap(),
+            Some(block(20, 1))
+        );
+        assert_eq!(tree.len(), 2);
+        assert!(tree.iter().eq([block(10, 4), block(20, 2)].iter()));
     }
 
     #[test]
-    fn test_case_4() {
-        /* Parent Node is red, uncle node is black, inserted node is Inner
-           grandchild should cause a rotation.
+    fn test_get_mut() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Region {
+            base: u64,
+            attributes: u64,
+        }
 
-          Final Expected State:
-                   [17B]
-                   /   \
-                [09B] [24B]
-                      /   \
-                    [19R] [75R]
-        */
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<i32>()];
-        let mut rbt: Rbt<i32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
-        rbt.insert(17).unwrap();
-        rbt.insert(9).unwrap();
-        rbt.insert(19).unwrap();
-        rbt.insert(75).unwrap();
-        rbt.insert(24).unwrap();
+        impl OrderedKey for Region {
+            type Key = u64;
+            fn ordering_key(&self) -> &u64 {
+                &self.base
+            }
+        }
 
-        // Validate head (17)
-        let head = rbt.head().unwrap();
-        assert!(head.is_black());
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Region>()]);
+        let mut tree = Rbt::<Region, 4>::new(&mut mem);
+        for base in [0x2000, 0x1000, 0x3000] {
+            tree.insert(Region {
+                base,
+                attributes: 0,
+            })
+            .unwrap();
+        }
 
-        // Validate left child (9)
-        let left = head.left().unwrap();
-        assert!(left.is_black());
-        assert_eq!(left.data, 9);
-        assert_eq!(left.parent_ptr(), head.as_mut_ptr());
+        tree.get_mut(&0x1000).unwrap().attributes = 0x8;
+        assert!(tree.get_mut(&0x4000).is_none());
+        assert_eq!(tree.search_ref(&0x1000).unwrap().attributes, 0x8);
+        assert_eq!(tree.search_ref(&0x2000).unwrap().attributes, 0);
+    }
 
-        // Validate right child(24)
-        let right = head.right().unwrap();
-        assert!(right.is_black());
-        assert_eq!(right.data, 24);
-        assert_eq!(right.parent_ptr(), head.as_mut_ptr());
+    #[test]
+    fn test_non_copy_elements() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Handle {
+            id: u32,
+            state: core::cell::Cell<u32>,
+        }
 
-        // Validate right child's left child (19)
-        let right_l = right.left().unwrap();
-        assert!(right_l.is_red());
-        assert_eq!(right_l.data, 19);
-        assert_eq!(right_l.parent_ptr(), right.as_mut_ptr());
+        impl OrderedKey for Handle {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.id
+            }
+        }
 
-        // Validate right child's right child (75)
-        let right_r = right.right().unwrap();
-        assert!(right_r.is_red());
-        assert_eq!(right_r.data, 75);
+        let handle = |id| Handle {
+            id,
+            state: core::cell::Cell::new(0),
+        };
+        let mut mem = AlignedBuffer([0; 4 * node_size::<Handle>()]);
+        let mut tree = Rbt::<Handle, 4>::new(&mut mem);
+        for id in [3, 1, 5] {
+            tree.insert(handle(id)).unwrap();
+        }
+
+        tree.search_ref(&3).unwrap().state.set(7);
+        assert_eq!(tree.floor_ref(&4).unwrap().state.get(), 7);
+        assert_eq!(tree.ceil_ref(&4).unwrap().id, 5);
+        assert_eq!(tree.ceiling_ref(&4).unwrap().id, 5);
+        assert_eq!(tree.successor_ref(&3).unwrap().id, 5);
+        assert_eq!(tree.predecessor_ref(&3).unwrap().id, 1);
+        assert_eq!(tree.min_ref().unwrap().id, 1);
+        assert_eq!(tree.max_ref().unwrap().id, 5);
+        assert_eq!(tree.select_ref(1).unwrap().id, 3);
+
+        // Removing moves the element out of the tree.
+        let removed = tree.remove(&3).unwrap();
+        assert_eq!(removed.state.into_inner(), 7);
+        assert!(tree.iter().map(|h| h.id).eq([1, 5]));
     }
 
     #[test]
-    fn test_rotate_right() {
-        /* Verifies that the rotate right function works as expected.
-             [50]              [75]
-             /  \              /  \
-           [10][75]    <--   [50][85]
-               /  \          /  \
-             [70][85]      [10][70]
-        */
-        let node = Node::new(75);
-        let left = Node::new(50);
-        let right = Node::new(85);
-        let left_l = Node::new(10);
-        let left_r = Node::new(70);
+    fn test_drop_elements() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Tracked<'c> {
+            id: u32,
+            drops: &'c core::cell::Cell<u32>,
+        }
 
-        left.set_left(&left_l);
-        left_l.set_parent(&left);
-        left.set_right(&left_r);
-        left_r.set_parent(&left);
-        node.set_left(&left);
-        left.set_parent(&node);
-        node.set_right(&right);
-        right.set_parent(&node);
+        impl OrderedKey for Tracked<'_> {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.id
+            }
+        }
 
-        let head = AtomicPtr::<Node<i32>>::default();
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
 
-        Rbt::<i32, RBT_MAX_SIZE>::rotate_right(&head, &node);
+        let drops = core::cell::Cell::new(0);
+        let tracked = |id| Tracked { id, drops: &drops };
+        let mut mem = AlignedBuffer([0; 8 * node_size::<Tracked>()]);
+        let mut tree = Rbt::<Tracked, 8>::new(&mut mem);
+        for id in 0..6 {
+            tree.insert(tracked(id)).unwrap();
+        }
 
-        // Check left[50] <-> left_l[10] connection
-        assert_eq!(left.left().unwrap().as_mut_ptr(), left_l.as_mut_ptr());
-        assert_eq!(left_l.parent().unwrap().as_mut_ptr(), left.as_mut_ptr());
+        // A removed element is moved out and dropped by the caller, exactly once.
+        drop(tree.remove(&2));
+        assert_eq!(drops.get(), 1);
+        assert!(matches!(tree.insert(tracked(3)), Err(Error::AlreadyExists)));
+        assert_eq!(drops.get(), 2);
 
-        // check left[50] <-> left_r[70] connection
-        assert_eq!(left.right().unwrap().as_mut_ptr(), node.as_mut_ptr());
-        assert_eq!(node.parent().unwrap().as_mut_ptr(), left.as_mut_ptr());
+        tree.clear();
+        assert_eq!(drops.get(), 7);
 
-        // check left_l[10] has no children
-        assert!(left_l.left().is_none());
-        assert!(left_l.right().is_none());
+        for id in 0..4 {
+            tree.insert(tracked(id)).unwrap();
+        }
+        drop(tree);
+        assert_eq!(drops.get(), 11);
+    }
 
-        // check node[75] <-> left_r[70] connection
-        assert_eq!(node.left().unwrap().as_mut_ptr(), left_r.as_mut_ptr());
-        assert_eq!(left_r.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+    #[test]
+    fn test_insert_out_of_space() {
+        const SIZE: usize = 10;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, SIZE>::new(&mut mem);
+        for i in 0..SIZE as i32 {
+            rbt.insert(i).unwrap();
+        }
+        assert_eq!(rbt.len(), SIZE);
 
-        // check node[75] <-> right[85] connection
-        assert_eq!(node.right().unwrap().as_mut_ptr(), right.as_mut_ptr());
-        assert_eq!(right.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+        let before: std::vec::Vec<_> = rbt.iter().copied().collect();
+        let head = rbt.head.load(Ordering::SeqCst);
 
-        // Check right_r[70] has no children
-        assert!(left_r.left().is_none());
-        assert!(left_r.right().is_none());
+        assert!(matches!(rbt.insert(SIZE as i32), Err(Error::OutOfSpace)));
+        assert_eq!(rbt.len(), SIZE);
+        assert_eq!(rbt.head.load(Ordering::SeqCst), head);
+        assert!(rbt.validate().is_ok());
 
-        // Check right[85] has no children
-        assert!(right.left().is_none());
-        assert!(right.right().is_none());
+        let after: std::vec::Vec<_> = rbt.iter().copied().collect();
+        assert_eq!(before, after);
+        assert_eq!(rbt.search(&(SIZE as i32)), None);
     }
 
     #[test]
-    fn test_rotate_left() {
-        /* Verifies that the rotate left function works as expected.
-             [50]              [75]
-             /  \              /  \
-           [10][75]    -->   [50][85]
-               /  \          /  \
-             [70][85]      [10][70]
-        */
-        let node = Node::new(50);
-        let left = Node::new(10);
-        let right = Node::new(75);
-        let right_l = Node::new(70);
-        let right_r = Node::new(85);
-
-        right.set_left(&right_l);
-        right_l.set_parent(&right);
-        right.set_right(&right_r);
-        right_r.set_parent(&right);
-        node.set_left(&left);
-        left.set_parent(&node);
-        node.set_right(&right);
-        right.set_parent(&node);
+    fn test_insert_duplicate() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        for i in 0..10 {
+            rbt.insert(i).unwrap();
+        }
 
-        let head = AtomicPtr::<Node<i32>>::default();
+        let before: std::vec::Vec<_> = rbt.iter().copied().collect();
+        for i in 0..10 {
+            assert!(matches!(rbt.insert(i), Err(Error::AlreadyExists)));
+        }
+        assert_eq!(rbt.len(), 10);
+        assert_eq!(rbt.storage.occupied().count(), 10);
+        assert!(rbt.validate().is_ok());
 
-        Rbt::<i32, RBT_MAX_SIZE>::rotate_left(&head, &node);
+        let after: std::vec::Vec<_> = rbt.iter().copied().collect();
+        assert_eq!(before, after);
+    }
 
-        // Check right[75] <-left-> node[50] connection
-        assert_eq!(right.left().unwrap().as_mut_ptr(), node.as_mut_ptr());
-        assert_eq!(node.parent().unwrap().as_mut_ptr(), right.as_mut_ptr());
+    #[test]
+    fn test_iter() {
+        const SIZE: usize = 64;
+        let mut mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut rbt = Rbt::<u32, SIZE>::new(&mut mem);
+        assert_eq!(rbt.iter().next(), None);
+
+        // Insert in a scrambled order so the tree rotates along the way.
+        let input =
+            ArrayVec::<u32, SIZE>::from_iter((0..SIZE as u32).map(|i| (i * 37) % SIZE as u32));
+        for num in &input {
+            rbt.insert(*num).unwrap();
+        }
+        let mut expected = input.clone();
+        expected.sort();
+        let values: ArrayVec<u32, SIZE> = rbt.iter().copied().collect();
+        assert_eq!(values, expected);
+        assert!(rbt.iter().rev().eq(expected.iter().rev()));
+
+        // Alternating ends meet in the middle without yielding a value twice.
+        let mut iter = rbt.iter();
+        let mut seen = ArrayVec::<u32, SIZE>::new();
+        while let Some(value) = iter.next() {
+            seen.push(*value);
+            if let Some(value) = iter.next_back() {
+                seen.push(*value);
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, expected);
 
-        // Check right[75] <-right-> right_r[85] connection
-        assert_eq!(right.right().unwrap().as_mut_ptr(), right_r.as_mut_ptr());
-        assert_eq!(right_r.parent().unwrap().as_mut_ptr(), right.as_mut_ptr());
+        for num in (0..SIZE as u32).step_by(3) {
+            rbt.delete(num).unwrap();
+        }
+        expected.retain(|num| *num % 3 != 0);
+        assert!(rbt.iter().copied().eq(expected.iter().copied()));
 
-        // Check node[50] <-left-> left[10] connection
-        assert_eq!(node.left().unwrap().as_mut_ptr(), left.as_mut_ptr());
-        assert_eq!(left.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+        // Borrowing the tree iterates it the same way.
+        let mut values = ArrayVec::<u32, SIZE>::new();
+        for value in &rbt {
+            values.push(*value);
+        }
+        assert_eq!(values, expected);
+    }
 
-        // Check node[50] <-right-> right_l[70] connection
-        assert_eq!(node.right().unwrap().as_mut_ptr(), right_l.as_mut_ptr());
-        assert_eq!(right_l.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
+    #[test]
+    fn test_ordering_key_differs_from_ord() {
+        // The derived `PartialOrd` compares `rank` first, which runs opposite to `key`.
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        struct Entry {
+            rank: u32,
+            key: u32,
+        }
 
-        // Check left[10] has no children
-        assert!(left.left().is_none());
-        assert!(left.right().is_none());
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.key
+            }
+        }
 
-        // Check right_r[85] has no children
-        assert!(right_r.left().is_none());
-        assert!(right_r.right().is_none());
+        let entry = |key| Entry {
+            rank: 100 - key,
+            key,
+        };
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<Entry>()]);
+        let mut tree: Rbt<Entry, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        let keys = [7, 3, 11, 1, 5, 9, 13];
+        for key in keys {
+            tree.insert(entry(key)).unwrap();
+        }
+        for key in keys {
+            assert_eq!(tree.search(&key), Some(entry(key)));
+        }
+        assert!(tree.iter().map(|e| e.key).eq([1, 3, 5, 7, 9, 11, 13]));
 
-        // Check right_l[70] has no children
-        assert!(right_l.left().is_none());
-        assert!(right_l.right().is_none());
+        tree.delete(entry(7)).unwrap();
+        tree.delete(entry(1)).unwrap();
+        assert_eq!(tree.search(&7), None);
+        assert!(tree.iter().map(|e| e.key).eq([3, 5, 9, 11, 13]));
     }
 
     #[test]
     fn test_delete_from_storage() {
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<i32>()];
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
         let mut rbt = Rbt::<i32, 10>::new(&mut mem);
         rbt.insert(5).unwrap();
         rbt.insert(3).unwrap();
         assert_eq!(rbt.storage.len(), 2);
-        assert_eq!(rbt.storage.data.iter().filter(|(i, _)| { *i }).count(), 2);
+        assert_eq!(rbt.storage.occupied().count(), 2);
         rbt.delete(5).unwrap();
         assert_eq!(rbt.storage.len(), 1);
-        assert_eq!(rbt.storage.data.iter().filter(|(i, _)| { *i }).count(), 1);
+        assert_eq!(rbt.storage.occupied().count(), 1);
         rbt.delete(3).unwrap();
         assert_eq!(rbt.storage.len(), 0);
-        assert_eq!(rbt.storage.data.iter().filter(|(i, _)| { *i }).count(), 0);
+        assert_eq!(rbt.storage.occupied().count(), 0);
     }
 
     #[test]
@@ -794,31 +2970,384 @@ mod tests {
                /
              [05]
         */
-        let node = Node::new(50);
-        let left = Node::new(10);
-        let left_l = Node::new(5);
+        let slots = slots([50, 10, 5]);
+        let [node, left, left_l] = anchored(&slots).each_ref();
 
-        node.set_left(&left);
-        left.set_parent(&node);
-        left.set_left(&left_l);
-        left_l.set_parent(&left);
+        node.set_left(left);
+        left.set_parent(node);
+        left.set_left(left_l);
+        left_l.set_parent(left);
 
-        // Delete a node with a single child.
-        Rbt::<i32, RBT_MAX_SIZE>::delete_simple(&node, &left);
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        rbt.head.store(node.as_mut_ptr(), Ordering::SeqCst);
+
+        // Delete a node with a single child. The nodes are all red, so no fixup runs.
+        rbt.unlink(0, left.as_mut_ptr());
         assert_eq!(node.left().unwrap().as_mut_ptr(), left_l.as_mut_ptr());
         assert_eq!(left_l.parent().unwrap().as_mut_ptr(), node.as_mut_ptr());
 
         // Delete a node with no children.
-        Rbt::<i32, RBT_MAX_SIZE>::delete_simple(&node, &left_l);
+        rbt.unlink(0, left_l.as_mut_ptr());
         assert!(node.left().is_none());
+        assert_eq!(node.size(), 1);
+
+        // Delete the head.
+        rbt.unlink(0, node.as_mut_ptr());
+        assert!(rbt.head.load(Ordering::SeqCst).is_null());
+    }
+
+    /// Returns the values of a sorted slice in the order they would be visited by a breadth-first walk of the
+    /// balanced tree built from it. Inserting in this order never triggers a rotation.
+    fn level_order<T: Copy>(sorted: &[T]) -> std::vec::Vec<T> {
+        let mut order = std::vec::Vec::new();
+        let mut queue = std::collections::VecDeque::from([(0, sorted.len())]);
+        while let Some((lo, hi)) = queue.pop_front() {
+            if lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                order.push(sorted[mid]);
+                queue.push_back((lo, mid));
+                queue.push_back((mid + 1, hi));
+            }
+        }
+        order
+    }
+
+    #[test]
+    fn test_shape_predicates() {
+        use rand::Rng;
+
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+
+        // An empty tree is trivially perfect and complete.
+        assert!(rbt.is_perfect());
+        assert!(rbt.is_complete());
+
+        let mut rng = rand::thread_rng();
+        let mut keys = std::collections::BTreeSet::new();
+        while keys.len() < (1 << 8) - 1 {
+            keys.insert(rng.gen_range(0..100_000u32));
+        }
+        let keys: std::vec::Vec<u32> = keys.into_iter().collect();
+
+        for key in level_order(&keys) {
+            rbt.insert(key).unwrap();
+        }
+        assert!(rbt.is_perfect());
+        assert!(rbt.is_complete());
+
+        // One more node keeps the tree complete, but it is no longer perfect.
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        for key in level_order(&[10, 20, 30, 40, 50, 60, 70, 80]) {
+            rbt.insert(key).unwrap();
+        }
+        assert!(!rbt.is_perfect());
+        assert!(rbt.is_complete());
+
+        // A right-leaning node is neither.
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        rbt.insert(10).unwrap();
+        rbt.insert(20).unwrap();
+        assert!(!rbt.is_perfect());
+        assert!(!rbt.is_complete());
+    }
+
+    #[test]
+    fn test_depth_histogram_into() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
+        let mut out = [usize::MAX; 6];
+        assert_eq!(rbt.depth_histogram_into(&mut out), 0);
+        assert_eq!(out, [0; 6]);
+
+        let keys: std::vec::Vec<u32> = (1..=20).collect();
+        for key in level_order(&keys) {
+            rbt.insert(key).unwrap();
+        }
+        assert_eq!(rbt.depth_histogram_into(&mut out), 5);
+        assert_eq!(out, [1, 2, 4, 8, 5, 0]);
+
+        // A short buffer only receives the top levels.
+        let mut out = [0; 2];
+        assert_eq!(rbt.depth_histogram_into(&mut out), 5);
+        assert_eq!(out, [1, 2]);
+    }
+
+    #[test]
+    fn test_delete_every_insert_order() {
+        // Every insertion order of a small tree, deleted front to back, back to front and in insertion order,
+        // reaches each of the delete fixup cases.
+        const N: usize = 7;
+        let mut order: [u32; N] = core::array::from_fn(|i| i as u32);
+        let mut counters = [0; N];
+        let mut permutations = 1;
+        let check = |order: &[u32; N]| {
+            let mut reversed = *order;
+            reversed.reverse();
+            for deletes in [
+                [0, 1, 2, 3, 4, 5, 6],
+                [6, 5, 4, 3, 2, 1, 0],
+                *order,
+                reversed,
+            ] {
+                let mut mem = AlignedBuffer([0; N * node_size::<u32>()]);
+                let mut rbt = Rbt::<u32, N>::new(&mut mem);
+                for num in order {
+                    rbt.insert(*num).unwrap();
+                }
+                for (deleted, num) in deletes.iter().enumerate() {
+                    rbt.delete(*num).unwrap();
+                    rbt.validate().unwrap();
+                    assert_eq!(rbt.len(), N - deleted - 1);
+                }
+            }
+        };
+
+        // Heap's algorithm.
+        check(&order);
+        let mut i = 0;
+        while i < N {
+            if counters[i] < i {
+                order.swap(if i % 2 == 0 { 0 } else { counters[i] }, i);
+                check(&order);
+                permutations += 1;
+                counters[i] += 1;
+                i = 0;
+            } else {
+                counters[i] = 0;
+                i += 1;
+            }
+        }
+        assert_eq!(permutations, 5040);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<i32>()]);
+        let mut rbt = Rbt::<i32, RBT_MAX_SIZE>::new(&mut mem);
+        assert_eq!(rbt.validate(), Ok(0));
+
+        /*
+                [2B]
+               /    \
+            [1B]    [3B]
+                       \
+                       [4R]
+        */
+        for num in 1..=4 {
+            rbt.insert(num).unwrap();
+        }
+        assert_eq!(rbt.validate(), Ok(3));
+        let head = rbt.head().unwrap();
+        let one = rbt.search_node(&1).unwrap();
+        let three = rbt.search_node(&3).unwrap();
+        let four = rbt.search_node(&4).unwrap();
+
+        head.set_color(RED);
+        assert_eq!(rbt.validate(), Err("head is red"));
+        head.set_color(BLACK);
+
+        three.set_color(RED);
+        assert_eq!(rbt.validate(), Err("red node has a red child"));
+        three.set_color(BLACK);
+
+        one.set_color(RED);
+        assert_eq!(rbt.validate(), Err("black height differs between paths"));
+        one.set_color(BLACK);
+
+        four.set_parent(one);
+        assert_eq!(
+            rbt.validate(),
+            Err("child does not point back to its parent")
+        );
+        four.set_parent(three);
+
+        assert_eq!(rbt.validate(), Ok(3));
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        for len in [0, 1, 2, 3, 7, 8, 100, 255, 256] {
+            let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+            let sorted: std::vec::Vec<u32> = (0..len).map(|i| i * 3).collect();
+            let rbt = Rbt::<u32, RBT_MAX_SIZE>::from_sorted(&mut mem, &sorted).unwrap();
+            rbt.validate().unwrap();
+            assert_eq!(rbt.len(), len as usize);
+            assert!(rbt.iter().eq(sorted.iter()));
+        }
+
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, RBT_MAX_SIZE>::from_sorted(&mut mem, &[1, 3, 2]),
+            Err(Error::AlreadyExists)
+        ));
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, RBT_MAX_SIZE>::from_sorted(&mut mem, &[1, 2, 2, 3]),
+            Err(Error::AlreadyExists)
+        ));
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, 4>::from_sorted(&mut mem, &[1, 2, 3, 4, 5]),
+            Err(Error::OutOfSpace)
+        ));
+    }
+
+    #[test]
+    fn test_from_sorted_validated() {
+        for len in [0, 1, 2, 3, 7, 8, 100, 255, 256] {
+            let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+            let sorted: std::vec::Vec<u32> = (0..len).map(|i| i * 3).collect();
+            let rbt = Rbt::<u32, RBT_MAX_SIZE>::from_sorted_validated(&mut mem, &sorted).unwrap();
+            assert_eq!(rbt.len(), len as usize);
+
+            let values: std::vec::Vec<_> = rbt.iter().copied().collect();
+            assert_eq!(values, sorted);
+        }
+
+        // Swapping two neighbours deep in the input breaks the ordering.
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        let mut sorted: std::vec::Vec<u32> = (0..100).collect();
+        sorted.swap(70, 71);
+        assert!(matches!(
+            Rbt::<u32, RBT_MAX_SIZE>::from_sorted_validated(&mut mem, &sorted),
+            Err(Error::ValidationFailed)
+        ));
+
+        // So does a duplicate.
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, RBT_MAX_SIZE>::from_sorted_validated(&mut mem, &[1, 2, 2, 3]),
+            Err(Error::ValidationFailed)
+        ));
+
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, 4>::from_sorted_validated(&mut mem, &[1, 2, 3, 4, 5]),
+            Err(Error::OutOfSpace)
+        ));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u64>()]);
+        let mut rbt = Rbt::<u64, RBT_MAX_SIZE>::new(&mut mem);
+        for i in [50u64, 10, 40, 20, 30] {
+            rbt.insert(i).unwrap();
+        }
+
+        // The snapshot is written at an odd offset, so neither side can rely on alignment.
+        const LEN: usize = SNAPSHOT_HEADER_SIZE + 5 * size_of::<u64>();
+        let mut snapshot = [0; 1 + LEN];
+        assert!(matches!(
+            rbt.save_to(&mut snapshot[1..LEN]),
+            Err(Error::OutOfSpace)
+        ));
+        assert_eq!(rbt.save_to(&mut snapshot[1..]).unwrap(), LEN);
+        let snapshot = &mut snapshot[1..];
+
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u64>()]);
+        let loaded = Rbt::<u64, 8>::load_from(snapshot, &mut mem).unwrap();
+        assert!(loaded.iter().eq(rbt.iter()));
+
+        let mut mem = AlignedBuffer([0; 4 * node_size::<u64>()]);
+        assert!(matches!(
+            Rbt::<u64, 4>::load_from(snapshot, &mut mem),
+            Err(Error::OutOfSpace)
+        ));
+
+        // A truncated snapshot, or one written for another element type, is rejected before any element is read.
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u64>()]);
+        for bytes in [&snapshot[..LEN - 1], &snapshot[..SNAPSHOT_HEADER_SIZE - 1]] {
+            assert!(matches!(
+                Rbt::<u64, 8>::load_from(bytes, &mut mem),
+                Err(Error::BadBuffer)
+            ));
+        }
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u32>()]);
+        assert!(matches!(
+            Rbt::<u32, 8>::load_from(snapshot, &mut mem),
+            Err(Error::BadBuffer)
+        ));
+
+        // So is one with a different magic or version.
+        for offset in [0, 4] {
+            snapshot[offset] ^= 1;
+            let mut mem = AlignedBuffer([0; 8 * node_size::<u64>()]);
+            assert!(matches!(
+                Rbt::<u64, 8>::load_from(snapshot, &mut mem),
+                Err(Error::BadBuffer)
+            ));
+            snapshot[offset] ^= 1;
+        }
+
+        snapshot[SNAPSHOT_HEADER_SIZE..][..8].copy_from_slice(&100u64.to_le_bytes());
+        let mut mem = AlignedBuffer([0; 8 * node_size::<u64>()]);
+        assert!(matches!(
+            Rbt::<u64, 8>::load_from(snapshot, &mut mem),
+            Err(Error::ValidationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_save_load_padded() {
+        // `(u8, u32)` holds three bytes of padding, none of which may end up in the snapshot.
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<(u8, u32)>()]);
+        let mut rbt = Rbt::<(u8, u32), RBT_MAX_SIZE>::new(&mut mem);
+        for i in [3u8, 1, 4, 2] {
+            rbt.insert((i, u32::MAX - i as u32)).unwrap();
+        }
+
+        const LEN: usize = SNAPSHOT_HEADER_SIZE + 4 * 5;
+        let mut snapshot = [0; LEN];
+        assert_eq!(rbt.save_to(&mut snapshot).unwrap(), LEN);
+        assert_eq!(
+            snapshot[SNAPSHOT_HEADER_SIZE..][..5],
+            [1, 0xfe, 0xff, 0xff, 0xff]
+        );
+
+        let mut mem = AlignedBuffer([0; 8 * node_size::<(u8, u32)>()]);
+        let loaded = Rbt::<(u8, u32), 8>::load_from(&snapshot, &mut mem).unwrap();
+        assert!(loaded.iter().eq(rbt.iter()));
+
+        // A byte that is no `bool` is rejected rather than read as one.
+        let mut mem = AlignedBuffer([0; 8 * node_size::<(u8, bool)>()]);
+        let mut rbt = Rbt::<(u8, bool), 8>::new(&mut mem);
+        rbt.insert((1, true)).unwrap();
+        let mut snapshot = [0; SNAPSHOT_HEADER_SIZE + 2];
+        rbt.save_to(&mut snapshot).unwrap();
+        snapshot[SNAPSHOT_HEADER_SIZE + 1] = 2;
+        let mut mem = AlignedBuffer([0; 8 * node_size::<(u8, bool)>()]);
+        assert!(matches!(
+            Rbt::<(u8, bool), 8>::load_from(&snapshot, &mut mem),
+            Err(Error::BadBuffer)
+        ));
+    }
+
+    #[test]
+    fn test_rbt_static() {
+        crate::rbt_static!(STATIC_TREE, u32, 256);
+
+        let rbt = STATIC_TREE::take().unwrap();
+        for num in [5, 3, 7, 2, 6, 8] {
+            rbt.insert(num).unwrap();
+        }
+        assert_eq!(rbt.len(), 6);
+        assert_eq!(rbt.search(&6), Some(6));
+
+        // The tree can only be taken once.
+        assert!(STATIC_TREE::take().is_none());
     }
 }
 
 #[cfg(test)]
 mod fuzz_tests {
     extern crate std;
-    use super::{node_size, Node, Rbt};
-    use core::sync::atomic::AtomicPtr;
+    use super::{node_size, Rbt};
+    use crate::{AlignedBuffer, Error};
     use rand::seq::SliceRandom;
     use rand::Rng;
     use std::collections::HashSet;
@@ -829,7 +3358,7 @@ mod fuzz_tests {
     #[test]
     fn fuzz_insert() {
         for _ in 0..100 {
-            let mut mem = [0; RBT_MAX_SIZE * node_size::<u32>()];
+            let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
             let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
             let mut rng = rand::thread_rng();
             let min = 1;
@@ -846,21 +3375,25 @@ mod fuzz_tests {
             random_numbers.shuffle(&mut rng);
 
             assert_eq!(random_numbers.len(), RBT_MAX_SIZE - 1);
-            for num in random_numbers.iter() {
+            for (i, num) in random_numbers.iter().enumerate() {
                 assert!(rbt.insert(*num).is_ok());
+                // Validating walks the whole tree, so only do it periodically to keep the test fast.
+                if i % 32 == 0 {
+                    rbt.validate().unwrap();
+                }
             }
+            assert!(rbt.validate().is_ok());
 
             random_numbers.sort();
 
-            let mut ordered_numbers = Vec::new();
-            rbt.dfs(rbt.head(), &mut ordered_numbers);
+            let ordered_numbers: Vec<_> = rbt.iter().copied().collect();
             assert_eq!(ordered_numbers, random_numbers);
         }
     }
 
     #[test]
     fn fuzz_delete() {
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<u32>()];
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
         let mut rbt: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
         let mut rng = rand::thread_rng();
         let min = 1;
@@ -884,12 +3417,15 @@ mod fuzz_tests {
         random_numbers.shuffle(&mut rng);
         while let Some(num) = random_numbers.pop() {
             assert!(rbt.delete(num).is_ok());
+            assert!(matches!(rbt.delete(num), Err(Error::NotFound)));
+            assert!(rbt.validate().is_ok());
         }
+        assert!(rbt.head().is_none());
     }
 
     #[test]
     fn fuzz_search() {
-        let mut mem = [0; RBT_MAX_SIZE * node_size::<u32>()];
+        let mut mem = AlignedBuffer([0; RBT_MAX_SIZE * node_size::<u32>()]);
         let mut bst: Rbt<u32, RBT_MAX_SIZE> = Rbt::new(&mut mem);
         let mut rng = rand::thread_rng();
         let min = 1;
@@ -926,4 +3462,69 @@ mod fuzz_tests {
             assert!(bst.search(&random_number).is_none());
         }
     }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Insert(u32),
+        Delete(u32),
+    }
+
+    /// Applies the same seeded stream of random operations to an [Rbt] and a [Bst](crate::bst::Bst), asserting
+    /// that both hold the same ordered set after every operation.
+    fn differential(seed: u64, operations: usize, delete_percent: u32) {
+        use crate::bst::{self, Bst};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        const SIZE: usize = 512;
+        let mut rbt_mem = AlignedBuffer([0; SIZE * node_size::<u32>()]);
+        let mut rbt: Rbt<u32, SIZE> = Rbt::new(&mut rbt_mem);
+        let mut bst_mem = AlignedBuffer([0; SIZE * bst::node_size::<u32>()]);
+        let mut bst: Bst<u32, SIZE> = Bst::new(&mut bst_mem);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut present = HashSet::new();
+
+        for i in 0..operations {
+            let value = rng.gen_range(0..1_000u32);
+            let op = if rng.gen_range(0..100) < delete_percent {
+                Op::Delete(value)
+            } else if present.contains(&value) || present.len() == SIZE {
+                continue;
+            } else {
+                Op::Insert(value)
+            };
+
+            let (rbt_ok, bst_ok) = match op {
+                Op::Insert(value) => {
+                    present.insert(value);
+                    (rbt.insert(value).is_ok(), bst.insert(value).is_ok())
+                }
+                Op::Delete(value) => {
+                    present.remove(&value);
+                    (rbt.delete(value).is_ok(), bst.delete(value).is_ok())
+                }
+            };
+
+            let rbt_values: Vec<_> = rbt.iter().copied().collect();
+            let mut bst_values = [0; SIZE];
+            let count = bst.keys_into(&mut bst_values).unwrap();
+            assert!(
+                rbt_ok == bst_ok && rbt_values == bst_values[..count],
+                "seed {seed}: trees diverged after operation {i} ({op:?})"
+            );
+        }
+    }
+
+    #[test]
+    fn differential_insert() {
+        for seed in 0..20 {
+            differential(seed, 1_000, 0);
+        }
+    }
+
+    #[test]
+    fn differential_insert_delete() {
+        for seed in 0..20 {
+            differential(seed, 2_000, 40);
+        }
+    }
 }
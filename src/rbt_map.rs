@@ -0,0 +1,59 @@
+//! A key-value map on top of [Rbt], for payloads that have no ordering of their own.
+use crate::map::{impl_map, KeyValue};
+use crate::rbt::{self, Rbt};
+
+/// Returns the number of bytes a single entry of a [RbtMap] occupies in its storage.
+pub const fn node_size<K, V>() -> usize
+where
+    K: Ord,
+{
+    rbt::node_size::<KeyValue<K, V>>()
+}
+
+/// A map from keys of type `K` to values of type `V`, backed by a [Rbt] holding up to `SIZE` entries. Only the keys
+/// need to be `Ord`; the values are stored alongside them untouched.
+pub struct RbtMap<'a, K, V, const SIZE: usize>
+where
+    K: Ord,
+{
+    tree: Rbt<'a, KeyValue<K, V>, SIZE>,
+}
+
+impl_map!(RbtMap, Rbt);
+
+#[cfg(test)]
+mod tests {
+    use super::{node_size, RbtMap};
+    use crate::{AlignedBuffer, Error};
+
+    #[test]
+    fn test_insert_get_remove() {
+        // The values have no ordering at all.
+        #[derive(Debug, PartialEq)]
+        struct Attributes(u64);
+
+        let mut mem = AlignedBuffer([0; 3 * node_size::<u32, Attributes>()]);
+        let mut map = RbtMap::<u32, Attributes, 3>::new(&mut mem);
+
+        assert_eq!(map.insert(20, Attributes(2)).unwrap(), None);
+        assert_eq!(map.insert(10, Attributes(1)).unwrap(), None);
+        assert_eq!(map.insert(30, Attributes(3)).unwrap(), None);
+        assert!(matches!(
+            map.insert(40, Attributes(4)),
+            Err(Error::OutOfSpace)
+        ));
+        assert_eq!(map.insert(10, Attributes(5)).unwrap(), Some(Attributes(1)));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.get(&10), Some(&Attributes(5)));
+        assert_eq!(map.get(&40), None);
+        map.get_mut(&20).unwrap().0 = 7;
+        assert!(map.iter().map(|(key, _)| *key).eq([10, 20, 30]));
+        assert!(map.iter().map(|(_, value)| value.0).eq([5, 7, 3]));
+
+        assert_eq!(map.remove(&20), Some(Attributes(7)));
+        assert_eq!(map.remove(&20), None);
+        assert!(!map.contains_key(&20));
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -0,0 +1,379 @@
+//! A segment tree over a caller-provided buffer, for range sums and range minimums under lazy range updates.
+//!
+//! Each node covers a run of values and holds their sum and minimum, plus an addition still owed to its children.
+//! The nodes are laid out in pre-order: the left child of a node directly follows it and the right child follows the
+//! whole left subtree, so `n` values take exactly `2n - 1` nodes and no links are stored. Range updates stop at the
+//! nodes that cover the range and leave the rest pending, so updates and queries each touch O(log n) nodes.
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Add, Bound, Mul, RangeBounds};
+use core::slice;
+
+use crate::{Error, Result};
+
+/// Returns the size in bytes of one node. A tree over `n` values needs `2n - 1` nodes.
+pub const fn node_size<T>() -> usize {
+    size_of::<Node<T>>()
+}
+
+#[derive(Clone, Copy)]
+struct Node<T> {
+    sum: T,
+    min: T,
+    /// Added to `sum` and `min` already, but not yet to the children.
+    pending: T,
+}
+
+impl<T: Default> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            sum: T::default(),
+            min: T::default(),
+            pending: T::default(),
+        }
+    }
+}
+
+/// A segment tree over a fixed number of values of type `T`, all of which start out as `T::default()`, which must
+/// be zero for the sums to make sense. The number of values is however many fit in the buffer.
+pub struct SegTree<'a, T> {
+    nodes: &'a mut [Node<T>],
+    len: usize,
+}
+
+impl<'a, T> SegTree<'a, T>
+where
+    T: Copy + Default + Ord + Add<Output = T> + Mul<Output = T> + TryFrom<usize>,
+{
+    /// Creates a tree over as many values as fit in `slice`, all zero. The bytes of `slice` are overwritten. Panics if
+    /// `slice` is not aligned for the nodes.
+    pub fn new(slice: &'a mut [u8]) -> Self {
+        Self::new_uninit(crate::as_uninit(slice))
+    }
+
+    /// Creates a tree like [Self::new], but returns `Error::BadBuffer` instead of panicking if `slice` is not aligned
+    /// for the nodes.
+    pub fn try_new(slice: &'a mut [u8]) -> Result<Self> {
+        if !slice.as_ptr().cast::<Node<T>>().is_aligned() {
+            return Err(Error::BadBuffer);
+        }
+        Ok(Self::new(slice))
+    }
+
+    /// Creates a tree over as many values as fit in `slice`, whose bytes need not be initialized. Panics like
+    /// [Self::new].
+    pub fn new_uninit(slice: &'a mut [MaybeUninit<u8>]) -> Self {
+        assert!(
+            slice.as_ptr().cast::<Node<T>>().is_aligned(),
+            "Buffer is not aligned to the {} byte alignment of the nodes",
+            core::mem::align_of::<Node<T>>()
+        );
+        let len = (slice.len() / node_size::<T>()).div_ceil(2);
+        let count = (2 * len).saturating_sub(1);
+        let nodes = unsafe {
+            slice::from_raw_parts_mut(slice.as_mut_ptr().cast::<MaybeUninit<Node<T>>>(), count)
+        };
+        for node in nodes.iter_mut() {
+            node.write(Node::default());
+        }
+        Self {
+            nodes: unsafe { slice::from_raw_parts_mut(nodes.as_mut_ptr().cast(), count) },
+            len,
+        }
+    }
+
+    /// Creates a tree over `slice` holding `values` at the front, with any remaining values zero. Builds the nodes in
+    /// O(n) rather than setting the values one by one. Returns `Error::OutOfSpace` if `slice` cannot hold every value.
+    pub fn from_values(slice: &'a mut [u8], values: &[T]) -> Result<Self> {
+        let mut tree = Self::try_new(slice)?;
+        if values.len() > tree.len() {
+            return Err(Error::OutOfSpace);
+        }
+        if !tree.is_empty() {
+            tree.build(0, 0, tree.len, values);
+        }
+        Ok(tree)
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets every value back to zero.
+    pub fn clear(&mut self) {
+        self.nodes.fill(Node::default());
+    }
+
+    /// Adds `delta` to every value at the indices in `range`. Panics if the range is out of bounds.
+    pub fn add<R: RangeBounds<usize>>(&mut self, range: R, delta: T) {
+        let (start, end) = self.bounds(range);
+        if start < end {
+            self.add_in(0, 0, self.len, start, end, delta);
+        }
+    }
+
+    /// Replaces the value at `index` with `value`. Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(index < self.len, "Index {} is out of bounds", index);
+        self.set_in(0, 0, self.len, index, value);
+    }
+
+    /// Returns the value at `index`. Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> T {
+        self.sum(index..=index)
+    }
+
+    /// Returns the sum of the values at the indices in `range`. Panics if the range is out of bounds.
+    pub fn sum<R: RangeBounds<usize>>(&self, range: R) -> T {
+        let (start, end) = self.bounds(range);
+        if start == end {
+            return T::default();
+        }
+        self.sum_in(0, 0, self.len, start, end)
+    }
+
+    /// Returns the smallest value at the indices in `range`, or `None` if the range is empty. Panics if the range is
+    /// out of bounds.
+    pub fn min<R: RangeBounds<usize>>(&self, range: R) -> Option<T> {
+        self.min_index(range).map(|(_, min)| min)
+    }
+
+    /// Returns the first index in `range` holding the smallest value there, along with that value, or `None` if the
+    /// range is empty. With per-bank wear counters, this finds the least worn bank. Panics if the range is out of
+    /// bounds.
+    pub fn min_index<R: RangeBounds<usize>>(&self, range: R) -> Option<(usize, T)> {
+        let (start, end) = self.bounds(range);
+        if start == end {
+            return None;
+        }
+        Some(self.min_in(0, 0, self.len, start, end))
+    }
+
+    fn bounds<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+        assert!(
+            start <= end,
+            "Range starts at {} but ends at {}",
+            start,
+            end
+        );
+        assert!(end <= self.len, "End {} is out of bounds", end);
+        (start, end)
+    }
+
+    /// Converts the number of values a node covers into a `T`, for scaling a per-value addition into a sum.
+    fn count(count: usize) -> T {
+        match T::try_from(count) {
+            Ok(count) => count,
+            Err(_) => panic!("Count {} does not fit in the value type", count),
+        }
+    }
+
+    /// Returns the children of the node at `node`, which covers `start..end`, and the index that splits them.
+    fn children(node: usize, start: usize, end: usize) -> (usize, usize, usize) {
+        let mid = start + (end - start) / 2;
+        (node + 1, node + 2 * (mid - start), mid)
+    }
+
+    fn pull(&mut self, node: usize, start: usize, end: usize) {
+        let (left, right, _) = Self::children(node, start, end);
+        let pending = self.nodes[node].pending;
+        self.nodes[node].sum =
+            self.nodes[left].sum + self.nodes[right].sum + pending * Self::count(end - start);
+        self.nodes[node].min = self.nodes[left].min.min(self.nodes[right].min) + pending;
+    }
+
+    fn build(&mut self, node: usize, start: usize, end: usize, values: &[T]) {
+        if end - start == 1 {
+            let value = values.get(start).copied().unwrap_or_default();
+            self.nodes[node].sum = value;
+            self.nodes[node].min = value;
+            return;
+        }
+        let (left, right, mid) = Self::children(node, start, end);
+        self.build(left, start, mid, values);
+        self.build(right, mid, end, values);
+        self.pull(node, start, end);
+    }
+
+    fn add_in(&mut self, node: usize, start: usize, end: usize, from: usize, to: usize, delta: T) {
+        if from <= start && end <= to {
+            let entry = &mut self.nodes[node];
+            entry.sum = entry.sum + delta * Self::count(end - start);
+            entry.min = entry.min + delta;
+            entry.pending = entry.pending + delta;
+            return;
+        }
+        let (left, right, mid) = Self::children(node, start, end);
+        if from < mid {
+            self.add_in(left, start, mid, from, to, delta);
+        }
+        if to > mid {
+            self.add_in(right, mid, end, from, to, delta);
+        }
+        self.pull(node, start, end);
+    }
+
+    /// Sets the value at `index` below `node` to `value`, less whatever additions are still pending above it.
+    fn set_in(&mut self, node: usize, start: usize, end: usize, index: usize, value: T) {
+        if end - start == 1 {
+            self.nodes[node] = Node {
+                sum: value,
+                min: value,
+                pending: T::default(),
+            };
+            return;
+        }
+        // Push the pending addition down so the leaf can be overwritten without it being applied again on the way up.
+        let (left, right, mid) = Self::children(node, start, end);
+        let pending = core::mem::take(&mut self.nodes[node].pending);
+        if pending != T::default() {
+            self.add_in(left, start, mid, start, mid, pending);
+            self.add_in(right, mid, end, mid, end, pending);
+        }
+        if index < mid {
+            self.set_in(left, start, mid, index, value);
+        } else {
+            self.set_in(right, mid, end, index, value);
+        }
+        self.pull(node, start, end);
+    }
+
+    fn sum_in(&self, node: usize, start: usize, end: usize, from: usize, to: usize) -> T {
+        if from <= start && end <= to {
+            return self.nodes[node].sum;
+        }
+        let (left, right, mid) = Self::children(node, start, end);
+        let overlap = to.min(end) - from.max(start);
+        let mut sum = self.nodes[node].pending * Self::count(overlap);
+        if from < mid {
+            sum = sum + self.sum_in(left, start, mid, from, to);
+        }
+        if to > mid {
+            sum = sum + self.sum_in(right, mid, end, from, to);
+        }
+        sum
+    }
+
+    fn min_in(&self, node: usize, start: usize, end: usize, from: usize, to: usize) -> (usize, T) {
+        let (left, right, mid) = Self::children(node, start, end);
+        let (index, min) = if from <= start && end <= to {
+            if end - start == 1 {
+                return (start, self.nodes[node].min);
+            }
+            // Descend toward the first child holding the minimum to find its index.
+            if self.nodes[left].min <= self.nodes[right].min {
+                self.min_in(left, start, mid, start, mid)
+            } else {
+                self.min_in(right, mid, end, mid, end)
+            }
+        } else if to <= mid {
+            self.min_in(left, start, mid, from, to)
+        } else if from >= mid {
+            self.min_in(right, mid, end, from, to)
+        } else {
+            let (left_index, left_min) = self.min_in(left, start, mid, from, to);
+            let (right_index, right_min) = self.min_in(right, mid, end, from, to);
+            if left_min <= right_min {
+                (left_index, left_min)
+            } else {
+                (right_index, right_min)
+            }
+        };
+        (index, min + self.nodes[node].pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, SegTree};
+    use crate::{AlignedBuffer, Error};
+    use rand::Rng;
+
+    #[test]
+    fn test_against_array() {
+        let mut mem = AlignedBuffer([0; (2 * 100 - 1) * node_size::<u64>()]);
+        assert_eq!(node_size::<u64>(), 24);
+        let mut tree = SegTree::<u64>::new(&mut mem);
+        assert_eq!(tree.len(), 100);
+        let mut values = [0u64; 100];
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..2000 {
+            let start = rng.gen_range(0..=100);
+            let end = rng.gen_range(start..=100);
+            if rng.gen_bool(0.7) {
+                let delta = rng.gen_range(0..50);
+                tree.add(start..end, delta);
+                values[start..end]
+                    .iter_mut()
+                    .for_each(|value| *value += delta);
+            } else {
+                let index = rng.gen_range(0..100);
+                let value = rng.gen_range(0..1000);
+                tree.set(index, value);
+                values[index] = value;
+            }
+
+            let start = rng.gen_range(0..=100);
+            let end = rng.gen_range(start..=100);
+            assert_eq!(tree.sum(start..end), values[start..end].iter().sum());
+            let expected = values[start..end]
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, value)| **value)
+                .map(|(index, value)| (start + index, *value));
+            assert_eq!(tree.min_index(start..end), expected);
+        }
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(tree.get(index), *value);
+        }
+
+        tree.clear();
+        assert_eq!(tree.sum(..), 0);
+        assert_eq!(tree.min(..), Some(0));
+    }
+
+    #[test]
+    fn test_from_values() {
+        // Erase counts of five flash banks, the least worn of which is bank 3.
+        let counts = [7u32, 4, 9, 2, 5];
+        let mut mem = AlignedBuffer([0; (2 * 5 - 1) * node_size::<u32>()]);
+        let mut tree = SegTree::from_values(&mut mem, &counts).unwrap();
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.sum(..), 27);
+        assert_eq!(tree.min_index(..), Some((3, 2)));
+        assert_eq!(tree.min_index(..3), Some((1, 4)));
+        assert_eq!(tree.min(2..2), None);
+
+        tree.add(3..=3, 3);
+        assert_eq!(tree.min_index(..), Some((1, 4)));
+        tree.add(.., 1);
+        assert_eq!(tree.sum(..), 35);
+        assert_eq!(tree.min_index(3..), Some((3, 6)));
+
+        let mut mem = AlignedBuffer([0; (2 * 3 - 1) * node_size::<u32>()]);
+        assert!(matches!(
+            SegTree::from_values(&mut mem, &counts),
+            Err(Error::OutOfSpace)
+        ));
+        assert!(matches!(
+            SegTree::<u32>::try_new(&mut mem[1..]),
+            Err(Error::BadBuffer)
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+//! A skip list over a caller-provided buffer, with a fixed number of levels.
+//!
+//! Every element is on the bottom level, and each level above holds roughly half of the elements of the one below,
+//! so a search skips ahead on the upper levels and finishes on the bottom one in expected O(log n) steps. Nothing is
+//! ever rotated: an insert or removal only rewrites the links of the element's neighbours on the levels it is on.
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
+use core::ptr;
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::{Error, OrderedKey, Result};
+
+/// The slot index that marks a missing link, and stands for the head in a list of predecessors.
+const NULL: u16 = u16::MAX;
+
+/// Returns the number of bytes a single node of a [SkipList] with `LEVELS` levels occupies in its storage.
+pub const fn node_size<D, const LEVELS: usize>() -> usize {
+    size_of::<Node<D, LEVELS>>()
+}
+
+/// A skip list holding up to `SIZE` elements of type `D`, or as many as fit in its buffer if `SIZE` is 0, on up to
+/// `LEVELS` levels.
+///
+/// Each inserted element goes on one more level for every trailing one bit of a number drawn from the source passed
+/// to [SkipList::new], so the numbers should be uniformly random. `LEVELS` around the base-2 logarithm of the
+/// capacity is enough; every node reserves a link for each level.
+pub struct SkipList<'a, D, const SIZE: usize, const LEVELS: usize>
+where
+    D: OrderedKey,
+{
+    storage: Storage<'a, Node<D, LEVELS>, SIZE>,
+    /// The first node on each level.
+    head: [u16; LEVELS],
+    random: &'a mut dyn FnMut() -> u32,
+}
+
+/// A [SkipList] that holds as many nodes as fit in the buffer it is created with.
+pub type SkipListDyn<'a, D, const LEVELS: usize> = SkipList<'a, D, 0, LEVELS>;
+
+impl<'a, D, const SIZE: usize, const LEVELS: usize> SkipList<'a, D, SIZE, LEVELS>
+where
+    D: OrderedKey,
+{
+    /// Creates an empty skip list backed by `slice`, drawing the levels of inserted elements from `random`. Panics if
+    /// `slice` is shorter than `SIZE * node_size::<D, LEVELS>()` bytes or is not aligned for the nodes, or if
+    /// `LEVELS` is not between 1 and 32.
+    pub fn new(slice: &'a mut [u8], random: &'a mut dyn FnMut() -> u32) -> Self {
+        Self::new_uninit(crate::as_uninit(slice), random)
+    }
+
+    /// Creates an empty skip list backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of
+    /// panicking if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8], random: &'a mut dyn FnMut() -> u32) -> Result<Self> {
+        Storage::<Node<D, LEVELS>, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice, random))
+    }
+
+    /// Creates an empty skip list backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(
+        slice: &'a mut [MaybeUninit<u8>],
+        random: &'a mut dyn FnMut() -> u32,
+    ) -> Self {
+        assert!(
+            (1..=32).contains(&LEVELS),
+            "Level count must be between 1 and 32, not {}",
+            LEVELS
+        );
+        Self {
+            storage: Storage::new(slice),
+            head: [NULL; LEVELS],
+            random,
+        }
+    }
+
+    /// Returns the number of elements in the skip list.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of elements the skip list can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Removes every element from the skip list. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.head = [NULL; LEVELS];
+    }
+
+    /// Returns the node after `index` on `level`, where [NULL] stands for the head.
+    fn next(&self, index: u16, level: usize) -> u16 {
+        match index {
+            NULL => self.head[level],
+            index => self.storage.node(index).next[level],
+        }
+    }
+
+    fn set_next(&mut self, index: u16, level: usize, next: u16) {
+        match index {
+            NULL => self.head[level] = next,
+            index => self.storage.node_mut(index).next[level] = next,
+        }
+    }
+
+    /// Returns, for every level, the last node whose key is `before`, or [NULL] if there is none. `before` must hold
+    /// for every key up to some point and for none after it.
+    fn predecessors(&self, before: impl Fn(&D::Key) -> bool) -> [u16; LEVELS] {
+        let mut predecessors = [NULL; LEVELS];
+        let mut current = NULL;
+        for level in (0..LEVELS).rev() {
+            loop {
+                let next = self.next(current, level);
+                if next == NULL || !before(self.storage.node(next).data.ordering_key()) {
+                    break;
+                }
+                current = next;
+            }
+            predecessors[level] = current;
+        }
+        predecessors
+    }
+
+    /// Returns the first node whose key is not `before`, or [NULL] if there is none.
+    fn seek(&self, before: impl Fn(&D::Key) -> bool) -> u16 {
+        self.next(self.predecessors(before)[0], 0)
+    }
+
+    /// Inserts `data`, linking it in after its predecessor on each of its levels. Returns `Error::AlreadyExists` if
+    /// an element with the same key is in the skip list, and `Error::OutOfSpace` if it is full.
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        let key = data.ordering_key();
+        let predecessors = self.predecessors(|other| other < key);
+        let next = self.next(predecessors[0], 0);
+        if next != NULL && self.storage.node(next).data.ordering_key() == key {
+            return Err(Error::AlreadyExists);
+        }
+        let levels = 1 + ((self.random)().trailing_ones() as usize).min(LEVELS - 1);
+        let index = self
+            .storage
+            .add(|index| Node::new(data, levels, index))?
+            .index;
+        for (level, &predecessor) in predecessors.iter().enumerate().take(levels) {
+            let next = self.next(predecessor, level);
+            self.storage.node_mut(index).next[level] = next;
+            self.set_next(predecessor, level, index);
+        }
+        Ok(())
+    }
+
+    /// Returns true if an element with the given key is in the skip list.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_ref(key).is_some()
+    }
+
+    /// Returns a reference to the element with the given key.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        let index = self.seek(|other| other < key);
+        let data = &self.storage.node((index != NULL).then_some(index)?).data;
+        (data.ordering_key() == key).then_some(data)
+    }
+
+    pub fn delete(&mut self, data: D) -> Result<()> {
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the skip list, returning it. Returns `None` if no element has the
+    /// key.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        let predecessors = self.predecessors(|other| other < key);
+        let index = self.next(predecessors[0], 0);
+        if index == NULL || self.storage.node(index).data.ordering_key() != key {
+            return None;
+        }
+        let levels = self.storage.node(index).levels as usize;
+        for (level, &predecessor) in predecessors.iter().enumerate().take(levels) {
+            let next = self.storage.node(index).next[level];
+            self.set_next(predecessor, level, next);
+        }
+        Some(self.storage.delete(index).data)
+    }
+
+    /// Checks that every level is in ascending order and only holds nodes that reach it, returning the number of
+    /// levels in use, or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        let mut used = 0;
+        for level in 0..LEVELS {
+            let mut count = 0;
+            let mut previous: Option<&D> = None;
+            let mut current = self.head[level];
+            while current != NULL {
+                let node = self.storage.node(current);
+                if (node.levels as usize) <= level {
+                    return Err("node is linked above its levels");
+                }
+                if previous
+                    .is_some_and(|previous| previous.ordering_key() >= node.data.ordering_key())
+                {
+                    return Err("keys are not in ascending order");
+                }
+                count += 1;
+                if count > self.len() {
+                    return Err("level holds more nodes than the length");
+                }
+                previous = Some(&node.data);
+                current = node.next[level];
+            }
+            if level == 0 && count != self.len() {
+                return Err("length does not match the number of nodes");
+            }
+            if count > 0 {
+                used = level + 1;
+            }
+        }
+        Ok(used)
+    }
+
+    /// Returns an iterator over the elements in ascending order.
+    pub fn iter(&self) -> Range<'_, 'a, D, SIZE, LEVELS> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over the elements whose keys lie in `range`, in ascending order. Both ends are found with
+    /// one search each, and the elements in between by walking the bottom level.
+    pub fn range<R: RangeBounds<D::Key>>(&self, range: R) -> Range<'_, 'a, D, SIZE, LEVELS> {
+        let next = match range.start_bound() {
+            Bound::Included(lo) => self.seek(|key| key < lo),
+            Bound::Excluded(lo) => self.seek(|key| key <= lo),
+            Bound::Unbounded => self.head[0],
+        };
+        let end = match range.end_bound() {
+            Bound::Included(hi) => self.seek(|key| key <= hi),
+            Bound::Excluded(hi) => self.seek(|key| key < hi),
+            Bound::Unbounded => NULL,
+        };
+        // An empty range may start past its end, in which case it never reaches it.
+        let empty = next == NULL
+            || (end != NULL
+                && self.storage.node(next).data.ordering_key()
+                    > self.storage.node(end).data.ordering_key());
+        Range {
+            list: self,
+            next: if empty { end } else { next },
+            end,
+        }
+    }
+}
+
+impl<D, const SIZE: usize, const LEVELS: usize> SkipList<'_, D, SIZE, LEVELS>
+where
+    D: Copy + OrderedKey,
+{
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
+    }
+}
+
+impl<'b, 'a, D, const SIZE: usize, const LEVELS: usize> IntoIterator
+    for &'b SkipList<'a, D, SIZE, LEVELS>
+where
+    D: OrderedKey,
+{
+    type Item = &'b D;
+    type IntoIter = Range<'b, 'a, D, SIZE, LEVELS>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over a range of the elements of a [SkipList], created by [SkipList::range].
+pub struct Range<'b, 'a, D, const SIZE: usize, const LEVELS: usize>
+where
+    D: OrderedKey,
+{
+    list: &'b SkipList<'a, D, SIZE, LEVELS>,
+    next: u16,
+    /// The first node past the range, or [NULL] if the range runs to the end of the list.
+    end: u16,
+}
+
+impl<'b, D, const SIZE: usize, const LEVELS: usize> Iterator for Range<'b, '_, D, SIZE, LEVELS>
+where
+    D: OrderedKey,
+{
+    type Item = &'b D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None;
+        }
+        let node = self.list.storage.node(self.next);
+        self.next = node.next[0];
+        Some(&node.data)
+    }
+}
+
+struct Node<D, const LEVELS: usize> {
+    data: D,
+    /// The number of levels the node is linked into.
+    levels: u8,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    /// The next node on each level, of which only the first `levels` are used. A free slot links to the next free
+    /// slot through the bottom one.
+    next: [u16; LEVELS],
+}
+
+impl<D, const LEVELS: usize> Node<D, LEVELS> {
+    /// Returns an unlinked node holding `data` on `levels` levels, to be written to slot `index`.
+    fn new(data: D, levels: usize, index: u16) -> Self {
+        Node {
+            data,
+            levels: levels as u8,
+            index,
+            next: [NULL; LEVELS],
+        }
+    }
+}
+
+impl<D, const LEVELS: usize> Slot for Node<D, LEVELS> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).next[0]).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).next[0]).write(index) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, SkipList, SkipListDyn};
+    use crate::testing::{self, impl_test_set, xorshift, Tracked};
+    use crate::{AlignedBuffer, Error};
+    use core::ops::Bound;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    impl_test_set!([const SIZE: usize, const LEVELS: usize] SkipList<'_, u32, SIZE, LEVELS>);
+
+    #[test]
+    fn test_insert_search_remove() {
+        let mut mem = AlignedBuffer([0; 500 * node_size::<u32, 12>()]);
+        let mut random = xorshift(0x2545_f491);
+        let mut list = SkipList::<u32, 500, 12>::new(&mut mem, &mut random);
+        testing::check_against_set(&mut list);
+        assert_eq!(list.validate(), Ok(0));
+    }
+
+    #[test]
+    fn test_range() {
+        let mut mem = AlignedBuffer([0; 200 * node_size::<u32, 8>()]);
+        let mut random = xorshift(42);
+        let mut list = SkipListDyn::<u32, 8>::new(&mut mem, &mut random);
+        assert_eq!(list.capacity(), 200);
+        for value in (0..200).map(|i| (i * 37) % 200 * 2) {
+            list.insert(value).unwrap();
+        }
+        assert!(matches!(list.insert(1), Err(Error::OutOfSpace)));
+        assert!(matches!(list.insert(2), Err(Error::AlreadyExists)));
+
+        assert!(list.range(10..20).copied().eq([10, 12, 14, 16, 18]));
+        assert!(list.range(11..=20).copied().eq([12, 14, 16, 18, 20]));
+        assert!(list
+            .range((Bound::Excluded(10), Bound::Excluded(16)))
+            .copied()
+            .eq([12, 14]));
+        assert!(list.range(..5).copied().eq([0, 2, 4]));
+        assert!(list.range(390..).copied().eq([390, 392, 394, 396, 398]));
+        assert!(list.range(..).copied().eq((0..200).map(|i| i * 2)));
+        assert_eq!(list.range(11..12).count(), 0);
+        assert_eq!(
+            list.range((Bound::Included(20), Bound::Excluded(10)))
+                .count(),
+            0
+        );
+        assert_eq!(list.range(1000..).count(), 0);
+        assert_eq!(
+            list.range((Bound::Included(1000), Bound::Included(5)))
+                .count(),
+            0
+        );
+
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_single_level() {
+        // With one level, or a source that never sets the low bit, the skip list is a sorted linked list.
+        let mut mem = AlignedBuffer([0; 64 * node_size::<u32, 1>()]);
+        let mut random = || u32::MAX;
+        let mut list = SkipList::<u32, 64, 1>::new(&mut mem, &mut random);
+        for value in [5, 1, 4, 2, 3] {
+            list.insert(value).unwrap();
+        }
+        assert_eq!(list.validate(), Ok(1));
+        assert!(list.iter().copied().eq(1..=5));
+        assert_eq!(list.remove(&3), Some(3));
+        assert!(list.iter().copied().eq([1, 2, 4, 5]));
+    }
+
+    #[test]
+    fn test_drop_elements() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut mem = AlignedBuffer([0; 16 * node_size::<Tracked, 4>()]);
+        let mut random = xorshift(7);
+        let mut list = SkipList::<Tracked, 16, 4>::new(&mut mem, &mut random);
+        for value in 0..10 {
+            list.insert(Tracked(value, &DROPPED)).unwrap();
+        }
+        drop(list.remove(&3));
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        list.clear();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 10);
+        list.insert(Tracked(1, &DROPPED)).unwrap();
+        drop(list);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 11);
+    }
+}
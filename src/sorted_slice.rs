@@ -1,17 +1,18 @@
 use core::{fmt::Debug, mem, ops::Deref, slice};
 
+use crate::OrderedKey;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     NotEnoughMemory,
     ElementAlreadyInserted,
     ElementsNeedToBeSorted,
     ElementNotFound,
+    BadBuffer,
 }
 
-pub trait SortedSliceKey {
-    type Key: Ord;
-    fn ordering_key(&self) -> &Self::Key;
-}
+/// Alias of [OrderedKey], kept so existing implementations continue to compile.
+pub use crate::OrderedKey as SortedSliceKey;
 
 pub struct SortedSlice<'a, T> {
     pub slice: &'a mut [T],
@@ -20,7 +21,7 @@ pub struct SortedSlice<'a, T> {
 
 impl<'a, T> SortedSlice<'a, T>
 where
-    T: Clone + Copy + SortedSliceKey + Sized,
+    T: Clone + Copy + OrderedKey + Sized,
 {
     pub fn new(slice: &'a mut [u8]) -> SortedSlice<'a, T> {
         Self {
@@ -34,6 +35,20 @@ where
         }
     }
 
+    /// Creates an empty sorted slice over the `len` bytes starting at `addr`, such as a reserved memory region.
+    /// Returns `Error::BadBuffer` if `addr` is not aligned for `T` or the region cannot hold a single element.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the region for the lifetime `'a`: it must be valid for reads and writes and not accessed
+    /// through any other pointer while the slice is alive.
+    pub unsafe fn new_at(addr: *mut u8, len: usize) -> Result<SortedSlice<'a, T>, Error> {
+        if !addr.cast::<T>().is_aligned() || len < mem::size_of::<T>() {
+            return Err(Error::BadBuffer);
+        }
+        Ok(Self::new(unsafe { slice::from_raw_parts_mut(addr, len) }))
+    }
+
     pub fn add(&mut self, element: T) -> Result<usize, Error> {
         if self.capacity() == self.len() {
             return Err(Error::NotEnoughMemory);
@@ -48,6 +63,21 @@ where
         Ok(idx)
     }
 
+    /// Inserts `element`, or replaces the element with the same key in place and returns the replaced element.
+    /// Returns `Error::NotEnoughMemory` if the key is new and the slice is full.
+    pub fn upsert(&mut self, element: T) -> Result<Option<T>, Error> {
+        match self.search(element) {
+            Ok(idx) => Ok(Some(mem::replace(&mut self.slice[idx], element))),
+            Err(_) if self.capacity() == self.len() => Err(Error::NotEnoughMemory),
+            Err(idx) => {
+                self.slice.copy_within(idx..self.len(), idx + 1);
+                self.slice[idx] = element;
+                self.item_count += 1;
+                Ok(None)
+            }
+        }
+    }
+
     pub fn add_contiguous_slice(&mut self, elements: &[T]) -> Result<usize, Error> {
         if elements.is_empty() {
             return Ok(0);
@@ -106,11 +136,35 @@ where
         Some(item)
     }
 
+    /// Removes every element whose key appears in the sorted `keys` list with a single compaction pass, returning
+    /// the number of elements removed.
+    pub fn remove_sorted(&mut self, keys: &[T::Key]) -> usize {
+        let mut keys = keys.iter().peekable();
+        let mut write = 0;
+        for read in 0..self.item_count {
+            let key = self.slice[read].ordering_key();
+            while keys.next_if(|k| *k < key).is_some() {}
+            if keys.next_if(|k| *k == key).is_none() {
+                self.slice[write] = self.slice[read];
+                write += 1;
+            }
+        }
+        let removed = self.item_count - write;
+        self.item_count = write;
+        removed
+    }
+
     pub fn search(&self, element: T) -> Result<usize, usize> {
         let target = element.ordering_key();
         self.binary_search_by_key(&target, |e| e.ordering_key())
     }
 
+    /// Returns true if an element with the given key is stored, without copying it out.
+    pub fn contains(&self, key: &T::Key) -> bool {
+        self.binary_search_by_key(&key, |e| e.ordering_key())
+            .is_ok()
+    }
+
     pub fn search_with_key(&self, key: &T::Key) -> Result<&T, &T> {
         self.binary_search_by_key(&key, |e| e.ordering_key())
             .map(|idx| &self[idx])
@@ -132,6 +186,71 @@ where
     pub fn capacity(&self) -> usize {
         self.slice.len()
     }
+
+    /// Returns the number of elements that can still be added before the slice runs out of space. `len` and
+    /// `is_empty` come from the slice of stored elements.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Returns an iterator over the elements whose keys fall within `lo..=hi`, in ascending key order. Both ends are
+    /// found with a binary search, so nothing outside the range is visited.
+    pub fn range(&self, lo: &T::Key, hi: &T::Key) -> slice::Iter<'_, T> {
+        let start = self.partition_point(|e| e.ordering_key() < lo);
+        let end = self.partition_point(|e| e.ordering_key() <= hi).max(start);
+        self[start..end].iter()
+    }
+
+    /// Returns an iterator over the stored elements in blocks of `n`, in ascending key order. Each block borrows the
+    /// storage, and the last one is shorter if the number of elements is not a multiple of `n`. Panics if `n` is 0.
+    pub fn chunks(&self, n: usize) -> slice::Chunks<'_, T> {
+        self.slice[..self.item_count].chunks(n)
+    }
+
+    /// Copies every element with a key less than `pivot` into `lo` and the rest into `hi`, returning the number of
+    /// elements written to each.
+    pub fn partition_into(
+        &self,
+        pivot: &T::Key,
+        lo: &mut [T],
+        hi: &mut [T],
+    ) -> Result<(usize, usize), Error> {
+        let split = self.partition_point(|e| e.ordering_key() < pivot);
+        let (below, above) = self.split_at(split);
+        if below.len() > lo.len() || above.len() > hi.len() {
+            return Err(Error::NotEnoughMemory);
+        }
+        lo[..below.len()].copy_from_slice(below);
+        hi[..above.len()].copy_from_slice(above);
+        Ok((below.len(), above.len()))
+    }
+}
+
+impl<T> SortedSlice<'_, T>
+where
+    T: Clone + Copy + OrderedKey + Sized,
+    T::Key: Copy + core::ops::Add<Output = T::Key> + From<u8>,
+{
+    /// Returns the first element and length of the longest run of consecutive keys (k, k + 1, k + 2, ...). The
+    /// earliest run wins ties.
+    pub fn longest_consecutive_run(&self) -> Option<(T, usize)> {
+        let first = *self.first()?;
+        let (mut best, mut best_len) = (first, 1);
+        let (mut start, mut len) = (first, 1);
+        for pair in self.windows(2) {
+            if *pair[0].ordering_key() + T::Key::from(1) == *pair[1].ordering_key() {
+                len += 1;
+            } else {
+                start = pair[1];
+                len = 1;
+            }
+            if len > best_len {
+                best = start;
+                best_len = len;
+            }
+        }
+        Some((best, best_len))
+    }
 }
 
 impl<T> core::ops::Deref for SortedSlice<'_, T> {
@@ -179,13 +298,133 @@ where
     }
 }
 
-impl<T> SortedSliceKey for T
+/// A sorted map that keeps its keys and values in two parallel slices. Lookups binary search the compact key slice
+/// and only touch the value slice on a hit, which keeps large values out of the cache while searching.
+pub struct SortedSliceMap<'a, K, V> {
+    keys: &'a mut [K],
+    values: &'a mut [V],
+    item_count: usize,
+}
+
+impl<'a, K, V> SortedSliceMap<'a, K, V>
 where
-    T: Ord,
+    K: Ord + Copy,
+    V: Copy,
 {
-    type Key = Self;
-    fn ordering_key(&self) -> &T {
-        self
+    /// Creates an empty map with its keys stored in `keys` and its values in `values`. The capacity is the number
+    /// of entries that fit in the smaller of the two. Panics if either buffer is not aligned for its type, or if `K`
+    /// or `V` is zero-sized.
+    pub fn new(keys: &'a mut [u8], values: &'a mut [u8]) -> SortedSliceMap<'a, K, V> {
+        assert!(
+            mem::size_of::<K>() > 0 && mem::size_of::<V>() > 0,
+            "Zero-sized keys and values take no space to store"
+        );
+        assert!(
+            keys.as_ptr().cast::<K>().is_aligned(),
+            "Key buffer is not aligned to the {} byte alignment of the keys",
+            mem::align_of::<K>()
+        );
+        assert!(
+            values.as_ptr().cast::<V>().is_aligned(),
+            "Value buffer is not aligned to the {} byte alignment of the values",
+            mem::align_of::<V>()
+        );
+        let capacity = (keys.len() / mem::size_of::<K>()).min(values.len() / mem::size_of::<V>());
+        Self {
+            keys: unsafe {
+                slice::from_raw_parts_mut::<'a, K>(keys as *mut [u8] as *mut K, capacity)
+            },
+            values: unsafe {
+                slice::from_raw_parts_mut::<'a, V>(values as *mut [u8] as *mut V, capacity)
+            },
+            item_count: 0,
+        }
+    }
+
+    /// Creates an empty map like [Self::new], but returns `Error::BadBuffer` instead of panicking if either buffer
+    /// is not aligned for its type, or if `K` or `V` is zero-sized.
+    pub fn try_new(
+        keys: &'a mut [u8],
+        values: &'a mut [u8],
+    ) -> Result<SortedSliceMap<'a, K, V>, Error> {
+        if mem::size_of::<K>() == 0
+            || mem::size_of::<V>() == 0
+            || !keys.as_ptr().cast::<K>().is_aligned()
+            || !values.as_ptr().cast::<V>().is_aligned()
+        {
+            return Err(Error::BadBuffer);
+        }
+        Ok(Self::new(keys, values))
+    }
+
+    pub fn len(&self) -> usize {
+        self.item_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Inserts `value` under `key`, or replaces the value already stored under `key` and returns it. Returns
+    /// `Error::NotEnoughMemory` if the key is new and the map is full.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
+        match self.search(&key) {
+            Ok(idx) => Ok(Some(mem::replace(&mut self.values[idx], value))),
+            Err(_) if self.capacity() == self.len() => Err(Error::NotEnoughMemory),
+            Err(idx) => {
+                self.keys.copy_within(idx..self.item_count, idx + 1);
+                self.values.copy_within(idx..self.item_count, idx + 1);
+                self.keys[idx] = key;
+                self.values[idx] = value;
+                self.item_count += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.search(key).ok().map(|idx| &self.values[idx])
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.search(key).ok().map(|idx| &mut self.values[idx])
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.search(key).is_ok()
+    }
+
+    /// Removes the entry stored under `key` and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.search(key).ok()?;
+        let value = self.values[idx];
+        self.keys.copy_within(idx + 1..self.item_count, idx);
+        self.values.copy_within(idx + 1..self.item_count, idx);
+        self.item_count -= 1;
+        Some(value)
+    }
+
+    /// Returns the stored keys in ascending order.
+    pub fn keys(&self) -> &[K] {
+        &self.keys[..self.item_count]
+    }
+
+    /// Returns the stored values, in the order of their keys.
+    pub fn values(&self) -> &[V] {
+        &self.values[..self.item_count]
+    }
+
+    /// Returns an iterator over the entries in ascending key order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        self.keys().iter().zip(self.values())
+    }
+
+    fn search(&self, key: &K) -> Result<usize, usize> {
+        self.keys().binary_search(key)
     }
 }
 
@@ -193,13 +432,14 @@ where
 mod tests {
     extern crate std;
     use super::*;
+    use crate::AlignedBuffer;
     extern crate alloc;
     use alloc::vec::Vec;
 
     #[test]
     fn test_init_state_of_new_sorted_slice() {
         const MEM_SIZE: usize = 4096;
-        let mut mem = [0; MEM_SIZE];
+        let mut mem = AlignedBuffer([0; MEM_SIZE]);
         let mem_ptr = mem.as_ptr();
         let ss = SortedSlice::<'_, u32>::new(&mut mem);
 
@@ -207,6 +447,8 @@ mod tests {
         assert_eq!(mem_ptr, ss.slice.as_ptr() as *const u8);
         assert_eq!(MEM_SIZE / mem::size_of::<u32>(), ss.slice.len());
         assert_eq!(MEM_SIZE / mem::size_of::<u32>(), ss.capacity());
+        assert_eq!(ss.capacity(), ss.remaining());
+        assert!(ss.is_empty());
         assert_eq!(
             0,
             ss.len(),
@@ -214,9 +456,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_new_at() {
+        #[repr(C, align(8))]
+        struct Region([u8; 4 * mem::size_of::<u64>()]);
+        let mut region = Region([0; 4 * mem::size_of::<u64>()]);
+        let addr = region.0.as_mut_ptr();
+
+        let misaligned = unsafe { SortedSlice::<'_, u64>::new_at(addr.add(1), 16) };
+        assert_eq!(Some(Error::BadBuffer), misaligned.err());
+        let too_short = unsafe { SortedSlice::<'_, u64>::new_at(addr, 7) };
+        assert_eq!(Some(Error::BadBuffer), too_short.err());
+
+        let mut ss = unsafe { SortedSlice::<'_, u64>::new_at(addr, 4 * 8).unwrap() };
+        assert_eq!(4, ss.capacity());
+        for n in [3, 1, 2] {
+            ss.add(n).unwrap();
+        }
+        assert_eq!([1, 2, 3], ss[..]);
+    }
+
     #[test]
     fn test_add_in_sorted_slice() {
-        let mut mem = [0; 10 * mem::size_of::<usize>()];
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<usize>()]);
         let mut ss = SortedSlice::<'_, usize>::new(&mut mem);
 
         for e in [1, 4, 3, 2, 5, 8, 0, 6, 7] {
@@ -231,7 +493,9 @@ mod tests {
             ss.add(0),
             "The slide should not allow duplicates."
         );
+        assert_eq!(1, ss.remaining());
         assert_eq!(Ok(9), ss.add(9));
+        assert_eq!(0, ss.remaining());
         assert_eq!(
             Err(Error::NotEnoughMemory),
             ss.add(10),
@@ -239,9 +503,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_upsert() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Entry {
+            id: u32,
+            payload: u32,
+        }
+
+        impl OrderedKey for Entry {
+            type Key = u32;
+            fn ordering_key(&self) -> &u32 {
+                &self.id
+            }
+        }
+
+        let entry = |id, payload| Entry { id, payload };
+        let mut mem = AlignedBuffer([0; 3 * mem::size_of::<Entry>()]);
+        let mut ss = SortedSlice::<'_, Entry>::new(&mut mem);
+
+        assert_eq!(Ok(None), ss.upsert(entry(5, 50)));
+        assert_eq!(Ok(None), ss.upsert(entry(1, 10)));
+        assert_eq!(2, ss.len());
+
+        assert_eq!(Ok(Some(entry(5, 50))), ss.upsert(entry(5, 51)));
+        assert_eq!(2, ss.len());
+        assert_eq!(Ok(&entry(5, 51)), ss.search_with_key(&5));
+
+        assert_eq!(Ok(None), ss.upsert(entry(3, 30)));
+        assert_eq!(3, ss.len());
+        assert_eq!(Err(Error::NotEnoughMemory), ss.upsert(entry(4, 40)));
+        assert_eq!(Ok(Some(entry(1, 10))), ss.upsert(entry(1, 11)));
+        assert_eq!(
+            [entry(1, 11), entry(3, 30), entry(5, 51)],
+            ss.iter().copied().collect::<Vec<_>>()[..]
+        );
+    }
+
+    #[test]
+    fn test_sorted_slice_map() {
+        let mut keys = AlignedBuffer([0; 3 * mem::size_of::<u32>()]);
+        let mut values = AlignedBuffer([0; 4 * mem::size_of::<[u64; 6]>()]);
+        let mut map = SortedSliceMap::<'_, u32, [u64; 6]>::new(&mut keys, &mut values);
+        assert_eq!(3, map.capacity());
+
+        assert_eq!(Ok(None), map.insert(20, [2; 6]));
+        assert_eq!(Ok(None), map.insert(10, [1; 6]));
+        assert_eq!(Ok(None), map.insert(30, [3; 6]));
+        assert_eq!(Err(Error::NotEnoughMemory), map.insert(40, [4; 6]));
+        assert_eq!(Ok(Some([1; 6])), map.insert(10, [5; 6]));
+        assert_eq!(3, map.len());
+
+        assert_eq!(Some(&[5; 6]), map.get(&10));
+        assert_eq!(None, map.get(&15));
+        map.get_mut(&30).unwrap()[0] = 7;
+        assert_eq!([10, 20, 30], map.keys());
+        assert_eq!(7, map.values()[2][0]);
+
+        assert_eq!(Some([2; 6]), map.remove(&20));
+        assert_eq!(None, map.remove(&20));
+        assert!(!map.contains_key(&20));
+        assert!(map.iter().map(|(k, v)| (*k, v[1])).eq([(10, 5), (30, 3)]));
+    }
+
+    #[test]
+    fn test_sorted_slice_map_bad_buffer() {
+        let mut keys = AlignedBuffer([0; 4 * mem::size_of::<u32>()]);
+        let mut values = AlignedBuffer([0; 4 * mem::size_of::<u64>()]);
+        assert!(matches!(
+            SortedSliceMap::<'_, u32, u64>::try_new(&mut keys[1..], &mut values),
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            SortedSliceMap::<'_, u32, u64>::try_new(&mut keys, &mut values[4..]),
+            Err(Error::BadBuffer)
+        ));
+        assert!(matches!(
+            SortedSliceMap::<'_, u32, ()>::try_new(&mut keys, &mut values),
+            Err(Error::BadBuffer)
+        ));
+        let map = SortedSliceMap::<'_, u32, u64>::try_new(&mut keys, &mut values).unwrap();
+        assert_eq!(4, map.capacity());
+    }
+
     #[test]
     fn test_add_contiguous_slice_in_sorted_array() {
-        let mut mem = [0; 10 * mem::size_of::<usize>()];
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<usize>()]);
         let mut ss = SortedSlice::<'_, usize>::new(&mut mem);
 
         assert_eq!(
@@ -287,14 +634,16 @@ mod tests {
 
     #[test]
     fn test_remove_in_sorted_array() {
-        let mut mem = [0; 10 * mem::size_of::<usize>()];
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<usize>()]);
         let mut ss = SortedSlice::new(&mut mem);
 
         ss.add_contiguous_slice(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9])
             .unwrap();
 
+        assert!(ss.contains(&5));
         assert_eq!(Ok(5), ss.remove(5));
         assert_eq!(Err(Error::ElementNotFound), ss.remove(5));
+        assert!(!ss.contains(&5));
 
         let mut len = ss.len();
         for e in [3, 2, 4, 9, 0, 1, 8, 7, 6] {
@@ -312,7 +661,7 @@ mod tests {
 
     #[test]
     fn test_iter_sorted_slice() {
-        let mut mem = [0; 10 * mem::size_of::<usize>()];
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<usize>()]);
         let mut ss = SortedSlice::new(&mut mem);
 
         let items = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
@@ -322,4 +671,104 @@ mod tests {
             ss.iter().collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn test_range_sorted_slice() {
+        let mut mem = AlignedBuffer([0; 20 * mem::size_of::<usize>()]);
+        let mut ss = SortedSlice::new(&mut mem);
+        assert_eq!(None, ss.range(&0, &10).next());
+
+        let items: Vec<usize> = (0..20).map(|i| i * 5).collect();
+        ss.add_contiguous_slice(&items).unwrap();
+        for (lo, hi) in [
+            (0, 95),
+            (10, 30),
+            (11, 29),
+            (12, 14),
+            (30, 30),
+            (31, 31),
+            (50, 20),
+            (96, 200),
+        ] {
+            assert_eq!(
+                items
+                    .iter()
+                    .filter(|i| (lo..=hi).contains(*i))
+                    .collect::<Vec<_>>(),
+                ss.range(&lo, &hi).collect::<Vec<_>>(),
+                "range {}..={}",
+                lo,
+                hi
+            );
+        }
+    }
+
+    #[test]
+    fn test_longest_consecutive_run() {
+        let mut mem = AlignedBuffer([0; 10 * mem::size_of::<u32>()]);
+        let mut ss = SortedSlice::<'_, u32>::new(&mut mem);
+        assert_eq!(None, ss.longest_consecutive_run());
+
+        ss.add_contiguous_slice(&[1, 2, 3, 7, 8]).unwrap();
+        assert_eq!(Some((1, 3)), ss.longest_consecutive_run());
+
+        ss.add_contiguous_slice(&[9, 10]).unwrap();
+        assert_eq!(Some((7, 4)), ss.longest_consecutive_run());
+    }
+
+    #[test]
+    fn test_remove_sorted() {
+        let mut mem = AlignedBuffer([0; 100 * mem::size_of::<usize>()]);
+        let mut ss = SortedSlice::new(&mut mem);
+        ss.add_contiguous_slice(&(0..100).collect::<Vec<usize>>())
+            .unwrap();
+
+        // Keys that are not present (100, 200) are skipped.
+        let to_remove: Vec<usize> = (0..100).filter(|i| i % 3 == 0).chain([100, 200]).collect();
+        assert_eq!(34, ss.remove_sorted(&to_remove));
+        assert_eq!(66, ss.len());
+        assert_eq!(
+            (0..100).filter(|i| i % 3 != 0).collect::<Vec<_>>(),
+            ss.iter().copied().collect::<Vec<_>>()
+        );
+
+        assert_eq!(0, ss.remove_sorted(&[]));
+        assert_eq!(0, ss.remove_sorted(&to_remove));
+        assert_eq!(66, ss.len());
+    }
+
+    #[test]
+    fn test_chunks_sorted_slice() {
+        let mut mem = AlignedBuffer([0; 16 * mem::size_of::<usize>()]);
+        let mut ss = SortedSlice::new(&mut mem);
+        ss.add_contiguous_slice(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9])
+            .unwrap();
+
+        // Only the stored elements are chunked, not the free tail of the buffer.
+        let chunks: Vec<&[usize]> = ss.chunks(3).collect();
+        assert_eq!(
+            chunks,
+            [&[0, 1, 2][..], &[3, 4, 5][..], &[6, 7, 8][..], &[9][..]]
+        );
+    }
+
+    #[test]
+    fn test_partition_into() {
+        let mut mem = AlignedBuffer([0; 100 * mem::size_of::<usize>()]);
+        let mut ss = SortedSlice::new(&mut mem);
+        ss.add_contiguous_slice(&(0..100).collect::<Vec<usize>>())
+            .unwrap();
+
+        let mut lo = [0; 40];
+        let mut hi = [0; 60];
+        assert_eq!(Ok((40, 60)), ss.partition_into(&40, &mut lo, &mut hi));
+        assert_eq!((0..40).collect::<Vec<_>>(), lo);
+        assert_eq!((40..100).collect::<Vec<_>>(), hi);
+
+        let mut small = [0; 59];
+        assert_eq!(
+            Err(Error::NotEnoughMemory),
+            ss.partition_into(&40, &mut lo, &mut small)
+        );
+    }
 }
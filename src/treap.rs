@@ -0,0 +1,443 @@
+//! A treap over a caller-provided buffer: a binary search tree whose nodes also form a max-heap on a random priority.
+//!
+//! Random priorities keep the expected depth logarithmic without any recoloring. An insert is a plain leaf insert
+//! followed by rotations up to where the priority fits, and a removal rotates the node down until it is a leaf.
+//! no_std targets have no default source of randomness, so the caller supplies the priorities.
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::pool::{NodePool, Slot, Storage};
+use crate::{Error, OrderedKey, Result};
+
+/// The slot index that marks a missing link.
+const NULL: u16 = u16::MAX;
+
+pub const fn node_size<D>() -> usize {
+    size_of::<Node<D>>()
+}
+
+/// A treap holding up to `SIZE` elements of type `D`, or as many as fit in its buffer if `SIZE` is 0.
+///
+/// Every inserted element is given a priority drawn from the source passed to [Treap::new], and the tree is kept
+/// ordered by key from left to right and by priority from the head down. The priorities should be uniformly random
+/// for the expected O(log n) depth; a poor source only costs speed, since nothing walks the tree recursively.
+pub struct Treap<'a, D, const SIZE: usize>
+where
+    D: OrderedKey,
+{
+    storage: Storage<'a, Node<D>, SIZE>,
+    /// The slot of the head node, or [NULL] if the treap is empty.
+    head: u16,
+    priority: &'a mut dyn FnMut() -> u32,
+}
+
+/// A [Treap] that holds as many nodes as fit in the buffer it is created with.
+pub type TreapDyn<'a, D> = Treap<'a, D, 0>;
+
+impl<'a, D, const SIZE: usize> Treap<'a, D, SIZE>
+where
+    D: OrderedKey,
+{
+    /// Creates an empty treap backed by `slice`, drawing the priority of every inserted element from `priority`.
+    /// Panics if `slice` is shorter than `SIZE * node_size::<D>()` bytes or is not aligned for the nodes.
+    pub fn new(slice: &'a mut [u8], priority: &'a mut dyn FnMut() -> u32) -> Self {
+        Self::new_uninit(crate::as_uninit(slice), priority)
+    }
+
+    /// Creates an empty treap backed by `slice` like [Self::new], but returns `Error::BadBuffer` instead of panicking
+    /// if `slice` is too short or not aligned for the nodes.
+    pub fn try_new(slice: &'a mut [u8], priority: &'a mut dyn FnMut() -> u32) -> Result<Self> {
+        Storage::<Node<D>, SIZE>::check(crate::as_uninit(slice))?;
+        Ok(Self::new(slice, priority))
+    }
+
+    /// Creates an empty treap backed by `slice`, whose bytes need not be initialized or zeroed. Panics like
+    /// [Self::new].
+    pub fn new_uninit(
+        slice: &'a mut [MaybeUninit<u8>],
+        priority: &'a mut dyn FnMut() -> u32,
+    ) -> Self {
+        Self {
+            storage: Storage::new(slice),
+            head: NULL,
+            priority,
+        }
+    }
+
+    /// Returns the number of elements in the treap.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of elements the treap can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Removes every element from the treap. The backing storage is kept, and all of its slots can be reused.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.head = NULL;
+    }
+
+    /// Inserts `data` as a leaf, then rotates it up past every ancestor with a lower priority. Returns
+    /// `Error::AlreadyExists` if an element with the same key is in the treap, and `Error::OutOfSpace` if it is full.
+    pub fn insert(&mut self, data: D) -> Result<()> {
+        let (parent, left) = match self.search_slot(data.ordering_key()) {
+            Ok(_) => return Err(Error::AlreadyExists),
+            Err(slot) => slot,
+        };
+        let priority = (self.priority)();
+        let index = self
+            .storage
+            .add(|index| Node::new(data, priority, index))?
+            .index;
+        self.storage.node_mut(index).parent = parent;
+        match parent {
+            NULL => self.head = index,
+            _ if left => self.storage.node_mut(parent).left = index,
+            _ => self.storage.node_mut(parent).right = index,
+        }
+
+        loop {
+            let parent = self.storage.node(index).parent;
+            if parent == NULL || self.storage.node(parent).priority >= priority {
+                return Ok(());
+            }
+            self.rotate_up(index);
+        }
+    }
+
+    /// Descends from the head towards `key`. Returns the node holding it, or the node it would be attached to along
+    /// with whether it would be the left child.
+    fn search_slot(&self, key: &D::Key) -> core::result::Result<u16, (u16, bool)> {
+        let mut parent = (NULL, false);
+        let mut index = self.head;
+        while index != NULL {
+            let node = self.storage.node(index);
+            match key.cmp(node.data.ordering_key()) {
+                core::cmp::Ordering::Equal => return Ok(index),
+                core::cmp::Ordering::Less => {
+                    parent = (index, true);
+                    index = node.left;
+                }
+                core::cmp::Ordering::Greater => {
+                    parent = (index, false);
+                    index = node.right;
+                }
+            }
+        }
+        Err(parent)
+    }
+
+    /// Rotates node `index` into the place of its parent, which becomes its child.
+    fn rotate_up(&mut self, index: u16) {
+        let parent = self.storage.node(index).parent;
+        let grandparent = self.storage.node(parent).parent;
+        if self.storage.node(parent).left == index {
+            let moved = self.storage.node(index).right;
+            self.storage.node_mut(parent).left = moved;
+            self.storage.node_mut(index).right = parent;
+            if moved != NULL {
+                self.storage.node_mut(moved).parent = parent;
+            }
+        } else {
+            let moved = self.storage.node(index).left;
+            self.storage.node_mut(parent).right = moved;
+            self.storage.node_mut(index).left = parent;
+            if moved != NULL {
+                self.storage.node_mut(moved).parent = parent;
+            }
+        }
+        self.storage.node_mut(parent).parent = index;
+        self.storage.node_mut(index).parent = grandparent;
+        self.replace_child(grandparent, parent, index);
+    }
+
+    /// Points the link of `parent` that leads to `old` at `new` instead, or the head if `parent` is [NULL].
+    fn replace_child(&mut self, parent: u16, old: u16, new: u16) {
+        if parent == NULL {
+            self.head = new;
+        } else if self.storage.node(parent).left == old {
+            self.storage.node_mut(parent).left = new;
+        } else {
+            self.storage.node_mut(parent).right = new;
+        }
+    }
+
+    /// Returns true if an element with the given key is in the treap.
+    pub fn contains(&self, key: &D::Key) -> bool {
+        self.search_slot(key).is_ok()
+    }
+
+    /// Returns a reference to the element with the given key.
+    pub fn search_ref(&self, key: &D::Key) -> Option<&D> {
+        let index = self.search_slot(key).ok()?;
+        Some(&self.storage.node(index).data)
+    }
+
+    pub fn delete(&mut self, data: D) -> Result<()> {
+        self.remove(data.ordering_key())
+            .map(|_| ())
+            .ok_or(Error::NotFound)
+    }
+
+    /// Removes the element with the given key from the treap, returning it. The node is rotated down below its
+    /// higher-priority child until it is a leaf, where it can be unlinked. Returns `None` if no element has the key.
+    pub fn remove(&mut self, key: &D::Key) -> Option<D> {
+        let index = self.search_slot(key).ok()?;
+        loop {
+            let node = self.storage.node(index);
+            let child = match (node.left, node.right) {
+                (NULL, NULL) => break,
+                (child, NULL) | (NULL, child) => child,
+                (left, right) => {
+                    match self.storage.node(left).priority > self.storage.node(right).priority {
+                        true => left,
+                        false => right,
+                    }
+                }
+            };
+            self.rotate_up(child);
+        }
+        self.replace_child(self.storage.node(index).parent, index, NULL);
+        Some(self.storage.delete(index).data)
+    }
+
+    /// Checks the ordering of the keys, the heap order of the priorities and the parent links, returning the depth
+    /// of the treap, or a description of the first invariant that does not hold.
+    pub fn validate(&self) -> core::result::Result<usize, &'static str> {
+        if self.head != NULL && self.storage.node(self.head).parent != NULL {
+            return Err("head has a parent");
+        }
+        let mut depth = 0;
+        let mut count = 0;
+        let mut previous: Option<&D> = None;
+        let mut next = self.min_index();
+        while next != NULL {
+            let node = self.storage.node(next);
+            if previous.is_some_and(|previous| previous.ordering_key() >= node.data.ordering_key())
+            {
+                return Err("keys are not in ascending order");
+            }
+            for child in [node.left, node.right] {
+                if child == NULL {
+                    continue;
+                }
+                if self.storage.node(child).parent != next {
+                    return Err("child does not point back to its parent");
+                }
+                if self.storage.node(child).priority > node.priority {
+                    return Err("child has a higher priority than its parent");
+                }
+            }
+            let mut node_depth = 1;
+            let mut ancestor = node.parent;
+            while ancestor != NULL && node_depth <= self.len() {
+                node_depth += 1;
+                ancestor = self.storage.node(ancestor).parent;
+            }
+            depth = depth.max(node_depth);
+            count += 1;
+            if count > self.len() {
+                return Err("treap holds more nodes than its length");
+            }
+            previous = Some(&node.data);
+            next = self.next_index(next);
+        }
+        if count != self.len() {
+            return Err("length does not match the number of nodes");
+        }
+        Ok(depth)
+    }
+
+    fn min_index(&self) -> u16 {
+        let mut index = self.head;
+        while index != NULL && self.storage.node(index).left != NULL {
+            index = self.storage.node(index).left;
+        }
+        index
+    }
+
+    /// Returns the in-order successor of node `index` by following parent links.
+    fn next_index(&self, index: u16) -> u16 {
+        let node = self.storage.node(index);
+        if node.right != NULL {
+            let mut current = node.right;
+            while self.storage.node(current).left != NULL {
+                current = self.storage.node(current).left;
+            }
+            return current;
+        }
+        let mut child = index;
+        let mut parent = node.parent;
+        while parent != NULL && self.storage.node(parent).right == child {
+            child = parent;
+            parent = self.storage.node(parent).parent;
+        }
+        parent
+    }
+
+    /// Returns an iterator over the elements in ascending order.
+    pub fn iter(&self) -> Iter<'_, 'a, D, SIZE> {
+        Iter {
+            treap: self,
+            next: self.min_index(),
+        }
+    }
+}
+
+impl<D, const SIZE: usize> Treap<'_, D, SIZE>
+where
+    D: Copy + OrderedKey,
+{
+    pub fn search(&self, key: &D::Key) -> Option<D> {
+        self.search_ref(key).copied()
+    }
+}
+
+/// An iterator over the elements of a [Treap] in ascending order.
+pub struct Iter<'b, 'a, D, const SIZE: usize>
+where
+    D: OrderedKey,
+{
+    treap: &'b Treap<'a, D, SIZE>,
+    next: u16,
+}
+
+impl<'b, D, const SIZE: usize> Iterator for Iter<'b, '_, D, SIZE>
+where
+    D: OrderedKey,
+{
+    type Item = &'b D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NULL {
+            return None;
+        }
+        let index = self.next;
+        self.next = self.treap.next_index(index);
+        Some(&self.treap.storage.node(index).data)
+    }
+}
+
+impl<'b, 'a, D, const SIZE: usize> IntoIterator for &'b Treap<'a, D, SIZE>
+where
+    D: OrderedKey,
+{
+    type Item = &'b D;
+    type IntoIter = Iter<'b, 'a, D, SIZE>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+struct Node<D> {
+    data: D,
+    priority: u32,
+    /// The slot of this node, or [NULL] if the slot is free.
+    index: u16,
+    parent: u16,
+    left: u16,
+    right: u16,
+}
+
+impl<D> Node<D> {
+    /// Returns an unlinked node holding `data`, to be written to slot `index`.
+    fn new(data: D, priority: u32, index: u16) -> Self {
+        Node {
+            data,
+            priority,
+            index,
+            parent: NULL,
+            left: NULL,
+            right: NULL,
+        }
+    }
+}
+
+impl<D> Slot for Node<D> {
+    unsafe fn slot_index(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).index).read() }
+    }
+
+    unsafe fn write_slot_index(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).index).write(index) };
+    }
+
+    unsafe fn parent_link(slot: *const Self) -> u16 {
+        unsafe { ptr::addr_of!((*slot).parent).read() }
+    }
+
+    unsafe fn set_parent_link(slot: *mut Self, index: u16) {
+        unsafe { ptr::addr_of_mut!((*slot).parent).write(index) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{node_size, Treap, TreapDyn};
+    use crate::testing::{self, impl_test_set, xorshift, Tracked};
+    use crate::{AlignedBuffer, Error};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    impl_test_set!([const SIZE: usize] Treap<'_, u32, SIZE>);
+
+    #[test]
+    fn test_insert_search_remove() {
+        let mut mem = AlignedBuffer([0; 500 * node_size::<u32>()]);
+        let mut priority = xorshift(0x2545_f491);
+        testing::check_against_set(&mut Treap::<u32, 500>::new(&mut mem, &mut priority));
+    }
+
+    #[test]
+    fn test_constant_priority() {
+        // Without random priorities the treap degenerates into a list, which is slow but still correct.
+        let mut mem = AlignedBuffer([0; 1000 * node_size::<u32>()]);
+        let mut priority = || 0;
+        let mut treap = TreapDyn::<u32>::new(&mut mem, &mut priority);
+        assert_eq!(treap.capacity(), 1000);
+        for value in 0..1000 {
+            treap.insert(value).unwrap();
+        }
+        assert!(matches!(treap.insert(1000), Err(Error::OutOfSpace)));
+        assert!(matches!(treap.insert(10), Err(Error::AlreadyExists)));
+        assert_eq!(treap.validate(), Ok(1000));
+        assert!(treap.iter().copied().eq(0..1000));
+
+        for value in (0..1000).step_by(2) {
+            assert_eq!(treap.remove(&value), Some(value));
+        }
+        treap.validate().unwrap();
+        assert!(treap.iter().copied().eq((1..1000).step_by(2)));
+
+        treap.clear();
+        assert!(treap.is_empty());
+        assert_eq!(treap.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_drop_elements() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut mem = AlignedBuffer([0; 16 * node_size::<Tracked>()]);
+        let mut priority = xorshift(7);
+        let mut treap = Treap::<Tracked, 16>::new(&mut mem, &mut priority);
+        for value in 0..10 {
+            treap.insert(Tracked(value, &DROPPED)).unwrap();
+        }
+        drop(treap.remove(&3));
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        treap.clear();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 10);
+        treap.insert(Tracked(1, &DROPPED)).unwrap();
+        drop(treap);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 11);
+    }
+}